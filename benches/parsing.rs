@@ -0,0 +1,72 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use http_server_starter_rust::{
+    http_request::{Encoding, RequestHeader, RequestLine},
+    http_response::{ContentType, HTTPResponse, ResponseStatus},
+};
+use std::hint::black_box;
+
+const REQUEST: &str = "GET /files/reports/2024/summary.txt?download=1 HTTP/1.1\r\n\
+    Host: localhost:4221\r\n\
+    User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n\
+    Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+    Accept-Language: en-US,en;q=0.5\r\n\
+    Accept-Encoding: gzip, deflate, br\r\n\
+    Connection: keep-alive\r\n\
+    Cookie: csrf_token=3q2-7wAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\r\n\
+    \r\n";
+
+fn parsing(c: &mut Criterion) {
+    let request_line = REQUEST.lines().next().unwrap_or_default();
+    let mut group = c.benchmark_group("parsing");
+    group.throughput(Throughput::Bytes(REQUEST.len() as u64));
+    group.bench_function("request_line", |b| {
+        b.iter(|| black_box(request_line).parse::<RequestLine>());
+    });
+    group.bench_function("request_line_lenient", |b| {
+        b.iter(|| RequestLine::parse_lenient(black_box(request_line)));
+    });
+    group.bench_function("request_header", |b| {
+        b.iter(|| black_box(REQUEST).parse::<RequestHeader>());
+    });
+    group.finish();
+}
+
+fn responses(c: &mut Criterion) {
+    let body = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+    let mut group = c.benchmark_group("responses");
+    group.throughput(Throughput::Bytes(body.len() as u64));
+    group.bench_function("build", |b| {
+        b.iter(|| {
+            HTTPResponse::new_builder(ResponseStatus::Http200)
+                .with_body(black_box(&body), ContentType::TextPlain, &[])
+                .build()
+        });
+    });
+    group.bench_function("serialize", |b| {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body(&body, ContentType::TextPlain, &[])
+            .build()
+            .with_header("X-Request-Id", "0190a7c4-5bd4-7e21-9f0e-3c1b2a6d8e47");
+        b.iter(|| black_box(&response).as_http_bytes());
+    });
+    group.bench_function("echo_head", |b| {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body("abc", ContentType::TextPlain, &[])
+            .build()
+            .with_header("X-Request-Id", "0190a7c4-5bd4-7e21-9f0e-3c1b2a6d8e47");
+        b.iter(|| black_box(&response).head_bytes());
+    });
+    group.bench_function("gzip", |b| {
+        b.iter_batched(
+            || HTTPResponse::new_builder(ResponseStatus::Http200),
+            |builder| {
+                builder.with_body(black_box(&body), ContentType::TextPlain, &[Encoding::Gzip])
+            },
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parsing, responses);
+criterion_main!(benches);
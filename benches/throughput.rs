@@ -0,0 +1,100 @@
+//! Requests served end to end over localhost connections.
+
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use http_server_starter_rust::{
+    access_log::AccessLog, client_handler::ClientHandler, config::Config,
+    server_context::ServerContext,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    runtime::Runtime,
+    sync::watch,
+};
+
+const REQUEST: &[u8] = b"GET /echo/bench HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+/// Starts a server on an ephemeral port, serving connections until the runtime is dropped.
+async fn serve() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Can't bind a local port");
+    let address = listener.local_addr().expect("Listener has an address");
+    // A benchmark sends far more requests on its connection than the default allows
+    let config = Arc::new(Config {
+        keepalive_requests: usize::MAX,
+        ..Config::default()
+    });
+    let context = Arc::new(ServerContext::new(
+        config,
+        AccessLog::spawn_to(tokio::io::sink()),
+    ));
+    let (draining_sender, draining) = watch::channel(false);
+    tokio::spawn(async move {
+        let _draining_sender = draining_sender;
+        while let Ok((mut stream, peer)) = listener.accept().await {
+            let (context, draining) = (Arc::clone(&context), draining.clone());
+            tokio::spawn(async move {
+                let _ =
+                    ClientHandler::handle_connection(&mut stream, peer, context, draining).await;
+            });
+        }
+    });
+    address
+}
+
+/// Sends a request and reads its response, which ends with the echoed `bench`.
+async fn exchange(stream: &mut TcpStream, buf: &mut Vec<u8>) {
+    stream
+        .write_all(REQUEST)
+        .await
+        .expect("Server reads requests");
+    buf.clear();
+    while !buf.ends_with(b"bench") {
+        let read = stream.read_buf(buf).await.expect("Server answers");
+        assert_ne!(read, 0, "Server closed the connection");
+    }
+}
+
+fn throughput(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("Can't start the async runtime");
+    let address = runtime.block_on(serve());
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Elements(1));
+
+    let mut stream = runtime
+        .block_on(TcpStream::connect(address))
+        .expect("Can't connect to the server");
+    let mut buf = Vec::with_capacity(256);
+    group.bench_function("keep_alive", |b| {
+        b.iter_custom(|iters| {
+            runtime.block_on(async {
+                let started = Instant::now();
+                for _ in 0..iters {
+                    exchange(&mut stream, &mut buf).await;
+                }
+                started.elapsed()
+            })
+        });
+    });
+    group.bench_function("connection_per_request", |b| {
+        b.iter_custom(|iters| {
+            runtime.block_on(async {
+                let started = Instant::now();
+                for _ in 0..iters {
+                    let mut stream = TcpStream::connect(address)
+                        .await
+                        .expect("Can't connect to the server");
+                    exchange(&mut stream, &mut buf).await;
+                }
+                started.elapsed()
+            })
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+#![no_main]
+
+use http_server_starter_rust::http_request::{Charset, RequestBody};
+use libfuzzer_sys::fuzz_target;
+
+// Bodies arrive as raw bytes and are decoded in the charset their request declares
+fuzz_target!(|data: &[u8]| {
+    for charset in [Charset::Utf8, Charset::UsAscii, Charset::Iso8859_1] {
+        if let Ok(decoded) = charset.decode(data) {
+            let _ = decoded.parse::<RequestBody>();
+        }
+    }
+    if let Ok(request) = std::str::from_utf8(data) {
+        let _ = request.parse::<RequestBody>();
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use http_server_starter_rust::http_request::RequestHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(head) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(header) = head.parse::<RequestHeader>() else {
+        return;
+    };
+    let _ = header.host().map(|host| host.name());
+    let _ = header.charset();
+    let _ = header.accept_language().negotiate(&["en", "en-GB", "fr"]);
+    for (name, _) in header.headers().iter() {
+        assert!(header.get(name).is_some());
+    }
+});
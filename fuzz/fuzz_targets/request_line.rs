@@ -0,0 +1,27 @@
+#![no_main]
+
+use http_server_starter_rust::http_request::{percent_decode, RequestLine};
+use libfuzzer_sys::fuzz_target;
+
+// Strict and lenient parsing must agree on what a valid request line means
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    let strict = line.parse::<RequestLine>();
+    let lenient = RequestLine::parse_lenient(line);
+    if let Ok(strict) = &strict {
+        assert_eq!(Ok(strict), lenient.as_ref());
+        let uri = strict.uri();
+        assert!(uri.path().starts_with('/'));
+        assert!(!uri.segments().any(|segment| segment == ".."));
+        let _ = uri.query_param("a");
+        if let Some(file) = uri.file_in("/", std::path::Path::new("/srv")) {
+            assert!(file.starts_with("/srv"));
+            assert!(!file
+                .components()
+                .any(|component| component == std::path::Component::ParentDir));
+        }
+    }
+    let _ = percent_decode(line);
+});
@@ -0,0 +1,364 @@
+use std::{
+    fmt::{Display, Write as _},
+    io::Write,
+    net::SocketAddr,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use crate::{
+    http_date::{civil_from_days, MONTHS},
+    http_response::HTTPResponse,
+};
+
+/// Lines waiting to be written before new ones are dropped.
+const ACCESS_LOG_BUFFER: usize = 1024;
+
+/// Layout of the access log lines.
+///
+/// Both formats end with the time taken to serve the request in microseconds,
+/// like Apache's `%D`, then the request ID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessLogFormat {
+    /// No access log at all.
+    Off,
+    /// Common Log Format: `host - - [time] "request" status bytes`.
+    #[default]
+    Common,
+    /// Common Log Format followed by the quoted `Referer` and `User-Agent`.
+    Combined,
+}
+impl FromStr for AccessLogFormat {
+    type Err = AccessLogFormatError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "common" => Ok(Self::Common),
+            "combined" => Ok(Self::Combined),
+            invalid_format => Err(AccessLogFormatError::InvalidFormat(
+                invalid_format.to_string(),
+            )),
+        }
+    }
+}
+impl Display for AccessLogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Common => write!(f, "common"),
+            Self::Combined => write!(f, "combined"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum AccessLogFormatError {
+    #[error("'{0}' is not an access log format, expected 'off', 'common' or 'combined'")]
+    InvalidFormat(String),
+}
+
+/// What the access log records about one request.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccessLogEntry {
+    pub peer: SocketAddr,
+    pub time: SystemTime,
+    /// The request line as received, `None` if the client never sent one.
+    pub request_line: Option<String>,
+    pub status: u16,
+    pub bytes: usize,
+    pub latency: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: String,
+}
+impl AccessLogEntry {
+    /// Describes the exchange of `request`, as read from the client, for `response`.
+    pub(crate) fn new(
+        peer: SocketAddr,
+        request: &[u8],
+        response: &HTTPResponse,
+        latency: Duration,
+        request_id: String,
+    ) -> Self {
+        let request = String::from_utf8_lossy(request);
+        let request_line = request
+            .split("\r\n")
+            .next()
+            .filter(|line| !line.is_empty())
+            .map(ToString::to_string);
+        let header = |name: &str| {
+            request
+                .split("\r\n")
+                .skip(1)
+                .take_while(|line| !line.is_empty())
+                .find_map(|line| {
+                    let (header, value) = line.split_once(':')?;
+                    header
+                        .trim()
+                        .eq_ignore_ascii_case(name)
+                        .then(|| value.trim().to_string())
+                })
+        };
+        Self {
+            peer,
+            time: SystemTime::now(),
+            request_line,
+            status: response.status().code(),
+            bytes: response.body_length(),
+            latency,
+            referer: header("Referer"),
+            user_agent: header("User-Agent"),
+            request_id,
+        }
+    }
+
+    /// Formats the entry as a log line, without the trailing newline.
+    #[must_use]
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        let request_line = self.request_line.as_deref().map_or_else(
+            || "-".to_string(),
+            |request_line| format!("\"{}\"", escape(request_line)),
+        );
+        let bytes = if self.bytes == 0 {
+            "-".to_string()
+        } else {
+            self.bytes.to_string()
+        };
+        let mut line = format!(
+            "{} - - [{}] {request_line} {} {bytes}",
+            self.peer.ip(),
+            format_time(self.time),
+            self.status,
+        );
+        if format == AccessLogFormat::Combined {
+            let quoted = |value: Option<&str>| {
+                value.map_or_else(
+                    || "\"-\"".to_string(),
+                    |value| format!("\"{}\"", escape(value)),
+                )
+            };
+            let _ = write!(
+                line,
+                " {} {}",
+                quoted(self.referer.as_deref()),
+                quoted(self.user_agent.as_deref())
+            );
+        }
+        let _ = write!(line, " {} {}", self.latency.as_micros(), self.request_id);
+        line
+    }
+}
+
+/// Hands access log lines over to a background task that writes them out.
+///
+/// Recording never waits: when the writer falls behind and its buffer is full,
+/// lines are dropped rather than slowing requests down.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    sender: mpsc::Sender<String>,
+}
+impl AccessLog {
+    /// Starts writing the access log to the standard output.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[must_use]
+    pub fn spawn() -> Self {
+        Self::spawn_to(tokio::io::stdout())
+    }
+
+    /// Starts writing the access log to `writer`.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn_to<W: AsyncWrite + Unpin + Send + 'static>(mut writer: W) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<String>(ACCESS_LOG_BUFFER);
+        tokio::spawn(async move {
+            while let Some(line) = receiver.recv().await {
+                let mut lines = line + "\n";
+                while let Ok(line) = receiver.try_recv() {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+                if writer.write_all(lines.as_bytes()).await.is_err()
+                    || writer.flush().await.is_err()
+                {
+                    break;
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    /// Starts writing the access log to `writer` from a thread of the blocking
+    /// pool, for writers such as files that can't be written asynchronously.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn_blocking_to<W: Write + Send + 'static>(mut writer: W) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<String>(ACCESS_LOG_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            while let Some(line) = receiver.blocking_recv() {
+                let mut lines = line + "\n";
+                while let Ok(line) = receiver.try_recv() {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+                if writer.write_all(lines.as_bytes()).is_err() || writer.flush().is_err() {
+                    break;
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    /// Queues `entry` to be written in `format`.
+    pub fn record(&self, format: AccessLogFormat, entry: &AccessLogEntry) {
+        if format == AccessLogFormat::Off {
+            return;
+        }
+        let _ = self.sender.try_send(entry.format(format));
+    }
+}
+
+/// Escapes quotes, backslashes and control characters so a client can't forge log lines.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\x{:02x}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats `time` as `10/Oct/2000:13:55:36 +0000`, always in UTC.
+fn format_time(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    let month = usize::try_from(month - 1).map_or("Jan", |month| MONTHS[month]);
+    format!(
+        "{day:02}/{month}/{year}:{:02}:{:02}:{:02} +0000",
+        seconds % 86_400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::http_response::{ContentType, ResponseStatus};
+
+    fn entry() -> AccessLogEntry {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body("abc", ContentType::TextPlain, &[])
+            .build();
+        AccessLogEntry {
+            time: UNIX_EPOCH + Duration::from_secs(971_186_136),
+            ..AccessLogEntry::new(
+                "127.0.0.1:1234".parse().unwrap(),
+                b"GET /echo/abc HTTP/1.1\r\nUser-Agent: curl/8.0\r\nreferer: http://x/\r\n\r\n",
+                &response,
+                Duration::from_micros(1500),
+                "abc-1".to_string(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_access_log_format_from_str() {
+        assert_eq!("combined".parse(), Ok(AccessLogFormat::Combined));
+        assert_eq!("OFF".parse(), Ok(AccessLogFormat::Off));
+        assert!(matches!(
+            "json".parse::<AccessLogFormat>(),
+            Err(AccessLogFormatError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_entry_format_common() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Common),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /echo/abc HTTP/1.1\" 200 3 1500 abc-1"
+        );
+    }
+
+    #[test]
+    fn test_entry_format_combined() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Combined),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /echo/abc HTTP/1.1\" 200 3 \"http://x/\" \"curl/8.0\" 1500 abc-1"
+        );
+    }
+
+    #[test]
+    fn test_entry_format_escapes_and_missing_fields() {
+        let entry = AccessLogEntry {
+            request_line: None,
+            bytes: 0,
+            referer: None,
+            user_agent: Some("evil\"\n".to_string()),
+            ..entry()
+        };
+        assert_eq!(
+            entry.format(AccessLogFormat::Combined),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] - 200 - \"-\" \"evil\\\"\\x0a\" 1500 abc-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_log_writes_lines_from_blocking_pool() {
+        struct Lines(std::sync::mpsc::Sender<Vec<u8>>);
+        impl Write for Lines {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let _ = self.0.send(buf.to_vec());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let access_log = AccessLog::spawn_blocking_to(Lines(sender));
+        access_log.record(AccessLogFormat::Combined, &entry());
+        drop(access_log);
+
+        let written = tokio::task::spawn_blocking(move || receiver.recv().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            format!("{}\n", entry().format(AccessLogFormat::Combined))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_log_writes_lines() {
+        let (writer, mut reader) = tokio::io::duplex(1024);
+        let access_log = AccessLog::spawn_to(writer);
+        access_log.record(AccessLogFormat::Off, &entry());
+        access_log.record(AccessLogFormat::Common, &entry());
+        drop(access_log);
+
+        let mut written = String::new();
+        reader.read_to_string(&mut written).await.unwrap();
+        assert_eq!(
+            written,
+            format!("{}\n", entry().format(AccessLogFormat::Common))
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::{fmt::Display, net::IpAddr, str::FromStr};
+
+use thiserror::Error;
+
+use crate::rate_limit::RateLimit;
+
+/// Policies applying to path prefixes, read from a file of
+/// `prefix rule rule...` lines like
+/// `/files/private/ auth methods=GET,HEAD clients=10.0.0.0/8,::1 rate=5/10`.
+///
+/// A request follows the policy with the longest prefix of its path, and
+/// paths no prefix matches stay open.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccessPolicies {
+    /// Longest prefixes first.
+    policies: Vec<AccessPolicy>,
+}
+impl AccessPolicies {
+    /// The policy of the longest prefix of `path`, `None` if it isn't covered.
+    #[must_use]
+    pub fn for_path(&self, path: &str) -> Option<&AccessPolicy> {
+        self.policies
+            .iter()
+            .find(|policy| path.starts_with(&policy.prefix))
+    }
+}
+impl FromStr for AccessPolicies {
+    type Err = AccessPolicyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policies = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::parse)
+            .collect::<Result<Vec<AccessPolicy>, _>>()?;
+        policies.sort_by_key(|policy| std::cmp::Reverse(policy.prefix.len()));
+        Ok(Self { policies })
+    }
+}
+
+/// What a request under `prefix` must satisfy. Rules left out don't restrict anything.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccessPolicy {
+    pub prefix: String,
+    /// Whether the client must authenticate, with any of the configured credentials (`auth`).
+    pub authenticated: bool,
+    /// Methods allowed, uppercase, all when empty (`methods=GET,HEAD`). `GET` allows `HEAD` too.
+    pub methods: Vec<String>,
+    /// Networks clients must connect from, any when empty (`clients=10.0.0.0/8,::1`).
+    pub clients: Vec<IpNetwork>,
+    /// Requests per second and burst per client under the prefix (`rate=5/10`, the burst defaulting to the rate).
+    pub rate_limit: Option<RateLimit>,
+}
+impl AccessPolicy {
+    #[must_use]
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.methods.is_empty()
+            || self
+                .methods
+                .iter()
+                .any(|allowed| allowed == method || (allowed == "GET" && method == "HEAD"))
+    }
+
+    #[must_use]
+    pub fn allows_client(&self, address: IpAddr) -> bool {
+        self.clients.is_empty() || self.clients.iter().any(|network| network.contains(address))
+    }
+
+    /// The methods allowed, as an `Allow` header value.
+    #[must_use]
+    pub fn allow(&self) -> String {
+        let mut methods = self.methods.clone();
+        if methods.iter().any(|method| method == "GET") && !methods.iter().any(|m| m == "HEAD") {
+            methods.push("HEAD".to_string());
+        }
+        methods.join(", ")
+    }
+}
+impl FromStr for AccessPolicy {
+    type Err = AccessPolicyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let prefix = words
+            .next()
+            .filter(|prefix| prefix.starts_with('/'))
+            .ok_or_else(|| AccessPolicyError::InvalidLine(s.to_string()))?;
+        let mut policy = Self {
+            prefix: prefix.to_string(),
+            authenticated: false,
+            methods: Vec::new(),
+            clients: Vec::new(),
+            rate_limit: None,
+        };
+        for rule in words {
+            let invalid = || AccessPolicyError::InvalidRule(rule.to_string());
+            match rule.split_once('=') {
+                None if rule == "auth" => policy.authenticated = true,
+                Some(("methods", methods)) => {
+                    policy.methods = methods
+                        .split(',')
+                        .map(|method| {
+                            (!method.is_empty() && method.bytes().all(|b| b.is_ascii_alphabetic()))
+                                .then(|| method.to_uppercase())
+                                .ok_or_else(invalid)
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                Some(("clients", clients)) => {
+                    policy.clients = clients
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()?;
+                }
+                Some(("rate", rate)) => {
+                    let (per_second, burst) = rate.split_once('/').unwrap_or((rate, rate));
+                    let (Ok(per_second), Ok(burst)) = (per_second.parse(), burst.parse()) else {
+                        return Err(invalid());
+                    };
+                    if per_second == 0 || burst == 0 {
+                        return Err(invalid());
+                    }
+                    policy.rate_limit = Some(RateLimit { per_second, burst });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// An IPv4 or IPv6 network in CIDR notation, like `10.0.0.0/8`, or a single
+/// address when the prefix length is left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_len: u8,
+}
+impl IpNetwork {
+    /// Whether `address` is in the network. IPv4 clients connecting to an IPv6
+    /// socket, as `::ffff:a.b.c.d`, are matched as IPv4.
+    #[must_use]
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+impl FromStr for IpNetwork {
+    type Err = AccessPolicyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AccessPolicyError::InvalidNetwork(s.to_string());
+        let (address, prefix_len) = s.split_once('/').map_or((s, None), |(a, p)| (a, Some(p)));
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(invalid());
+        }
+        Ok(Self {
+            address,
+            prefix_len,
+        })
+    }
+}
+impl Display for IpNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum AccessPolicyError {
+    #[error("'{0}' is not a 'prefix rule rule...' line, the prefix must start with '/'")]
+    InvalidLine(String),
+    #[error("'{0}' is not a rule, expected 'auth', 'methods=', 'clients=' or 'rate='")]
+    InvalidRule(String),
+    #[error("'{0}' is not an IP address or a network like '10.0.0.0/8'")]
+    InvalidNetwork(String),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies_match_longest_prefix() {
+        let policies: AccessPolicies =
+            "# locked down\n/files/ methods=GET\n\n/files/private/ auth rate=5/10\n"
+                .parse()
+                .unwrap();
+        let private = policies.for_path("/files/private/a.txt").unwrap();
+        assert!(private.authenticated);
+        assert_eq!(
+            private.rate_limit,
+            Some(RateLimit {
+                per_second: 5,
+                burst: 10
+            })
+        );
+        let files = policies.for_path("/files/public/a.txt").unwrap();
+        assert!(!files.authenticated);
+        assert!(files.allows_method("HEAD"));
+        assert!(!files.allows_method("PUT"));
+        assert_eq!(files.allow(), "GET, HEAD");
+        assert!(policies.for_path("/echo/abc").is_none());
+    }
+
+    #[test]
+    fn test_policy_from_str_with_invalid_rules() {
+        assert!(matches!(
+            "files/ auth".parse::<AccessPolicy>(),
+            Err(AccessPolicyError::InvalidLine(_))
+        ));
+        for rule in ["admin", "methods=GET,", "rate=0", "rate=5/x", "clients="] {
+            assert!(
+                format!("/files/ {rule}").parse::<AccessPolicy>().is_err(),
+                "{rule}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ip_network_contains() {
+        let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        assert!(!network.contains("::1".parse().unwrap()));
+
+        let any: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("192.168.1.1".parse().unwrap()));
+        let loopback: IpNetwork = "::1".parse().unwrap();
+        assert_eq!(loopback.to_string(), "::1/128");
+        assert!(loopback.contains("::1".parse().unwrap()));
+        assert!(!loopback.contains("::2".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("localhost".parse::<IpNetwork>().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use tokio::sync::Notify;
+
+/// What the admin API changes while the server runs, shared by reloaded
+/// contexts so that a change outlives `SIGHUP`.
+#[derive(Debug, Default)]
+pub struct Admin {
+    connections: Connections,
+    read_only: Mutex<Option<bool>>,
+    shutdown: Notify,
+}
+impl Admin {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn connections(&self) -> &Connections {
+        &self.connections
+    }
+
+    /// Whether files are only published, set through the admin API, `None`
+    /// when the configuration decides.
+    pub fn read_only(&self) -> Option<bool> {
+        *self
+            .read_only
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Overrides the `read_only` setting of the configuration, or goes back to it when `None`.
+    pub fn set_read_only(&self, read_only: Option<bool>) {
+        *self
+            .read_only
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = read_only;
+    }
+
+    /// Asks the server to stop accepting connections and drain, like `SIGTERM`.
+    pub fn request_shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Completes once [`Admin::request_shutdown`] was called, even before this is awaited.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
+    }
+}
+
+/// The connections open, listed by `GET /admin/connections`.
+#[derive(Debug, Default)]
+pub struct Connections {
+    next_id: AtomicU64,
+    open: Mutex<BTreeMap<u64, Arc<OpenConnection>>>,
+}
+impl Connections {
+    /// Lists a connection from `peer` until the returned guard is dropped.
+    pub fn register(&self, peer: SocketAddr, protocol: &'static str) -> Registration<'_> {
+        let connection = Arc::new(OpenConnection {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            peer,
+            protocol,
+            opened: Instant::now(),
+            requests: AtomicUsize::new(0),
+        });
+        self.open
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(connection.id, Arc::clone(&connection));
+        Registration {
+            connections: self,
+            connection,
+        }
+    }
+
+    /// The connections open, oldest first.
+    pub fn list(&self) -> Vec<Arc<OpenConnection>> {
+        self.open
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Reports the connections open as a JSON array.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, connection) in self.list().iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{{\"id\":{},\"peer\":\"{}\",\"protocol\":\"{}\",\"open_seconds\":{},\"requests\":{}}}",
+                if i == 0 { "" } else { "," },
+                connection.id,
+                connection.peer,
+                connection.protocol,
+                connection.opened.elapsed().as_secs(),
+                connection.requests(),
+            );
+        }
+        json.push(']');
+        json
+    }
+}
+
+#[derive(Debug)]
+pub struct OpenConnection {
+    pub id: u64,
+    pub peer: SocketAddr,
+    /// `HTTP/1.1` for TCP connections, whatever version their requests use, or `HTTP/3`.
+    pub protocol: &'static str,
+    pub opened: Instant,
+    requests: AtomicUsize,
+}
+impl OpenConnection {
+    /// Requests received on the connection so far.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps a connection listed while it lives.
+#[derive(Debug)]
+pub struct Registration<'a> {
+    connections: &'a Connections,
+    connection: Arc<OpenConnection>,
+}
+impl Registration<'_> {
+    /// Counts a request received on the connection.
+    ///
+    /// # Returns
+    ///
+    /// Returns the requests received so far, this one included.
+    #[allow(clippy::must_use_candidate)] // Callers counting requests may not need the count
+    pub fn count_request(&self) -> usize {
+        self.connection.requests.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.connections
+            .open
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(&self.connection.id);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_listed_while_registered() {
+        let connections = Connections::default();
+        let first = connections.register("127.0.0.1:5000".parse().unwrap(), "HTTP/1.1");
+        first.count_request();
+        let second = connections.register("[::1]:5001".parse().unwrap(), "HTTP/3");
+        assert_eq!(
+            connections.to_json(),
+            "[{\"id\":1,\"peer\":\"127.0.0.1:5000\",\"protocol\":\"HTTP/1.1\",\"open_seconds\":0,\"requests\":1},\
+             {\"id\":2,\"peer\":\"[::1]:5001\",\"protocol\":\"HTTP/3\",\"open_seconds\":0,\"requests\":0}]"
+        );
+        drop(first);
+        assert_eq!(connections.list().len(), 1);
+        drop(second);
+        assert_eq!(connections.to_json(), "[]");
+    }
+
+    #[test]
+    fn test_read_only_override() {
+        let admin = Admin::new();
+        assert_eq!(admin.read_only(), None);
+        admin.set_read_only(Some(true));
+        assert_eq!(admin.read_only(), Some(true));
+        admin.set_read_only(None);
+        assert_eq!(admin.read_only(), None);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_requested_before_awaited() {
+        let admin = Admin::new();
+        admin.request_shutdown();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            admin.shutdown_requested(),
+        )
+        .await
+        .unwrap();
+    }
+}
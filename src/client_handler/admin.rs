@@ -0,0 +1,235 @@
+use tracing::{info, warn};
+
+use super::{proxy, ClientHandler};
+use crate::{
+    auth::Authorization,
+    http_request::{percent_decode, HTTPRequest, RequestHeader, RequestMethod},
+    http_response::{ContentType, HTTPResponse, ResponseStatus},
+    server_context::ServerContext,
+};
+
+/// The endpoints of the admin API and the methods they answer.
+const ADMIN_ENDPOINTS: [(&str, &str); 6] = [
+    ("/admin/log-level", "GET, HEAD, PUT"),
+    ("/admin/connections", "GET, HEAD"),
+    ("/admin/read-only", "GET, HEAD, PUT, DELETE"),
+    ("/admin/caches/rate-limiter", "DELETE"),
+    ("/admin/caches/response-cache", "DELETE"),
+    ("/admin/caches/proxy-cache", "DELETE"),
+];
+
+impl ClientHandler {
+    /// Whether `path` is an endpoint of the admin API.
+    pub(super) fn is_admin(path: &str) -> bool {
+        path.starts_with("/admin/caches/")
+            || ADMIN_ENDPOINTS
+                .iter()
+                .any(|(endpoint, _)| *endpoint == path)
+    }
+
+    /// Answers the admin API, which changes the running server without a
+    /// restart. Changes last until the server stops, configuration reloads
+    /// included, except the log level which a reload doesn't touch either.
+    ///
+    /// - `GET /admin/log-level` gives the log filter, `PUT` replaces it with the directives of the body.
+    /// - `GET /admin/connections` lists the connections open in JSON.
+    /// - `DELETE /admin/caches/rate-limiter` forgets the rate limits of every
+    ///   client, or of the one given as `?client=`.
+    /// - `DELETE /admin/caches/response-cache` forgets every cached response,
+    ///   or those of the path given as `?path=`.
+    /// - `DELETE /admin/caches/proxy-cache` forgets every proxied response kept
+    ///   on disk, or the one of the path and query given as `?path=`.
+    /// - `GET /admin/read-only` tells whether files are read-only, `PUT` sets
+    ///   it to the `true` or `false` of the body, `DELETE` goes back to the configuration.
+    ///
+    /// # Returns
+    ///
+    /// Returns `401 Unauthorized` unless one of the authentication checks identified the client.
+    pub(super) async fn admin(request: &HTTPRequest, context: &ServerContext) -> HTTPResponse {
+        if let Some(response) = Self::without_identity(request) {
+            return response;
+        }
+        let request_line = request.line();
+        let uri = request_line.uri();
+        let body = request.body().map(ToString::to_string).unwrap_or_default();
+        let admin = context.admin();
+        match (request_line.method(), uri.path()) {
+            (
+                method @ (RequestMethod::Get | RequestMethod::Head | RequestMethod::Put),
+                "/admin/log-level",
+            ) => Self::log_level(method, body.trim(), context),
+            (RequestMethod::Get | RequestMethod::Head, "/admin/connections") => {
+                HTTPResponse::new_builder(ResponseStatus::Http200)
+                    .with_body(
+                        &admin.connections().to_json(),
+                        ContentType::ApplicationJson,
+                        request.header().accept_encoding(),
+                    )
+                    .with_header("Cache-Control", "no-store")
+                    .build()
+            }
+            (RequestMethod::Delete, "/admin/caches/rate-limiter") => {
+                let client = uri
+                    .query_param("client")
+                    .map(|client| percent_decode(client).unwrap_or_else(|_| client.to_string()));
+                let evicted = context.rate_limiter().evict(client.as_deref());
+                info!(client, evicted, "Rate limiter entries evicted");
+                HTTPResponse::new_builder(ResponseStatus::Http200)
+                    .with_body(
+                        &format!("{{\"evicted\":{evicted}}}"),
+                        ContentType::ApplicationJson,
+                        &[],
+                    )
+                    .build()
+            }
+            (RequestMethod::Delete, "/admin/caches/response-cache") => {
+                let path = uri
+                    .query_param("path")
+                    .map(|path| percent_decode(path).unwrap_or_else(|_| path.to_string()));
+                let evicted = context
+                    .response_cache()
+                    .map_or(0, |cache| cache.evict(path.as_deref()));
+                info!(path, evicted, "Cached responses evicted");
+                HTTPResponse::new_builder(ResponseStatus::Http200)
+                    .with_body(
+                        &format!("{{\"evicted\":{evicted}}}"),
+                        ContentType::ApplicationJson,
+                        &[],
+                    )
+                    .build()
+            }
+            (RequestMethod::Delete, "/admin/caches/proxy-cache") => {
+                let path = uri
+                    .query_param("path")
+                    .map(|path| percent_decode(path).unwrap_or_else(|_| path.to_string()));
+                Self::purge_proxy_cache(path.as_deref(), context).await
+            }
+            (RequestMethod::Get | RequestMethod::Head, "/admin/read-only") => {
+                text(ResponseStatus::Http200, &context.read_only().to_string())
+            }
+            (RequestMethod::Put, "/admin/read-only") => {
+                let Ok(read_only) = body.trim().parse() else {
+                    return text(ResponseStatus::Http400, "Expected 'true' or 'false'");
+                };
+                info!(read_only, "Read-only mode set");
+                admin.set_read_only(Some(read_only));
+                HTTPResponse::new_builder(ResponseStatus::Http204).build()
+            }
+            (RequestMethod::Delete, "/admin/read-only") => {
+                info!(
+                    read_only = context.config().read_only,
+                    "Read-only mode back to the configuration"
+                );
+                admin.set_read_only(None);
+                HTTPResponse::new_builder(ResponseStatus::Http204).build()
+            }
+            (_, path) => match ADMIN_ENDPOINTS
+                .iter()
+                .find(|(endpoint, _)| *endpoint == path)
+            {
+                Some((_, allow)) => HTTPResponse::new_builder(ResponseStatus::Http405)
+                    .with_header("Allow", allow)
+                    .build(),
+                None => text(ResponseStatus::Http404, "No such cache"),
+            },
+        }
+    }
+
+    /// Stops the server on `POST /admin/shutdown`, draining connections like
+    /// on `SIGTERM`, for orchestration tools that can't send signals.
+    ///
+    /// # Returns
+    ///
+    /// Returns `401 Unauthorized` unless the request carries one of the
+    /// shutdown tokens as a bearer token, whatever other credentials it has.
+    pub(super) fn shutdown(
+        method: &RequestMethod,
+        request_header: &RequestHeader,
+        context: &ServerContext,
+    ) -> HTTPResponse {
+        if *method != RequestMethod::Post {
+            return HTTPResponse::new_builder(ResponseStatus::Http405)
+                .with_header("Allow", "POST")
+                .build();
+        }
+        let authorized = matches!(
+            (request_header.authorization(), &context.config().shutdown_tokens),
+            (Some(Authorization::Bearer(token)), Some(tokens)) if tokens.verify(&token)
+        );
+        if !authorized {
+            return HTTPResponse::new_builder(ResponseStatus::Http401)
+                .with_header("WWW-Authenticate", "Bearer")
+                .with_body("Shutdown token required", ContentType::TextPlain, &[])
+                .build();
+        }
+        warn!("Shutdown requested through the admin API");
+        context.admin().request_shutdown();
+        text(ResponseStatus::Http202, "Shutting down")
+    }
+
+    /// Forgets the proxied responses cached on disk: the one of `path`, a
+    /// path and query like `/api/items?page=2`, or all of them.
+    async fn purge_proxy_cache(path: Option<&str>, context: &ServerContext) -> HTTPResponse {
+        let config = context.config();
+        let purged = match (proxy::proxy_cache(config), path) {
+            (None, _) => Ok(0),
+            (Some(cache), Some(path)) => {
+                let proxy = config.proxy_for(path.split('?').next().unwrap_or(path));
+                match proxy {
+                    Some(proxy) => cache
+                        .purge(&proxy::cache_key(proxy, path))
+                        .await
+                        .map(usize::from),
+                    None => Ok(0),
+                }
+            }
+            (Some(cache), None) => cache.purge_all().await,
+        };
+        match purged {
+            Ok(evicted) => {
+                info!(path, evicted, "Proxied responses evicted");
+                HTTPResponse::new_builder(ResponseStatus::Http200)
+                    .with_body(
+                        &format!("{{\"evicted\":{evicted}}}"),
+                        ContentType::ApplicationJson,
+                        &[],
+                    )
+                    .build()
+            }
+            Err(error) => {
+                let directory = config.proxy_cache_directory.as_deref();
+                warn!(%error, directory, "Can't purge proxy cache");
+                text(ResponseStatus::Http500, "Failed to purge the proxy cache")
+            }
+        }
+    }
+
+    /// Gives the log filter, or replaces it with `directives` on `PUT`.
+    fn log_level(
+        method: &RequestMethod,
+        directives: &str,
+        context: &ServerContext,
+    ) -> HTTPResponse {
+        let Some(log_filter) = context.log_filter() else {
+            return text(
+                ResponseStatus::Http503,
+                "Logging isn't managed by the server",
+            );
+        };
+        if *method != RequestMethod::Put {
+            return text(ResponseStatus::Http200, &log_filter.directives());
+        }
+        if let Err(e) = log_filter.set(directives) {
+            warn!(error = %e, "Can't change the log filter");
+            return text(ResponseStatus::Http400, &e.to_string());
+        }
+        info!(directives, "Log filter changed");
+        HTTPResponse::new_builder(ResponseStatus::Http204).build()
+    }
+}
+
+fn text(status: ResponseStatus, body: &str) -> HTTPResponse {
+    HTTPResponse::new_builder(status)
+        .with_body(body, ContentType::TextPlain, &[])
+        .build()
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use super::ClientHandler;
+use crate::{
+    config::Config,
+    http_request::{Encoding, HTTPRequest, RequestMethod},
+    http_response::{HTTPResponse, ResponseStatus},
+    response_cache::{CacheKey, Hit, Lifetime, Staleness},
+    server_context::ServerContext,
+    typed_headers::CacheControl,
+};
+
+/// Headers making a read conditional, evaluated against the file itself
+/// rather than a response cached before it changed.
+const CONDITIONAL_HEADERS: [&str; 4] = [
+    "If-Match",
+    "If-None-Match",
+    "If-Modified-Since",
+    "If-Unmodified-Since",
+];
+
+impl ClientHandler {
+    /// Answers `GET` and `HEAD` for `/`, `/echo/`, `/files/` and mounts from the
+    /// response cache while it holds a fresh response, building and caching
+    /// the response otherwise. Cached responses carry their `Age`.
+    ///
+    /// Past its freshness, a response within its `stale-while-revalidate`
+    /// period is still sent while a new one is built in the background, and
+    /// one within its `stale-if-error` period is sent instead of a new one
+    /// failing with a server error.
+    ///
+    /// A request sent with `Cache-Control: no-cache` gets a response built
+    /// anew, one with `no-store` a response that isn't kept, and conditional
+    /// requests are checked against the file. `/user-agent` isn't cached, its
+    /// response depending on a header.
+    pub(super) async fn cached_get(request: &HTTPRequest, context: &ServerContext) -> HTTPResponse {
+        let (request_line, request_header) = (request.line(), request.header());
+        let config = context.config();
+        let cache = context
+            .response_cache()
+            .filter(|_| !request_line.uri().path().starts_with("/user-agent"));
+        let Some(cache) = cache else {
+            return Self::get(request, context.root_for(request_header.host()), config).await;
+        };
+        // The coding `with_bytes` picks for the body
+        let encoding = match request_header.accept_encoding().first() {
+            Some(Encoding::Gzip) => Encoding::Gzip,
+            _ => Encoding::Identity,
+        };
+        let mut key = CacheKey::new(request_line.uri().to_string(), encoding);
+        if let Some(host) = request_header
+            .host()
+            .filter(|_| !config.host_directories.is_empty())
+        {
+            key = key.for_host(host.name());
+        }
+        let cache_control = request_header.cache_control();
+        let conditional = CONDITIONAL_HEADERS
+            .iter()
+            .any(|name| request_header.get(name).is_some());
+        let mut stale = None;
+        if !cache_control.no_cache && !conditional {
+            let cached = cache.get(&key);
+            context.stats().count_response_cache_lookup(
+                cached
+                    .as_ref()
+                    .is_some_and(|hit| hit.staleness != Staleness::IfError),
+            );
+            if let Some(hit) = cached {
+                match hit.staleness {
+                    Staleness::Fresh | Staleness::Revalidating { refresh: false } => {
+                        return aged(hit);
+                    }
+                    Staleness::Revalidating { refresh: true } => {
+                        Self::refresh_cached(request, context, key);
+                        return aged(hit);
+                    }
+                    Staleness::IfError => stale = Some(hit),
+                }
+            }
+        }
+        let response = Self::get(request, context.root_for(request_header.host()), config).await;
+        if let Some(hit) = stale.filter(|_| is_error(&response)) {
+            warn!(
+                target = %request_line.uri(),
+                status = response.status().code(),
+                "Serving a stale cached response instead of an error"
+            );
+            return aged(hit);
+        }
+        let lifetime = lifetime(&response, config);
+        if let Some(lifetime) = lifetime.filter(|_| !cache_control.no_store) {
+            cache.insert(key, response.clone(), lifetime);
+        }
+        response
+    }
+
+    /// Builds the response to `request` in the background, caching it under
+    /// `key` in place of the stale one sent meanwhile. If it can't be cached,
+    /// the next request finding the stale one builds it again.
+    fn refresh_cached(request: &HTTPRequest, context: &ServerContext, key: CacheKey) {
+        let (request, context) = (request.without_extensions(), context.clone());
+        tokio::spawn(async move {
+            let config = context.config();
+            let root = context.root_for(request.header().host());
+            let response = Self::get(&request, root, config).await;
+            let Some(cache) = context.response_cache() else {
+                return;
+            };
+            let refreshed = lifetime(&response, config)
+                .is_some_and(|lifetime| cache.insert(key.clone(), response, lifetime));
+            if !refreshed {
+                // Left stale, for the next lookup to try again
+                cache.refresh_failed(&key);
+            }
+        });
+    }
+
+    /// Forgets the cached responses of the file `request` writes or deletes,
+    /// whatever the outcome, for the next read to see the file as it is now.
+    pub(super) fn invalidate_cached(request: &HTTPRequest, context: &ServerContext) {
+        let request_line = request.line();
+        let path = request_line.uri().path();
+        if matches!(
+            request_line.method(),
+            RequestMethod::Get | RequestMethod::Head
+        ) || !(path.starts_with("/files/") || context.config().mount_for(path).is_some())
+        {
+            return;
+        }
+        if let Some(cache) = context.response_cache() {
+            let evicted = cache.evict(Some(path));
+            debug!(path, evicted, "Cached responses invalidated");
+        }
+    }
+}
+
+/// A cached response with its `Age`.
+fn aged(hit: Hit) -> HTTPResponse {
+    hit.response
+        .with_header("Age", &hit.age.as_secs().to_string())
+}
+
+/// Whether `response` is an error a stale response may be sent instead of,
+/// per RFC 5861 section 4.
+const fn is_error(response: &HTTPResponse) -> bool {
+    matches!(response.status().code(), 500 | 502 | 503 | 504)
+}
+
+/// How long `response` may be cached and then sent stale: as long as its
+/// `Cache-Control` says, or else as `config` says.
+///
+/// # Returns
+///
+/// Returns `None` if the response can't be cached: it isn't a `200 OK`, its
+/// `Cache-Control` forbids it, it sets a cookie, it varies on more than
+/// the coding the cache keys it by, or it would never be sent.
+fn lifetime(response: &HTTPResponse, config: &Config) -> Option<Lifetime> {
+    let cache_control = response
+        .header("Cache-Control")
+        .and_then(|value| value.parse::<CacheControl>().ok())
+        .unwrap_or_default();
+    let storable = response.status() == ResponseStatus::Http200
+        && !(cache_control.no_store || cache_control.no_cache || cache_control.private)
+        && response.header("Set-Cookie").is_none()
+        && response
+            .header("Vary")
+            .is_none_or(|vary| vary.trim().eq_ignore_ascii_case("Accept-Encoding"));
+    let or = |directive: Option<u64>, default| directive.map_or(default, Duration::from_secs);
+    let lifetime = Lifetime {
+        fresh: or(cache_control.shared_max_age(), config.response_cache_ttl),
+        stale_while_revalidate: or(
+            cache_control.stale_while_revalidate,
+            config.response_cache_stale_while_revalidate,
+        ),
+        stale_if_error: or(
+            cache_control.stale_if_error,
+            config.response_cache_stale_if_error,
+        ),
+    };
+    storable
+        .then_some(lifetime)
+        .filter(|lifetime| *lifetime != Lifetime::default())
+}
@@ -0,0 +1,1571 @@
+use std::{
+    future::Future, io::IoSlice, net::SocketAddr, panic::AssertUnwindSafe, sync::Arc, task::Poll,
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream},
+    net::TcpStream,
+    sync::watch,
+    time::{sleep, timeout_at, Instant},
+};
+use tracing::{field, info_span, trace, warn, Instrument, Span};
+
+use super::{response_writer::ResponseWriter, ClientHandler, ClientHandlerError};
+use crate::{
+    access_log::AccessLogEntry,
+    config::Config,
+    extensions::ConnectionState,
+    hooks::{ResponseEvent, Timing},
+    http_request::{self, RequestHeaderError},
+    http_response::{ContentType, HTTPResponse, InformationalResponse, ResponseStatus},
+    request_id::{self, REQUEST_ID_HEADER},
+    routes::Routes,
+    server_context::ServerContext,
+    throttle::Throttle,
+    trace_context,
+    upgrade::{OnUpgrade, Upgraded},
+};
+
+/// Largest request, head and body included, the server accepts.
+pub(super) const MAX_REQUEST_SIZE: usize = 4096;
+
+/// A stream the server reads requests from and answers on, like a TCP
+/// connection or an in-memory `tokio::io::duplex` pipe in tests.
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Makes dropping the connection discard what the peer hasn't read yet,
+    /// instead of holding on to it. Does nothing by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transport can't be configured.
+    fn discard_unsent(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl Connection for TcpStream {
+    fn discard_unsent(&mut self) -> std::io::Result<()> {
+        // Deprecated for blocking on close, which a zero linger doesn't: it resets
+        #[allow(deprecated)]
+        self.set_linger(Some(Duration::ZERO))
+    }
+}
+#[cfg(unix)]
+impl Connection for tokio::net::UnixStream {}
+impl Connection for DuplexStream {}
+
+impl ClientHandler {
+    /// Serves every request sent on a connection until the client closes it.
+    ///
+    /// HTTP/1.1 connections are kept open between requests, until they stay idle
+    /// for the keep-alive timeout or have served the maximum number of requests
+    /// per connection, the last one being answered with `Connection: close`.
+    /// Once `draining` turns `true`, an idle connection is closed right away and a
+    /// busy one answers its current request with `Connection: close` before being
+    /// closed.
+    ///
+    /// Each request must be read, handled and answered within the configured
+    /// request timeout, counted from its first byte, or the timeout of its route
+    /// once it is read. A client too slow to send
+    /// its request gets `408 Request Timeout`, a handler too slow to answer gets
+    /// `504 Gateway Timeout`, and the connection is closed in both cases.
+    /// A malformed request gets `400 Bad Request` and the connection is closed too.
+    ///
+    /// Responses are written no faster than the configured rates, the one of the
+    /// connection and the one shared by all connections.
+    ///
+    /// Requests slower than the configured threshold are logged with the time
+    /// spent reading, handling and answering them.
+    ///
+    /// Every request gets an ID, the one from its `X-Request-Id` header or a new
+    /// one, sent back in the same header and attached to its logs. Every response
+    /// sent is recorded in the access log of `context`. The requests of the
+    /// connection share the same `ConnectionState`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ClientHandlerError` if the stream cannot be read or the response cannot be sent.
+    pub async fn handle_connection<S: Connection>(
+        stream: &mut S,
+        peer: SocketAddr,
+        context: Arc<ServerContext>,
+        mut draining: watch::Receiver<bool>,
+    ) -> Result<(), ClientHandlerError> {
+        let config = context.config();
+        let error_handler = context.error_handler();
+        let _open = context.stats().open_connection();
+        let registration = context.admin().connections().register(peer, "HTTP/1.1");
+        let connection_throttle = config.max_rate.map(Throttle::new);
+        let pacing = Pacing {
+            write_timeout: config.write_timeout,
+            throttles: connection_throttle
+                .iter()
+                .chain(context.throttle())
+                .collect(),
+        };
+        // One buffer per connection, requests are split off it without copying and
+        // the space they used is reclaimed once they are dropped.
+        let mut buf = BytesMut::with_capacity(MAX_REQUEST_SIZE);
+        let mut writer = ResponseWriter::new();
+        let state = Arc::new(ConnectionState::new(peer));
+        loop {
+            if buf.is_empty()
+                && !Self::next_request_starts(stream, &mut buf, &mut draining, config).await?
+            {
+                break;
+            }
+            let mut exchange = Exchange::start(peer, &context);
+            let span = exchange.span.clone();
+            let deadline = exchange.started + config.request_timeout;
+
+            let reading = Self::read_request(stream, &mut buf, &mut writer, &context);
+            let read = timeout_at(deadline, reading).instrument(span.clone()).await;
+            exchange.read = exchange.started.elapsed();
+            let request_len = match read {
+                Ok(Ok(Some(request_len))) => Ok(request_len),
+                Ok(Ok(None)) => break,
+                // What is left in the buffer can't be framed anymore, so the
+                // connection is closed after telling the client what went wrong.
+                Ok(Err(e)) if !e.is_bad_request() => return Err(e),
+                Ok(Err(e)) => Err(e),
+                Err(_) => {
+                    warn!(parent: &span, "Timed out reading request");
+                    Err(ClientHandlerError::ReadTimedOut)
+                }
+            };
+            let request = match request_len {
+                Ok(request_len) => buf.split_to(request_len).freeze(),
+                Err(e) => {
+                    exchange.identify(&buf);
+                    let response = exchange.stamp(span.in_scope(|| error_handler.respond(&e)));
+                    let Some(response) =
+                        Self::respond_before(stream, &mut writer, response, b"", deadline, &pacing)
+                            .instrument(span)
+                            .await?
+                    else {
+                        return Self::abort(stream);
+                    };
+                    exchange.finish(&buf, &response);
+                    break;
+                }
+            };
+            exchange.identify(&request);
+            let (method, path) = route_of(&request);
+            let deadline = exchange.started + config.request_timeout_for(method, path);
+
+            let _in_flight = context.stats().start_request();
+            let shutting_down = *draining.borrow();
+            let closing =
+                shutting_down || registration.count_request() >= config.keepalive_requests;
+            let processed = timeout_at(
+                deadline,
+                catch_panic(Self::process(
+                    &request,
+                    &exchange.request_id,
+                    &state,
+                    &context,
+                    shutting_down,
+                    closing,
+                )),
+            )
+            .instrument(span.clone())
+            .await;
+            exchange.handle = exchange.started.elapsed().saturating_sub(exchange.read);
+            let response = processed
+                .unwrap_or_else(|_| {
+                    warn!(parent: &span, "Timed out handling request");
+                    Err(ClientHandlerError::HandlingTimedOut)
+                })
+                .unwrap_or_else(|e| span.in_scope(|| error_handler.respond(&e)));
+            let response = exchange.stamp(response);
+            let Some(response) =
+                Self::respond_before(stream, &mut writer, response, &request, deadline, &pacing)
+                    .instrument(span)
+                    .await?
+            else {
+                return Self::abort(stream);
+            };
+            exchange.finish(&request, &response);
+            match response.upgrade() {
+                Some(upgrade) => return Self::upgrade(stream, buf.split().freeze(), upgrade).await,
+                None if response.closes_connection() => break,
+                None => {}
+            }
+        }
+        // Let the client read everything we sent before the socket goes away,
+        // it may already be gone so there is nothing to do on failure.
+        let _ = stream.shutdown().await;
+        Ok(())
+    }
+
+    /// Hands the connection to the `upgrade` handler of a response that switched
+    /// protocols, along with the bytes the client sent after its request, and
+    /// closes it once the handler is done. No request timeout applies anymore.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handler fails or panics.
+    async fn upgrade<S: Connection>(
+        stream: &mut S,
+        buffered: Bytes,
+        upgrade: &OnUpgrade,
+    ) -> Result<(), ClientHandlerError> {
+        let upgraded = Upgraded::new(stream, buffered);
+        catch_panic(async { Ok(upgrade.run(upgraded).await?) }).await?;
+        let _ = stream.shutdown().await;
+        Ok(())
+    }
+
+    /// Waits for the first bytes of the next request on an idle connection.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if the connection should be closed instead: the client
+    /// closed it, the server is draining, or the keep-alive timeout passed.
+    async fn next_request_starts<S: Connection>(
+        stream: &mut S,
+        buf: &mut BytesMut,
+        draining: &mut watch::Receiver<bool>,
+        config: &Config,
+    ) -> Result<bool, ClientHandlerError> {
+        Ok(tokio::select! {
+            biased;
+            read = stream.read_buf(buf) => read? > 0,
+            _ = draining.wait_for(|draining| *draining) => false,
+            () = sleep(config.keepalive_timeout) => false,
+        })
+    }
+
+    /// Drops the connection without waiting for the client to take what is left
+    /// in the socket buffers.
+    fn abort<S: Connection>(stream: &mut S) -> Result<(), ClientHandlerError> {
+        stream.discard_unsent()?;
+        Ok(())
+    }
+
+    /// Parses the incoming request from the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The client connection.
+    /// * `peer` - The address of the client.
+    /// * `context` - What the server serves and how.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the request is successfully parsed, or an error of type `ClientHandlerError` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ClientHandlerError` if the request is too large, the stream cannot be read, the request line is empty, or the request cannot be decoded to UTF-8.
+    pub async fn parse_request<S: Connection>(
+        stream: &mut S,
+        peer: SocketAddr,
+        context: &ServerContext,
+    ) -> Result<HTTPResponse, ClientHandlerError> {
+        let mut buf = BytesMut::with_capacity(MAX_REQUEST_SIZE);
+        let mut writer = ResponseWriter::new();
+        let reading = Self::read_request(stream, &mut buf, &mut writer, context);
+        let Some(request_len) = reading.await? else {
+            return Err(ClientHandlerError::NoRequestLineFound);
+        };
+        let request = buf.split_to(request_len).freeze();
+        let response = Self::process(
+            &request,
+            &request_id::from_request(&request),
+            &Arc::new(ConnectionState::new(peer)),
+            context,
+            false,
+            false,
+        )
+        .await?;
+        Self::respond(
+            stream,
+            &mut writer,
+            response,
+            &String::from_utf8_lossy(&request),
+        )
+        .await
+    }
+
+    /// Turns the client away because the server already serves as many connections as it is allowed to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ClientHandlerError::ClientUnreachable` if the response cannot be sent to the client.
+    pub async fn reject_overloaded<S: Connection>(
+        stream: &mut S,
+    ) -> Result<HTTPResponse, ClientHandlerError> {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http503)
+            .with_body(
+                "Too many connections, try again later",
+                ContentType::TextPlain,
+                &[],
+            )
+            .build()
+            .with_connection_close();
+        let response = Self::respond(stream, &mut ResponseWriter::new(), response, "").await?;
+        let _ = stream.shutdown().await;
+        Ok(response)
+    }
+
+    /// Reads from the stream until `buf` holds a full request head and the body
+    /// announced by its `Content-Length`.
+    ///
+    /// Bytes received past the end of the request stay in `buf` for the next one.
+    /// The route of the request gets its `103 Early Hints` sent once the head is
+    /// read, and a client waiting with `Expect: 100-continue` is told to send its body.
+    ///
+    /// # Returns
+    ///
+    /// Returns the length of the request at the start of `buf`, or `None` if the client closed the connection before sending anything.
+    /// A client closing the connection mid-head gets what was received so far parsed as the request, but not one closing it mid-body.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ClientHandlerError` if the stream cannot be read, the request is too large or has more header lines than the config of `context` allows, its `Content-Length` is invalid, or the client closes the connection before sending the whole body.
+    async fn read_request<S: Connection>(
+        stream: &mut S,
+        buf: &mut BytesMut,
+        writer: &mut ResponseWriter,
+        context: &ServerContext,
+    ) -> Result<Option<usize>, ClientHandlerError> {
+        let config = context.config();
+        loop {
+            if let Some(head_len) = buf
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+                .map(|position| position + 4)
+            {
+                if header_count(&buf[..head_len]) > config.max_header_count {
+                    return Err(ClientHandlerError::TooManyHeaders(config.max_header_count));
+                }
+                let request_len = head_len
+                    .checked_add(Self::content_length(&buf[..head_len])?)
+                    .filter(|request_len| *request_len < MAX_REQUEST_SIZE)
+                    .ok_or(ClientHandlerError::RequestTooLarge)?;
+                if let Some(hints) = Self::early_hints(&buf[..head_len], context.routes()) {
+                    stream.write_all(writer.interim(hints)).await?;
+                    stream.flush().await?;
+                }
+                if buf.len() < request_len && Self::expects_continue(&buf[..head_len]) {
+                    stream
+                        .write_all(writer.interim(&InformationalResponse::CONTINUE))
+                        .await?;
+                    stream.flush().await?;
+                }
+                while buf.len() < request_len {
+                    if stream.read_buf(buf).await? == 0 {
+                        return Err(ClientHandlerError::IncompleteBody(
+                            buf.len() - head_len,
+                            request_len - head_len,
+                        ));
+                    }
+                }
+                return Ok(Some(request_len));
+            }
+            if buf.len() >= MAX_REQUEST_SIZE {
+                return Err(ClientHandlerError::RequestTooLarge);
+            }
+            if stream.read_buf(buf).await? == 0 {
+                return Ok((!buf.is_empty()).then_some(buf.len()));
+            }
+        }
+    }
+
+    /// The length of the body following `head`, by the rules the parsed
+    /// request reads it with, for bytes past it to be taken as the next request.
+    ///
+    /// Bodies are only framed by `Content-Length`, no transfer coding being
+    /// decoded. A request with both headers is refused rather than framed by
+    /// one, an intermediary framing it by the other being open to request
+    /// smuggling (RFC 9112 section 6.1).
+    fn content_length(head: &[u8]) -> Result<usize, ClientHandlerError> {
+        let head = String::from_utf8_lossy(head);
+        let field = |wanted: &str| -> Vec<&str> {
+            head.lines()
+                .filter_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case(wanted).then(|| value.trim())
+                })
+                .collect()
+        };
+        let (values, codings) = (field("Content-Length"), field("Transfer-Encoding"));
+        if !codings.is_empty() {
+            return Err(if values.is_empty() {
+                ClientHandlerError::UnsupportedTransferEncoding(codings.join(", "))
+            } else {
+                ClientHandlerError::ConflictingLengths
+            });
+        }
+        match http_request::content_length(&values) {
+            Ok(length) => {
+                Ok(length.map_or(0, |length| usize::try_from(length).unwrap_or(usize::MAX)))
+            }
+            Err(RequestHeaderError::InvalidContentLength(value)) => {
+                Err(ClientHandlerError::InvalidContentLength(value))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The `103 Early Hints` of the route the request with `head` is for, sent
+    /// as soon as it is read so the client loads what they link to while the
+    /// request is handled. HTTP/1.0 clients don't get them.
+    fn early_hints<'a>(head: &[u8], routes: &'a Routes) -> Option<&'a InformationalResponse> {
+        if speaks_http_1_0(head) {
+            return None;
+        }
+        let (method, path) = route_of(head);
+        routes.early_hints(&method.parse().ok()?, path)
+    }
+
+    /// Whether the client waits for `100 Continue` before sending the body
+    /// announced in `head`, HTTP/1.0 clients can't ask for it.
+    fn expects_continue(head: &[u8]) -> bool {
+        !speaks_http_1_0(head)
+            && String::from_utf8_lossy(head).lines().any(|line| {
+                line.split_once(':').is_some_and(|(name, value)| {
+                    name.eq_ignore_ascii_case("Expect")
+                        && value.trim().eq_ignore_ascii_case("100-continue")
+                })
+            })
+    }
+
+    /// Sends the response to the client at the pace of `pacing` unless `deadline`
+    /// passes first or the client stops reading for too long.
+    ///
+    /// # Returns
+    ///
+    /// Returns the response sent, or `None` if the client didn't take it in time and the connection must be aborted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ClientHandlerError::ClientUnreachable` if the response cannot be sent to the client.
+    async fn respond_before<S: Connection>(
+        stream: &mut S,
+        writer: &mut ResponseWriter,
+        response: HTTPResponse,
+        request: &[u8],
+        deadline: Instant,
+        pacing: &Pacing<'_>,
+    ) -> Result<Option<HTTPResponse>, ClientHandlerError> {
+        // Only serialized whole when traced, the body is sent from where it is otherwise
+        trace!(response = %String::from_utf8_lossy(&response.as_http_bytes()), "Responding");
+        let informational = writer.informational(&response, speaks_http_1_0(request));
+        // Flushed on their own, the client may act on them before the rest arrives
+        let mut written = if informational.is_empty() {
+            Ok(true)
+        } else {
+            Self::write_draining(stream, &[informational], deadline, pacing).await
+        };
+        if matches!(written, Ok(true)) {
+            let parts = writer.parts(&response);
+            written = Self::write_draining(stream, &parts, deadline, pacing).await;
+        }
+        match written {
+            Ok(true) => Ok(Some(response)),
+            Ok(false) => {
+                warn!("Client doesn't take the response in time, aborting connection");
+                Ok(None)
+            }
+            Err(e) => Err(ClientHandlerError::ClientUnreachable(
+                e,
+                String::from_utf8_lossy(request).to_string(),
+            )),
+        }
+    }
+
+    /// Writes `parts` one after the other as fast as the client drains them and
+    /// the throttles of `pacing` allow, with vectored writes so they don't have
+    /// to be copied together first.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if `deadline` passed or no byte could be written for the write timeout of `pacing`.
+    async fn write_draining<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        parts: &[&[u8]],
+        deadline: Instant,
+        pacing: &Pacing<'_>,
+    ) -> std::io::Result<bool> {
+        let (write_timeout, throttles) = (pacing.write_timeout, pacing.throttles.as_slice());
+        let mut slices = io_slices(parts);
+        let mut slices = slices.as_mut_slice();
+        while !slices.is_empty() {
+            let wanted = slices.iter().map(|slice| slice.len()).sum();
+            let Ok(allowed) = timeout_at(deadline, take_all(throttles, wanted)).await else {
+                return Ok(false);
+            };
+            let limited;
+            let writing: &[IoSlice] = if allowed < wanted {
+                limited = limit_slices(slices, allowed);
+                &limited
+            } else {
+                slices
+            };
+            let stalled_at = deadline.min(Instant::now() + write_timeout);
+            match timeout_at(stalled_at, writer.write_vectored(writing)).await {
+                Ok(Ok(0)) => return Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(Ok(n)) => {
+                    for throttle in throttles {
+                        throttle.give_back(allowed - n);
+                    }
+                    IoSlice::advance_slices(&mut slices, n);
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => return Ok(false),
+            }
+        }
+        timeout_at(deadline.min(Instant::now() + write_timeout), writer.flush())
+            .await
+            .map_or(Ok(false), |flushed| flushed.map(|()| true))
+    }
+
+    /// Sends the response to the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The client connection.
+    /// * `response` - The response string to send to the client.
+    /// * `request` - The original request string.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the response is successfully sent, or an error of type `ClientHandlerError` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ClientHandlerError::ClientUnreachable` if the response cannot be sent to the client.
+    async fn respond<S: Connection>(
+        stream: &mut S,
+        writer: &mut ResponseWriter,
+        response: HTTPResponse,
+        request: &str,
+    ) -> Result<HTTPResponse, ClientHandlerError> {
+        let unreachable = |e| ClientHandlerError::ClientUnreachable(e, request.to_string());
+        let informational = writer.informational(&response, speaks_http_1_0(request.as_bytes()));
+        if !informational.is_empty() {
+            stream.write_all(informational).await.map_err(unreachable)?;
+            stream.flush().await.map_err(unreachable)?;
+        }
+        let parts = writer.parts(&response);
+        let mut slices = io_slices(&parts);
+        let mut slices = slices.as_mut_slice();
+        while !slices.is_empty() {
+            let written = stream.write_vectored(slices).await.map_err(unreachable)?;
+            if written == 0 {
+                return Err(unreachable(std::io::ErrorKind::WriteZero.into()));
+            }
+            IoSlice::advance_slices(&mut slices, written);
+        }
+        stream.flush().await.map_err(unreachable)?;
+
+        Ok(response)
+    }
+}
+
+/// The non-empty `parts` to write, writing an empty slice would look like a closed connection.
+fn io_slices<'a>(parts: &[&'a [u8]]) -> Vec<IoSlice<'a>> {
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| IoSlice::new(part))
+        .collect()
+}
+
+/// Whether the request line starting `request` is an HTTP/1.0 one.
+fn speaks_http_1_0(request: &[u8]) -> bool {
+    request
+        .split(|byte| *byte == b'\r')
+        .next()
+        .is_some_and(|line| line.ends_with(b" HTTP/1.0"))
+}
+
+/// Number of header lines in the request `head`, which ends with the empty
+/// line, leaving out the request line.
+fn header_count(head: &[u8]) -> usize {
+    String::from_utf8_lossy(head)
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .count()
+}
+
+/// The method and path, query excluded, of the request line starting `request`,
+/// empty when they can't be read.
+pub(super) fn route_of(request: &[u8]) -> (&str, &str) {
+    let line = request
+        .split(|byte| *byte == b'\r')
+        .next()
+        .and_then(|line| std::str::from_utf8(line).ok())
+        .unwrap_or_default();
+    let mut parts = line.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    (method, target.split('?').next().unwrap_or_default())
+}
+
+/// How long the client may take to read a response, and how fast it may be sent.
+struct Pacing<'a> {
+    /// Time the client may go without reading any of the response.
+    write_timeout: Duration,
+    /// Caps on the bytes written per second.
+    throttles: Vec<&'a Throttle>,
+}
+
+/// The first `bytes` of `slices`.
+fn limit_slices<'a>(slices: &'a [IoSlice<'_>], bytes: usize) -> Vec<IoSlice<'a>> {
+    let mut remaining = bytes;
+    let mut limited = Vec::with_capacity(slices.len());
+    for slice in slices {
+        if remaining == 0 {
+            break;
+        }
+        let taken = slice.len().min(remaining);
+        limited.push(IoSlice::new(&slice[..taken]));
+        remaining -= taken;
+    }
+    limited
+}
+
+/// Waits until every one of `throttles` lets some of `wanted` bytes through.
+///
+/// # Returns
+///
+/// Returns how many bytes may be written, taken from each throttle.
+async fn take_all(throttles: &[&Throttle], wanted: usize) -> usize {
+    let mut allowed = wanted;
+    for (i, throttle) in throttles.iter().enumerate() {
+        let granted = throttle.take(allowed).await;
+        for earlier in &throttles[..i] {
+            earlier.give_back(allowed - granted);
+        }
+        allowed = granted;
+    }
+    allowed
+}
+
+/// What is known about the request being served, for its logs.
+pub(super) struct Exchange<'a> {
+    pub(super) span: Span,
+    pub(super) started: Instant,
+    /// Time spent reading the request.
+    pub(super) read: Duration,
+    /// Time spent building the response.
+    pub(super) handle: Duration,
+    pub(super) request_id: String,
+    peer: SocketAddr,
+    context: &'a ServerContext,
+}
+impl<'a> Exchange<'a> {
+    /// Starts timing a request whose first bytes just arrived.
+    pub(super) fn start(peer: SocketAddr, context: &'a ServerContext) -> Self {
+        Self {
+            span: info_span!(
+                "request",
+                method = field::Empty,
+                path = field::Empty,
+                version = field::Empty,
+                status = field::Empty,
+                duration = field::Empty,
+                request_id = field::Empty,
+                trace_id = field::Empty,
+                parent_span_id = field::Empty,
+                tracestate = field::Empty,
+            ),
+            started: Instant::now(),
+            read: Duration::ZERO,
+            handle: Duration::ZERO,
+            request_id: String::new(),
+            peer,
+            context,
+        }
+    }
+
+    /// Picks the ID of `request` and attaches it to the span, along with the
+    /// trace the client says the request is part of.
+    pub(super) fn identify(&mut self, request: &[u8]) {
+        self.request_id = request_id::from_request(request);
+        self.span.record("request_id", self.request_id.as_str());
+        if let Some(trace) = trace_context::from_request(request) {
+            self.span
+                .record("trace_id", trace.parent.trace_id())
+                .record("parent_span_id", trace.parent.parent_id());
+            if let Some(state) = &trace.state {
+                self.span.record("tracestate", state.as_str());
+            }
+        }
+    }
+
+    /// Adds the `Date` and the request ID to the response, and where HTTP/3 is
+    /// served when it is.
+    pub(super) fn stamp(&self, response: HTTPResponse) -> HTTPResponse {
+        let response = response
+            .with_header("Date", &self.context.date().current())
+            .with_header(REQUEST_ID_HEADER, &self.request_id);
+        #[cfg(feature = "http3")]
+        if let Some(port) = self.context.config().http3_port {
+            return response.with_header("Alt-Svc", &format!("h3=\":{port}\"; ma=86400"));
+        }
+        response
+    }
+
+    /// Records the response sent to `request` in the span, the stats and the
+    /// access log, and warns about it if it was slow.
+    pub(super) fn finish(self, request: &[u8], response: &HTTPResponse) {
+        let latency = self.started.elapsed();
+        self.span.record("status", response.status().code());
+        self.span.record("duration", field::debug(latency));
+        let config = self.context.config();
+        if config
+            .slow_request_threshold
+            .is_some_and(|threshold| latency > threshold)
+        {
+            let (read, handle) = (self.read, self.handle);
+            let write = latency.saturating_sub(read + handle);
+            warn!(parent: &self.span, ?read, ?handle, ?write, "Slow request");
+        }
+        self.context.stats().request_served();
+        let (method, path) = route_of(request);
+        self.context.hooks().response(&ResponseEvent {
+            peer: self.peer,
+            request_id: &self.request_id,
+            method,
+            path,
+            response,
+            timing: Timing {
+                read: self.read,
+                handle: self.handle,
+                total: latency,
+            },
+        });
+        let entry = AccessLogEntry::new(self.peer, request, response, latency, self.request_id);
+        self.context.access_log().record(config.access_log, &entry);
+    }
+}
+
+/// Runs a handler, turning a panic into an error so the connection can still
+/// be answered.
+pub(super) async fn catch_panic<T>(
+    handler: impl Future<Output = Result<T, ClientHandlerError>>,
+) -> Result<T, ClientHandlerError> {
+    let mut handler = Box::pin(handler);
+    std::future::poll_fn(|cx| {
+        match std::panic::catch_unwind(AssertUnwindSafe(|| handler.as_mut().poll(cx))) {
+            Ok(poll) => poll,
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(ToString::to_string)
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                Poll::Ready(Err(ClientHandlerError::HandlerPanicked(message)))
+            }
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_log::AccessLog,
+        client_handler::ErrorHandler,
+        http_date::HttpDate,
+        http_request::RequestMethod,
+        request_id::RequestId,
+        routes::Routes,
+        test_util::{without_dates, without_request_ids},
+        upgrade,
+    };
+    use tokio::net::TcpListener;
+
+    const PEER: SocketAddr =
+        SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 40_000);
+
+    #[tokio::test]
+    async fn test_parse_request_in_memory() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client
+            .write_all(b"GET /echo/duplex HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let context = ServerContext::new(
+            Arc::new(Config::default()),
+            AccessLog::spawn_to(tokio::io::sink()),
+        );
+        let response = ClientHandler::parse_request(&mut server, PEER, &context)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), ResponseStatus::Http200);
+        drop(server);
+        let mut sent = String::new();
+        client.read_to_string(&mut sent).await.unwrap();
+        assert!(sent.ends_with("\r\n\r\nduplex"));
+    }
+
+    async fn exchange_on_connection(requests: &[u8], draining: bool) -> String {
+        exchange_with_config(requests, Config::default(), draining).await
+    }
+
+    async fn exchange_with_config(requests: &[u8], config: Config, draining: bool) -> String {
+        let access_log = AccessLog::spawn_to(tokio::io::sink());
+        without_dates(&without_request_ids(
+            &exchange_logged(requests, config, draining, access_log).await,
+        ))
+    }
+
+    async fn exchange_logged(
+        requests: &[u8],
+        config: Config,
+        draining: bool,
+        access_log: AccessLog,
+    ) -> String {
+        // Large enough for the server to write every response before the client reads them
+        let (mut client, mut server) = tokio::io::duplex(1 << 20);
+        client.write_all(requests).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let (_draining_sender, draining) = watch::channel(draining);
+        ClientHandler::handle_connection(
+            &mut server,
+            PEER,
+            Arc::new(ServerContext::new(Arc::new(config), access_log)),
+            draining,
+        )
+        .await
+        .unwrap();
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        responses
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_sends_continue() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let context = Arc::new(ServerContext::new(
+            Arc::new(Config::default()),
+            AccessLog::spawn_to(tokio::io::sink()),
+        ));
+        let (_draining_sender, draining) = watch::channel(false);
+        let server = tokio::spawn(async move {
+            ClientHandler::handle_connection(&mut server, PEER, context, draining).await
+        });
+        client
+            .write_all(b"POST /echo HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\n")
+            .await
+            .unwrap();
+        let mut interim = [0; 25];
+        client.read_exact(&mut interim).await.unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        client.write_all(b"body").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nbody"));
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_informational_responses() {
+        let mut routes = Routes::default();
+        routes.add(RequestMethod::Get, "/hints", |_| {
+            HTTPResponse::new_builder(ResponseStatus::Http204)
+                .build()
+                .with_informational(InformationalResponse::early_hints(&[
+                    "</a.css>; rel=preload",
+                ]))
+        });
+        let exchange = |request: &'static [u8]| {
+            let routes = routes.clone();
+            async move {
+                let (mut client, mut server) = tokio::io::duplex(4096);
+                client.write_all(request).await.unwrap();
+                client.shutdown().await.unwrap();
+                let context = ServerContext::new(
+                    Arc::new(Config::default()),
+                    AccessLog::spawn_to(tokio::io::sink()),
+                )
+                .with_routes(routes);
+                let (_draining_sender, draining) = watch::channel(false);
+                ClientHandler::handle_connection(&mut server, PEER, Arc::new(context), draining)
+                    .await
+                    .unwrap();
+                let mut responses = String::new();
+                client.read_to_string(&mut responses).await.unwrap();
+                responses
+            }
+        };
+
+        let responses = exchange(b"GET /hints HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(responses.starts_with(
+            "HTTP/1.1 103 Early Hints\r\nLink: </a.css>; rel=preload\r\n\r\nHTTP/1.1 204 No Content\r\n"
+        ));
+        let responses = exchange(b"GET /hints HTTP/1.0\r\n\r\n").await;
+        assert!(responses.starts_with("HTTP/1.1 204 No Content\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_sends_early_hints() {
+        let mut routes = Routes::default();
+        routes.add(RequestMethod::Post, "/hints", |_| {
+            HTTPResponse::new_builder(ResponseStatus::Http204).build()
+        });
+        routes.add_early_hints(RequestMethod::Post, "/hints", &["</a.css>; rel=preload"]);
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let context = Arc::new(
+            ServerContext::new(
+                Arc::new(Config::default()),
+                AccessLog::spawn_to(tokio::io::sink()),
+            )
+            .with_routes(routes),
+        );
+        let (_draining_sender, draining) = watch::channel(false);
+        let server = tokio::spawn(async move {
+            ClientHandler::handle_connection(&mut server, PEER, context, draining).await
+        });
+        client
+            .write_all(b"POST /hints HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\n")
+            .await
+            .unwrap();
+        // Sent before the body arrives, so before the handler runs
+        let mut interim = [0; 57];
+        client.read_exact(&mut interim).await.unwrap();
+        assert_eq!(
+            &interim,
+            b"HTTP/1.1 103 Early Hints\r\nLink: </a.css>; rel=preload\r\n\r\n"
+        );
+        client.write_all(b"body").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_shares_extensions() {
+        struct Visits(u32);
+        let mut routes = Routes::default();
+        routes.add(RequestMethod::Get, "/visits", |request| {
+            let mut extensions = request.connection().extensions();
+            let visits = extensions.get::<Visits>().map_or(1, |visits| visits.0 + 1);
+            extensions.insert(Visits(visits));
+            drop(extensions);
+            let request_id = request.extensions().get::<RequestId>().unwrap();
+            HTTPResponse::new_builder(ResponseStatus::Http200)
+                .with_body(
+                    &format!("{visits} {}", request_id.as_str()),
+                    ContentType::TextPlain,
+                    &[],
+                )
+                .build()
+        });
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client
+            .write_all(b"GET /visits HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: first\r\n\r\nGET /visits HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: second\r\n\r\n")
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+        let context = ServerContext::new(
+            Arc::new(Config::default()),
+            AccessLog::spawn_to(tokio::io::sink()),
+        )
+        .with_routes(routes);
+        let (_draining_sender, draining) = watch::channel(false);
+        ClientHandler::handle_connection(&mut server, PEER, Arc::new(context), draining)
+            .await
+            .unwrap();
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        assert!(responses.contains("\r\n\r\n1 first"));
+        assert!(responses.ends_with("\r\n\r\n2 second"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_upgrades() {
+        let mut routes = Routes::default();
+        routes.add(RequestMethod::Get, "/echo-protocol", |request| {
+            if !upgrade::requested(request, "echo") {
+                return HTTPResponse::new_builder(ResponseStatus::Http400).build();
+            }
+            upgrade::switching_protocols("echo", |mut upgraded| {
+                Box::pin(async move {
+                    let mut received = Vec::new();
+                    upgraded.read_to_end(&mut received).await?;
+                    upgraded.write_all(&received.to_ascii_uppercase()).await
+                })
+            })
+        });
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client
+            .write_all(b"GET /echo-protocol HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\nping")
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+        let context = ServerContext::new(
+            Arc::new(Config::default()),
+            AccessLog::spawn_to(tokio::io::sink()),
+        )
+        .with_routes(routes);
+        let (_draining_sender, draining) = watch::channel(false);
+        ClientHandler::handle_connection(&mut server, PEER, Arc::new(context), draining)
+            .await
+            .unwrap();
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        let responses = without_dates(&without_request_ids(&responses));
+        assert_eq!(
+            responses,
+            "HTTP/1.1 101 Switching Protocols\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\nPING"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_sends_date() {
+        let responses = exchange_logged(
+            b"GET /echo/one HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+            Config::default(),
+            false,
+            AccessLog::spawn_to(tokio::io::sink()),
+        )
+        .await;
+        let dates: Vec<&str> = responses
+            .split("\r\n")
+            .filter_map(|line| line.strip_prefix("Date: "))
+            .collect();
+        assert_eq!(dates.len(), 2, "{responses}");
+        for date in dates {
+            assert!(date.parse::<HttpDate>().is_ok(), "{date}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_keep_alive() {
+        let responses = exchange_on_connection(
+            b"GET /echo/one HTTP/1.1\r\nHost: localhost\r\n\r\nGET /echo/two HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            false,
+        )
+        .await;
+        assert_eq!(
+            responses,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\none\
+             HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\ntwo"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_client_closes() {
+        let responses = exchange_on_connection(
+            b"GET /echo/one HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n\
+              GET /echo/two HTTP/1.1\r\nHost: localhost\r\nConnection: Keep-Alive, CLOSE\r\n\r\n\
+              GET /echo/three HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            false,
+        )
+        .await;
+        assert_eq!(
+            responses,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\none\
+             HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\nConnection: close\r\n\r\ntwo"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_splits_pipelined_requests_after_body() {
+        let responses = exchange_on_connection(
+            b"POST /unknown HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhelloGET /echo/two HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            false,
+        )
+        .await;
+        assert_eq!(
+            responses,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\ntwo"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_http_1_0_closes() {
+        let responses = exchange_on_connection(
+            b"GET /echo/one HTTP/1.0\r\n\r\nGET /echo/two HTTP/1.0\r\n\r\n",
+            false,
+        )
+        .await;
+        assert_eq!(
+            responses,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\nConnection: close\r\n\r\none"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_draining_closes_after_current_request() {
+        let responses = exchange_on_connection(
+            b"GET /echo/one HTTP/1.1\r\nHost: localhost\r\n\r\nGET /echo/two HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            true,
+        )
+        .await;
+        assert_eq!(
+            responses,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\nConnection: close\r\n\r\none"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_access_log() {
+        let (writer, mut reader) = tokio::io::duplex(1024);
+        let access_log = AccessLog::spawn_to(writer);
+        exchange_logged(
+            b"GET /echo/abc HTTP/1.1\r\nHost: localhost\r\n\r\nGET /unknown HTTP/1.0\r\n\r\n",
+            Config::default(),
+            false,
+            access_log,
+        )
+        .await;
+
+        let mut log = String::new();
+        reader.read_to_string(&mut log).await.unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("127.0.0.1 - - ["));
+        assert!(lines[0].contains("] \"GET /echo/abc HTTP/1.1\" 200 3 "));
+        assert!(lines[1].contains("] \"GET /unknown HTTP/1.0\" 404 - "));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_request_ids() {
+        let responses = exchange_logged(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: from-client\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            Config::default(),
+            false,
+            AccessLog::spawn_to(tokio::io::sink()),
+        )
+        .await;
+        let request_ids: Vec<&str> = responses
+            .lines()
+            .filter_map(|line| line.strip_prefix("X-Request-Id: "))
+            .collect();
+        assert_eq!(request_ids.len(), 2);
+        assert_eq!(request_ids[0], "from-client");
+        assert_ne!(request_ids[1], "from-client");
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_not_ready() {
+        let config = Config {
+            directory: Some("/nonexistent/http-server-files".to_string()),
+            ..Config::default()
+        };
+        let responses = exchange_with_config(
+            b"GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            config,
+            true,
+        )
+        .await;
+        assert!(responses.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(responses.contains(
+            "not ready: shutting down, directory '/nonexistent/http-server-files' is not readable"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_logs_slow_requests() {
+        #[derive(Clone, Default)]
+        struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let _subscriber = tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish(),
+        );
+
+        let config = Config {
+            slow_request_threshold: Some(Duration::ZERO),
+            ..Config::default()
+        };
+        exchange_with_config(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", config, false).await;
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let slow = logs
+            .lines()
+            .find(|line| line.contains("Slow request"))
+            .unwrap();
+        assert!(slow.contains("WARN"));
+        assert!(slow.contains("read="));
+        assert!(slow.contains("handle="));
+        assert!(slow.contains("write="));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_bad_request_line() {
+        let response = exchange_on_connection(
+            b"GET\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            false,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_invalid_content_length() {
+        let response = exchange_on_connection(
+            b"POST /files/test HTTP/1.1\r\nHost: localhost\r\nContent-Length: ten\r\n\r\n",
+            false,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.ends_with("'ten' is not a valid Content-Length"));
+
+        let response = exchange_on_connection(
+            b"POST /files/test HTTP/1.1\r\nHost: localhost\r\n\
+              Content-Length: 3\r\nContent-Length: 30\r\n\r\nabc",
+            false,
+        )
+        .await;
+        assert!(response.ends_with("'3, 30' is not a valid Content-Length"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_transfer_encoding() {
+        // The chunks mustn't be read as a pipelined request
+        let response = exchange_on_connection(
+            b"POST /echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n0\r\n\r\n",
+            false,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with(
+            "Transfer-Encoding 'chunked' is not supported, send a Content-Length instead"
+        ));
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+
+        let response = exchange_on_connection(
+            b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\n\
+              Transfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            false,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("Request has both a Transfer-Encoding and a Content-Length"));
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_content_length_overflow() {
+        let request = format!(
+            "POST /files/test HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+            u64::MAX
+        );
+        let response = exchange_on_connection(request.as_bytes(), false).await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.ends_with("Request is larger than the maximum buffer size"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_incomplete_body() {
+        let response = exchange_on_connection(
+            b"POST /echo/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nabc",
+            false,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(
+            response.ends_with("Request body ended after 3 of the 10 bytes of its Content-Length")
+        );
+
+        // A client still sending its body times out
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client
+            .write_all(b"POST /echo/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nabc")
+            .await
+            .unwrap();
+        let config = Config {
+            request_timeout: Duration::from_millis(50),
+            ..Config::default()
+        };
+        let (_draining_sender, draining) = watch::channel(false);
+        ClientHandler::handle_connection(
+            &mut server,
+            PEER,
+            Arc::new(ServerContext::new(
+                Arc::new(config),
+                AccessLog::spawn_to(tokio::io::sink()),
+            )),
+            draining,
+        )
+        .await
+        .unwrap();
+        drop(server);
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_too_many_headers() {
+        let config = Config {
+            max_header_count: 2,
+            ..Config::default()
+        };
+        let responses = exchange_with_config(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n\
+              GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\nX-A: 1\r\n\r\n",
+            config,
+            false,
+        )
+        .await;
+        assert!(responses.starts_with("HTTP/1.1 200 OK\r\n"));
+        let rejected = &responses[responses.rfind("HTTP/1.1").unwrap()..];
+        assert!(rejected.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert!(rejected.contains("Connection: close\r\n"));
+        assert!(rejected.ends_with("Request has more than 2 header lines"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_max_requests() {
+        let config = Config {
+            keepalive_requests: 2,
+            ..Config::default()
+        };
+        let responses = exchange_with_config(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            config,
+            false,
+        )
+        .await;
+        assert_eq!(
+            responses,
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_idle_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, peer) = listener.accept().await.unwrap();
+
+        let config = Config {
+            keepalive_timeout: Duration::from_millis(20),
+            ..Config::default()
+        };
+        let (_draining_sender, draining) = watch::channel(false);
+        ClientHandler::handle_connection(
+            &mut server,
+            peer,
+            Arc::new(ServerContext::new(
+                Arc::new(config),
+                AccessLog::spawn_to(tokio::io::sink()),
+            )),
+            draining,
+        )
+        .await
+        .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let result = catch_panic::<HTTPResponse>(async { panic!("handler bug") }).await;
+        assert!(matches!(
+            result,
+            Err(ClientHandlerError::HandlerPanicked(message)) if message == "handler bug"
+        ));
+        let result =
+            catch_panic(async { Ok(HTTPResponse::new_builder(ResponseStatus::Http200).build()) })
+                .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_error_handler() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, peer) = listener.accept().await.unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let error_handler = ErrorHandler::new(|error, response| {
+            HTTPResponse::new_builder(response.status())
+                .with_body(
+                    &format!("{{\"error\":\"{error}\"}}"),
+                    ContentType::ApplicationJson,
+                    &[],
+                )
+                .build()
+        });
+        let (_draining_sender, draining) = watch::channel(false);
+        ClientHandler::handle_connection(
+            &mut server,
+            peer,
+            Arc::new(
+                ServerContext::new(
+                    Arc::new(Config::default()),
+                    AccessLog::spawn_to(tokio::io::sink()),
+                )
+                .with_error_handler(error_handler),
+            ),
+            draining,
+        )
+        .await
+        .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(
+            without_dates(&without_request_ids(&response)),
+            "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\nContent-Length: 53\r\n\
+             Connection: close\r\n\r\n{\"error\":\"'Host: ' is required in HTTP/1.1 requests\"}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_route_timeout() {
+        let config = Config {
+            simulation_routes: true,
+            route_timeouts: vec![("* /delay/".parse().unwrap(), Duration::from_millis(50))],
+            ..Config::default()
+        };
+        let responses = exchange_with_config(
+            b"GET /delay/5 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            config,
+            false,
+        )
+        .await;
+        assert!(responses.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_request_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, peer) = listener.accept().await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n")
+            .await
+            .unwrap();
+
+        let config = Config {
+            request_timeout: Duration::from_millis(50),
+            ..Config::default()
+        };
+        let (_draining_sender, draining) = watch::channel(false);
+        ClientHandler::handle_connection(
+            &mut server,
+            peer,
+            Arc::new(ServerContext::new(
+                Arc::new(config),
+                AccessLog::spawn_to(tokio::io::sink()),
+            )),
+            draining,
+        )
+        .await
+        .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(
+            without_dates(&without_request_ids(&response)),
+            "HTTP/1.1 408 Request Timeout\r\nContent-Type: text/plain\r\nContent-Length: 17\r\nConnection: close\r\n\r\nRequest timed out"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_draining_gives_up_on_stalled_client() {
+        let (mut server, _client) = tokio::io::duplex(8);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let sent = ClientHandler::write_draining(
+            &mut server,
+            &[b"more than ", b"", b"the client buffers"],
+            deadline,
+            &Pacing {
+                write_timeout: Duration::from_millis(20),
+                throttles: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(!sent);
+    }
+
+    #[tokio::test]
+    async fn test_write_draining_throttled() {
+        let (mut server, mut client) = tokio::io::duplex(1024);
+        let throttle = Throttle::new("2000B/s".parse().unwrap());
+        let started = Instant::now();
+        let sent = ClientHandler::write_draining(
+            &mut server,
+            &[&[b'a'; 250], &[b'b'; 250]],
+            started + Duration::from_secs(5),
+            &Pacing {
+                write_timeout: Duration::from_secs(1),
+                throttles: vec![&throttle],
+            },
+        )
+        .await
+        .unwrap();
+        assert!(sent);
+        // 100 bytes go at once, the other 400 at 2000 bytes per second
+        assert!(started.elapsed() >= Duration::from_millis(190));
+        drop(server);
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), 500);
+        assert!(received.starts_with(&[b'a'; 250]) && received.ends_with(&[b'b'; 250]));
+    }
+
+    #[tokio::test]
+    async fn test_write_draining_follows_reading_client() {
+        let (mut server, mut client) = tokio::io::duplex(8);
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            client.read_to_end(&mut received).await.unwrap();
+            received
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let sent = ClientHandler::write_draining(
+            &mut server,
+            &[b"more than ", b"", b"the client buffers"],
+            deadline,
+            &Pacing {
+                write_timeout: Duration::from_millis(20),
+                throttles: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(sent);
+        drop(server);
+        assert_eq!(reader.await.unwrap(), b"more than the client buffers");
+    }
+}
@@ -0,0 +1,380 @@
+//! HTTP/3: each request read off a QUIC stream goes through `process` as the
+//! HTTP/1.1 request it stands for, and its response is sent back as HTTP/3
+//! frames.
+
+use std::{fmt::Write, sync::Arc};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use h3::{
+    error::ConnectionError,
+    server::{RequestResolver, RequestStream},
+};
+use http::{
+    header::{HeaderName, HeaderValue},
+    uri::PathAndQuery,
+    HeaderMap, StatusCode,
+};
+use quinn::{Endpoint, Incoming, VarInt};
+use tokio::{sync::watch, task::JoinSet, time::timeout_at};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use super::{
+    connection::{catch_panic, route_of, Exchange, MAX_REQUEST_SIZE},
+    ClientHandler, ClientHandlerError,
+};
+use crate::{
+    config::Config,
+    extensions::ConnectionState,
+    http_response::HTTPResponse,
+    server_context::ServerContext,
+    tls::{HandshakeFailure, HANDSHAKE_LOG_TARGET},
+};
+
+/// Code of a connection closed on purpose, see RFC 9114 section 8.1.
+const H3_NO_ERROR: VarInt = VarInt::from_u32(0x100);
+
+type Stream = RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>;
+
+/// Headers about the HTTP/1.1 connection, which HTTP/3 responses must not carry.
+const CONNECTION_HEADERS: [&str; 5] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+impl ClientHandler {
+    /// Serves the HTTP/3 connections of `endpoint` until `draining` turns `true`,
+    /// then lets them answer the requests already sent.
+    ///
+    /// Connections keep the configuration they were accepted with, like TCP
+    /// ones, and are served the same routes with the same checks. Requests can't
+    /// upgrade their connection, and a response switching protocols is sent as is.
+    pub async fn serve_http3(
+        endpoint: Endpoint,
+        mut context: Arc<ServerContext>,
+        mut config: watch::Receiver<Arc<Config>>,
+        mut draining: watch::Receiver<bool>,
+    ) {
+        if let Ok(address) = endpoint.local_addr() {
+            info!(%address, "Listening for HTTP/3");
+        }
+        let mut connections = JoinSet::new();
+        loop {
+            let incoming = tokio::select! {
+                incoming = endpoint.accept() => incoming,
+                _ = draining.wait_for(|draining| *draining) => None,
+            };
+            let Some(incoming) = incoming else {
+                break;
+            };
+            if config.has_changed().unwrap_or(false) {
+                let reloaded = config.borrow_and_update().clone();
+                context = Arc::new(context.with_config(reloaded));
+            }
+            let span = info_span!("connection", peer = %incoming.remote_address(), protocol = "h3");
+            let (context, draining) = (Arc::clone(&context), draining.clone());
+            connections.spawn(
+                async move {
+                    if let Err(e) = Self::handle_http3_connection(incoming, context, draining).await
+                    {
+                        error!(error = %e, "Error handling HTTP/3 connection");
+                    }
+                }
+                .instrument(span),
+            );
+        }
+        endpoint.set_server_config(None);
+        while connections.join_next().await.is_some() {}
+        endpoint.wait_idle().await;
+    }
+
+    /// Serves the requests of an HTTP/3 connection, each on its own stream and
+    /// task, until the client closes it or the server drains.
+    async fn handle_http3_connection(
+        incoming: Incoming,
+        context: Arc<ServerContext>,
+        mut draining: watch::Receiver<bool>,
+    ) -> Result<(), ClientHandlerError> {
+        let _open = context.stats().open_connection();
+        let registration = context
+            .admin()
+            .connections()
+            .register(incoming.remote_address(), "HTTP/3");
+        let state = Arc::new(ConnectionState::new(incoming.remote_address()).with_tls());
+        let peer = incoming.remote_address();
+        let quic = match incoming.await {
+            Ok(quic) => quic,
+            Err(e) => {
+                let (failure, alert, by_client) = HandshakeFailure::from_quic(&e);
+                warn!(
+                    target: HANDSHAKE_LOG_TARGET,
+                    %peer,
+                    reason = failure.name(),
+                    ?alert,
+                    by_client,
+                    error = %e,
+                    "TLS handshake failed"
+                );
+                context.stats().count_tls_handshake_failure(failure.name());
+                return Ok(());
+            }
+        };
+        let mut connection =
+            h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(quic.clone()))
+                .await?;
+        let mut requests = JoinSet::new();
+        let drained = loop {
+            tokio::select! {
+                accepted = connection.accept() => {
+                    let resolver = match accepted {
+                        Ok(Some(resolver)) => resolver,
+                        Ok(None) => break false,
+                        Err(e) if closes_gracefully(&e) => break false,
+                        Err(e) => return Err(e.into()),
+                    };
+                    registration.count_request();
+                    let (state, context) = (Arc::clone(&state), Arc::clone(&context));
+                    let shutting_down = *draining.borrow();
+                    requests.spawn(async move {
+                        Self::handle_http3_request(resolver, &state, &context, shutting_down).await;
+                    });
+                }
+                // Not holding on to the value borrowed from `draining`, which isn't `Send`
+                () = async { drop(draining.wait_for(|draining| *draining).await) } => {
+                    // The client is told to send no more requests, those accepted are answered
+                    connection.shutdown(0).await?;
+                    break true;
+                }
+            }
+        };
+        while requests.join_next().await.is_some() {}
+        if drained {
+            quic.close(H3_NO_ERROR, b"");
+        }
+        Ok(())
+    }
+
+    /// Reads a request off its stream, answers it and records the exchange,
+    /// within the request timeout of its route like on TCP connections.
+    async fn handle_http3_request(
+        resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+        state: &Arc<ConnectionState>,
+        context: &ServerContext,
+        shutting_down: bool,
+    ) {
+        let config = context.config();
+        let mut exchange = Exchange::start(state.peer(), context);
+        let span = exchange.span.clone();
+        let deadline = exchange.started + config.request_timeout;
+        let read = timeout_at(deadline, async {
+            let (head, mut stream) = resolver.resolve_request().await?;
+            // Refused before the body is read, like on TCP connections
+            let body = if head.headers().len() > config.max_header_count {
+                Err(ClientHandlerError::TooManyHeaders(config.max_header_count))
+            } else {
+                read_body(&mut stream).await
+            };
+            Ok::<_, ClientHandlerError>((head, stream, body))
+        })
+        .instrument(span.clone())
+        .await;
+        exchange.read = exchange.started.elapsed();
+        let (head, mut stream, body) = match read {
+            Ok(Ok(read)) => read,
+            // Without the stream of the request, there is nobody to tell
+            Ok(Err(e)) => {
+                warn!(parent: &span, error = %e, "Can't read HTTP/3 request");
+                return;
+            }
+            Err(_) => {
+                warn!(parent: &span, "Timed out reading request");
+                return;
+            }
+        };
+        let request = http1_request(&head, body.as_deref().unwrap_or_default());
+        exchange.identify(&request);
+        let response = match body {
+            Ok(_) => Self::process_http3(&request, &exchange, state, context, shutting_down).await,
+            Err(e) if e.is_bad_request() => Err(e),
+            Err(e) => {
+                warn!(parent: &span, error = %e, "Can't read HTTP/3 request body");
+                return;
+            }
+        };
+        exchange.handle = exchange.started.elapsed().saturating_sub(exchange.read);
+        let response =
+            response.unwrap_or_else(|e| span.in_scope(|| context.error_handler().respond(&e)));
+        let response = exchange.stamp(response);
+        if let Err(e) = send(&mut stream, &response).instrument(span.clone()).await {
+            warn!(parent: &span, error = %e, "Can't send HTTP/3 response");
+            return;
+        }
+        exchange.finish(&request, &response);
+    }
+
+    /// Handles `request` within the request timeout of its route.
+    async fn process_http3(
+        request: &Bytes,
+        exchange: &Exchange<'_>,
+        state: &Arc<ConnectionState>,
+        context: &ServerContext,
+        shutting_down: bool,
+    ) -> Result<HTTPResponse, ClientHandlerError> {
+        let (method, path) = route_of(request);
+        let deadline = exchange.started + context.config().request_timeout_for(method, path);
+        let _in_flight = context.stats().start_request();
+        timeout_at(
+            deadline,
+            catch_panic(Self::process(
+                request,
+                &exchange.request_id,
+                state,
+                context,
+                shutting_down,
+                false,
+            )),
+        )
+        .instrument(exchange.span.clone())
+        .await
+        .unwrap_or_else(|_| {
+            warn!(parent: &exchange.span, "Timed out handling request");
+            Err(ClientHandlerError::HandlingTimedOut)
+        })
+    }
+}
+
+/// Whether the connection ended without error, closed by either side or idle
+/// for too long.
+fn closes_gracefully(error: &ConnectionError) -> bool {
+    error.is_h3_no_error() || matches!(error, ConnectionError::Timeout { .. })
+}
+
+/// Reads the body of a request, as long as the request fits in the same limit
+/// as HTTP/1.1 ones.
+async fn read_body(stream: &mut Stream) -> Result<Bytes, ClientHandlerError> {
+    let mut body = BytesMut::new();
+    while let Some(mut chunk) = stream.recv_data().await? {
+        if body.len() + chunk.remaining() >= MAX_REQUEST_SIZE {
+            return Err(ClientHandlerError::RequestTooLarge);
+        }
+        body.put(&mut chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// The HTTP/1.1 request `head` and `body` stand for, with the authority as its
+/// `Host` header and the length of the body as its `Content-Length`.
+fn http1_request(head: &http::Request<()>, body: &[u8]) -> Bytes {
+    let mut request = String::new();
+    let target = head
+        .uri()
+        .path_and_query()
+        .map_or("/", PathAndQuery::as_str);
+    let _ = write!(request, "{} {target} HTTP/1.1\r\n", head.method());
+    let authority = head.uri().authority();
+    if let Some(authority) = authority {
+        let _ = write!(request, "Host: {authority}\r\n");
+    }
+    let mut request = BytesMut::from(request.as_bytes());
+    for (name, value) in head.headers() {
+        let replaced = (authority.is_some() && name == http::header::HOST)
+            || name == http::header::CONTENT_LENGTH
+            || name == http::header::TRANSFER_ENCODING;
+        if !replaced {
+            request.put_slice(name.as_str().as_bytes());
+            request.put_slice(b": ");
+            request.put_slice(value.as_bytes());
+            request.put_slice(b"\r\n");
+        }
+    }
+    if !body.is_empty() {
+        request.put_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+    }
+    request.put_slice(b"\r\n");
+    request.put_slice(body);
+    request.freeze()
+}
+
+/// The head of `response` as HTTP/3 sends it, without the headers about the
+/// HTTP/1.1 connection nor those `http` doesn't take.
+fn http3_response(response: &HTTPResponse) -> http::Response<()> {
+    let mut head = http::Response::new(());
+    *head.status_mut() =
+        StatusCode::from_u16(response.status().code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    *head.headers_mut() = header_map(response.headers().iter().filter(|(name, _)| {
+        !CONNECTION_HEADERS
+            .iter()
+            .any(|connection| name.eq_ignore_ascii_case(connection))
+    }));
+    head
+}
+
+fn header_map<'a>(fields: impl Iterator<Item = (&'a str, &'a str)>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in fields {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+/// Sends `response` on the stream of its request, trailers included, and
+/// closes the stream.
+async fn send(stream: &mut Stream, response: &HTTPResponse) -> Result<(), ClientHandlerError> {
+    stream.send_response(http3_response(response)).await?;
+    let body = response.body();
+    if !body.is_empty() {
+        stream.send_data(body).await?;
+    }
+    if !response.trailers().is_empty() {
+        stream
+            .send_trailers(header_map(response.trailers().iter()))
+            .await?;
+    }
+    stream.finish().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::http_response::{ContentType, ResponseStatus};
+
+    #[test]
+    fn test_http1_request() {
+        let head = http::Request::builder()
+            .method("POST")
+            .uri("https://example.com:4433/echo?a=1")
+            .header("host", "ignored")
+            .header("content-length", "99")
+            .header("x-request-id", "abc")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            http1_request(&head, b"hello"),
+            "POST /echo?a=1 HTTP/1.1\r\nHost: example.com:4433\r\nx-request-id: abc\r\nContent-Length: 5\r\n\r\nhello"
+        );
+    }
+
+    #[test]
+    fn test_http3_response_drops_connection_headers() {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body("hi", ContentType::TextPlain, &[])
+            .with_header("Keep-Alive", "timeout=5")
+            .build()
+            .with_connection_close();
+        let head = http3_response(&response);
+        assert_eq!(head.status(), StatusCode::OK);
+        assert_eq!(head.headers()["content-type"], "text/plain");
+        assert_eq!(head.headers()["content-length"], "2");
+        assert!(head.headers().get("connection").is_none());
+        assert!(head.headers().get("keep-alive").is_none());
+    }
+}
@@ -0,0 +1,306 @@
+//! Serving clients: `connection` reads requests off a stream and writes the
+//! responses back with a `response_writer`, `handler` decides what each
+//! request gets.
+
+mod admin;
+mod cache;
+mod connection;
+mod handler;
+#[cfg(feature = "http3")]
+mod http3;
+mod proxy;
+mod response_writer;
+
+use std::sync::Arc;
+
+use thiserror::Error;
+use tracing::{error, info};
+
+pub use connection::Connection;
+
+use crate::{
+    http_request::{
+        CharsetError, HTTPRequestError, HTTPRequestLineError, HTTPVersionError, RequestBodyError,
+        RequestHeaderError,
+    },
+    http_response::{ContentType, HTTPResponse, ResponseStatus},
+};
+
+/// The `ClientHandler` struct represents a handler for client connections.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClientHandler;
+impl ClientHandler {
+    /// Answers a request that went wrong, closing the connection: with
+    /// `400 Bad Request` when the request was at fault, `431 Request Header
+    /// Fields Too Large` when it has too many headers, `501 Not Implemented`
+    /// when its body has a transfer coding, `505 HTTP Version Not
+    /// Supported` for versions other than HTTP/1.x, `408` or `504` when it took
+    /// too long, `500 Internal Server Error` otherwise. The cause of a server
+    /// error is logged but kept from the client.
+    pub(super) fn error_response(error: &ClientHandlerError) -> HTTPResponse {
+        let status = match error {
+            ClientHandlerError::ReadTimedOut => ResponseStatus::Http408,
+            ClientHandlerError::HandlingTimedOut => ResponseStatus::Http504,
+            ClientHandlerError::CharsetError(CharsetError::UnsupportedCharset(_)) => {
+                ResponseStatus::Http415
+            }
+            ClientHandlerError::TooManyHeaders(_) => {
+                info!(%error, "Bad request");
+                ResponseStatus::Http431
+            }
+            ClientHandlerError::UnsupportedTransferEncoding(_) => {
+                info!(%error, "Bad request");
+                ResponseStatus::Other(501)
+            }
+            error if error.is_unsupported_version() => ResponseStatus::Http505,
+            error if error.is_bad_request() => {
+                info!(%error, "Bad request");
+                ResponseStatus::Http400
+            }
+            error => {
+                error!(%error, "Failed handling request");
+                return Self::failure(ResponseStatus::Http500, "Internal Server Error");
+            }
+        };
+        Self::failure(status, &error.to_string())
+    }
+
+    /// Builds a plain text response to a request that went wrong, closing the connection.
+    fn failure(status: ResponseStatus, message: &str) -> HTTPResponse {
+        HTTPResponse::new_builder(status)
+            .with_body(message, ContentType::TextPlain, &[])
+            .build()
+            .with_connection_close()
+    }
+}
+
+/// Turns the errors of requests into responses.
+///
+/// Errors get plain text responses unless a hook is given to
+/// [`ErrorHandler::new`], which is handed the error along with that default
+/// response to build its own, e.g. a JSON error envelope.
+#[derive(Clone, Default)]
+pub struct ErrorHandler {
+    hook: Option<Arc<ErrorHook>>,
+}
+type ErrorHook = dyn Fn(&ClientHandlerError, HTTPResponse) -> HTTPResponse + Send + Sync;
+impl ErrorHandler {
+    pub fn new(
+        hook: impl Fn(&ClientHandlerError, HTTPResponse) -> HTTPResponse + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            hook: Some(Arc::new(hook)),
+        }
+    }
+
+    /// The response to `error`, which closes the connection whatever the hook returns
+    /// since the rest of the request may still be in flight.
+    #[must_use]
+    pub fn respond(&self, error: &ClientHandlerError) -> HTTPResponse {
+        let response = ClientHandler::error_response(error);
+        match &self.hook {
+            Some(hook) => hook(error, response).with_connection_close(),
+            None => response,
+        }
+    }
+}
+impl std::fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ErrorHandler")
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+#[derive(Debug, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum ClientHandlerError {
+    #[error("Request has no request line")]
+    NoRequestLineFound,
+    #[error("Request handler panicked: {0}")]
+    HandlerPanicked(String),
+    #[error("Request timed out")]
+    ReadTimedOut,
+    #[error("Request handling timed out")]
+    HandlingTimedOut,
+    #[error("Stream cannot be read")]
+    UnreadableStream(#[from] std::io::Error),
+    #[error("Request line is empty")]
+    EmptyRequestLine,
+    #[error("Can't respond to client to request : '{1}'\r\n{0} ")]
+    ClientUnreachable(tokio::io::Error, String),
+    #[error("Can't decode request to Utf8 : '{1}'\r\n{0}")]
+    Utf8Error(std::str::Utf8Error, String),
+    #[error("Request is larger than the maximum buffer size")]
+    RequestTooLarge,
+    #[error("Request has more than {0} header lines")]
+    TooManyHeaders(usize),
+    #[error("'{0}' is not a valid Content-Length")]
+    InvalidContentLength(String),
+    #[error("Transfer-Encoding '{0}' is not supported, send a Content-Length instead")]
+    UnsupportedTransferEncoding(String),
+    #[error("Request has both a Transfer-Encoding and a Content-Length")]
+    ConflictingLengths,
+    #[error("Request body ended after {0} of the {1} bytes of its Content-Length")]
+    IncompleteBody(usize, usize),
+    #[error("Error handling GET command: {0}")]
+    GetCommandError(#[from] GetCommandError),
+    #[error("{0}")]
+    HTTPRequestLineError(#[from] HTTPRequestLineError),
+    #[error("{0}")]
+    RequestHeaderError(#[from] RequestHeaderError),
+    #[error("{0}")]
+    RequestBodyError(#[from] RequestBodyError),
+    #[error("{0}")]
+    CharsetError(#[from] CharsetError),
+    #[cfg(feature = "http3")]
+    #[error("QUIC connection failed: {0}")]
+    QuicConnection(#[from] quinn::ConnectionError),
+    #[cfg(feature = "http3")]
+    #[error("HTTP/3 connection failed: {0}")]
+    Http3Connection(#[from] h3::error::ConnectionError),
+    #[cfg(feature = "http3")]
+    #[error("HTTP/3 stream failed: {0}")]
+    Http3Stream(#[from] h3::error::StreamError),
+}
+impl ClientHandlerError {
+    /// Whether the error comes from a request the client got wrong rather than from the connection.
+    #[must_use]
+    pub const fn is_bad_request(&self) -> bool {
+        matches!(
+            self,
+            Self::NoRequestLineFound
+                | Self::EmptyRequestLine
+                | Self::Utf8Error(..)
+                | Self::RequestTooLarge
+                | Self::TooManyHeaders(_)
+                | Self::InvalidContentLength(_)
+                | Self::UnsupportedTransferEncoding(_)
+                | Self::ConflictingLengths
+                | Self::IncompleteBody(..)
+                | Self::GetCommandError(_)
+                | Self::HTTPRequestLineError(_)
+                | Self::RequestHeaderError(_)
+                | Self::RequestBodyError(_)
+                | Self::CharsetError(_)
+        )
+    }
+
+    /// Whether the request is well-formed but in a version other than HTTP/1.x.
+    #[must_use]
+    pub const fn is_unsupported_version(&self) -> bool {
+        matches!(
+            self,
+            Self::HTTPRequestLineError(HTTPRequestLineError::HTTPVersionError(
+                HTTPVersionError::UnsupportedVersion(_)
+            ))
+        )
+    }
+}
+
+impl From<HTTPRequestError> for ClientHandlerError {
+    fn from(error: HTTPRequestError) -> Self {
+        match error {
+            HTTPRequestError::MissingRequestLine => Self::NoRequestLineFound,
+            HTTPRequestError::RequestLine(e) => Self::HTTPRequestLineError(e),
+            HTTPRequestError::RequestHeader(e) => Self::RequestHeaderError(e),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GetCommandError {
+    #[error("HTTP get command missing path")]
+    MissingPath,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        test_util::{TestClient, TestResponse},
+    };
+
+    #[tokio::test]
+    async fn test_serves_valid_request() {
+        let client = TestClient::start().await;
+        let response = client.get("/").await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Content-Length"), Some("0"));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_request_too_large() {
+        let client = TestClient::start().await;
+        let response = TestResponse::parse(&client.raw(&b"A".repeat(4097)).await);
+        assert_eq!(response.status, 400);
+        assert_eq!(
+            response.text(),
+            "Request is larger than the maximum buffer size"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closes_without_request_line() {
+        let client = TestClient::start().await;
+        assert!(client.raw(b"").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_empty_request_line() {
+        let client = TestClient::start().await;
+        let response = TestResponse::parse(&client.raw(b"\r\n\r\n").await);
+        assert_eq!(response.status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_utf8() {
+        let client = TestClient::start().await;
+        let response = TestResponse::parse(&client.raw(&[0x80, 0x80, 0x80, 0x80]).await);
+        assert_eq!(response.status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_test_client_helpers() {
+        let directory = std::env::temp_dir().join("http-server-test-client");
+        std::fs::create_dir_all(&directory).unwrap();
+        let client = TestClient::with_config(Config {
+            directory: Some(directory.to_string_lossy().into_owned()),
+            ..Config::default()
+        })
+        .await;
+        assert_eq!(client.put("/files/note", "first").await.status, 201);
+        assert_eq!(client.post("/echo", "body").await.text(), "body");
+        assert_eq!(
+            client.post("/echo", "a\r\n\r\nb").await.text(),
+            "a\r\n\r\nb"
+        );
+        let response = client
+            .request(
+                "GET",
+                "/files/note",
+                &[("Accept-Encoding", "identity")],
+                b"",
+            )
+            .await;
+        assert_eq!(response.text(), "first");
+        assert!(response.header("ETag").is_some());
+        assert_eq!(client.delete("/files/note").await.status, 204);
+        assert_eq!(client.get("/files/note").await.status, 404);
+    }
+
+    #[test]
+    fn test_error_response_hides_server_errors() {
+        let response = ClientHandler::error_response(&ClientHandlerError::HandlerPanicked(
+            "secret detail".to_string(),
+        ));
+        assert_eq!(
+            response.as_http_bytes(),
+            b"HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\nContent-Length: 21\r\nConnection: close\r\n\r\nInternal Server Error"
+        );
+        let response = ClientHandler::error_response(&ClientHandlerError::EmptyRequestLine);
+        assert_eq!(response.status(), ResponseStatus::Http400);
+    }
+}
@@ -0,0 +1,196 @@
+use std::time::SystemTime;
+
+use tracing::warn;
+
+use super::ClientHandler;
+use crate::{
+    config::{Config, ProxyPass},
+    forwarding,
+    header_map::HeaderMap,
+    http_request::{HTTPRequest, RequestMethod},
+    http_response::{ContentType, HTTPResponse, ResponseStatus},
+    proxy_cache::{self, CachedResponse, ProxyCache},
+    trace_context,
+    upstream::{self, UpstreamError, UpstreamResponse},
+};
+
+impl ClientHandler {
+    /// Forwards `request` to the upstream server of `proxy` and answers with
+    /// its response, both with the hop-by-hop headers and `proxy_strip_headers`
+    /// removed, the server added to `Via` and the client to `Forwarded`, with
+    /// the scheme it was sent with. The request keeps its trace context, if valid.
+    ///
+    /// With `proxy_cache_directory` set, `GET` requests are answered from the
+    /// cache while their response is fresh, see [`cached_exchange`].
+    ///
+    /// # Returns
+    ///
+    /// Returns `502 Bad Gateway` if upstream can't be reached or its response is invalid.
+    pub(super) async fn proxy(
+        request: &HTTPRequest,
+        proxy: &ProxyPass,
+        config: &Config,
+    ) -> HTTPResponse {
+        let strip = &config.proxy_strip_headers;
+        let mut headers = forwarding::upstream_headers(request, strip);
+        // Only a valid trace context is passed on, an invalid one starts a trace upstream
+        headers.remove(trace_context::TRACEPARENT_HEADER);
+        headers.remove(trace_context::TRACESTATE_HEADER);
+        if let Some(trace) = trace_context::from_headers(request.header().headers()) {
+            for (name, value) in trace.headers() {
+                headers.append(name, &value);
+            }
+        }
+        let cache = proxy_cache(config).filter(|_| is_cacheable(request));
+        let response = match cache {
+            Some(cache) => cached_exchange(request, proxy, headers, cache).await,
+            None => upstream::send(request, &proxy.upstream, &headers).await,
+        };
+        response
+            .and_then(|response| response.into_response(strip))
+            .unwrap_or_else(|error| {
+                warn!(upstream = proxy.upstream, %error, "Can't proxy request");
+                bad_gateway()
+            })
+    }
+}
+
+/// The cache of `proxy_cache_directory`, `None` when proxied responses aren't cached.
+pub(super) fn proxy_cache(config: &Config) -> Option<ProxyCache> {
+    config.proxy_cache_directory.as_ref().map(ProxyCache::new)
+}
+
+/// The key the response to `target`, a path and query like `/api/items?page=2`,
+/// is cached under: the URL it is fetched from.
+pub(super) fn cache_key(proxy: &ProxyPass, target: &str) -> String {
+    format!("http://{}{target}", proxy.upstream)
+}
+
+/// Whether the response to `request` may come from and go to the cache: a
+/// `GET` for the whole resource, without credentials a shared cache must not
+/// answer for.
+fn is_cacheable(request: &HTTPRequest) -> bool {
+    let headers = request.header().headers();
+    *request.line().method() == RequestMethod::Get
+        && !headers.contains("Authorization")
+        && !headers.contains("Range")
+}
+
+/// Answers `request` from `cache` when it holds a fresh response, or one
+/// still usable while it is revalidated in the background. Otherwise the
+/// request is sent upstream, made conditional if a stale response is held,
+/// and a stale response within its `stale-if-error` is sent if upstream fails.
+/// What upstream answers is stored when a shared cache may store it.
+async fn cached_exchange(
+    request: &HTTPRequest,
+    proxy: &ProxyPass,
+    headers: HeaderMap,
+    cache: ProxyCache,
+) -> Result<UpstreamResponse, UpstreamError> {
+    let key = cache_key(proxy, &request.line().uri().to_string());
+    let now = SystemTime::now();
+    let cached = cache.get(&key).await.unwrap_or_else(|error| {
+        warn!(key, %error, "Can't read cached response");
+        None
+    });
+    let Some(cached) = cached else {
+        let response = upstream::send(request, &proxy.upstream, &headers).await?;
+        store(&cache, &key, &response, now).await;
+        return Ok(response);
+    };
+    if cached.is_fresh(now) {
+        return Ok(from_cache(cached, now));
+    }
+    if cached.usable_while_revalidating(now) {
+        let (request, upstream) = (request.without_extensions(), proxy.upstream.clone());
+        let stale = cached.clone();
+        tokio::spawn(async move {
+            let _ = revalidate(&request, &upstream, headers, &cache, &key, stale).await;
+        });
+        return Ok(from_cache(cached, now));
+    }
+    let revalidated = revalidate(
+        request,
+        &proxy.upstream,
+        headers,
+        &cache,
+        &key,
+        cached.clone(),
+    )
+    .await;
+    let failed = !revalidated
+        .as_ref()
+        .is_ok_and(|response| !matches!(response.status, 500 | 502 | 503 | 504));
+    if failed && cached.usable_on_error(now) {
+        return Ok(from_cache(cached, now));
+    }
+    revalidated
+}
+
+/// Asks upstream whether `stale` is still valid and stores what it answers.
+///
+/// # Returns
+///
+/// Returns `stale` refreshed when upstream answers `304 Not Modified`, its new response otherwise.
+async fn revalidate(
+    request: &HTTPRequest,
+    upstream: &str,
+    mut headers: HeaderMap,
+    cache: &ProxyCache,
+    key: &str,
+    stale: CachedResponse,
+) -> Result<UpstreamResponse, UpstreamError> {
+    // A 304 must confirm the stale response, not what the client holds
+    headers.remove("If-None-Match");
+    headers.remove("If-Modified-Since");
+    for (name, value) in stale.revalidation_headers().iter() {
+        headers.insert(name, value);
+    }
+    let response = upstream::send(request, upstream, &headers).await?;
+    let now = SystemTime::now();
+    if response.status != 304 {
+        store(cache, key, &response, now).await;
+        return Ok(response);
+    }
+    let refreshed = stale.revalidated(&response.headers, now);
+    if let Err(error) = cache.put(key, &refreshed).await {
+        warn!(key, %error, "Can't cache response");
+    }
+    Ok(from_cache(refreshed, now))
+}
+
+/// Stores `response`, received at `now`, if a shared cache may. A failure is
+/// only logged, the response being sent all the same.
+async fn store(cache: &ProxyCache, key: &str, response: &UpstreamResponse, now: SystemTime) {
+    // A 304 answers the validators of the client, not the request as cached
+    if response.status == 304 || !proxy_cache::storable(response.status, &response.headers) {
+        return;
+    }
+    let cached = CachedResponse {
+        status: response.status,
+        headers: response.headers.clone(),
+        body: response.body.to_vec(),
+        stored: now,
+    };
+    if let Err(error) = cache.put(key, &cached).await {
+        warn!(key, %error, "Can't cache response");
+    }
+}
+
+/// The response of `cached` to send at `now`, its `Age` telling how long it has been stored.
+fn from_cache(cached: CachedResponse, now: SystemTime) -> UpstreamResponse {
+    let age = cached.age(now).as_secs().to_string();
+    let mut headers = cached.headers;
+    headers.insert("Age", &age);
+    UpstreamResponse {
+        status: cached.status,
+        headers,
+        body: cached.body.into(),
+    }
+}
+
+fn bad_gateway() -> HTTPResponse {
+    HTTPResponse::new_builder(ResponseStatus::Other(502))
+        .with_body("Bad Gateway", ContentType::TextPlain, &[])
+        .build()
+}
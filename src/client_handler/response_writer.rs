@@ -0,0 +1,86 @@
+use crate::http_response::{HTTPResponse, InformationalResponse};
+
+/// What the buffer of a connection holds before any response was written,
+/// enough for the heads of most responses.
+const INITIAL_CAPACITY: usize = 512;
+
+/// Serializes the responses of a connection into a buffer kept from one
+/// response to the next, so once it has grown to the size of the usual heads,
+/// answering a request doesn't allocate for them. Bodies are never copied into
+/// it, they are written from where they are in between.
+#[derive(Debug)]
+pub(super) struct ResponseWriter {
+    buf: Vec<u8>,
+}
+impl ResponseWriter {
+    pub(super) fn new() -> Self {
+        Self {
+            buf: Vec::with_capacity(INITIAL_CAPACITY),
+        }
+    }
+
+    /// The interim responses to send before `response`, none for clients
+    /// speaking HTTP/1.0 which don't expect them. They are to be flushed on
+    /// their own, so the client gets them before the final response is sent.
+    pub(super) fn informational(&mut self, response: &HTTPResponse, http_1_0: bool) -> &[u8] {
+        self.buf.clear();
+        if !http_1_0 {
+            for informational in response.informational() {
+                informational.write_to(&mut self.buf);
+            }
+        }
+        &self.buf
+    }
+
+    /// A single interim response, like `100 Continue` sent while reading a request.
+    pub(super) fn interim(&mut self, informational: &InformationalResponse) -> &[u8] {
+        self.buf.clear();
+        informational.write_to(&mut self.buf);
+        &self.buf
+    }
+
+    /// `response` as the parts to write in order: its head, its body and what
+    /// ends its last chunk when it is sent chunked.
+    pub(super) fn parts<'a>(&'a mut self, response: &'a HTTPResponse) -> [&'a [u8]; 3] {
+        self.buf.clear();
+        response.write_head(&mut self.buf);
+        let head_length = self.buf.len();
+        response.write_trailer(&mut self.buf);
+        let (head, trailer) = self.buf.split_at(head_length);
+        [head, response.body_bytes(), trailer]
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::http_response::{ContentType, ResponseStatus};
+
+    #[test]
+    fn test_response_writer_reuses_its_buffer() {
+        let mut writer = ResponseWriter::new();
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body("body", ContentType::TextPlain, &[])
+            .build()
+            .with_informational(InformationalResponse::early_hints(&[
+                "</a.css>; rel=preload",
+            ]))
+            .with_trailer("Server-Timing", "db;dur=53");
+        assert_eq!(
+            writer.informational(&response, false),
+            b"HTTP/1.1 103 Early Hints\r\nLink: </a.css>; rel=preload\r\n\r\n"
+        );
+        assert!(writer.informational(&response, true).is_empty());
+        assert_eq!(writer.parts(&response).concat(), response.as_http_bytes());
+        let capacity = writer.buf.capacity();
+        let buffer = writer.buf.as_ptr();
+        for _ in 0..3 {
+            let [head, body, _] = writer.parts(&response);
+            assert!(head.ends_with(b"\r\n4\r\n"));
+            assert_eq!(body, b"body");
+        }
+        assert_eq!(writer.buf.capacity(), capacity);
+        assert_eq!(writer.buf.as_ptr(), buffer);
+    }
+}
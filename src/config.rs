@@ -0,0 +1,1334 @@
+use std::{fmt::Display, num::NonZeroUsize, str::FromStr, sync::Arc, time::Duration};
+
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+#[cfg(feature = "templates")]
+use crate::templates::{Templates, TemplatesError};
+#[cfg(feature = "http3")]
+use crate::tls::{CipherSuite, TlsVersion};
+use crate::{
+    access_log::AccessLogFormat,
+    access_policy::{AccessPolicies, AccessPolicyError},
+    auth::{ApiKeys, ApiKeysError, BearerTokens, Htpasswd, HtpasswdError, Route},
+    connection_limit::SaturationPolicy,
+    directory,
+    http_request::Host,
+    log_file::LogRotation,
+    logging::{self, LogFormat},
+    rate_limit::{RateLimit, RateLimitKey},
+    throttle::ByteRate,
+};
+
+/// Settings the server reads at startup and again on every reload.
+///
+/// Values come from an optional config file (`--config <path>`) made of
+/// `key = value` lines, then command line flags override them. Every key can
+/// be given as a flag by prefixing it with `--` and using dashes instead of
+/// underscores, e.g. `--max-connections 100`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(clippy::struct_excessive_bools)] // Independent switches, one key each
+pub struct Config {
+    pub directory: Option<String>,
+    /// Host names, in the form `Host::name` gives them, whose requests are
+    /// served files from their own directory instead of `directory`.
+    pub host_directories: Vec<(String, String)>,
+    /// Directories served under their own path prefix, besides `directory`
+    /// under `/files/`, one `mount` key each.
+    pub mounts: Vec<Mount>,
+    /// Path prefixes whose requests are forwarded to an upstream server, one
+    /// `proxy_pass` key each.
+    pub proxy_passes: Vec<ProxyPass>,
+    /// Headers removed from proxied requests and responses, besides the
+    /// hop-by-hop ones.
+    pub proxy_strip_headers: Vec<String>,
+    /// Directory proxied responses are cached in, none are when unset.
+    pub proxy_cache_directory: Option<String>,
+    /// Whether a missing `directory` is created at startup instead of being an error.
+    pub create_directory: bool,
+    /// Whether files are only published, `POST`, `PUT` and `DELETE` on
+    /// `/files/` being answered with `405 Method Not Allowed`.
+    pub read_only: bool,
+    /// Whether files are sent with `Content-Disposition: attachment` so browsers
+    /// download them, which `?download=0` or `?download=1` overrides per request.
+    pub downloads_as_attachment: bool,
+    /// Extensions, lowercase and without their dot, the files uploaded to
+    /// `/files/` must have. Any is accepted when empty.
+    pub upload_allowed_extensions: Vec<String>,
+    /// Extensions files uploaded to `/files/` must not have.
+    pub upload_denied_extensions: Vec<String>,
+    /// Largest file, in bytes, accepted on `/files/`, unlimited when `None`.
+    pub max_upload_size: Option<usize>,
+    /// How long open connections get to finish once shutdown is requested
+    /// before they are aborted.
+    pub shutdown_grace_period: Duration,
+    /// Time allowed to read, handle and answer a request, counted from its first byte.
+    pub request_timeout: Duration,
+    /// Time allowed to handle and answer requests on some routes, instead of
+    /// `request_timeout`. The first route matching a request applies.
+    pub route_timeouts: Vec<(Route, Duration)>,
+    /// Time a client may go without reading any of its response before the
+    /// connection is aborted.
+    pub write_timeout: Duration,
+    /// Time an open connection may wait for its next request.
+    pub keepalive_timeout: Duration,
+    /// Requests served on a connection before it is closed.
+    pub keepalive_requests: usize,
+    /// Header lines a request may have, those with more being answered
+    /// `431 Request Header Fields Too Large`.
+    pub max_header_count: usize,
+    /// Connections served at the same time, unlimited when `None`.
+    /// Only read at startup.
+    pub max_connections: Option<usize>,
+    pub on_saturation: SaturationPolicy,
+    /// Whether the listening socket is bound with `SO_REUSEPORT` and `SIGUSR2`
+    /// starts a new server replacing this one. Unix only, read at startup.
+    pub graceful_restart: bool,
+    /// Whether the server detaches from the terminal and runs in the
+    /// background, its standard output and error going to `access_log_file`
+    /// and `log_file`. Unix only, read at startup.
+    pub daemon: bool,
+    /// File the server writes its PID to while it runs. Unix only, read at startup.
+    pub pid_file: Option<String>,
+    /// Requests per second allowed for each client, unlimited when `None`.
+    pub rate_limit: Option<u32>,
+    /// Requests a client may send at once, defaults to `rate_limit`.
+    pub rate_limit_burst: Option<u32>,
+    pub rate_limit_key: RateLimitKey,
+    /// Bytes per second each connection may send, unlimited when `None`.
+    pub max_rate: Option<ByteRate>,
+    /// Bytes per second all connections together may send, unlimited when `None`.
+    pub max_total_rate: Option<ByteRate>,
+    /// Only read at startup, like the other runtime settings.
+    pub runtime: RuntimeFlavor,
+    /// Threads running connections, one per CPU core when `None`.
+    pub worker_threads: Option<usize>,
+    /// Threads available for blocking work such as file I/O, tokio's default when `None`.
+    pub max_blocking_threads: Option<usize>,
+    pub access_log: AccessLogFormat,
+    /// Only read at startup.
+    pub log_format: LogFormat,
+    /// Levels per module, like `RUST_LOG` which it takes precedence over.
+    /// Only read at startup.
+    pub log_level: Option<String>,
+    /// File the access log is written to instead of the standard output.
+    /// Only read at startup, like the other log file settings.
+    pub access_log_file: Option<String>,
+    /// File diagnostic logs are written to instead of the standard error.
+    pub log_file: Option<String>,
+    pub log_rotation: LogRotation,
+    /// Rotated files kept for each log file.
+    pub log_retention: usize,
+    /// Whether `GET /admin/stats` reports the server's counters.
+    pub stats: bool,
+    /// Whether `GET /admin/storage` reports the size, file count and free
+    /// space of `directory` to authenticated clients.
+    pub storage_usage: bool,
+    /// Whether authenticated clients may change the log level, list connections,
+    /// evict cache entries and toggle read-only mode under `/admin/`.
+    pub admin_api: bool,
+    /// Tokens `POST /admin/shutdown` must be sent with to stop the server,
+    /// read from the `shutdown_tokens_file` key. The endpoint is off when `None`.
+    pub shutdown_tokens: Option<BearerTokens>,
+    /// Bytes of response bodies kept in memory for `GET` and `HEAD`, no
+    /// response being cached when `None`.
+    pub response_cache_size: Option<u64>,
+    /// Bytes of the largest body cached.
+    pub response_cache_max_entry_size: u64,
+    /// Time responses are served from the cache, unless their `Cache-Control` says otherwise.
+    pub response_cache_ttl: Duration,
+    /// Time stale responses are still served while a new one is built,
+    /// unless their `Cache-Control` says otherwise.
+    pub response_cache_stale_while_revalidate: Duration,
+    /// Time stale responses are still served when building a new one fails,
+    /// unless their `Cache-Control` says otherwise.
+    pub response_cache_stale_if_error: Duration,
+    /// Requests taking longer are logged as slow, none when `None`.
+    pub slow_request_threshold: Option<Duration>,
+    /// Users let in by HTTP Basic authentication, read from the
+    /// `basic_auth_file` key. Nothing is protected when `None`.
+    pub basic_auth: Option<Htpasswd>,
+    /// Path prefixes requiring authentication.
+    pub basic_auth_paths: Vec<String>,
+    /// Realm announced to clients in `WWW-Authenticate`.
+    pub basic_auth_realm: String,
+    /// Tokens accepted by bearer authentication, read from the
+    /// `bearer_tokens_file` key or else the `HTTP_SERVER_BEARER_TOKENS`
+    /// environment variable. Nothing is protected when `None`.
+    pub bearer_tokens: Option<BearerTokens>,
+    /// Requests requiring a bearer token, writes to `/files/` by default.
+    pub bearer_auth_routes: Vec<Route>,
+    /// Keys and their permissions, read from the `api_keys_file` key.
+    /// Nothing is protected when `None`.
+    pub api_keys: Option<ApiKeys>,
+    /// Requests requiring an API key, all but probes by default.
+    pub api_key_routes: Vec<Route>,
+    /// Header carrying the API key.
+    pub api_key_header: String,
+    /// Query parameter carrying the API key when the header is missing.
+    pub api_key_param: String,
+    /// Policies of path prefixes, read from the `access_policies_file` key.
+    /// Nothing is restricted when `None`.
+    pub access_policies: Option<AccessPolicies>,
+    /// Whether `POST` requests must carry the token of the CSRF cookie, handed
+    /// out on safe requests.
+    pub csrf: bool,
+    pub csrf_cookie: String,
+    /// Whether `POST` requests may ask to be handled as `PUT` or `DELETE` with
+    /// the `X-HTTP-Method-Override` header or the `_method` form field.
+    pub method_override: bool,
+    /// Host names requests may be addressed to, without ports, in the form
+    /// `Host::name` gives them. Any host is served when empty.
+    pub allowed_hosts: Vec<String>,
+    /// Whether request lines may use any whitespace between their parts,
+    /// instead of the single spaces RFC 9112 requires.
+    pub lenient_request_line: bool,
+    /// Whether `/status/{code}` and `/delay/{seconds}` are served, for clients
+    /// to be tested against errors and latency.
+    pub simulation_routes: bool,
+    /// Templates rendered into HTML, read from the `templates_directory` key.
+    /// Error responses use `errors/<status>.html` when it exists.
+    #[cfg(feature = "templates")]
+    pub templates: Option<Templates>,
+    /// UDP port HTTP/3 is served on, at the address of the TCP listener, and
+    /// advertised to HTTP/1.1 clients with `Alt-Svc`. Read at startup only.
+    #[cfg(feature = "http3")]
+    pub http3_port: Option<u16>,
+    /// PEM file of the certificate chain HTTP/3 clients are shown.
+    #[cfg(feature = "http3")]
+    pub tls_cert_file: Option<String>,
+    /// PEM file of the private key of `tls_cert_file`.
+    #[cfg(feature = "http3")]
+    pub tls_key_file: Option<String>,
+    /// DER file of the OCSP response stapled to the certificate in handshakes.
+    #[cfg(feature = "http3")]
+    pub tls_ocsp_file: Option<String>,
+    /// How often the certificate files are checked for changes, never when zero.
+    #[cfg(feature = "http3")]
+    pub tls_reload_interval: Duration,
+    /// Oldest TLS version clients may negotiate, TLS 1.3 by default.
+    #[cfg(feature = "http3")]
+    pub tls_min_version: TlsVersion,
+    /// Cipher suites offered, in order of preference, all those supported when empty.
+    #[cfg(feature = "http3")]
+    pub tls_cipher_suites: Vec<CipherSuite>,
+    /// Whether clients may resume earlier sessions, skipping a full handshake.
+    #[cfg(feature = "http3")]
+    pub tls_session_resumption: bool,
+    /// Whether resumed sessions are kept by clients in encrypted tickets rather
+    /// than by the server.
+    #[cfg(feature = "http3")]
+    pub tls_session_tickets: bool,
+    /// Protocols offered to clients with ALPN, in order of preference.
+    #[cfg(feature = "http3")]
+    pub tls_alpn_protocols: Vec<String>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            host_directories: Vec::new(),
+            mounts: Vec::new(),
+            proxy_passes: Vec::new(),
+            proxy_strip_headers: Vec::new(),
+            proxy_cache_directory: None,
+            create_directory: false,
+            read_only: false,
+            downloads_as_attachment: false,
+            upload_allowed_extensions: Vec::new(),
+            upload_denied_extensions: Vec::new(),
+            max_upload_size: None,
+            shutdown_grace_period: Duration::from_secs(30),
+            request_timeout: Duration::from_secs(30),
+            route_timeouts: Vec::new(),
+            write_timeout: Duration::from_secs(10),
+            keepalive_timeout: Duration::from_secs(75),
+            keepalive_requests: 1000,
+            max_header_count: 100,
+            max_connections: None,
+            on_saturation: SaturationPolicy::default(),
+            graceful_restart: false,
+            daemon: false,
+            pid_file: None,
+            rate_limit: None,
+            rate_limit_burst: None,
+            rate_limit_key: RateLimitKey::default(),
+            max_rate: None,
+            max_total_rate: None,
+            runtime: RuntimeFlavor::default(),
+            worker_threads: None,
+            max_blocking_threads: None,
+            access_log: AccessLogFormat::default(),
+            log_format: LogFormat::default(),
+            log_level: None,
+            access_log_file: None,
+            log_file: None,
+            log_rotation: LogRotation::default(),
+            log_retention: 7,
+            stats: false,
+            storage_usage: false,
+            admin_api: false,
+            shutdown_tokens: None,
+            response_cache_size: None,
+            response_cache_max_entry_size: 1 << 20,
+            response_cache_ttl: Duration::from_secs(10),
+            response_cache_stale_while_revalidate: Duration::ZERO,
+            response_cache_stale_if_error: Duration::ZERO,
+            slow_request_threshold: None,
+            basic_auth: None,
+            basic_auth_paths: vec!["/".to_string()],
+            basic_auth_realm: "http-server".to_string(),
+            bearer_tokens: None,
+            bearer_auth_routes: ["POST /files/", "PUT /files/", "DELETE /files/"]
+                .iter()
+                .filter_map(|route| route.parse().ok())
+                .collect(),
+            api_keys: None,
+            api_key_routes: "* /".parse().into_iter().collect(),
+            api_key_header: "X-Api-Key".to_string(),
+            api_key_param: "api_key".to_string(),
+            access_policies: None,
+            csrf: false,
+            csrf_cookie: "csrf_token".to_string(),
+            method_override: false,
+            allowed_hosts: Vec::new(),
+            lenient_request_line: false,
+            simulation_routes: false,
+            #[cfg(feature = "templates")]
+            templates: None,
+            #[cfg(feature = "http3")]
+            http3_port: None,
+            #[cfg(feature = "http3")]
+            tls_cert_file: None,
+            #[cfg(feature = "http3")]
+            tls_key_file: None,
+            #[cfg(feature = "http3")]
+            tls_ocsp_file: None,
+            #[cfg(feature = "http3")]
+            tls_reload_interval: Duration::from_mins(1),
+            #[cfg(feature = "http3")]
+            tls_min_version: TlsVersion::default(),
+            #[cfg(feature = "http3")]
+            tls_cipher_suites: Vec::new(),
+            #[cfg(feature = "http3")]
+            tls_session_resumption: true,
+            #[cfg(feature = "http3")]
+            tls_session_tickets: false,
+            #[cfg(feature = "http3")]
+            tls_alpn_protocols: vec!["h3".to_string()],
+        }
+    }
+}
+impl Config {
+    /// Builds the configuration from the command line arguments, reading the
+    /// config file they point to if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `ConfigError` if a flag is unknown or has no value, the config file cannot be read or one of its lines is invalid.
+    pub fn load(args: &[String]) -> Result<Self, ConfigError> {
+        let flags = parse_flags(args)?;
+        let config_file = flags
+            .iter()
+            .rev()
+            .find_map(|(key, value)| (key == "config").then_some(value));
+        let mut config = match config_file {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| ConfigError::UnreadableFile(e.to_string(), path.clone()))?
+                .parse()?,
+            None => Self::default(),
+        };
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            config.set(key, value)?;
+        }
+        if config.bearer_tokens.is_none() {
+            config.bearer_tokens = std::env::var(BearerTokens::ENV)
+                .ok()
+                .map(|tokens| BearerTokens::from_comma_separated(&tokens));
+        }
+        Ok(config)
+    }
+
+    /// The directory files are served from for requests to `host`, the one of
+    /// `host_directories` naming it or else `directory`.
+    #[must_use]
+    pub fn directory_for(&self, host: Option<&str>) -> Option<&str> {
+        host.and_then(|host| self.host_directories.iter().find(|(name, _)| name == host))
+            .map_or(self.directory.as_deref(), |(_, directory)| {
+                Some(directory.as_str())
+            })
+    }
+
+    /// Every directory files are served from, `directory` first, with
+    /// whether files are written to it.
+    pub fn directories(&self) -> impl Iterator<Item = (&str, bool)> {
+        let writable = !self.read_only;
+        self.directory
+            .as_deref()
+            .into_iter()
+            .chain(
+                self.host_directories
+                    .iter()
+                    .map(|(_, directory)| directory.as_str()),
+            )
+            .map(move |directory| (directory, writable))
+            .chain(
+                self.mounts
+                    .iter()
+                    .map(move |mount| (mount.directory.as_str(), writable && !mount.read_only)),
+            )
+    }
+
+    /// The mount serving `path`, the one with the longest prefix when several do.
+    #[must_use]
+    pub fn mount_for(&self, path: &str) -> Option<&Mount> {
+        self.mounts
+            .iter()
+            .filter(|mount| path.starts_with(&mount.prefix))
+            .max_by_key(|mount| mount.prefix.len())
+    }
+
+    /// Where requests for `path` are forwarded, the proxy with the longest
+    /// prefix when several match.
+    #[must_use]
+    pub fn proxy_for(&self, path: &str) -> Option<&ProxyPass> {
+        self.proxy_passes
+            .iter()
+            .filter(|proxy| path.starts_with(&proxy.prefix))
+            .max_by_key(|proxy| proxy.prefix.len())
+    }
+
+    /// Time allowed to handle and answer a `method` request for `path`, counted
+    /// from its first byte.
+    #[must_use]
+    pub fn request_timeout_for(&self, method: &str, path: &str) -> Duration {
+        self.route_timeouts
+            .iter()
+            .find(|(route, _)| route.matches(method, path))
+            .map_or(self.request_timeout, |(_, timeout)| *timeout)
+    }
+
+    /// The per-client rate limit, if one is configured.
+    #[must_use]
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit.map(|per_second| RateLimit {
+            per_second,
+            burst: self.rate_limit_burst.unwrap_or(per_second),
+        })
+    }
+
+    #[allow(clippy::too_many_lines)] // One arm per key
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "directory" => self.directory = Some(value.to_string()),
+            "host_directories" => self.host_directories = parse_host_directories(key, value)?,
+            "mount" => self.mounts.push(parse_value(key, value)?),
+            "proxy_pass" => self.proxy_passes.push(parse_value(key, value)?),
+            "proxy_strip_headers" => {
+                self.proxy_strip_headers = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "proxy_cache_directory" => self.proxy_cache_directory = Some(value.to_string()),
+            "create_directory" => self.create_directory = parse_value(key, value)?,
+            "read_only" => self.read_only = parse_value(key, value)?,
+            "downloads_as_attachment" => {
+                self.downloads_as_attachment = parse_value(key, value)?;
+            }
+            "upload_allowed_extensions" => self.upload_allowed_extensions = parse_extensions(value),
+            "upload_denied_extensions" => self.upload_denied_extensions = parse_extensions(value),
+            "max_upload_size" => self.max_upload_size = Some(parse_value(key, value)?),
+            "shutdown_grace_period" => self.shutdown_grace_period = parse_seconds(key, value)?,
+            "request_timeout" => self.request_timeout = parse_seconds(key, value)?,
+            "route_timeouts" => self.route_timeouts = parse_route_timeouts(key, value)?,
+            "write_timeout" => self.write_timeout = parse_seconds(key, value)?,
+            "keepalive_timeout" => self.keepalive_timeout = parse_seconds(key, value)?,
+            "keepalive_requests" => self.keepalive_requests = parse_value(key, value)?,
+            "max_header_count" => self.max_header_count = parse_value(key, value)?,
+            "max_connections" => self.max_connections = Some(parse_value(key, value)?),
+            "on_saturation" => self.on_saturation = parse_value(key, value)?,
+            "graceful_restart" => self.graceful_restart = parse_value(key, value)?,
+            "daemon" => self.daemon = parse_value(key, value)?,
+            "pid_file" => self.pid_file = Some(value.to_string()),
+            "rate_limit" => self.rate_limit = Some(parse_value(key, value)?),
+            "rate_limit_burst" => self.rate_limit_burst = Some(parse_value(key, value)?),
+            "rate_limit_key" => self.rate_limit_key = parse_value(key, value)?,
+            "max_rate" => self.max_rate = Some(parse_value(key, value)?),
+            "max_total_rate" => self.max_total_rate = Some(parse_value(key, value)?),
+            "runtime" => self.runtime = parse_value(key, value)?,
+            // The runtime can't start without threads
+            "worker_threads" => {
+                self.worker_threads = Some(parse_value::<NonZeroUsize>(key, value)?.get());
+            }
+            "max_blocking_threads" => {
+                self.max_blocking_threads = Some(parse_value::<NonZeroUsize>(key, value)?.get());
+            }
+            "access_log" => self.access_log = parse_value(key, value)?,
+            "log_format" => self.log_format = parse_value(key, value)?,
+            "log_level" => {
+                logging::validate_filter(value)
+                    .map_err(|_| ConfigError::InvalidValue(key.to_string(), value.to_string()))?;
+                self.log_level = Some(value.to_string());
+            }
+            "access_log_file" => self.access_log_file = Some(value.to_string()),
+            "log_file" => self.log_file = Some(value.to_string()),
+            "log_rotation" => self.log_rotation = parse_value(key, value)?,
+            "log_retention" => self.log_retention = parse_value(key, value)?,
+            "stats" => self.stats = parse_value(key, value)?,
+            "storage_usage" => self.storage_usage = parse_value(key, value)?,
+            "admin_api" => self.admin_api = parse_value(key, value)?,
+            "response_cache_size" => self.response_cache_size = Some(parse_value(key, value)?),
+            "response_cache_max_entry_size" => {
+                self.response_cache_max_entry_size = parse_value(key, value)?;
+            }
+            "response_cache_ttl" => self.response_cache_ttl = parse_seconds(key, value)?,
+            "response_cache_stale_while_revalidate" => {
+                self.response_cache_stale_while_revalidate = parse_seconds(key, value)?;
+            }
+            "response_cache_stale_if_error" => {
+                self.response_cache_stale_if_error = parse_seconds(key, value)?;
+            }
+            "shutdown_tokens_file" => {
+                let tokens = std::fs::read_to_string(value)
+                    .map_err(|e| ConfigError::UnreadableFile(e.to_string(), value.to_string()))?;
+                self.shutdown_tokens = Some(BearerTokens::from_str(&tokens).unwrap_or_default());
+            }
+            "slow_request_threshold_ms" => {
+                self.slow_request_threshold =
+                    Some(parse_value(key, value).map(Duration::from_millis)?);
+            }
+            "basic_auth_file" => {
+                let users = std::fs::read_to_string(value)
+                    .map_err(|e| ConfigError::UnreadableFile(e.to_string(), value.to_string()))?
+                    .parse()
+                    .map_err(|e: HtpasswdError| {
+                        ConfigError::InvalidCredentials(e.to_string(), value.to_string())
+                    })?;
+                self.basic_auth = Some(users);
+            }
+            "basic_auth_paths" => {
+                self.basic_auth_paths = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|prefix| !prefix.is_empty())
+                    .map(ToString::to_string)
+                    .collect();
+            }
+            "basic_auth_realm" => self.basic_auth_realm = value.replace(['"', '\r', '\n'], ""),
+            "bearer_tokens_file" => {
+                let tokens = std::fs::read_to_string(value)
+                    .map_err(|e| ConfigError::UnreadableFile(e.to_string(), value.to_string()))?;
+                self.bearer_tokens = Some(BearerTokens::from_str(&tokens).unwrap_or_default());
+            }
+            "bearer_auth_routes" => self.bearer_auth_routes = parse_routes(key, value)?,
+            "api_keys_file" => {
+                let keys = std::fs::read_to_string(value)
+                    .map_err(|e| ConfigError::UnreadableFile(e.to_string(), value.to_string()))?
+                    .parse()
+                    .map_err(|e: ApiKeysError| {
+                        ConfigError::InvalidCredentials(e.to_string(), value.to_string())
+                    })?;
+                self.api_keys = Some(keys);
+            }
+            "api_key_routes" => self.api_key_routes = parse_routes(key, value)?,
+            "api_key_header" => self.api_key_header = value.to_string(),
+            "api_key_param" => self.api_key_param = value.to_string(),
+            "access_policies_file" => {
+                let policies = std::fs::read_to_string(value)
+                    .map_err(|e| ConfigError::UnreadableFile(e.to_string(), value.to_string()))?
+                    .parse()
+                    .map_err(|e: AccessPolicyError| {
+                        ConfigError::InvalidAccessPolicies(e.to_string(), value.to_string())
+                    })?;
+                self.access_policies = Some(policies);
+            }
+            "csrf" => self.csrf = parse_value(key, value)?,
+            "lenient_request_line" => self.lenient_request_line = parse_value(key, value)?,
+            "simulation_routes" => self.simulation_routes = parse_value(key, value)?,
+            #[cfg(feature = "templates")]
+            "templates_directory" => {
+                self.templates = Some(Templates::load(value)?);
+            }
+            #[cfg(feature = "http3")]
+            "http3_port" => self.http3_port = Some(parse_value(key, value)?),
+            #[cfg(feature = "http3")]
+            "tls_cert_file" => self.tls_cert_file = Some(value.to_string()),
+            #[cfg(feature = "http3")]
+            "tls_key_file" => self.tls_key_file = Some(value.to_string()),
+            #[cfg(feature = "http3")]
+            "tls_ocsp_file" => self.tls_ocsp_file = Some(value.to_string()),
+            #[cfg(feature = "http3")]
+            "tls_reload_interval" => self.tls_reload_interval = parse_seconds(key, value)?,
+            #[cfg(feature = "http3")]
+            "tls_min_version" => self.tls_min_version = parse_value(key, value)?,
+            #[cfg(feature = "http3")]
+            "tls_cipher_suites" => {
+                self.tls_cipher_suites = value
+                    .split(',')
+                    .filter(|suite| !suite.trim().is_empty())
+                    .map(|suite| parse_value(key, suite))
+                    .collect::<Result<_, _>>()?;
+            }
+            #[cfg(feature = "http3")]
+            "tls_session_resumption" => self.tls_session_resumption = parse_value(key, value)?,
+            #[cfg(feature = "http3")]
+            "tls_session_tickets" => self.tls_session_tickets = parse_value(key, value)?,
+            #[cfg(feature = "http3")]
+            "tls_alpn_protocols" => {
+                self.tls_alpn_protocols = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|protocol| !protocol.is_empty())
+                    .map(ToString::to_string)
+                    .collect();
+            }
+            "allowed_hosts" => {
+                self.allowed_hosts = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|host| !host.is_empty())
+                    .map(|host| {
+                        host.parse::<Host>()
+                            .map(|host| host.name().to_string())
+                            .map_err(|_| {
+                                ConfigError::InvalidValue(key.to_string(), value.to_string())
+                            })
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "csrf_cookie" => {
+                let valid = !value.is_empty()
+                    && value
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || b"-_".contains(&byte));
+                if !valid {
+                    return Err(ConfigError::InvalidValue(
+                        key.to_string(),
+                        value.to_string(),
+                    ));
+                }
+                self.csrf_cookie = value.to_string();
+            }
+            "method_override" => self.method_override = parse_value(key, value)?,
+            unknown_key => return Err(ConfigError::UnknownKey(unknown_key.to_string())),
+        }
+        Ok(())
+    }
+}
+impl FromStr for Config {
+    type Err = ConfigError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ConfigError::InvalidLine(line.to_string()));
+            };
+            config.set(key.trim(), value.trim())?;
+        }
+        Ok(config)
+    }
+}
+
+/// A directory served under a path prefix, like `/assets=/var/www/assets`,
+/// followed by `;read_only` for its files to only be published.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mount {
+    /// The prefix of the paths served, ending with `/`.
+    pub prefix: String,
+    pub directory: String,
+    /// Whether `POST`, `PUT` and `DELETE` are answered with `405 Method Not Allowed`.
+    pub read_only: bool,
+}
+impl FromStr for Mount {
+    type Err = MountError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MountError::InvalidMount(s.to_string());
+        let (mount, options) = s.split_once(';').unwrap_or((s, ""));
+        let (prefix, directory) = mount.split_once('=').ok_or_else(invalid)?;
+        let (prefix, directory) = (prefix.trim(), directory.trim());
+        if !prefix.starts_with('/') || directory.is_empty() {
+            return Err(invalid());
+        }
+        let read_only = match options.trim() {
+            "" => false,
+            "read_only" => true,
+            _ => return Err(invalid()),
+        };
+        let prefix = format!("{}/", prefix.trim_end_matches('/'));
+        // The endpoints are answered first, a mount over them would never be reached
+        let shadows = |endpoint: &str| endpoint.starts_with(&prefix);
+        if BUILT_IN_ENDPOINTS.iter().any(|endpoint| shadows(endpoint))
+            || BUILT_IN_PREFIXES
+                .iter()
+                .any(|built_in| shadows(built_in) || prefix.starts_with(built_in))
+        {
+            return Err(MountError::BuiltInPrefix(prefix));
+        }
+        Ok(Self {
+            prefix,
+            directory: directory.to_string(),
+            read_only,
+        })
+    }
+}
+
+/// The paths of the built-in endpoints, which no mount may serve.
+const BUILT_IN_ENDPOINTS: [&str; 5] = ["/", "/echo", "/headers", "/healthz", "/readyz"];
+
+/// The prefixes of the paths of the built-in endpoints, which no mount may
+/// serve paths under either.
+const BUILT_IN_PREFIXES: [&str; 6] = [
+    "/files/",
+    "/echo/",
+    "/user-agent",
+    "/admin/",
+    "/status/",
+    "/delay/",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum MountError {
+    #[error("'{0}' is not a mount, expected a path prefix and a directory like '/assets=/var/www/assets'")]
+    InvalidMount(String),
+    #[error("Mount prefix '{0}' overlaps the paths of the built-in endpoints")]
+    BuiltInPrefix(String),
+}
+
+/// Requests under a path prefix forwarded to an upstream server, like
+/// `/api/=127.0.0.1:8080`, their target kept as is.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProxyPass {
+    /// The prefix of the paths forwarded, ending with `/`.
+    pub prefix: String,
+    /// The `host:port` of the upstream server, spoken to in plain HTTP/1.1.
+    pub upstream: String,
+}
+impl FromStr for ProxyPass {
+    type Err = ProxyPassError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProxyPassError::InvalidProxyPass(s.to_string());
+        let (prefix, upstream) = s.split_once('=').ok_or_else(invalid)?;
+        let (prefix, upstream) = (prefix.trim(), upstream.trim());
+        let valid_upstream = upstream.rsplit_once(':').is_some_and(|(host, port)| {
+            !host.is_empty() && port.parse::<u16>().is_ok_and(|port| port > 0)
+        });
+        if !prefix.starts_with('/') || !valid_upstream {
+            return Err(invalid());
+        }
+        Ok(Self {
+            prefix: format!("{}/", prefix.trim_end_matches('/')),
+            upstream: upstream.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum ProxyPassError {
+    #[error("'{0}' is not a proxy, expected a path prefix and a host and port like '/api=127.0.0.1:8080'")]
+    InvalidProxyPass(String),
+}
+
+/// Which tokio scheduler runs the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RuntimeFlavor {
+    /// Connections are spread over a pool of worker threads.
+    #[default]
+    MultiThread,
+    /// Everything runs on the main thread, for tiny containers.
+    CurrentThread,
+}
+impl FromStr for RuntimeFlavor {
+    type Err = RuntimeFlavorError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "multi-thread" | "multi_thread" => Ok(Self::MultiThread),
+            "current-thread" | "current_thread" => Ok(Self::CurrentThread),
+            invalid_flavor => Err(RuntimeFlavorError::InvalidFlavor(
+                invalid_flavor.to_string(),
+            )),
+        }
+    }
+}
+impl Display for RuntimeFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MultiThread => write!(f, "multi-thread"),
+            Self::CurrentThread => write!(f, "current-thread"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum RuntimeFlavorError {
+    #[error("'{0}' is not a runtime, expected 'multi-thread' or 'current-thread'")]
+    InvalidFlavor(String),
+}
+
+/// Turns `--some-flag value` arguments into `(some_flag, value)` pairs, the
+/// program name excluded.
+fn parse_flags(args: &[String]) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut flags = Vec::new();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(ConfigError::UnknownFlag(arg.clone()));
+        };
+        let value = args
+            .next()
+            .ok_or_else(|| ConfigError::MissingFlagValue(arg.clone()))?;
+        flags.push((flag.replace('-', "_"), value.clone()));
+    }
+    Ok(flags)
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(key.to_string(), value.to_string()))
+}
+
+/// Parses comma-separated routes like `POST /files/, * /admin/`.
+fn parse_routes(key: &str, value: &str) -> Result<Vec<Route>, ConfigError> {
+    value
+        .split(',')
+        .filter(|route| !route.trim().is_empty())
+        .map(|route| parse_value(key, route))
+        .collect()
+}
+
+/// Parses comma-separated file extensions like `txt, .PNG` into `["txt", "png"]`.
+fn parse_extensions(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+/// Parses comma-separated routes followed by seconds, like `GET /files/ 300, * /echo 5`.
+fn parse_route_timeouts(key: &str, value: &str) -> Result<Vec<(Route, Duration)>, ConfigError> {
+    value
+        .split(',')
+        .filter(|route| !route.trim().is_empty())
+        .map(|route_timeout| {
+            let (route, seconds) = route_timeout.trim().rsplit_once(' ').ok_or_else(|| {
+                ConfigError::InvalidValue(key.to_string(), route_timeout.to_string())
+            })?;
+            Ok((parse_value(key, route)?, parse_seconds(key, seconds)?))
+        })
+        .collect()
+}
+
+/// Parses comma-separated host names followed by directories, like
+/// `alice.example.com /srv/alice, bob.example.com /srv/bob`.
+fn parse_host_directories(key: &str, value: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    value
+        .split(',')
+        .filter(|host_directory| !host_directory.trim().is_empty())
+        .map(|host_directory| {
+            let invalid = || ConfigError::InvalidValue(key.to_string(), host_directory.to_string());
+            let (host, directory) = host_directory.trim().split_once(' ').ok_or_else(invalid)?;
+            let host = host.parse::<Host>().map_err(|_| invalid())?;
+            Ok((host.name().to_string(), directory.trim().to_string()))
+        })
+        .collect()
+}
+
+fn parse_seconds(key: &str, value: &str) -> Result<Duration, ConfigError> {
+    parse_value(key, value).map(Duration::from_secs)
+}
+
+/// Reloads the configuration each time the process receives `SIGHUP` and
+/// publishes it through `sender`.
+///
+/// Connections take a snapshot of the configuration when they are accepted, so
+/// the ones in flight keep running with the previous settings. A configuration
+/// that fails to load, or points to a directory that can't be served, is ignored.
+///
+/// # Errors
+///
+/// Returns an error if the `SIGHUP` handler cannot be installed.
+#[cfg(unix)]
+pub async fn reload_on_sighup(
+    args: Vec<String>,
+    sender: watch::Sender<Arc<Config>>,
+) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        let config = match Config::load(&args) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!(error = %e, "Keeping previous configuration, reload failed");
+                continue;
+            }
+        };
+        let mut prepared = Ok(());
+        for (dir, writable) in config.directories() {
+            prepared = directory::prepare(dir, config.create_directory, writable).await;
+            if prepared.is_err() {
+                break;
+            }
+        }
+        if let Err(e) = prepared {
+            warn!(error = %e, "Keeping previous configuration, can't serve directory");
+            continue;
+        }
+        info!(?config, "Configuration reloaded");
+        sender.send_replace(Arc::new(config));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum ConfigError {
+    #[error("'{0}' flag is given without a value")]
+    MissingFlagValue(String),
+    #[error("'{0}' is not a flag")]
+    UnknownFlag(String),
+    #[error("Can't read file '{1}': {0}")]
+    UnreadableFile(String, String),
+    #[error("Invalid credentials file '{1}': {0}")]
+    InvalidCredentials(String, String),
+    #[error("Invalid access policies file '{1}': {0}")]
+    InvalidAccessPolicies(String, String),
+    #[error("'{0}' is not a 'key = value' line")]
+    InvalidLine(String),
+    #[error("'{0}' is not a known configuration key")]
+    UnknownKey(String),
+    #[error("'{1}' is not a valid value for '{0}'")]
+    InvalidValue(String, String),
+    #[cfg(feature = "templates")]
+    #[error("{0}")]
+    Templates(#[from] TemplatesError),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_config_from_str() {
+        let config: Config = "# served files\ndirectory = /tmp/files\n\n"
+            .parse()
+            .unwrap();
+        assert_eq!(config.directory.as_deref(), Some("/tmp/files"));
+    }
+
+    #[test]
+    fn test_config_from_str_with_unknown_key() {
+        let result = Config::from_str("port = 80");
+        assert!(matches!(result, Err(ConfigError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn test_config_from_str_with_invalid_line() {
+        let result = Config::from_str("directory");
+        assert!(matches!(result, Err(ConfigError::InvalidLine(_))));
+    }
+
+    #[test]
+    fn test_config_from_str_with_grace_period() {
+        let config: Config = "shutdown_grace_period = 5".parse().unwrap();
+        assert_eq!(config.shutdown_grace_period, Duration::from_secs(5));
+        let result = Config::from_str("shutdown_grace_period = soon");
+        assert!(matches!(result, Err(ConfigError::InvalidValue(..))));
+    }
+
+    #[test]
+    fn test_load_directory_flag() {
+        let config = Config::load(&args(&["server", "--directory", "/tmp"])).unwrap();
+        assert_eq!(config.directory.as_deref(), Some("/tmp"));
+    }
+
+    #[test]
+    fn test_load_flag_overrides_config_file() {
+        let path = std::env::temp_dir().join("http-server-config-test.conf");
+        std::fs::write(&path, "directory = /from/file\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let config = Config::load(&args(&["server", "--config", &path])).unwrap();
+        assert_eq!(config.directory.as_deref(), Some("/from/file"));
+
+        let config =
+            Config::load(&args(&["server", "--config", &path, "--directory", "/cli"])).unwrap();
+        assert_eq!(config.directory.as_deref(), Some("/cli"));
+    }
+
+    #[test]
+    fn test_load_connection_limit_flags() {
+        let config = Config::load(&args(&[
+            "server",
+            "--max-connections",
+            "2",
+            "--on-saturation",
+            "reject",
+        ]))
+        .unwrap();
+        assert_eq!(config.max_connections, Some(2));
+        assert_eq!(config.on_saturation, SaturationPolicy::Reject);
+    }
+
+    #[test]
+    fn test_config_rate_limit() {
+        assert_eq!(Config::default().rate_limit(), None);
+        let config: Config = "rate_limit = 5\nrate_limit_key = header:X-Api-Key"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.rate_limit(),
+            Some(RateLimit {
+                per_second: 5,
+                burst: 5
+            })
+        );
+        assert_eq!(
+            config.rate_limit_key,
+            RateLimitKey::Header("X-Api-Key".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_runtime_flags() {
+        let config = Config::load(&args(&[
+            "server",
+            "--runtime",
+            "current-thread",
+            "--max-blocking-threads",
+            "4",
+        ]))
+        .unwrap();
+        assert_eq!(config.runtime, RuntimeFlavor::CurrentThread);
+        assert_eq!(config.worker_threads, None);
+        assert_eq!(config.max_blocking_threads, Some(4));
+        for flag in ["--worker-threads", "--max-blocking-threads"] {
+            let result = Config::load(&args(&["server", flag, "0"]));
+            assert!(matches!(result, Err(ConfigError::InvalidValue(..))));
+        }
+    }
+
+    #[test]
+    fn test_load_log_level_flag() {
+        let config = Config::load(&args(&[
+            "server",
+            "--log-level",
+            "warn,http_server_starter_rust::client_handler=error",
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.log_level.as_deref(),
+            Some("warn,http_server_starter_rust::client_handler=error")
+        );
+        let result = Config::load(&args(&["server", "--log-level", "client_handler=chatty"]));
+        assert!(matches!(result, Err(ConfigError::InvalidValue(..))));
+    }
+
+    #[test]
+    fn test_load_daemon_flags() {
+        let config = Config::load(&args(&[
+            "server",
+            "--daemon",
+            "true",
+            "--pid-file",
+            "/run/http-server.pid",
+        ]))
+        .unwrap();
+        assert!(config.daemon);
+        assert_eq!(config.pid_file.as_deref(), Some("/run/http-server.pid"));
+        assert!(matches!(
+            Config::load(&args(&["server", "--daemon", "yes"])),
+            Err(ConfigError::InvalidValue(key, _)) if key == "daemon"
+        ));
+    }
+
+    #[test]
+    fn test_load_response_cache_flags() {
+        let config = Config::load(&args(&[
+            "server",
+            "--response-cache-size",
+            "67108864",
+            "--response-cache-ttl",
+            "60",
+            "--response-cache-stale-if-error",
+            "300",
+        ]))
+        .unwrap();
+        assert_eq!(config.response_cache_size, Some(64 << 20));
+        assert_eq!(config.response_cache_max_entry_size, 1 << 20);
+        assert_eq!(config.response_cache_ttl, Duration::from_mins(1));
+        assert_eq!(config.response_cache_stale_while_revalidate, Duration::ZERO);
+        assert_eq!(config.response_cache_stale_if_error, Duration::from_mins(5));
+    }
+
+    #[test]
+    fn test_load_log_file_flags() {
+        let config = Config::load(&args(&[
+            "server",
+            "--access-log-file",
+            "/var/log/access.log",
+            "--log-rotation",
+            "100MB",
+            "--log-retention",
+            "3",
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.access_log_file.as_deref(),
+            Some("/var/log/access.log")
+        );
+        assert_eq!(config.log_file, None);
+        assert_eq!(config.log_rotation, LogRotation::Size(100 << 20));
+        assert_eq!(config.log_retention, 3);
+    }
+
+    #[test]
+    fn test_config_from_str_with_slow_request_threshold() {
+        let config: Config = "slow_request_threshold_ms = 250".parse().unwrap();
+        assert_eq!(
+            config.slow_request_threshold,
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(Config::default().slow_request_threshold, None);
+    }
+
+    #[test]
+    fn test_config_from_str_with_route_timeouts() {
+        let config: Config = "request_timeout = 30\nroute_timeouts = GET /files/ 300, * /echo 5"
+            .parse()
+            .unwrap();
+        let seconds = Duration::from_secs;
+        assert_eq!(
+            config.request_timeout_for("GET", "/files/big.iso"),
+            seconds(300)
+        );
+        assert_eq!(
+            config.request_timeout_for("PUT", "/files/big.iso"),
+            seconds(30)
+        );
+        assert_eq!(config.request_timeout_for("POST", "/echo"), seconds(5));
+        assert_eq!(config.request_timeout_for("GET", "/"), seconds(30));
+        assert!(matches!(
+            "route_timeouts = GET /files/".parse::<Config>(),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_load_basic_auth_flags() {
+        let path = std::env::temp_dir().join("http-server-config-test.htpasswd");
+        let hash = bcrypt::hash("secret", 4).unwrap();
+        std::fs::write(&path, format!("alice:{hash}\n")).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let config = Config::load(&args(&[
+            "server",
+            "--basic-auth-file",
+            &path,
+            "--basic-auth-paths",
+            "/files/, /admin/",
+        ]))
+        .unwrap();
+        assert!(config.basic_auth.is_some());
+        assert_eq!(config.basic_auth_paths, ["/files/", "/admin/"]);
+
+        std::fs::write(&path, "alice:secret\n").unwrap();
+        let result = Config::load(&args(&["server", "--basic-auth-file", &path]));
+        assert!(matches!(result, Err(ConfigError::InvalidCredentials(..))));
+    }
+
+    #[test]
+    fn test_load_bearer_auth_flags() {
+        let path = std::env::temp_dir().join("http-server-config-test.tokens");
+        std::fs::write(&path, "s3cr3t\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let config = Config::load(&args(&[
+            "server",
+            "--bearer-tokens-file",
+            &path,
+            "--bearer-auth-routes",
+            "POST /files/, * /admin/",
+        ]))
+        .unwrap();
+        assert!(config.bearer_tokens.unwrap().verify("s3cr3t"));
+        assert_eq!(
+            config.bearer_auth_routes,
+            [
+                "POST /files/".parse().unwrap(),
+                "* /admin/".parse::<Route>().unwrap()
+            ]
+        );
+        let result = Config::load(&args(&["server", "--bearer-auth-routes", "/files/"]));
+        assert!(matches!(result, Err(ConfigError::InvalidValue(..))));
+    }
+
+    #[test]
+    fn test_load_api_key_flags() {
+        let path = std::env::temp_dir().join("http-server-config-test.keys");
+        std::fs::write(&path, "k3y: GET /files/\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let config = Config::load(&args(&[
+            "server",
+            "--api-keys-file",
+            &path,
+            "--api-key-header",
+            "X-Key",
+        ]))
+        .unwrap();
+        assert!(config.api_keys.unwrap().permissions("k3y").is_some());
+        assert_eq!(config.api_key_header, "X-Key");
+        assert_eq!(config.api_key_routes, ["* /".parse().unwrap()]);
+
+        std::fs::write(&path, "k3y: /files/\n").unwrap();
+        let result = Config::load(&args(&["server", "--api-keys-file", &path]));
+        assert!(matches!(result, Err(ConfigError::InvalidCredentials(..))));
+    }
+
+    #[test]
+    fn test_load_access_policies_file() {
+        let path = std::env::temp_dir().join("http-server-config-test.policies");
+        std::fs::write(&path, "/files/private/ auth clients=10.0.0.0/8\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let config = Config::load(&args(&["server", "--access-policies-file", &path])).unwrap();
+        let policies = config.access_policies.unwrap();
+        assert!(policies.for_path("/files/private/a").unwrap().authenticated);
+        assert!(policies.for_path("/files/public/a").is_none());
+
+        std::fs::write(&path, "/files/private/ admin\n").unwrap();
+        let result = Config::load(&args(&["server", "--access-policies-file", &path]));
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidAccessPolicies(..))
+        ));
+    }
+
+    #[test]
+    fn test_config_from_str_with_host_directories() {
+        let config: Config =
+            "directory = /srv/default\nhost_directories = Alice.example.com /srv/alice, bob.example.com:8080 /srv/my files"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            config.host_directories,
+            [
+                ("alice.example.com".to_string(), "/srv/alice".to_string()),
+                ("bob.example.com".to_string(), "/srv/my files".to_string())
+            ]
+        );
+        assert_eq!(
+            config.directory_for(Some("alice.example.com")),
+            Some("/srv/alice")
+        );
+        assert_eq!(
+            config.directory_for(Some("carol.example.com")),
+            Some("/srv/default")
+        );
+        assert_eq!(config.directory_for(None), Some("/srv/default"));
+        assert_eq!(config.directories().count(), 3);
+        assert!(Config::from_str("host_directories = example.com").is_err());
+    }
+
+    #[test]
+    fn test_load_mount_flags() {
+        let config = Config::load(&args(&[
+            "server",
+            "--mount",
+            "/assets=/var/www/assets; read_only",
+            "--mount",
+            "/assets/uploads/ = /srv/uploads",
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.mounts,
+            [
+                Mount {
+                    prefix: "/assets/".to_string(),
+                    directory: "/var/www/assets".to_string(),
+                    read_only: true,
+                },
+                Mount {
+                    prefix: "/assets/uploads/".to_string(),
+                    directory: "/srv/uploads".to_string(),
+                    read_only: false,
+                }
+            ]
+        );
+        let directory = |path| config.mount_for(path).map(|mount| mount.directory.as_str());
+        assert_eq!(directory("/assets/app.js"), Some("/var/www/assets"));
+        assert_eq!(directory("/assets/uploads/a.png"), Some("/srv/uploads"));
+        assert_eq!(directory("/assetsx"), None);
+        assert_eq!(
+            config.directories().collect::<Vec<_>>(),
+            [("/var/www/assets", false), ("/srv/uploads", true)]
+        );
+        for invalid in [
+            "assets=/var/www",
+            "/assets",
+            "/assets=/var/www;rw",
+            "/=/var/www",
+            "/files/=/var/www",
+            "/files/uploads=/var/www",
+            "/admin=/var/www",
+            "/user-agents=/var/www",
+        ] {
+            assert!(Config::load(&args(&["server", "--mount", invalid])).is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_from_str_with_proxy_passes() {
+        let config: Config = "proxy_pass = /api=127.0.0.1:8080\n\
+                              proxy_pass = /api/v2/ = [::1]:9000\n\
+                              proxy_strip_headers = X-Internal, Server\n\
+                              proxy_cache_directory = /var/cache/proxy"
+            .parse()
+            .unwrap();
+        let upstream = |path| config.proxy_for(path).map(|proxy| proxy.upstream.as_str());
+        assert_eq!(upstream("/api/users"), Some("127.0.0.1:8080"));
+        assert_eq!(upstream("/api/v2/users"), Some("[::1]:9000"));
+        assert_eq!(upstream("/apis"), None);
+        assert_eq!(config.proxy_strip_headers, ["X-Internal", "Server"]);
+        assert_eq!(
+            config.proxy_cache_directory.as_deref(),
+            Some("/var/cache/proxy")
+        );
+        for invalid in ["api=127.0.0.1:80", "/api=127.0.0.1", "/api=:80", "/api"] {
+            assert!(format!("proxy_pass = {invalid}").parse::<Config>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_from_str_with_allowed_hosts() {
+        let config: Config = "allowed_hosts = Example.com, localhost".parse().unwrap();
+        assert_eq!(config.allowed_hosts, ["example.com", "localhost"]);
+        assert!(Config::default().allowed_hosts.is_empty());
+    }
+
+    #[test]
+    fn test_load_unknown_flag() {
+        let result = Config::load(&args(&["server", "--directory", "/tmp", "extra"]));
+        assert!(matches!(result, Err(ConfigError::UnknownFlag(_))));
+    }
+
+    #[test]
+    fn test_load_missing_flag_value() {
+        let result = Config::load(&args(&["server", "--directory"]));
+        assert!(matches!(result, Err(ConfigError::MissingFlagValue(_))));
+    }
+}
@@ -0,0 +1,152 @@
+use std::{fmt::Display, net::SocketAddr, str::FromStr, sync::Arc};
+
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+/// What to do with new connections once the limit is reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SaturationPolicy {
+    /// Stop accepting until a connection closes, clients wait in the listen backlog.
+    #[default]
+    Queue,
+    /// Accept and answer `503 Service Unavailable` right away.
+    Reject,
+}
+impl FromStr for SaturationPolicy {
+    type Err = SaturationPolicyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "queue" => Ok(Self::Queue),
+            "reject" => Ok(Self::Reject),
+            invalid_policy => Err(SaturationPolicyError::InvalidPolicy(
+                invalid_policy.to_string(),
+            )),
+        }
+    }
+}
+impl Display for SaturationPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Queue => write!(f, "queue"),
+            Self::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum SaturationPolicyError {
+    #[error("'{0}' is not a saturation policy, expected 'queue' or 'reject'")]
+    InvalidPolicy(String),
+}
+
+/// Outcome of accepting a connection under the limit.
+#[derive(Debug)]
+pub enum Admission {
+    /// The connection may be served, it keeps its slot until the permit is dropped.
+    Admitted(Option<OwnedSemaphorePermit>),
+    /// The server is saturated and the connection must be turned away.
+    Rejected,
+}
+
+/// Caps the number of connections served at the same time.
+#[derive(Debug, Clone)]
+pub struct ConnectionLimit {
+    slots: Option<Arc<Semaphore>>,
+    policy: SaturationPolicy,
+}
+impl ConnectionLimit {
+    /// Creates a limit of `max_connections` concurrent connections, or no limit at all for `None`.
+    #[must_use]
+    pub fn new(max_connections: Option<usize>, policy: SaturationPolicy) -> Self {
+        Self {
+            slots: max_connections.map(|max| Arc::new(Semaphore::new(max))),
+            policy,
+        }
+    }
+
+    /// Accepts the next connection, waiting for a free slot first when the policy is to queue.
+    ///
+    /// This is cancel safe, no connection is lost if the future is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listener fails to accept a connection.
+    pub async fn accept(
+        &self,
+        listener: &TcpListener,
+    ) -> std::io::Result<(TcpStream, SocketAddr, Admission)> {
+        let Some(slots) = &self.slots else {
+            let (stream, socket_address) = listener.accept().await?;
+            return Ok((stream, socket_address, Admission::Admitted(None)));
+        };
+        match self.policy {
+            SaturationPolicy::Queue => {
+                // Slots are never closed so acquiring only fails in theory
+                let permit = Arc::clone(slots).acquire_owned().await.ok();
+                let (stream, socket_address) = listener.accept().await?;
+                Ok((stream, socket_address, Admission::Admitted(permit)))
+            }
+            SaturationPolicy::Reject => {
+                let (stream, socket_address) = listener.accept().await?;
+                let admission = Arc::clone(slots)
+                    .try_acquire_owned()
+                    .map_or(Admission::Rejected, |permit| {
+                        Admission::Admitted(Some(permit))
+                    });
+                Ok((stream, socket_address, admission))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_saturation_policy_from_str() {
+        assert_eq!("queue".parse(), Ok(SaturationPolicy::Queue));
+        assert_eq!("REJECT".parse(), Ok(SaturationPolicy::Reject));
+        assert!(matches!(
+            "drop".parse::<SaturationPolicy>(),
+            Err(SaturationPolicyError::InvalidPolicy(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_accept_rejects_when_saturated() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let limit = ConnectionLimit::new(Some(1), SaturationPolicy::Reject);
+        let _first_client = TcpStream::connect(addr).await.unwrap();
+        let _second_client = TcpStream::connect(addr).await.unwrap();
+
+        let (_stream, _, first) = limit.accept(&listener).await.unwrap();
+        assert!(matches!(first, Admission::Admitted(Some(_))));
+        let (_stream, _, second) = limit.accept(&listener).await.unwrap();
+        assert!(matches!(second, Admission::Rejected));
+    }
+
+    #[tokio::test]
+    async fn test_accept_queues_when_saturated() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let limit = ConnectionLimit::new(Some(1), SaturationPolicy::Queue);
+        let _first_client = TcpStream::connect(addr).await.unwrap();
+        let _second_client = TcpStream::connect(addr).await.unwrap();
+
+        let (_stream, _, first) = limit.accept(&listener).await.unwrap();
+        let queued = tokio::time::timeout(Duration::from_millis(50), limit.accept(&listener)).await;
+        assert!(queued.is_err());
+
+        drop(first);
+        let (_stream, _, second) = limit.accept(&listener).await.unwrap();
+        assert!(matches!(second, Admission::Admitted(Some(_))));
+    }
+}
@@ -0,0 +1,106 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::{auth::constant_time_eq, http_request::RequestHeader};
+
+/// Header scripts send the token in.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Field HTML forms send the token in, as `application/x-www-form-urlencoded`.
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+
+/// Returns a new unguessable token to hand to a browser in a cookie.
+///
+/// # Errors
+///
+/// Returns an error if the operating system has no randomness to give.
+pub fn generate_token() -> Result<String, getrandom::Error> {
+    let mut bytes = [0; 32];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// The token of the cookie called `name`, if the client sent one.
+pub(crate) fn cookie<'a>(request_header: &'a RequestHeader, name: &str) -> Option<&'a str> {
+    request_header.get("Cookie")?.split(';').find_map(|cookie| {
+        let (cookie_name, value) = cookie.trim().split_once('=')?;
+        (cookie_name == name)
+            .then_some(value)
+            .filter(|value| !value.is_empty())
+    })
+}
+
+/// Checks the double-submitted token of a request: the cookie must match the
+/// token sent in the `X-CSRF-Token` header or the `csrf_token` form field.
+///
+/// A cross-site page can make a browser send the cookie, but can't read it to
+/// submit it a second time.
+pub(crate) fn verify(request_header: &RequestHeader, body: &str, cookie_name: &str) -> bool {
+    let Some(expected) = cookie(request_header, cookie_name) else {
+        return false;
+    };
+    let is_form = request_header
+        .content_type()
+        .is_some_and(|content_type| content_type.essence() == "application/x-www-form-urlencoded");
+    let submitted = request_header.get(CSRF_HEADER).map(str::trim).or_else(|| {
+        is_form
+            .then(|| {
+                body.split('&').find_map(|field| {
+                    let (name, value) = field.split_once('=')?;
+                    (name == CSRF_FORM_FIELD).then_some(value)
+                })
+            })
+            .flatten()
+    });
+    submitted.is_some_and(|submitted| constant_time_eq(submitted.as_bytes(), expected.as_bytes()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn header(lines: &str) -> RequestHeader {
+        format!("POST /files/a HTTP/1.1\r\nHost: localhost\r\n{lines}\r\n")
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token().unwrap();
+        assert_eq!(token.len(), 43);
+        assert_ne!(token, generate_token().unwrap());
+    }
+
+    #[test]
+    fn test_cookie() {
+        let request_header = header("Cookie: theme=dark; csrf_token=abc\r\n");
+        assert_eq!(cookie(&request_header, "csrf_token"), Some("abc"));
+        assert_eq!(cookie(&request_header, "session"), None);
+    }
+
+    #[test]
+    fn test_verify_header_and_form_field() {
+        let request_header = header("Cookie: csrf_token=abc\r\nX-CSRF-Token: abc\r\n");
+        assert!(verify(&request_header, "", "csrf_token"));
+        let request_header = header("Cookie: csrf_token=abc\r\nX-CSRF-Token: abd\r\n");
+        assert!(!verify(&request_header, "", "csrf_token"));
+
+        let request_header =
+            header("Cookie: csrf_token=abc\r\nContent-Type: application/x-www-form-urlencoded\r\n");
+        assert!(verify(
+            &request_header,
+            "name=x&csrf_token=abc",
+            "csrf_token"
+        ));
+        assert!(!verify(&request_header, "name=x", "csrf_token"));
+        let request_header = header("Cookie: csrf_token=abc\r\n");
+        assert!(!verify(&request_header, "csrf_token=abc", "csrf_token"));
+    }
+
+    #[test]
+    fn test_verify_without_cookie() {
+        let request_header = header("X-CSRF-Token: abc\r\n");
+        assert!(!verify(&request_header, "", "csrf_token"));
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+};
+
+use rustix::process::{kill_process, setsid, test_kill_process, Pid, Signal};
+use thiserror::Error;
+use tokio::{
+    process::Command,
+    runtime::Builder,
+    signal::unix::{signal, SignalKind},
+};
+
+use crate::config::Config;
+
+/// Environment variable telling a server it was started in the background by [`spawn`].
+pub const DAEMONIZED_ENV: &str = "HTTP_SERVER_DAEMONIZED";
+
+/// Environment variable giving the background server started by [`spawn`]
+/// the PID of the process waiting for it to listen, see [`notify_ready`].
+pub const READY_PID_ENV: &str = "HTTP_SERVER_READY_PID";
+
+/// Whether this process is the background server started by [`spawn`].
+#[must_use]
+pub fn is_daemonized() -> bool {
+    std::env::var_os(DAEMONIZED_ENV).is_some()
+}
+
+/// Starts the server again in the background with the command line `args`,
+/// for the process started from the terminal to exit.
+///
+/// The background server reads nothing, its standard output goes to
+/// `access_log_file` and its standard error to `log_file`, or nowhere when
+/// they aren't set. It keeps the working directory, relative paths in the
+/// configuration meaning the same.
+///
+/// Only returns once the background server listens, which it tells with
+/// `SIGUSR1`, so that a server failing to start is reported from the terminal.
+///
+/// # Returns
+///
+/// Returns the PID of the background server.
+///
+/// # Errors
+///
+/// Returns an error if the server already runs according to `pid_file`, a
+/// log file can't be opened, or the server can't be started or exits
+/// before listening.
+pub fn spawn(args: &[String], config: &Config) -> Result<u32, DaemonError> {
+    if let Some(pid) = config.pid_file.as_deref().and_then(running) {
+        return Err(DaemonError::AlreadyRunning(pid));
+    }
+    let stdout = redirect(config.access_log_file.as_deref())?;
+    let stderr = redirect(config.log_file.as_deref())?;
+    let runtime = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(DaemonError::Spawn)?;
+    runtime.block_on(async {
+        // Listened for before the server starts, for its signal not to be missed
+        let mut ready = signal(SignalKind::user_defined1()).map_err(DaemonError::Spawn)?;
+        let mut child = std::env::current_exe()
+            .and_then(|executable| {
+                Command::new(executable)
+                    .args(args.iter().skip(1))
+                    .env(DAEMONIZED_ENV, "1")
+                    .env(READY_PID_ENV, std::process::id().to_string())
+                    .stdin(Stdio::null())
+                    .stdout(stdout)
+                    .stderr(stderr)
+                    .spawn()
+            })
+            .map_err(DaemonError::Spawn)?;
+        let pid = child.id().unwrap_or_default();
+        tokio::select! {
+            _ = ready.recv() => Ok(pid),
+            status = child.wait() => Err(match status {
+                Ok(status) => DaemonError::Exited(status),
+                Err(e) => DaemonError::Spawn(e),
+            }),
+        }
+    })
+}
+
+/// Tells the process that started this one with [`spawn`] that the server
+/// listens, for it to exit. Does nothing if no process waits for this one.
+///
+/// # Errors
+///
+/// Returns an error if the PID in [`READY_PID_ENV`] is invalid or the process can't be signaled.
+pub fn notify_ready() -> Result<(), DaemonError> {
+    let Ok(pid) = std::env::var(READY_PID_ENV) else {
+        return Ok(());
+    };
+    // Parsed unsigned, negative PIDs signal whole process groups
+    let raw = pid
+        .parse::<u32>()
+        .ok()
+        .and_then(|waiting| Pid::from_raw(i32::try_from(waiting).ok()?))
+        .ok_or_else(|| DaemonError::InvalidPid(pid.clone()))?;
+    kill_process(raw, Signal::USR1).map_err(|e| DaemonError::Notify(pid, e.into()))
+}
+
+/// Detaches the background server from the terminal it was started from, in
+/// a session of its own, so that closing the terminal doesn't stop it.
+///
+/// # Errors
+///
+/// Returns an error if the session can't be created.
+pub fn detach() -> Result<(), DaemonError> {
+    setsid().map_err(|e| DaemonError::Detach(e.into()))?;
+    Ok(())
+}
+
+/// Opens the file standard output or error goes to, appending to it.
+fn redirect(path: Option<&str>) -> Result<Stdio, DaemonError> {
+    let Some(path) = path else {
+        return Ok(Stdio::null());
+    };
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(Stdio::from)
+        .map_err(|e| DaemonError::Redirect(PathBuf::from(path), e))
+}
+
+/// Reads the PID in the PID file at `path`, if the process it names still runs.
+fn running(path: impl AsRef<Path>) -> Option<u32> {
+    let pid = fs::read_to_string(path).ok()?.trim().parse::<u32>().ok()?;
+    let raw = Pid::from_raw(i32::try_from(pid).ok()?)?;
+    test_kill_process(raw).ok().map(|()| pid)
+}
+
+/// A PID file naming this process, removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    pid: u32,
+}
+impl PidFile {
+    /// Writes the PID of this process to `path`.
+    ///
+    /// A file left by a server that no longer runs is replaced, as is the one
+    /// of the server this one replaces on a graceful restart.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if another running server wrote the file, or it can't be written.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self, DaemonError> {
+        let path = path.into();
+        let pid = std::process::id();
+        if let Some(running) = running(&path) {
+            let replaced = std::env::var(crate::restart::REPLACES_PID_ENV)
+                .ok()
+                .and_then(|replaced| replaced.parse().ok());
+            if running != pid && replaced != Some(running) {
+                return Err(DaemonError::AlreadyRunning(running));
+            }
+        }
+        fs::write(&path, format!("{pid}\n")).map_err(|e| DaemonError::PidFile(path.clone(), e))?;
+        Ok(Self { path, pid })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // A server replacing this one may have written its own PID already
+        let ours = fs::read_to_string(&self.path)
+            .is_ok_and(|content| content.trim() == self.pid.to_string());
+        if ours {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("Server already running with PID {0}")]
+    AlreadyRunning(u32),
+    #[error("Can't open {0:?} for the server's output: {1}")]
+    Redirect(PathBuf, io::Error),
+    #[error("Can't start the server in the background: {0}")]
+    Spawn(io::Error),
+    #[error("Server exited before listening: {0}")]
+    Exited(ExitStatus),
+    #[error("'{0}' is not the PID of a process waiting for the server")]
+    InvalidPid(String),
+    #[error("Can't tell process {0} the server listens: {1}")]
+    Notify(String, io::Error),
+    #[error("Can't detach from the terminal: {0}")]
+    Detach(io::Error),
+    #[error("Can't write PID file {0:?}: {1}")]
+    PidFile(PathBuf, io::Error),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn pid_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("http-server-{name}.pid"));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_pid_file_removed_on_drop() {
+        let path = pid_path("removed");
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(pid_file.path()).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        assert_eq!(running(&path), Some(std::process::id()));
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_pid_file_of_running_server() {
+        let path = pid_path("running");
+        let mut other = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        fs::write(&path, other.id().to_string()).unwrap();
+        assert!(matches!(
+            PidFile::create(&path),
+            Err(DaemonError::AlreadyRunning(pid)) if pid == other.id()
+        ));
+        other.kill().unwrap();
+        other.wait().unwrap();
+        // Left by a server that no longer runs
+        assert!(PidFile::create(&path).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_notify_ready() {
+        let mut ready = signal(SignalKind::user_defined1()).unwrap();
+        std::env::set_var(READY_PID_ENV, std::process::id().to_string());
+        let notified = notify_ready();
+        std::env::set_var(READY_PID_ENV, "-1");
+        let invalid = notify_ready();
+        std::env::remove_var(READY_PID_ENV);
+        assert!(notified.is_ok());
+        ready.recv().await.unwrap();
+        assert!(matches!(invalid, Err(DaemonError::InvalidPid(pid)) if pid == "-1"));
+    }
+
+    #[test]
+    fn test_pid_file_kept_for_replacement() {
+        let path = pid_path("replaced");
+        let pid_file = PidFile::create(&path).unwrap();
+        fs::write(&path, "1\n").unwrap();
+        drop(pid_file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\n");
+    }
+}
@@ -0,0 +1,184 @@
+use std::{io, path::Path};
+
+use thiserror::Error;
+
+/// Checks that `directory` is a directory the server can list, and write files
+/// to when `writable` is set.
+///
+/// When `create` is set, a missing directory is created along with its parents
+/// instead of being an error.
+///
+/// # Errors
+///
+/// Returns an error of type `DirectoryError` if `directory` is missing and can't
+/// be created, isn't a directory, or can't be read or written to.
+pub async fn prepare(directory: &str, create: bool, writable: bool) -> Result<(), DirectoryError> {
+    match tokio::fs::metadata(directory).await {
+        Ok(metadata) if !metadata.is_dir() => {
+            return Err(DirectoryError::NotADirectory(directory.to_string()));
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound && create => {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .map_err(|e| DirectoryError::Uncreatable(directory.to_string(), e.to_string()))?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(DirectoryError::Missing(directory.to_string()));
+        }
+        Err(e) => {
+            return Err(DirectoryError::Unreadable(
+                directory.to_string(),
+                e.to_string(),
+            ))
+        }
+    }
+    let _ = tokio::fs::read_dir(directory)
+        .await
+        .map_err(|e| DirectoryError::Unreadable(directory.to_string(), e.to_string()))?;
+    if !writable {
+        return Ok(());
+    }
+    check_writable(Path::new(directory))
+        .await
+        .map_err(|e| DirectoryError::Unwritable(directory.to_string(), e.to_string()))
+}
+
+/// Creates and removes a file in `directory`, permission bits alone don't tell
+/// whether the server may write there.
+async fn check_writable(directory: &Path) -> io::Result<()> {
+    let probe = directory.join(format!(".http-server-write-check-{}", std::process::id()));
+    tokio::fs::write(&probe, b"").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+/// What the files under a directory take up, and what is left on its filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Usage {
+    /// Bytes in the files, summed over subdirectories.
+    pub total_size: u64,
+    pub files: u64,
+    /// Bytes unprivileged processes may still write, `None` where it can't be known.
+    pub free_space: Option<u64>,
+}
+impl Usage {
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let free_space = self
+            .free_space
+            .map_or_else(|| "null".to_string(), |free| free.to_string());
+        format!(
+            "{{\"total_size\":{},\"files\":{},\"free_space\":{free_space}}}",
+            self.total_size, self.files
+        )
+    }
+}
+
+/// Walks `directory` off the async runtime to add up its files. Symbolic
+/// links are counted as links, not followed.
+///
+/// # Errors
+///
+/// Returns an error if `directory` or one of its subdirectories can't be read.
+pub async fn usage(directory: &Path) -> io::Result<Usage> {
+    let directory = directory.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut usage = Usage {
+            total_size: 0,
+            files: 0,
+            free_space: free_space(&directory),
+        };
+        let mut pending = vec![directory];
+        while let Some(directory) = pending.pop() {
+            for entry in std::fs::read_dir(&directory)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    pending.push(entry.path());
+                } else {
+                    usage.total_size += metadata.len();
+                    usage.files += 1;
+                }
+            }
+        }
+        Ok(usage)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+#[cfg(unix)]
+fn free_space(directory: &Path) -> Option<u64> {
+    let stats = rustix::fs::statvfs(directory).ok()?;
+    Some(stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+#[cfg(not(unix))]
+const fn free_space(_directory: &Path) -> Option<u64> {
+    None
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum DirectoryError {
+    #[error("Directory '{0}' doesn't exist, set 'create_directory = true' to create it")]
+    Missing(String),
+    #[error("'{0}' is not a directory")]
+    NotADirectory(String),
+    #[error("Can't create directory '{0}': {1}")]
+    Uncreatable(String, String),
+    #[error("Can't read directory '{0}': {1}")]
+    Unreadable(String, String),
+    #[error("Can't write to directory '{0}': {1}")]
+    Unwritable(String, String),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prepare_directory() {
+        let root = std::env::temp_dir().join("http-server-prepare-directory");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let missing = root.join("missing/nested");
+        let missing = missing.to_str().unwrap();
+
+        assert_eq!(
+            prepare(missing, false, true).await,
+            Err(DirectoryError::Missing(missing.to_string()))
+        );
+        assert_eq!(prepare(missing, true, true).await, Ok(()));
+        assert!(Path::new(missing).is_dir());
+        assert_eq!(std::fs::read_dir(missing).unwrap().count(), 0);
+
+        let file = root.join("file");
+        std::fs::write(&file, "not a directory").unwrap();
+        let file = file.to_str().unwrap();
+        assert_eq!(
+            prepare(file, true, false).await,
+            Err(DirectoryError::NotADirectory(file.to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_usage() {
+        let root = std::env::temp_dir().join("http-server-directory-usage");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("nested/deeper")).unwrap();
+        std::fs::write(root.join("a.txt"), "hello").unwrap();
+        std::fs::write(root.join("nested/b.txt"), "hi").unwrap();
+        std::fs::write(root.join("nested/deeper/c.txt"), "").unwrap();
+
+        let usage = usage(&root).await.unwrap();
+        assert_eq!((usage.total_size, usage.files), (7, 3));
+        #[cfg(unix)]
+        assert!(usage.free_space.is_some());
+        assert!(usage
+            .to_json()
+            .starts_with("{\"total_size\":7,\"files\":3,\"free_space\":"));
+
+        assert!(super::usage(&root.join("missing")).await.is_err());
+    }
+}
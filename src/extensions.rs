@@ -0,0 +1,174 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Mutex, MutexGuard},
+};
+
+/// Values of any type, at most one per type, that the checks a request goes
+/// through leave for the handlers after them, like the identity
+/// authentication found or the request ID.
+///
+/// Library users add their own types, a newtype being enough to tell two
+/// values of the same type apart.
+#[derive(Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+impl Extensions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value` in place of the value of the same type, if any.
+    ///
+    /// # Returns
+    ///
+    /// Returns the value replaced.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    #[must_use]
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())?
+            .downcast()
+            .ok()
+            .map(|value| *value)
+    }
+
+    #[must_use]
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Moves the values of `other` in, replacing those of the same types.
+    pub fn extend(&mut self, other: Self) {
+        self.values.extend(other.values);
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Extensions")
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+/// What the requests of a connection share, from the first to the last.
+///
+/// Its extensions hold e.g. a session a route opened on an earlier request of a
+/// kept-alive connection, and are dropped along with the connection.
+#[derive(Debug)]
+pub struct ConnectionState {
+    peer: SocketAddr,
+    tls: bool,
+    extensions: Mutex<Extensions>,
+}
+impl ConnectionState {
+    #[must_use]
+    pub fn new(peer: SocketAddr) -> Self {
+        Self {
+            peer,
+            tls: false,
+            extensions: Mutex::default(),
+        }
+    }
+
+    /// The same state, for a connection secured with TLS.
+    #[must_use]
+    pub fn with_tls(self) -> Self {
+        Self { tls: true, ..self }
+    }
+
+    #[must_use]
+    pub const fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// The scheme the requests of the connection were sent with, `https`
+    /// over TLS and `http` otherwise.
+    #[must_use]
+    pub const fn scheme(&self) -> &'static str {
+        if self.tls {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// The extensions of the connection, locked until the guard is dropped.
+    pub fn extensions(&self) -> MutexGuard<'_, Extensions> {
+        self.extensions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct User(&'static str);
+
+    #[test]
+    fn test_one_value_per_type() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.is_empty());
+        assert_eq!(extensions.insert(User("ann")), None);
+        assert_eq!(extensions.insert(7_u32), None);
+        assert_eq!(extensions.insert(User("bob")), Some(User("ann")));
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions.get::<User>(), Some(&User("bob")));
+        assert_eq!(extensions.get::<u64>(), None);
+        if let Some(count) = extensions.get_mut::<u32>() {
+            *count += 1;
+        }
+        assert_eq!(extensions.remove::<u32>(), Some(8));
+        assert!(!extensions.contains::<u32>());
+    }
+
+    #[test]
+    fn test_extend_replaces_same_types() {
+        let mut extensions = Extensions::new();
+        extensions.insert(User("ann"));
+        extensions.insert(1_u8);
+        let mut other = Extensions::new();
+        other.insert(User("bob"));
+        extensions.extend(other);
+        assert_eq!(extensions.get::<User>(), Some(&User("bob")));
+        assert_eq!(extensions.get::<u8>(), Some(&1));
+    }
+
+    #[test]
+    fn test_connection_state_is_shared() {
+        let state = ConnectionState::new("127.0.0.1:4000".parse().unwrap());
+        state.extensions().insert(User("ann"));
+        assert_eq!(state.extensions().get::<User>(), Some(&User("ann")));
+    }
+}
@@ -0,0 +1,151 @@
+use std::net::SocketAddr;
+
+use crate::{header_map::HeaderMap, http_request::HTTPRequest};
+
+/// What the server adds to the `Via` header of the messages it forwards.
+pub const VIA: &str = "1.1 http-server-rust";
+
+/// Headers describing a single connection, which a proxy must not forward
+/// (RFC 9110 section 7.6.1), along with the ones listed in `Connection`.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// The headers of `request` to send upstream, hop-by-hop ones and `strip`
+/// removed, with the server added to `Via` and the client to `Forwarded`,
+/// along with the scheme its connection uses.
+#[must_use]
+pub fn upstream_headers(request: &HTTPRequest, strip: &[String]) -> HeaderMap {
+    let mut headers = end_to_end(request.header().headers(), strip);
+    append(&mut headers, "Via", VIA);
+    let proto = request.connection().scheme();
+    append(
+        &mut headers,
+        "Forwarded",
+        &forwarded_element(request.peer(), proto),
+    );
+    headers
+}
+
+/// The headers of an upstream response to send back to the client, hop-by-hop
+/// ones and `strip` removed, with the server added to `Via`.
+#[must_use]
+pub fn downstream_headers(headers: &HeaderMap, strip: &[String]) -> HeaderMap {
+    let mut headers = end_to_end(headers, strip);
+    append(&mut headers, "Via", VIA);
+    headers
+}
+
+/// A `Forwarded` element for a request from `peer` (RFC 7239), like
+/// `for=192.0.2.60;proto=http` or `for="[2001:db8::1]";proto=https`.
+#[must_use]
+pub fn forwarded_element(peer: SocketAddr, proto: &str) -> String {
+    match peer {
+        SocketAddr::V4(peer) => format!("for={};proto={proto}", peer.ip()),
+        // IPv6 addresses hold colons, which a token can't
+        SocketAddr::V6(peer) => format!("for=\"[{}]\";proto={proto}", peer.ip()),
+    }
+}
+
+/// `headers` without the hop-by-hop ones, those named by their `Connection`
+/// header and `strip`, names compared case-insensitively.
+fn end_to_end(headers: &HeaderMap, strip: &[String]) -> HeaderMap {
+    let connection_options: Vec<&str> = headers
+        .get_all("Connection")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            !HOP_BY_HOP_HEADERS
+                .iter()
+                .copied()
+                .chain(connection_options.iter().copied())
+                .chain(strip.iter().map(String::as_str))
+                .any(|hop| hop.eq_ignore_ascii_case(name))
+        })
+        .collect()
+}
+
+/// Adds `value` to the list in the `name` header, creating it if missing.
+fn append(headers: &mut HeaderMap, name: &str, value: &str) {
+    let list = headers
+        .get_all(name)
+        .chain(std::iter::once(value))
+        .collect::<Vec<&str>>()
+        .join(", ");
+    headers.insert(name, &list);
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::extensions::ConnectionState;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_upstream_headers() {
+        let request = HTTPRequest::parse(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive, X-Secret\r\nX-Secret: 1\r\nTE: trailers\r\nVia: 1.0 edge\r\nX-Kept: yes\r\n\r\n",
+            Some(Bytes::new()),
+            "192.0.2.60:4000".parse().unwrap(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            upstream_headers(&request, &["host".to_string()]),
+            headers(&[
+                ("Via", "1.0 edge, 1.1 http-server-rust"),
+                ("X-Kept", "yes"),
+                ("Forwarded", "for=192.0.2.60;proto=http"),
+            ])
+        );
+        // Over HTTP/3, which always uses TLS
+        let peer = request.peer();
+        let request = request.with_connection(Arc::new(ConnectionState::new(peer).with_tls()));
+        assert_eq!(
+            upstream_headers(&request, &[]).get("Forwarded"),
+            Some("for=192.0.2.60;proto=https")
+        );
+    }
+
+    #[test]
+    fn test_downstream_headers() {
+        assert_eq!(
+            downstream_headers(
+                &headers(&[
+                    ("Content-Type", "text/plain"),
+                    ("transfer-encoding", "chunked"),
+                    ("Keep-Alive", "timeout=5"),
+                ]),
+                &[]
+            ),
+            headers(&[("Content-Type", "text/plain"), ("Via", VIA)])
+        );
+    }
+
+    #[test]
+    fn test_forwarded_element() {
+        assert_eq!(
+            forwarded_element("[2001:db8::1]:4000".parse().unwrap(), "https"),
+            "for=\"[2001:db8::1]\";proto=https"
+        );
+    }
+}
@@ -1,16 +1,91 @@
-use std::io::Write;
+use std::{
+    cell::RefCell,
+    io::{self, Read},
+};
 
-use flate2::{write::GzEncoder, Compression};
+use flate2::{read::GzDecoder, Compress, Compression, Crc, FlushCompress, Status};
+
+/// Gzip header of the members written: no file name, time or flags, an
+/// unknown OS, as `GzEncoder` writes it at the default level.
+const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+
+/// Bytes of output buffer an encoder keeps between responses, larger ones
+/// being let go for a worker not to hold on to the largest body it compressed.
+const MAX_KEPT_OUTPUT: usize = 1 << 20;
+
+thread_local! {
+    /// Encoder of the worker thread, its state and output buffer reused
+    /// from one response to the next.
+    static ENCODER: RefCell<Encoder> = RefCell::new(Encoder::new());
+}
+
+struct Encoder {
+    compress: Compress,
+    output: Vec<u8>,
+}
+impl Encoder {
+    fn new() -> Self {
+        Self {
+            compress: Compress::new(Compression::default(), false),
+            output: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, s: &[u8]) -> Vec<u8> {
+        self.compress.reset();
+        self.output.clear();
+        self.output.extend_from_slice(&HEADER);
+        loop {
+            // Deflate rarely grows data by more than a few bytes per block
+            self.output.reserve(s.len() / 2 + 64);
+            let consumed = usize::try_from(self.compress.total_in()).unwrap_or(usize::MAX);
+            let status = self
+                .compress
+                .compress_vec(&s[consumed..], &mut self.output, FlushCompress::Finish)
+                .expect("Compressing into a Vec cannot fail");
+            if status == Status::StreamEnd {
+                break;
+            }
+        }
+        let mut crc = Crc::new();
+        crc.update(s);
+        self.output.extend_from_slice(&crc.sum().to_le_bytes());
+        self.output.extend_from_slice(&crc.amount().to_le_bytes());
+        let encoded = self.output.clone();
+        if self.output.capacity() > MAX_KEPT_OUTPUT {
+            self.output = Vec::new();
+        }
+        encoded
+    }
+}
 
 pub struct Gzip(Vec<u8>);
 impl Gzip {
-    pub fn parse(s: &str) -> Self {
-        let mut e = GzEncoder::new(Vec::new(), Compression::default());
-        e.write_all(s.as_bytes()).unwrap();
-        let hex_str = e.finish().unwrap();
-        Self(hex_str)
+    pub fn parse(s: &[u8]) -> Self {
+        Self(ENCODER.with_borrow_mut(|encoder| encoder.encode(s)))
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+    pub fn decode(s: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        GzDecoder::new(s).read_to_end(&mut decoded)?;
+        Ok(decoded)
     }
-    pub fn as_bytes(&self) -> &[u8] {
-        &self.0
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder_reused() {
+        let large: Vec<u8> = (0..=u8::MAX).cycle().take(MAX_KEPT_OUTPUT * 3).collect();
+        for body in [&b"hello world"[..], &large, b"", b"hello again"] {
+            let encoded = Gzip::parse(body).into_bytes();
+            assert_eq!(Gzip::decode(&encoded).unwrap(), body);
+        }
+        ENCODER.with_borrow(|encoder| assert!(encoder.output.capacity() <= MAX_KEPT_OUTPUT));
     }
 }
@@ -0,0 +1,154 @@
+/// Header fields in the order they were added, looked up by case-insensitive
+/// name, as requests and responses carry them.
+///
+/// A name can have several fields: `append` adds one more, `insert` replaces
+/// them all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HeaderMap {
+    fields: Vec<(String, String)>,
+}
+impl HeaderMap {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { fields: Vec::new() }
+    }
+
+    /// Adds a field after the others, keeping those with the same name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Sets the value of `name`, in place of its first field if it has one and
+    /// after the others otherwise. Its other fields are removed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the value of the first field replaced, if any.
+    pub fn insert(&mut self, name: &str, value: &str) -> Option<String> {
+        let Some(first) = self.position(name) else {
+            self.append(name, value);
+            return None;
+        };
+        let previous = std::mem::replace(&mut self.fields[first].1, value.to_string());
+        let mut index = 0;
+        self.fields.retain(|(field, _)| {
+            index += 1;
+            index - 1 <= first || !field.eq_ignore_ascii_case(name)
+        });
+        Some(previous)
+    }
+
+    /// Removes every field named `name`.
+    ///
+    /// # Returns
+    ///
+    /// Returns their values, in order.
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.fields.retain_mut(|(field, value)| {
+            let matches = field.eq_ignore_ascii_case(name);
+            if matches {
+                removed.push(std::mem::take(value));
+            }
+            !matches
+        });
+        removed
+    }
+
+    /// The value of the first field named `name`.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.position(name)
+            .map(|position| self.fields[position].1.as_str())
+    }
+
+    /// The values of every field named `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    /// Whether `name` has more than one field, which headers that can't be
+    /// combined into a list, like `Host` or `Content-Length`, mustn't.
+    #[must_use]
+    pub fn is_duplicated(&self, name: &str) -> bool {
+        self.get_all(name).nth(1).is_some()
+    }
+
+    /// Every field, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Number of fields, those sharing a name counted each.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .position(|(field, _)| field.eq_ignore_ascii_case(name))
+    }
+}
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(fields: I) -> Self {
+        Self {
+            fields: fields
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_map_append_and_insert() {
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Accept");
+        headers.append("Content-Type", "text/plain");
+        headers.append("vary", "Accept-Language");
+        assert_eq!(headers.get("VARY"), Some("Accept"));
+        assert_eq!(
+            headers.get_all("Vary").collect::<Vec<_>>(),
+            ["Accept", "Accept-Language"]
+        );
+        assert!(headers.is_duplicated("Vary"));
+        assert!(!headers.is_duplicated("Content-Type"));
+
+        assert_eq!(headers.insert("Vary", "*"), Some("Accept".to_string()));
+        assert_eq!(headers.insert("Location", "/"), None);
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [
+                ("Vary", "*"),
+                ("Content-Type", "text/plain"),
+                ("Location", "/")
+            ]
+        );
+
+        assert_eq!(headers.remove("content-type"), ["text/plain"]);
+        assert!(!headers.contains("Content-Type"));
+        assert_eq!(headers.len(), 2);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::{http_request::HTTPRequest, http_response::HTTPResponse};
+
+type RequestHook = dyn Fn(&HTTPRequest) + Send + Sync;
+type ResponseHook = dyn Fn(&ResponseEvent) + Send + Sync;
+
+/// How long the server took over an exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timing {
+    /// Time spent reading the request, from its first byte.
+    pub read: Duration,
+    /// Time spent building the response.
+    pub handle: Duration,
+    /// Time from the first byte of the request to the last byte of the response.
+    pub total: Duration,
+}
+impl Timing {
+    /// Time spent sending the response.
+    #[must_use]
+    pub fn write(&self) -> Duration {
+        self.total.saturating_sub(self.read + self.handle)
+    }
+}
+
+/// A response the server finished sending, as response hooks see it.
+#[derive(Debug)]
+pub struct ResponseEvent<'a> {
+    pub peer: SocketAddr,
+    pub request_id: &'a str,
+    /// Method of the request line, empty when it couldn't be read.
+    pub method: &'a str,
+    /// Path of the request line without its query, empty when it couldn't be read.
+    pub path: &'a str,
+    pub response: &'a HTTPResponse,
+    pub timing: Timing,
+}
+
+/// Callbacks observing the requests served, for logging, auditing or metrics
+/// the built-in ones don't cover.
+///
+/// Request hooks see every request that could be parsed, before it is checked
+/// or answered. Response hooks see every response once sent, failed requests
+/// included. Both run on the connection's task, so they shouldn't block.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    on_request: Vec<Arc<RequestHook>>,
+    on_response: Vec<Arc<ResponseHook>>,
+}
+impl Hooks {
+    /// Calls `hook` with every request parsed, after the hooks added before.
+    pub fn on_request(&mut self, hook: impl Fn(&HTTPRequest) + Send + Sync + 'static) {
+        self.on_request.push(Arc::new(hook));
+    }
+
+    /// Calls `hook` with every response sent, after the hooks added before.
+    pub fn on_response(&mut self, hook: impl Fn(&ResponseEvent) + Send + Sync + 'static) {
+        self.on_response.push(Arc::new(hook));
+    }
+
+    pub(crate) fn request(&self, request: &HTTPRequest) {
+        for hook in &self.on_request {
+            hook(request);
+        }
+    }
+
+    pub(crate) fn response(&self, event: &ResponseEvent) {
+        for hook in &self.on_response {
+            hook(event);
+        }
+    }
+}
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_request", &self.on_request.len())
+            .field("on_response", &self.on_response.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::http_response::ResponseStatus;
+
+    #[test]
+    fn test_hooks_run_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut hooks = Hooks::default();
+        for name in ["first", "second"] {
+            let calls = Arc::clone(&calls);
+            hooks.on_request(move |request| {
+                calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("{name} {}", request.line().uri()));
+            });
+        }
+        let calls_on_response = Arc::clone(&calls);
+        hooks.on_response(move |event| {
+            calls_on_response.lock().unwrap().push(format!(
+                "{} {} {}",
+                event.method,
+                event.response.status().code(),
+                event.timing.write().as_millis()
+            ));
+        });
+        let peer = "127.0.0.1:4000".parse().unwrap();
+        let request = HTTPRequest::parse(
+            "GET /a HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            Some(Bytes::new()),
+            peer,
+            false,
+        )
+        .unwrap();
+        hooks.request(&request);
+        hooks.response(&ResponseEvent {
+            peer,
+            request_id: "id",
+            method: "GET",
+            path: "/a",
+            response: &HTTPResponse::new_builder(ResponseStatus::Http204).build(),
+            timing: Timing {
+                read: Duration::from_millis(1),
+                handle: Duration::from_millis(2),
+                total: Duration::from_millis(10),
+            },
+        });
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["first /a", "second /a", "GET 204 7"]
+        );
+    }
+}
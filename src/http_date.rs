@@ -0,0 +1,234 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+use tokio::sync::watch;
+
+pub(crate) const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// A date as HTTP headers carry it, like `Sun, 06 Nov 1994 08:49:37 GMT`
+/// (RFC 9110 section 5.6.7), to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HttpDate {
+    seconds: u64,
+}
+impl From<SystemTime> for HttpDate {
+    /// Truncates `time` to the second, times before the Unix epoch become the epoch.
+    fn from(time: SystemTime) -> Self {
+        Self {
+            seconds: time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        }
+    }
+}
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> Self {
+        UNIX_EPOCH + Duration::from_secs(date.seconds)
+    }
+}
+impl FromStr for HttpDate {
+    type Err = HttpDateError;
+    /// Parses the IMF-fixdate format, the only one servers send since HTTP/1.1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HttpDateError::InvalidDate(s.to_string());
+        let (weekday, rest) = s.trim().split_once(", ").ok_or_else(invalid)?;
+        let parts: Vec<&str> = rest.split(' ').collect();
+        let [day, month, year, time, "GMT"] = parts[..] else {
+            return Err(invalid());
+        };
+        let number = |digits: &str, len: usize| {
+            (digits.len() == len && digits.chars().all(|c| c.is_ascii_digit()))
+                .then(|| digits.parse::<u64>().ok())
+                .flatten()
+                .ok_or_else(invalid)
+        };
+        let day = number(day, 2)?;
+        let month = MONTHS
+            .iter()
+            .position(|name| *name == month)
+            .ok_or_else(invalid)?;
+        let year = number(year, 4)?;
+        let time: Vec<&str> = time.split(':').collect();
+        let [hours, minutes, seconds] = time[..] else {
+            return Err(invalid());
+        };
+        let (hours, minutes, seconds) =
+            (number(hours, 2)?, number(minutes, 2)?, number(seconds, 2)?);
+        let month = u64::try_from(month).map_err(|_| invalid())? + 1;
+        if year < 1970 || day == 0 || hours > 23 || minutes > 59 || seconds > 60 {
+            return Err(invalid());
+        }
+        let days = days_from_civil(year, month, day);
+        if civil_from_days(days) != (year, month, day) {
+            return Err(invalid());
+        }
+        let date = Self {
+            seconds: days * 86_400 + hours * 3600 + minutes * 60 + seconds,
+        };
+        if date.weekday() != weekday {
+            return Err(invalid());
+        }
+        Ok(date)
+    }
+}
+impl HttpDate {
+    fn weekday(self) -> &'static str {
+        let day = usize::try_from(self.seconds / 86_400 % 7).unwrap_or_default();
+        WEEKDAYS[day]
+    }
+}
+impl Display for HttpDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days(self.seconds / 86_400);
+        let month = usize::try_from(month - 1).map_or("Jan", |month| MONTHS[month]);
+        write!(
+            f,
+            "{}, {day:02} {month} {year} {:02}:{:02}:{:02} GMT",
+            self.weekday(),
+            self.seconds % 86_400 / 3600,
+            self.seconds % 3600 / 60,
+            self.seconds % 60
+        )
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum HttpDateError {
+    #[error(
+        "'{0}' is not a valid HTTP date, expected a date like 'Sun, 06 Nov 1994 08:49:37 GMT'"
+    )]
+    InvalidDate(String),
+}
+
+/// The `Date` header every response carries (RFC 9110 section 6.6.1), formatted
+/// once per second by a background task rather than for each response.
+///
+/// The task stops once the header and all its clones are dropped.
+#[derive(Debug, Clone)]
+pub struct DateHeader {
+    current: watch::Receiver<String>,
+}
+impl DateHeader {
+    /// Starts formatting the date at the start of every second.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[must_use]
+    pub fn spawn() -> Self {
+        let now = || HttpDate::from(SystemTime::now()).to_string();
+        let (sender, current) = watch::channel(now());
+        tokio::spawn(async move {
+            loop {
+                let since_epoch = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let next_second = Duration::from_secs(1)
+                    .saturating_sub(Duration::from_nanos(since_epoch.subsec_nanos().into()));
+                tokio::select! {
+                    () = tokio::time::sleep(next_second) => {}
+                    () = sender.closed() => break,
+                }
+                sender.send_replace(now());
+            }
+        });
+        Self { current }
+    }
+
+    /// The date of the current second, like `Sun, 06 Nov 1994 08:49:37 GMT`.
+    #[must_use]
+    pub fn current(&self) -> String {
+        self.current.borrow().clone()
+    }
+}
+
+/// Converts days since the Unix epoch into a `(year, month, day)` date.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+pub(crate) const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a `(year, month, day)` date from 1970 on into days since the Unix epoch.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+const fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_date_header() {
+        let date = DateHeader::spawn();
+        let current = SystemTime::from(date.current().parse::<HttpDate>().unwrap());
+        let now = SystemTime::now();
+        assert!(now.duration_since(current).unwrap() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 2, 29), 11_016);
+        assert_eq!(days_from_civil(2024, 1, 1), 19_723);
+    }
+
+    #[test]
+    fn test_http_date_display() {
+        let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(784_111_777));
+        assert_eq!(date.to_string(), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn test_http_date_from_str() {
+        let date: HttpDate = "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap();
+        assert_eq!(
+            SystemTime::from(date),
+            UNIX_EPOCH + Duration::from_secs(784_111_777)
+        );
+        for invalid in [
+            "Mon, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Thu, 30 Feb 2023 08:49:37 GMT",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+        ] {
+            assert!(invalid.parse::<HttpDate>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,9 +1,187 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    fmt::Display,
+    net::{Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
 
+use bytes::Bytes;
 use thiserror::Error;
 
+pub use crate::user_agent::UserAgent;
+use crate::{
+    auth::Authorization,
+    extensions::{ConnectionState, Extensions},
+    header_map::HeaderMap,
+    typed_headers::{Accept, CacheControl, EntityTags, MediaType, Range},
+    uri::Uri,
+};
+
+/// A request as handlers see it: its line, headers and body, parsed once from
+/// what was read on a connection, the address it came from, and the
+/// extensions of the request and of its connection.
+#[derive(Debug)]
+pub struct HTTPRequest {
+    line: RequestLine,
+    header: RequestHeader,
+    body: Option<RequestBody>,
+    peer: SocketAddr,
+    extensions: Extensions,
+    connection: Arc<ConnectionState>,
+}
+impl HTTPRequest {
+    /// Parses a request from its `head`, the request line and headers, and its
+    /// `body` as received, `None` if the head wasn't complete.
+    /// The request line is parsed with `RequestLine::parse_lenient` when
+    /// `lenient` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request has no request line, or if its request
+    /// line or headers are invalid.
+    pub fn parse(
+        head: &str,
+        body: Option<Bytes>,
+        peer: SocketAddr,
+        lenient: bool,
+    ) -> Result<Self, HTTPRequestError> {
+        let Some(line) = head.lines().next() else {
+            return Err(HTTPRequestError::MissingRequestLine);
+        };
+        let line = if lenient {
+            RequestLine::parse_lenient(line)?
+        } else {
+            line.parse()?
+        };
+        Ok(Self {
+            line,
+            header: head.parse()?,
+            body: body.map(RequestBody),
+            peer,
+            extensions: Extensions::new(),
+            connection: Arc::new(ConnectionState::new(peer)),
+        })
+    }
+
+    #[must_use]
+    pub const fn line(&self) -> &RequestLine {
+        &self.line
+    }
+
+    /// The same request, to be handled as if sent with `method`.
+    #[must_use]
+    pub fn with_method(self, method: RequestMethod) -> Self {
+        Self {
+            line: RequestLine {
+                method,
+                ..self.line
+            },
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub const fn header(&self) -> &RequestHeader {
+        &self.header
+    }
+
+    /// The body, empty when the request has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client closed the connection before the end of the request head.
+    pub fn body(&self) -> Result<&RequestBody, RequestBodyError> {
+        self.body.as_ref().ok_or(RequestBodyError::MissingBody)
+    }
+
+    #[must_use]
+    pub const fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// What the checks the request went through left for its handler, like its
+    /// `request_id::RequestId` and, once authenticated, its `auth::Identity`.
+    #[must_use]
+    pub const fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub const fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// A copy of the request without its extensions, which can't be copied,
+    /// for work carried on once it is answered, like refreshing a cached response.
+    #[must_use]
+    pub fn without_extensions(&self) -> Self {
+        Self {
+            line: self.line.clone(),
+            header: self.header.clone(),
+            body: self.body.clone(),
+            peer: self.peer,
+            extensions: Extensions::new(),
+            connection: Arc::clone(&self.connection),
+        }
+    }
+
+    /// The same request, with `extensions` added to its own.
+    #[must_use]
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions.extend(extensions);
+        self
+    }
+
+    /// The state shared by the requests of the connection this one came on.
+    #[must_use]
+    pub fn connection(&self) -> &ConnectionState {
+        &self.connection
+    }
+
+    /// The same request, as one of the requests of `connection`.
+    #[must_use]
+    pub fn with_connection(self, connection: Arc<ConnectionState>) -> Self {
+        Self { connection, ..self }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum HTTPRequestError {
+    #[error("Request has no request line")]
+    MissingRequestLine,
+    #[error("{0}")]
+    RequestLine(#[from] HTTPRequestLineError),
+    #[error("{0}")]
+    RequestHeader(#[from] RequestHeaderError),
+}
+
+/// The body of a request as received, transcoded only when read as text, see
+/// [`RequestBody::decode`]. Clones share the same bytes.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct RequestBody(String);
+pub struct RequestBody(Bytes);
+impl RequestBody {
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+    /// The bytes of the body, shared rather than copied.
+    #[must_use]
+    pub fn bytes(&self) -> Bytes {
+        self.0.clone()
+    }
+    /// The body, read as `charset`, transcoded to UTF-8. A UTF-8 body is
+    /// shared rather than copied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the body isn't valid in `charset`.
+    pub fn decode(&self, charset: Charset) -> Result<Bytes, CharsetError> {
+        match charset {
+            Charset::Utf8 => std::str::from_utf8(&self.0)
+                .map(|_| self.bytes())
+                .map_err(|_| CharsetError::InvalidBody(charset)),
+            charset => charset.decode(&self.0).map(Bytes::from),
+        }
+    }
+}
 impl FromStr for RequestBody {
     type Err = RequestBodyError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -11,12 +189,12 @@ impl FromStr for RequestBody {
             .split("\r\n\r\n")
             .nth(1)
             .ok_or(RequestBodyError::MissingBody)?;
-        Ok(Self(body_content.to_string()))
+        Ok(Self(Bytes::copy_from_slice(body_content.as_bytes())))
     }
 }
 impl Display for RequestBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
     }
 }
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
@@ -29,38 +207,173 @@ pub struct RequestHeader {
     host: Option<Host>,
     user_agent: Option<UserAgent>,
     accept_encoding: Vec<Encoding>,
+    te: Te,
+    headers: HeaderMap,
 }
 impl RequestHeader {
-    pub const fn _host(&self) -> Option<&Host> {
+    #[must_use]
+    pub const fn host(&self) -> Option<&Host> {
         self.host.as_ref()
     }
+    #[must_use]
     pub const fn user_agent(&self) -> Option<&UserAgent> {
         self.user_agent.as_ref()
     }
+    #[must_use]
     pub const fn _empty() -> Self {
         Self {
             host: None,
             user_agent: None,
             accept_encoding: vec![],
+            te: Te {
+                trailers: false,
+                codings: vec![],
+            },
+            headers: HeaderMap::new(),
         }
     }
 
+    /// The codings the body of the response may be sent with, most preferred
+    /// first, from the `Accept-Encoding` headers. Empty without them, as
+    /// bodies are only compressed for clients asking for it.
+    #[must_use]
     pub fn accept_encoding(&self) -> &[Encoding] {
         &self.accept_encoding
     }
+
+    /// What the client accepts in the response framing, from its `TE` headers.
+    #[must_use]
+    pub const fn te(&self) -> &Te {
+        &self.te
+    }
+
+    /// The charset the body is declared in by the `charset` parameter of its
+    /// `Content-Type`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the charset isn't supported.
+    pub fn charset(&self) -> Result<Option<Charset>, CharsetError> {
+        self.content_type()
+            .as_ref()
+            .and_then(|content_type| content_type.parameter("charset"))
+            .map(str::parse)
+            .transpose()
+    }
+
+    /// The languages the client prefers, from `Accept-Language`.
+    #[must_use]
+    pub fn accept_language(&self) -> AcceptLanguage {
+        self.get("Accept-Language")
+            .map(|accept_language| accept_language.parse().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// All the headers in the order they were received.
+    #[must_use]
+    pub const fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Whether the client asks for the connection to be closed after the
+    /// response, with the `close` option in any of its `Connection` headers.
+    #[must_use]
+    pub fn closes_connection(&self) -> bool {
+        self.connection().iter().any(|option| option == "close")
+    }
+
+    /// The options of all the `Connection` headers, lowercased, like `close`
+    /// or the names of headers meant for this connection only.
+    #[must_use]
+    pub fn connection(&self) -> Vec<String> {
+        self.headers
+            .get_all("Connection")
+            .flat_map(|value| value.split(','))
+            .map(|option| option.trim().to_ascii_lowercase())
+            .filter(|option| !option.is_empty())
+            .collect()
+    }
+
+    /// The credentials of `Authorization`, `None` if it is missing or invalid.
+    #[must_use]
+    pub fn authorization(&self) -> Option<Authorization> {
+        self.get("Authorization")?.parse().ok()
+    }
+
+    /// The page the request was made from, from `Referer`, as sent.
+    #[must_use]
+    pub fn referer(&self) -> Option<&str> {
+        self.get("Referer").filter(|referer| !referer.is_empty())
+    }
+
+    /// The length of the body announced by `Content-Length`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it isn't a number, or the header is repeated with
+    /// different values.
+    pub fn content_length(&self) -> Result<Option<u64>, RequestHeaderError> {
+        content_length(&self.headers.get_all("Content-Length").collect::<Vec<_>>())
+    }
+
+    /// The media type of the body with its parameters, from `Content-Type`,
+    /// `None` if it is missing or invalid. See `charset` for its `charset`
+    /// parameter.
+    #[must_use]
+    pub fn content_type(&self) -> Option<MediaType> {
+        self.get("Content-Type")?.parse().ok()
+    }
+
+    /// The media types the client accepts, from `Accept`.
+    #[must_use]
+    pub fn accept(&self) -> Accept {
+        self.get("Accept")
+            .map(|accept| accept.parse().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// The directives of `Cache-Control`, none without the header.
+    #[must_use]
+    pub fn cache_control(&self) -> CacheControl {
+        self.get("Cache-Control")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// The byte ranges asked for by `Range`, `None` without a valid one, as
+    /// an invalid `Range` is ignored (RFC 9110 section 14.2).
+    #[must_use]
+    pub fn range(&self) -> Option<Range> {
+        self.get("Range")?.parse().ok()
+    }
+
+    /// The tags of `If-Match`. An invalid value is read as an empty list,
+    /// which matches nothing.
+    #[must_use]
+    pub fn if_match(&self) -> Option<EntityTags> {
+        self.entity_tags("If-Match")
+    }
+
+    /// The tags of `If-None-Match`, read like `if_match`.
+    #[must_use]
+    pub fn if_none_match(&self) -> Option<EntityTags> {
+        self.entity_tags("If-None-Match")
+    }
+
+    fn entity_tags(&self, name: &str) -> Option<EntityTags> {
+        let tags = self.get(name)?;
+        Some(tags.parse().unwrap_or(EntityTags::List(vec![])))
+    }
+
+    /// Returns the value of the first header called `name`, compared case-insensitively.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
 }
 impl FromStr for RequestHeader {
     type Err = RequestHeaderError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let host = s
-            .lines()
-            .find(|l| l.starts_with("Host: "))
-            .map(|l| Host(l["Host: ".len()..].to_string()));
-        let host = match host {
-            Some(host) if host.0.is_empty() => return Err(RequestHeaderError::InvalidHost),
-            Some(host) => Some(host),
-            None => None,
-        };
         let user_agent = s
             .lines()
             .find(|l| l.starts_with("User-Agent: "))
@@ -72,93 +385,665 @@ impl FromStr for RequestHeader {
             Some(user_agent) => Some(user_agent),
             None => None,
         };
-        let accept_encoding = s
+        let headers: HeaderMap = s
             .lines()
-            .find(|l| l.starts_with("Accept-Encoding: "))
-            .map(|l| l["Accept-Encoding: ".len()..].to_string());
-        let accept_encoding = accept_encoding.map_or_else(Vec::new, |encoding| {
-            encoding
-                .split(',')
-                .filter_map(|s| s.trim().parse().ok())
-                .collect::<Vec<Encoding>>()
-        });
+            .skip(1)
+            .take_while(|l| !l.is_empty())
+            .filter_map(|l| l.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        let accept_encoding = if headers.contains("Accept-Encoding") {
+            negotiate_encodings(
+                &headers
+                    .get_all("Accept-Encoding")
+                    .collect::<Vec<&str>>()
+                    .join(","),
+            )
+        } else {
+            vec![]
+        };
+        let te: Te = headers
+            .get_all("TE")
+            .collect::<Vec<&str>>()
+            .join(",")
+            .parse()?;
+        // Responses are only ever framed with chunked, which needn't be asked for
+        if let Some(coding) = te.codings().first() {
+            return Err(RequestHeaderError::UnsupportedTransferCoding(
+                coding.clone(),
+            ));
+        }
+        // RFC 9112 section 3.2: HTTP/1.1 requests carry exactly one Host
+        let host = match headers.get("Host") {
+            Some(_) if headers.is_duplicated("Host") => {
+                return Err(RequestHeaderError::DuplicateHost)
+            }
+            Some("") => return Err(RequestHeaderError::InvalidHost),
+            Some(host) => Some(host.parse()?),
+            None if s
+                .lines()
+                .next()
+                .is_some_and(|l| l.trim_end().ends_with("HTTP/1.1")) =>
+            {
+                return Err(RequestHeaderError::MissingHost)
+            }
+            None => None,
+        };
         Ok(Self {
             host,
             user_agent,
             accept_encoding,
+            te,
+            headers,
         })
     }
 }
 
+/// What a client accepts in the framing of responses, from its `TE` header
+/// (RFC 9110 section 10.1.4), like `trailers, deflate;q=0.5`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Te {
+    trailers: bool,
+    codings: Vec<String>,
+}
+impl Te {
+    /// Whether the client takes trailer fields after a chunked body.
+    #[must_use]
+    pub const fn trailers(&self) -> bool {
+        self.trailers
+    }
+
+    /// The transfer codings accepted besides `chunked`, lowercased, the ones
+    /// refused with `q=0` left out.
+    #[must_use]
+    pub fn codings(&self) -> &[String] {
+        &self.codings
+    }
+}
+impl FromStr for Te {
+    type Err = RequestHeaderError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RequestHeaderError::InvalidTe(s.to_string());
+        let mut te = Self {
+            trailers: false,
+            codings: vec![],
+        };
+        for element in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut params = element.split(';').map(str::trim);
+            let coding = params.next().unwrap_or_default().to_ascii_lowercase();
+            if coding.is_empty() || !coding.bytes().all(is_token_byte) {
+                return Err(invalid());
+            }
+            if coding == "trailers" {
+                te.trailers = true;
+                continue;
+            }
+            let mut accepted = true;
+            for param in params {
+                let (name, value) = param.split_once('=').ok_or_else(invalid)?;
+                if name.trim().eq_ignore_ascii_case("q") {
+                    accepted = is_positive_weight(value.trim()).ok_or_else(invalid)?;
+                }
+            }
+            if accepted && coding != "chunked" {
+                te.codings.push(coding);
+            }
+        }
+        Ok(te)
+    }
+}
+
+/// Whether a weight like `0.5` is above zero, `None` if it isn't one (RFC 9110 section 12.4.2).
+fn is_positive_weight(weight: &str) -> Option<bool> {
+    parse_quality(weight).map(|weight| weight > 0)
+}
+
+/// The codings of `Encoding` acceptable to a client sending `accept_encoding`,
+/// like `gzip;q=0.8, identity, *;q=0`, most preferred first.
+///
+/// Following RFC 9110 section 12.5.3, `*` stands for the codings not listed
+/// and `q=0` refuses a coding. `identity` is acceptable unless refused that
+/// way, but only preferred to a coding when weighted above it. Ties go to
+/// compressing. Elements that can't be read are ignored, and when nothing is
+/// acceptable the body is sent as is rather than refused.
+fn negotiate_encodings(accept_encoding: &str) -> Vec<Encoding> {
+    let mut weights: Vec<(String, u16)> = vec![];
+    for element in accept_encoding.split(',').map(str::trim) {
+        let mut params = element.split(';').map(str::trim);
+        let coding = params.next().unwrap_or_default().to_ascii_lowercase();
+        let weight = params.try_fold(1000, |weight, param| match param.split_once('=') {
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("q") => {
+                parse_quality(value.trim())
+            }
+            Some(_) => Some(weight),
+            None => None,
+        });
+        if let Some(weight) = weight.filter(|_| !coding.is_empty()) {
+            weights.push((coding, weight));
+        }
+    }
+    let weight_of = |names: &[&str]| {
+        weights
+            .iter()
+            .find(|(coding, _)| names.contains(&coding.as_str()))
+            .map(|(_, weight)| *weight)
+    };
+    let any = weight_of(&["*"]);
+    let mut encodings: Vec<(Encoding, u16)> = [
+        (Encoding::Gzip, weight_of(&["gzip", "x-gzip"]).or(any)),
+        (
+            Encoding::Identity,
+            weight_of(&["identity"]).or(any).or(Some(1)),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(encoding, weight)| weight.filter(|weight| *weight > 0).map(|w| (encoding, w)))
+    .collect();
+    // Stable, so ties keep gzip first
+    encodings.sort_by(|(_, a), (_, b)| b.cmp(a));
+    encodings
+        .into_iter()
+        .map(|(encoding, _)| encoding)
+        .collect()
+}
+
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
 pub enum Encoding {
     Gzip,
+    /// No coding, the body sent as is.
+    Identity,
 }
 impl FromStr for Encoding {
     type Err = RequestHeaderError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.to_lowercase() == "gzip" {
-            return Ok(Self::Gzip);
+        match s.to_lowercase().as_str() {
+            // `x-gzip` is the name older clients use (RFC 9110 section 8.4.1.3)
+            "gzip" | "x-gzip" => Ok(Self::Gzip),
+            "identity" => Ok(Self::Identity),
+            _ => Err(RequestHeaderError::InvalidEncoding(s.to_string())),
+        }
+    }
+}
+impl Encoding {
+    /// The coding as named in headers, like `gzip`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Identity => "identity",
         }
-        Err(RequestHeaderError::InvalidEncoding(s.to_string()))
     }
 }
 impl Display for Encoding {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Languages a client accepts, from an `Accept-Language` header like `fr-CH, fr;q=0.9, *;q=0.5`.
+///
+/// Without ranges, as when the header is missing, any language is acceptable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AcceptLanguage {
+    ranges: Vec<LanguageRange>,
+}
+impl AcceptLanguage {
+    /// Picks the language of `available` the client prefers, following the
+    /// basic filtering of RFC 4647: a range matches a tag equal to it or
+    /// starting with it and `-`, `*` matches any tag.
+    ///
+    /// # Returns
+    ///
+    /// Returns the first of `available` on ties or when any language is acceptable,
+    /// `None` if the client accepts none of them.
+    #[must_use]
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        if self.ranges.is_empty() {
+            return available.first().copied();
+        }
+        let mut best: Option<(u16, &str)> = None;
+        for tag in available {
+            let quality = self.quality(tag);
+            if quality > 0 && best.is_none_or(|(best_quality, _)| quality > best_quality) {
+                best = Some((quality, tag));
+            }
+        }
+        best.map(|(_, tag)| tag)
+    }
+
+    /// Quality of `tag` in thousandths, given by the most specific range that matches it.
+    fn quality(&self, tag: &str) -> u16 {
+        let tag = tag.to_lowercase();
+        self.ranges
+            .iter()
+            .filter(|range| range.matches(&tag))
+            .max_by_key(|range| (range.tag != "*", range.tag.len()))
+            .map_or(0, |range| range.quality)
+    }
+}
+impl FromStr for AcceptLanguage {
+    type Err = std::convert::Infallible;
+    /// Parses the comma-separated ranges, skipping the invalid ones.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            ranges: s
+                .split(',')
+                .filter(|range| !range.trim().is_empty())
+                .filter_map(|range| range.parse().ok())
+                .collect(),
+        })
+    }
+}
+
+/// A language range of `Accept-Language` with its quality in thousandths.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LanguageRange {
+    tag: String,
+    quality: u16,
+}
+impl LanguageRange {
+    fn matches(&self, tag: &str) -> bool {
+        self.tag == "*"
+            || tag == self.tag
+            || tag
+                .strip_prefix(&self.tag)
+                .is_some_and(|rest| rest.starts_with('-'))
+    }
+}
+impl FromStr for LanguageRange {
+    type Err = LanguageRangeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let tag = parts.next().unwrap_or_default().trim().to_lowercase();
+        let is_valid_tag = tag == "*"
+            || (!tag.is_empty()
+                && tag.split('-').all(|subtag| {
+                    (1..=8).contains(&subtag.len())
+                        && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+                }));
+        if !is_valid_tag {
+            return Err(LanguageRangeError::InvalidTag(tag));
+        }
+        let mut quality = 1000;
+        for parameter in parts {
+            let Some((name, value)) = parameter.split_once('=') else {
+                continue;
+            };
+            if name.trim().eq_ignore_ascii_case("q") {
+                quality = parse_quality(value.trim())
+                    .ok_or_else(|| LanguageRangeError::InvalidQuality(value.trim().to_string()))?;
+            }
+        }
+        Ok(Self { tag, quality })
+    }
+}
+impl Display for LanguageRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.tag)?;
+        if self.quality < 1000 {
+            write!(f, ";q={}", f64::from(self.quality) / 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a q-value (RFC 9110 section 12.4.2) like `0.8` to thousandths.
+pub(crate) fn parse_quality(value: &str) -> Option<u16> {
+    let (units, decimals) = value.split_once('.').unwrap_or((value, ""));
+    if decimals.len() > 3 || !decimals.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = format!("{decimals:0<3}").parse::<u16>().ok()?;
+    match units {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum LanguageRangeError {
+    #[error("'{0}' is not a valid language range")]
+    InvalidTag(String),
+    #[error("'{0}' is not a valid quality, expected a number between 0 and 1")]
+    InvalidQuality(String),
+}
+
+/// Character encoding of a text body, from the `charset` parameter of its `Content-Type`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
+pub enum Charset {
+    Utf8,
+    UsAscii,
+    /// Latin-1, each byte is the code point of the same value.
+    Iso8859_1,
+}
+impl Charset {
+    /// The charset as named in `Content-Type` parameters, like `utf-8`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
         match self {
-            Self::Gzip => write!(f, "gzip"),
+            Self::Utf8 => "utf-8",
+            Self::UsAscii => "us-ascii",
+            Self::Iso8859_1 => "iso-8859-1",
         }
     }
+
+    /// Transcodes `body`, read as this charset, to a UTF-8 string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `body` isn't valid in this charset.
+    pub fn decode(self, body: &[u8]) -> Result<String, CharsetError> {
+        let invalid = || CharsetError::InvalidBody(self);
+        match self {
+            Self::Utf8 => String::from_utf8(body.to_vec()).map_err(|_| invalid()),
+            Self::UsAscii if body.is_ascii() => Ok(String::from_utf8_lossy(body).to_string()),
+            Self::UsAscii => Err(invalid()),
+            Self::Iso8859_1 => Ok(body.iter().copied().map(char::from).collect()),
+        }
+    }
+}
+impl FromStr for Charset {
+    type Err = CharsetError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_matches('"').to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "us-ascii" | "ascii" => Ok(Self::UsAscii),
+            "iso-8859-1" | "latin1" => Ok(Self::Iso8859_1),
+            unsupported => Err(CharsetError::UnsupportedCharset(unsupported.to_string())),
+        }
+    }
+}
+impl Display for Charset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum CharsetError {
+    #[error("'{0}' is not a supported charset, expected 'utf-8', 'us-ascii' or 'iso-8859-1'")]
+    UnsupportedCharset(String),
+    #[error("Body is not valid {0}")]
+    InvalidBody(Charset),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum RequestHeaderError {
     #[error("'Host: ' is found in HTTP request but seems empty")]
     InvalidHost,
+    #[error("'Host: ' is required in HTTP/1.1 requests")]
+    MissingHost,
+    #[error("'Host: ' is found more than once in HTTP request")]
+    DuplicateHost,
+    #[error("Request target is for '{0}' but 'Host: ' is '{1}'")]
+    HostMismatch(String, String),
+    #[error("{0}")]
+    HostError(#[from] HostError),
     #[error("'User-Agent: ' is found in HTTP request but seems empty")]
     InvalidUserAgent,
     #[error("'{0}' is not a supported encoding")]
     InvalidEncoding(String),
+    #[error("'Content-Length: {0}' is not a single length in bytes")]
+    InvalidContentLength(String),
+    #[error("'TE: {0}' is not a list of transfer codings with optional weights")]
+    InvalidTe(String),
+    #[error("'TE: ' accepts the '{0}' transfer coding, which the server can't produce")]
+    UnsupportedTransferCoding(String),
 }
+/// The host a request is for, from its `Host` header or an absolute-form
+/// target, like `example.com:8080` (RFC 9110 section 7.2).
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Host(String);
+pub struct Host {
+    name: String,
+    port: Option<u16>,
+}
+impl Host {
+    /// The host name in a form fit for comparisons: lowercased, with
+    /// internationalized labels in their ASCII `xn--` form and IPv6 addresses
+    /// in brackets and written the shortest way, like `[::1]`.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The port, `None` when the host doesn't name one and the default of the
+    /// scheme applies.
+    #[must_use]
+    pub const fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+impl FromStr for Host {
+    type Err = HostError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HostError::InvalidHost(s.to_string());
+        let (name, port) = if let Some(literal) = s.strip_prefix('[') {
+            let (address, port) = literal.split_once(']').ok_or_else(invalid)?;
+            // Zone identifiers and future address formats have no use in a Host
+            let address: Ipv6Addr = address.parse().map_err(|_| invalid())?;
+            let port = match port {
+                "" => None,
+                port => Some(port.strip_prefix(':').ok_or_else(invalid)?),
+            };
+            (format!("[{address}]"), port)
+        } else {
+            let (name, port) = s
+                .rsplit_once(':')
+                .map_or((s, None), |(name, port)| (name, Some(port)));
+            if name.is_empty() || !name.chars().all(is_host_char) {
+                return Err(invalid());
+            }
+            (
+                idn_to_ascii(&name.to_lowercase()).ok_or_else(invalid)?,
+                port,
+            )
+        };
+        let port = match port {
+            // `example.com:` has an empty port, standing for the default one
+            None | Some("") => None,
+            Some(port) if port.bytes().all(|byte| byte.is_ascii_digit()) => Some(
+                port.parse()
+                    .map_err(|_| HostError::InvalidPort(s.to_string()))?,
+            ),
+            Some(_) => return Err(HostError::InvalidPort(s.to_string())),
+        };
+        Ok(Self { name, port })
+    }
+}
 impl Display for Host {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        match self.port {
+            Some(port) => write!(f, "{}:{port}", self.name),
+            None => f.write_str(&self.name),
+        }
     }
 }
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct UserAgent(String);
-impl Display for UserAgent {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum HostError {
+    #[error("'{0}' is not a valid host name or IP address")]
+    InvalidHost(String),
+    #[error("'{0}' has a port that isn't a number from 0 to 65535")]
+    InvalidPort(String),
+}
+
+/// Whether `c` may appear in a registered host name (RFC 3986 section 3.2.2),
+/// letters outside ASCII included for internationalized names.
+fn is_host_char(c: char) -> bool {
+    !c.is_ascii() || c.is_ascii_alphanumeric() || "-._~%!$&'()*+,;=".contains(c)
+}
+
+/// Converts the labels of `name` outside ASCII to their `xn--` form, so
+/// `bücher.example` is `xn--bcher-kva.example` (RFC 5891 section 4.4).
+/// Names are expected lowercased already, the other IDNA mappings aren't done.
+///
+/// # Returns
+///
+/// Returns `None` if a label is too long to be encoded.
+fn idn_to_ascii(name: &str) -> Option<String> {
+    if name.is_ascii() {
+        return Some(name.to_string());
+    }
+    let labels = name
+        .split('.')
+        .map(|label| {
+            if label.is_ascii() {
+                Some(label.to_string())
+            } else {
+                punycode(label).map(|encoded| format!("xn--{encoded}"))
+            }
+        })
+        .collect::<Option<Vec<String>>>()?;
+    Some(labels.join("."))
+}
+
+/// Encodes `label` with Punycode, as RFC 3492 section 6.3 describes.
+///
+/// # Returns
+///
+/// Returns `None` on overflow, for labels far longer than DNS allows.
+fn punycode(label: &str) -> Option<String> {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+    let adapt = |delta: u32, points: u32, first: bool| {
+        let mut delta = if first { delta / 700 } else { delta / 2 };
+        delta += delta / points;
+        let mut k = 0;
+        while delta > (BASE - T_MIN) * T_MAX / 2 {
+            delta /= BASE - T_MIN;
+            k += BASE;
+        }
+        k + (BASE - T_MIN + 1) * delta / (delta + 38)
+    };
+    // Digits 0 to 25 are `a` to `z`, 26 to 35 are `0` to `9`
+    let digit = |d: u32| char::from_digit((d + 10) % BASE, BASE).unwrap_or('a');
+    let code_points: Vec<u32> = label.chars().map(u32::from).collect();
+    let mut output: String = label.chars().filter(char::is_ascii).collect();
+    let basic = u32::try_from(output.len()).ok()?;
+    if basic > 0 {
+        output.push('-');
+    }
+    let (mut n, mut delta, mut bias, mut handled) = (128, 0u32, 72, basic);
+    while (handled as usize) < code_points.len() {
+        let next = *code_points.iter().filter(|&&c| c >= n).min()?;
+        delta = delta.checked_add((next - n).checked_mul(handled + 1)?)?;
+        n = next;
+        for &c in &code_points {
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c != n {
+                continue;
+            }
+            let mut q = delta;
+            let mut k = BASE;
+            loop {
+                let t = k.saturating_sub(bias).clamp(T_MIN, T_MAX);
+                if q < t {
+                    break;
+                }
+                output.push(digit(t + (q - t) % (BASE - t)));
+                q = (q - t) / (BASE - t);
+                k += BASE;
+            }
+            output.push(digit(q));
+            bias = adapt(delta, handled + 1, handled == basic);
+            delta = 0;
+            handled += 1;
+        }
+        delta += 1;
+        n += 1;
     }
+    Some(output)
 }
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RequestLine {
     method: RequestMethod,
-    path: RequestPath,
+    authority: Option<Host>,
+    /// Port of the scheme of an absolute-form target, which a `Host` header
+    /// without a port stands for.
+    default_port: u16,
+    uri: Uri,
     version: RequestVersion,
 }
 impl RequestLine {
+    #[must_use]
     pub const fn method(&self) -> &RequestMethod {
         &self.method
     }
 
-    pub const fn path(&self) -> &RequestPath {
-        &self.path
+    /// Host and port of an absolute-form target like `http://example.com/path`.
+    #[must_use]
+    pub const fn authority(&self) -> Option<&Host> {
+        self.authority.as_ref()
+    }
+
+    /// Checks that an absolute-form target names the host and port of the
+    /// `Host` header, a missing port standing for the default one of the
+    /// target's scheme.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header names another host or port.
+    pub fn check_host(&self, request_header: &RequestHeader) -> Result<(), RequestHeaderError> {
+        let (Some(authority), Some(host)) = (self.authority(), request_header.host()) else {
+            return Ok(());
+        };
+        let port = |host: &Host| host.port().unwrap_or(self.default_port);
+        if authority.name() == host.name() && port(authority) == port(host) {
+            return Ok(());
+        }
+        Err(RequestHeaderError::HostMismatch(
+            authority.to_string(),
+            host.to_string(),
+        ))
+    }
+
+    /// The target of an origin-form request line, or the path and query of an
+    /// absolute-form one.
+    #[must_use]
+    pub const fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    #[must_use]
+    pub const fn version(&self) -> &RequestVersion {
+        &self.version
     }
 }
-impl FromStr for RequestLine {
-    type Err = HTTPRequestLineError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut request_line = s.split_whitespace();
+impl RequestLine {
+    /// Parses a request line the way older clients may send it: any run of
+    /// spaces and tabs separates its parts, and words after the version are
+    /// ignored. Other control characters, bare CRs and NULs included, are
+    /// still refused, for them not to reach logs or be taken as line ends.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line has control characters other than tabs,
+    /// or the method, target or version is missing or invalid.
+    pub fn parse_lenient(s: &str) -> Result<Self, HTTPRequestLineError> {
+        if s.chars().any(|c| c.is_ascii_control() && c != '\t') {
+            return Err(HTTPRequestLineError::ControlCharacter(
+                s.escape_debug().to_string(),
+            ));
+        }
+        let mut request_line = s.split([' ', '\t']).filter(|part| !part.is_empty());
         let Some(method) = request_line.next() else {
             return Err(HTTPRequestLineError::MissingMethod(s.to_string()));
         };
-        let Some(path) = request_line.next() else {
+        let Some(target) = request_line.next() else {
             return Err(HTTPRequestLineError::MissingPath(s.to_string()));
         };
+        let (authority, default_port, path) = match absolute_form(target) {
+            Some((authority, default_port, path)) => (Some(authority.parse()?), default_port, path),
+            None => (None, 80, target),
+        };
         if !path.starts_with('/') {
             return Err(HTTPRequestLineError::MissingPath(s.to_string()));
         }
@@ -168,21 +1053,78 @@ impl FromStr for RequestLine {
         };
         Ok(Self {
             method: method.parse()?,
-            path: path.parse()?,
+            authority,
+            default_port,
+            uri: path.parse()?,
             version: version.parse()?,
         })
     }
 }
+impl FromStr for RequestLine {
+    type Err = HTTPRequestLineError;
+    /// Parses a request line strictly, as RFC 9112 section 3 describes it: the
+    /// method, target and version separated by single spaces, without control
+    /// characters such as tabs, bare CRs or NULs.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.chars().any(|c| c.is_ascii_control()) {
+            return Err(HTTPRequestLineError::ControlCharacter(
+                s.escape_debug().to_string(),
+            ));
+        }
+        if s.starts_with(' ') || s.ends_with(' ') || s.contains("  ") || s.split(' ').count() > 3 {
+            return Err(HTTPRequestLineError::Malformed(s.to_string()));
+        }
+        Self::parse_lenient(s)
+    }
+}
+/// Splits an absolute-form target like `http://example.com/path?query`, as
+/// sent to proxies, into its authority, the default port of its scheme and its
+/// path, `/` when it has none.
+fn absolute_form(target: &str) -> Option<(&str, u16, &str)> {
+    let (scheme, rest) = target.split_once("://")?;
+    let default_port = if scheme.eq_ignore_ascii_case("http") {
+        80
+    } else if scheme.eq_ignore_ascii_case("https") {
+        443
+    } else {
+        return None;
+    };
+    let path_start = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(path_start);
+    if authority.is_empty() || authority.contains('@') {
+        return None;
+    }
+    Some((
+        authority,
+        default_port,
+        if path.is_empty() { "/" } else { path },
+    ))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RequestMethod {
     Get,
+    /// A `GET` answered with the headers only.
+    Head,
     Post,
+    Put,
+    Delete,
+}
+impl RequestMethod {
+    /// Whether requests with this method only read, like `GET` and `HEAD`.
+    #[must_use]
+    pub const fn is_safe(&self) -> bool {
+        matches!(self, Self::Get | Self::Head)
+    }
 }
 impl Display for RequestMethod {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Get => write!(f, "GET"),
+            Self::Head => write!(f, "HEAD"),
             Self::Post => write!(f, "POST"),
+            Self::Put => write!(f, "PUT"),
+            Self::Delete => write!(f, "DELETE"),
         }
     }
 }
@@ -191,7 +1133,10 @@ impl FromStr for RequestMethod {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "get" => Ok(Self::Get),
+            "head" => Ok(Self::Head),
             "post" => Ok(Self::Post),
+            "put" => Ok(Self::Put),
+            "delete" => Ok(Self::Delete),
             invalid_command => Err(HTTPMethodError::InvalidHTTPMethod(
                 invalid_command.to_string(),
             )),
@@ -212,37 +1157,81 @@ pub enum HTTPRequestLineError {
     MissingPath(String),
     #[error("'{0}' HTTP request line has no version")]
     MissingVersion(String),
+    #[error("'{0}' HTTP request line has a control character")]
+    ControlCharacter(String),
+    #[error("'{0}' HTTP request line is not 'method target version' separated by single spaces")]
+    Malformed(String),
     #[error("{0}")]
     HTTPMethodError(#[from] HTTPMethodError),
     #[error("{0}")]
     HTTPPathError(#[from] HTTPPathError),
     #[error("{0}")]
     HTTPVersionError(#[from] HTTPVersionError),
+    #[error("{0}")]
+    HostError(#[from] HostError),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct RequestPath(String);
-impl FromStr for RequestPath {
-    type Err = HTTPPathError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.starts_with('/') {
-            return Err(HTTPPathError::InvalidHTTPPath(format!(
-                "Path '{s}' does not start with '/'"
-            )));
+/// The length of the body announced by the `Content-Length` header `values`,
+/// read by the same rules when framing a request as once it is parsed, for
+/// both to agree on where its body ends.
+///
+/// # Errors
+///
+/// Returns an error if a value isn't a number, or the values differ.
+pub fn content_length(values: &[&str]) -> Result<Option<u64>, RequestHeaderError> {
+    let mut lengths = values.iter().map(|length| {
+        let length = length.trim();
+        length
+            .bytes()
+            .all(|byte| byte.is_ascii_digit())
+            .then(|| length.parse().ok())
+            .flatten()
+            .ok_or_else(|| RequestHeaderError::InvalidContentLength(length.to_string()))
+    });
+    let Some(first) = lengths.next().transpose()? else {
+        return Ok(None);
+    };
+    for length in lengths {
+        if length? != first {
+            return Err(RequestHeaderError::InvalidContentLength(values.join(", ")));
         }
-        Ok(Self(s.to_string()))
     }
+    Ok(Some(first))
 }
-impl Display for RequestPath {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+
+/// Decodes the `%XX` escapes of a path or query component.
+///
+/// # Errors
+///
+/// Returns an error if an escape isn't two hexadecimal digits or the decoded
+/// bytes aren't UTF-8.
+pub fn percent_decode(s: &str) -> Result<String, HTTPPathError> {
+    let invalid = || HTTPPathError::InvalidPercentEncoding(s.to_string());
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = rest.get(..2).ok_or_else(invalid)?;
+        let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+        rest = &rest[2..];
     }
+    String::from_utf8(bytes).map_err(|_| invalid())
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
 pub enum HTTPPathError {
     #[error("Invalid HTTP Path :'{0}'")]
     InvalidHTTPPath(String),
+    #[error("'{0}' has an invalid percent-encoded sequence")]
+    InvalidPercentEncoding(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -257,44 +1246,216 @@ impl FromStr for RequestVersion {
             Some(version) if !version.is_empty() => version,
             _ => return Err(HTTPVersionError::MissingVersionNumber(s.to_string())),
         };
+        // RFC 9112 section 2.3: a single digit on each side of the dot
+        let digits = version.as_bytes();
+        if digits.len() != 3
+            || !digits[0].is_ascii_digit()
+            || digits[1] != b'.'
+            || !digits[2].is_ascii_digit()
+        {
+            return Err(HTTPVersionError::InvalidVersionNumber(s.to_string()));
+        }
+        if digits[0] != b'1' {
+            return Err(HTTPVersionError::UnsupportedVersion(s.to_string()));
+        }
         Ok(Self(version.to_string()))
     }
 }
 
+impl Display for RequestVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP/{}", self.0)
+    }
+}
+impl RequestVersion {
+    /// The minor version of HTTP/1 the client speaks, later ones are
+    /// answered like HTTP/1.1.
+    #[must_use]
+    pub fn minor(&self) -> u8 {
+        self.0.as_bytes()[2] - b'0'
+    }
+
+    /// HTTP/1.1 connections stay open between requests unless told otherwise,
+    /// older versions close after each response.
+    #[must_use]
+    pub fn is_persistent_by_default(&self) -> bool {
+        self.minor() >= 1
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
 pub enum HTTPVersionError {
     #[error("Invalid HTTP Version format, missing HTTP/ prefix : '{0}'")]
     InvalidHTTPVersionFormat(String),
     #[error("Missing HTTP Version number: '{0}'")]
     MissingVersionNumber(String),
+    #[error("Invalid HTTP Version number, expected 'HTTP/<digit>.<digit>': '{0}'")]
+    InvalidVersionNumber(String),
+    #[error("HTTP Version is not supported, expected HTTP/1.x: '{0}'")]
+    UnsupportedVersion(String),
 }
 
 #[cfg(test)]
-#[allow(clippy::unwrap_used)]
+#[allow(clippy::unwrap_used, clippy::used_underscore_items)]
 mod tests {
     use super::*;
+    use crate::http_response::ContentType;
+
+    #[test]
+    fn test_http_request_parse() {
+        let peer: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let request = HTTPRequest::parse(
+            "POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\n",
+            Some(Bytes::from_static(b"hello")),
+            peer,
+            false,
+        )
+        .unwrap();
+        assert_eq!(*request.line().method(), RequestMethod::Post);
+        assert_eq!(request.header().get("content-length"), Some("5"));
+        assert_eq!(request.body().unwrap().as_bytes(), b"hello");
+        assert_eq!(request.peer(), peer);
+
+        let cut =
+            HTTPRequest::parse("GET / HTTP/1.1\r\nHost: localhost", None, peer, false).unwrap();
+        assert_eq!(cut.body(), Err(RequestBodyError::MissingBody));
+        let head = "GET  /  HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert!(HTTPRequest::parse(head, Some(Bytes::new()), peer, true).is_ok());
+        assert!(matches!(
+            HTTPRequest::parse(head, Some(Bytes::new()), peer, false),
+            Err(HTTPRequestError::RequestLine(_))
+        ));
+        assert!(matches!(
+            HTTPRequest::parse("", None, peer, false),
+            Err(HTTPRequestError::MissingRequestLine)
+        ));
+    }
+
+    #[test]
+    fn test_request_body_decode() {
+        let latin1 = RequestBody(Bytes::from_static(b"caf\xe9"));
+        // Kept as received until read as text
+        assert_eq!(latin1.as_bytes(), b"caf\xe9");
+        assert_eq!(
+            latin1.decode(Charset::Iso8859_1).unwrap(),
+            "café".as_bytes()
+        );
+        assert_eq!(
+            latin1.decode(Charset::Utf8),
+            Err(CharsetError::InvalidBody(Charset::Utf8))
+        );
+        let utf8 = RequestBody(Bytes::from_static("café".as_bytes()));
+        assert_eq!(utf8.decode(Charset::Utf8).unwrap(), "café".as_bytes());
+    }
 
     #[test]
     fn test_request_header_from_valid_str() {
         let request_str = "GET / HTTP/1.1\r\nHost: example.com\r\nUser-Agent: TestAgent\r\n\r\n";
         let header = RequestHeader::from_str(request_str).unwrap();
-        assert_eq!(header._host().unwrap().0, "example.com");
+        assert_eq!(header.host().unwrap().name(), "example.com");
         assert_eq!(header.user_agent().unwrap().0, "TestAgent");
     }
 
+    #[test]
+    fn test_request_header_get() {
+        let request_str =
+            "GET / HTTP/1.1\r\nHost: localhost\r\nX-Api-Key: secret\r\n\r\nbody: not a header";
+        let header = RequestHeader::from_str(request_str).unwrap();
+        assert_eq!(header.get("x-api-key"), Some("secret"));
+        assert_eq!(header.get("body"), None);
+    }
+
+    #[test]
+    fn test_request_header_closes_connection() {
+        let header = |lines: &str| {
+            RequestHeader::from_str(&format!("GET / HTTP/1.1\r\nHost: localhost\r\n{lines}\r\n"))
+                .unwrap()
+        };
+        assert!(!header("").closes_connection());
+        assert!(!header("Connection: keep-alive\r\n").closes_connection());
+        assert!(!header("Connection: closed\r\n").closes_connection());
+        assert!(header("Connection: Close\r\n").closes_connection());
+        assert!(header("Connection: TE\r\nconnection: keep-alive, close\r\n").closes_connection());
+    }
+
     #[test]
     fn test_request_header_from_str_without_host() {
-        let request_str = "GET / HTTP/1.1\r\nUser-Agent: TestAgent\r\n\r\n";
+        let request_str = "GET / HTTP/1.0\r\nUser-Agent: TestAgent\r\n\r\n";
         let header = RequestHeader::from_str(request_str).unwrap();
-        assert!(header._host().is_none());
+        assert!(header.host().is_none());
         assert_eq!(header.user_agent().unwrap().0, "TestAgent");
+        let request_str = "GET / HTTP/1.1\r\nUser-Agent: TestAgent\r\n\r\n";
+        let result = RequestHeader::from_str(request_str);
+        assert!(matches!(result, Err(RequestHeaderError::MissingHost)));
+    }
+
+    #[test]
+    fn test_request_header_from_str_with_duplicate_host() {
+        let request_str = "GET / HTTP/1.1\r\nhost: a.example\r\nHost: b.example\r\n\r\n";
+        let result = RequestHeader::from_str(request_str);
+        assert!(matches!(result, Err(RequestHeaderError::DuplicateHost)));
+    }
+
+    #[test]
+    fn test_host_from_str() {
+        let host = |s: &str| {
+            s.parse::<Host>()
+                .map(|host| (host.name().to_string(), host.port()))
+        };
+        assert_eq!(
+            host("Example.com:4221"),
+            Ok(("example.com".to_string(), Some(4221)))
+        );
+        assert_eq!(host("example.com"), Ok(("example.com".to_string(), None)));
+        assert_eq!(host("example.com:"), Ok(("example.com".to_string(), None)));
+        assert_eq!(
+            host("127.0.0.1:80"),
+            Ok(("127.0.0.1".to_string(), Some(80)))
+        );
+        assert_eq!(host("[::1]:4221"), Ok(("[::1]".to_string(), Some(4221))));
+        assert_eq!(host("[0:0::1]"), Ok(("[::1]".to_string(), None)));
+        assert_eq!(
+            host("[::FFFF:7F00:1]"),
+            Ok(("[::ffff:127.0.0.1]".to_string(), None))
+        );
+        assert_eq!(
+            host("Bücher.example"),
+            Ok(("xn--bcher-kva.example".to_string(), None))
+        );
+        assert_eq!(
+            host("münchen.de:8080"),
+            Ok(("xn--mnchen-3ya.de".to_string(), Some(8080)))
+        );
+        assert_eq!(host("例え.jp"), Ok(("xn--r8jz45g.jp".to_string(), None)));
+
+        for invalid in [
+            "",
+            ":80",
+            "a b",
+            "exa/mple",
+            "[::1",
+            "[fe80::1%25eth0]",
+            "[::1]x",
+            "[v1.x]",
+        ] {
+            assert_eq!(
+                host(invalid),
+                Err(HostError::InvalidHost(invalid.to_string()))
+            );
+        }
+        for invalid in ["example.com:http", "example.com:65536", "[::1]:-1"] {
+            assert_eq!(
+                host(invalid),
+                Err(HostError::InvalidPort(invalid.to_string()))
+            );
+        }
     }
 
     #[test]
     fn test_request_header_from_str_without_user_agent() {
         let request_str = "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
         let header = RequestHeader::from_str(request_str).unwrap();
-        assert_eq!(header._host().unwrap().0, "example.com");
+        assert_eq!(header.host().unwrap().name(), "example.com");
         assert!(header.user_agent().is_none());
     }
 
@@ -317,7 +1478,7 @@ mod tests {
         let request_str = "GET / HTTP/1.1";
         let request_line = RequestLine::from_str(request_str).unwrap();
         assert_eq!(request_line.method(), &RequestMethod::Get);
-        assert_eq!(request_line.path().0, "/");
+        assert_eq!(request_line.uri().to_string(), "/");
         assert_eq!(request_line.version.0, "1.1");
     }
 
@@ -351,17 +1512,67 @@ mod tests {
     }
 
     #[test]
-    fn test_request_path_from_valid_str() {
-        let path_str = "/test/path";
-        let path = RequestPath::from_str(path_str).unwrap();
-        assert_eq!(path.0, "/test/path");
+    fn test_request_line_from_str_rejects_control_characters() {
+        for request_str in ["GET /a\rb HTTP/1.1", "GET /a\0 HTTP/1.1", "GET\t/ HTTP/1.1"] {
+            assert!(matches!(
+                RequestLine::from_str(request_str),
+                Err(HTTPRequestLineError::ControlCharacter(_))
+            ));
+        }
+        let error = RequestLine::from_str("GET /\x1b[31m HTTP/1.1").unwrap_err();
+        assert!(!error.to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn test_request_line_from_str_rejects_whitespace_in_target() {
+        for request_str in ["GET /a b HTTP/1.1", "GET  / HTTP/1.1", "GET / HTTP/1.1 "] {
+            assert!(matches!(
+                RequestLine::from_str(request_str),
+                Err(HTTPRequestLineError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_request_line_parse_lenient() {
+        let request_line = RequestLine::parse_lenient("GET\t/echo/a   HTTP/1.1 ").unwrap();
+        assert_eq!(request_line.uri().to_string(), "/echo/a");
+        assert!(RequestLine::parse_lenient("GET /a b HTTP/1.1").is_err());
+        assert!(RequestLine::parse_lenient("GET /a\0b HTTP/1.1").is_err());
+        assert!(RequestLine::parse_lenient("GET /a\rb HTTP/1.1").is_err());
+        assert!(RequestLine::parse_lenient("GET /a\x1b[2J HTTP/1.1").is_err());
+    }
+
+    #[test]
+    fn test_request_line_from_absolute_form() {
+        let request_line =
+            RequestLine::from_str("GET http://Example.com:4221/echo/a?b=c HTTP/1.1").unwrap();
+        assert_eq!(
+            request_line.authority().unwrap().to_string(),
+            "example.com:4221"
+        );
+        assert_eq!(request_line.uri().to_string(), "/echo/a?b=c");
+
+        let request_line = RequestLine::from_str("GET https://example.com HTTP/1.1").unwrap();
+        assert_eq!(request_line.uri().to_string(), "/");
+        assert!(RequestLine::from_str("GET ftp://example.com/ HTTP/1.1").is_err());
+        assert!(RequestLine::from_str("GET http://user@example.com/ HTTP/1.1").is_err());
     }
 
     #[test]
-    fn test_request_path_from_str_without_leading_slash() {
-        let path_str = "test/path";
-        let result = RequestPath::from_str(path_str);
-        assert!(matches!(result, Err(HTTPPathError::InvalidHTTPPath(_))));
+    fn test_request_line_check_host() {
+        let request_line = RequestLine::from_str("GET http://example.com/ HTTP/1.1").unwrap();
+        let header =
+            RequestHeader::from_str("GET / HTTP/1.1\r\nHost: EXAMPLE.com:80\r\n\r\n").unwrap();
+        assert!(request_line.check_host(&header).is_ok());
+        let header =
+            RequestHeader::from_str("GET / HTTP/1.1\r\nHost: other.example\r\n\r\n").unwrap();
+        assert!(matches!(
+            request_line.check_host(&header),
+            Err(RequestHeaderError::HostMismatch(..))
+        ));
+        let origin_form = RequestLine::from_str("GET / HTTP/1.1").unwrap();
+        assert!(origin_form.check_host(&header).is_ok());
     }
 
     #[test]
@@ -390,10 +1601,242 @@ mod tests {
             Err(HTTPVersionError::MissingVersionNumber(_))
         ));
     }
+    #[test]
+    fn test_request_version_from_str_validates_number() {
+        assert_eq!(RequestVersion::from_str("HTTP/1.0").unwrap().minor(), 0);
+        assert!(RequestVersion::from_str("HTTP/1.2")
+            .unwrap()
+            .is_persistent_by_default());
+        for version_str in ["HTTP/1", "HTTP/1.10", "HTTP/x.y"] {
+            assert!(matches!(
+                RequestVersion::from_str(version_str),
+                Err(HTTPVersionError::InvalidVersionNumber(_))
+            ));
+        }
+        for version_str in ["HTTP/2.0", "HTTP/9.9", "HTTP/0.9"] {
+            assert!(matches!(
+                RequestVersion::from_str(version_str),
+                Err(HTTPVersionError::UnsupportedVersion(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_request_header_charset() {
+        let charset = |content_type: &str| {
+            RequestHeader::from_str(&format!(
+                "POST / HTTP/1.0\r\nContent-Type: {content_type}\r\n\r\n"
+            ))
+            .unwrap()
+            .charset()
+        };
+        assert_eq!(
+            charset("text/plain; charset=UTF-8"),
+            Ok(Some(Charset::Utf8))
+        );
+        assert_eq!(
+            charset("text/plain;format=flowed; charset=\"iso-8859-1\""),
+            Ok(Some(Charset::Iso8859_1))
+        );
+        assert_eq!(charset("text/plain"), Ok(None));
+        assert!(matches!(
+            charset("text/plain; charset=koi8-r"),
+            Err(CharsetError::UnsupportedCharset(_))
+        ));
+    }
+
+    #[test]
+    fn test_language_range() {
+        assert_eq!(
+            "fr-CH;q=0.9".parse(),
+            Ok(LanguageRange {
+                tag: "fr-ch".to_string(),
+                quality: 900
+            })
+        );
+        assert_eq!(
+            "*".parse::<LanguageRange>().unwrap().to_string(),
+            "*".to_string()
+        );
+        assert_eq!(
+            "en;q=0.25".parse::<LanguageRange>().unwrap().to_string(),
+            "en;q=0.25"
+        );
+        assert!(matches!(
+            "en;q=1.5".parse::<LanguageRange>(),
+            Err(LanguageRangeError::InvalidQuality(_))
+        ));
+        assert!(matches!(
+            "en_US".parse::<LanguageRange>(),
+            Err(LanguageRangeError::InvalidTag(_))
+        ));
+    }
+
+    #[test]
+    fn test_accept_language_negotiate() {
+        let accept_language: AcceptLanguage = "fr-CH, fr;q=0.9, en;q=0.8, *;q=0.1".parse().unwrap();
+        assert_eq!(accept_language.negotiate(&["en", "fr"]), Some("fr"));
+        assert_eq!(accept_language.negotiate(&["en", "fr-CH"]), Some("fr-CH"));
+        assert_eq!(accept_language.negotiate(&["en-GB", "de"]), Some("en-GB"));
+        assert_eq!(accept_language.negotiate(&["de"]), Some("de"));
+
+        let accept_language: AcceptLanguage = "en, *;q=0".parse().unwrap();
+        assert_eq!(accept_language.negotiate(&["de", "fr"]), None);
+        assert_eq!(
+            AcceptLanguage::default().negotiate(&["de", "fr"]),
+            Some("de")
+        );
+    }
+
+    #[test]
+    fn test_request_header_accept_language() {
+        let request_header: RequestHeader =
+            "GET / HTTP/1.0\r\naccept-language: da, en-gb;q=0.8, bad;q=2\r\n\r\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            request_header
+                .accept_language()
+                .negotiate(&["en", "en-GB", "da"]),
+            Some("da")
+        );
+        assert_eq!(
+            request_header.accept_language().negotiate(&["en", "en-GB"]),
+            Some("en-GB")
+        );
+    }
+
+    #[test]
+    fn test_request_header_typed_accessors() {
+        let request_header: RequestHeader = "GET / HTTP/1.1\r\nHost: localhost\r\n\
+             Connection: Keep-Alive, X-Trace\r\nConnection: close\r\n\
+             Referer: http://localhost/form\r\nContent-Length: 12\r\n\
+             Content-Type: text/plain; charset=utf-8\r\nRange: bytes=0-9, -5\r\n\
+             If-None-Match: W/\"a\", \"b\"\r\nAccept: application/json\r\n\r\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            request_header.connection(),
+            ["keep-alive", "x-trace", "close"]
+        );
+        assert!(request_header.closes_connection());
+        assert_eq!(request_header.referer(), Some("http://localhost/form"));
+        assert_eq!(request_header.content_length(), Ok(Some(12)));
+        assert_eq!(
+            request_header.content_type().unwrap().essence(),
+            "text/plain"
+        );
+        assert_eq!(request_header.range().unwrap().ranges().len(), 2);
+        assert!(request_header
+            .if_none_match()
+            .unwrap()
+            .matches_weak(&"\"a\"".parse().unwrap()));
+        assert_eq!(request_header.if_match(), None);
+        assert_eq!(
+            request_header
+                .accept()
+                .negotiate(&[ContentType::TextPlain, ContentType::ApplicationJson]),
+            Some(&ContentType::ApplicationJson)
+        );
+
+        let content_length = |lines: &str| {
+            RequestHeader::from_str(&format!("GET / HTTP/1.1\r\nHost: localhost\r\n{lines}\r\n"))
+                .unwrap()
+                .content_length()
+        };
+        assert_eq!(content_length(""), Ok(None));
+        assert_eq!(
+            content_length("Content-Length: 5\r\nContent-Length: 5\r\n"),
+            Ok(Some(5))
+        );
+        assert!(content_length("Content-Length: +5\r\n").is_err());
+        assert!(content_length("Content-Length: 5\r\nContent-Length: 6\r\n").is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b/%C3%A9%2f").unwrap(), "a b/é/");
+        assert_eq!(percent_decode("plain").unwrap(), "plain");
+        assert!(percent_decode("100%").is_err());
+        assert!(percent_decode("%zz").is_err());
+        assert!(percent_decode("%ff").is_err());
+    }
+
+    #[test]
+    fn test_charset_decode() {
+        assert_eq!(Charset::Iso8859_1.decode(b"caf\xe9").unwrap(), "café");
+        assert_eq!(Charset::UsAscii.decode(b"plain").unwrap(), "plain");
+        assert!(Charset::UsAscii.decode("café".as_bytes()).is_err());
+        assert!(Charset::Utf8.decode(b"caf\xe9").is_err());
+    }
+
+    #[test]
+    fn test_negotiate_encodings() {
+        let gzip = Encoding::Gzip;
+        let identity = Encoding::Identity;
+        assert_eq!(negotiate_encodings("gzip"), [gzip, identity]);
+        assert_eq!(negotiate_encodings("deflate, gzip, br"), [gzip, identity]);
+        assert_eq!(negotiate_encodings("X-GZIP"), [gzip, identity]);
+        assert_eq!(negotiate_encodings("identity"), [identity]);
+        assert_eq!(negotiate_encodings("*"), [gzip, identity]);
+        assert_eq!(
+            negotiate_encodings("gzip;q=0.5, identity"),
+            [identity, gzip]
+        );
+        assert_eq!(negotiate_encodings("identity;q=0.5, *"), [gzip, identity]);
+        assert_eq!(negotiate_encodings("gzip;q=0"), [identity]);
+        assert_eq!(negotiate_encodings("*;q=0, identity"), [identity]);
+        assert_eq!(negotiate_encodings("gzip, *;q=0"), [gzip]);
+        assert_eq!(negotiate_encodings("*;q=0"), []);
+        assert_eq!(negotiate_encodings(""), [identity]);
+        assert_eq!(
+            negotiate_encodings("gzip;q=2, identity;q=0.001"),
+            [identity]
+        );
+        assert_eq!(parse_quality("0.05"), Some(50));
+        assert_eq!(parse_quality("1.000"), Some(1000));
+        assert_eq!(parse_quality("0.0001"), None);
+
+        let header: RequestHeader =
+            "GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: identity;q=0\r\nAccept-Encoding: *\r\n\r\n"
+                .parse()
+                .unwrap();
+        assert_eq!(header.accept_encoding(), [gzip]);
+        assert_eq!(
+            RequestHeader::from_str("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap()
+                .accept_encoding(),
+            []
+        );
+    }
+
+    #[test]
+    fn test_te_from_str() {
+        let te: Te = "trailers, Deflate;q=0.5, gzip;q=0, chunked"
+            .parse()
+            .unwrap();
+        assert!(te.trailers());
+        assert_eq!(te.codings(), ["deflate"]);
+        assert!(!"gzip;q=0.000".parse::<Te>().unwrap().trailers());
+        for invalid in ["gzip;q=2", "gzip;q=0.1234", "gzip;level", "g zip"] {
+            assert_eq!(
+                invalid.parse::<Te>(),
+                Err(RequestHeaderError::InvalidTe(invalid.to_string()))
+            );
+        }
+        assert_eq!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nTE: trailers\r\nte: gzip;q=1\r\n\r\n"
+                .parse::<RequestHeader>(),
+            Err(RequestHeaderError::UnsupportedTransferCoding(
+                "gzip".to_string()
+            ))
+        );
+    }
+
     #[test]
     fn test_display_host() {
-        let host = Host("example.com".to_string());
-        assert_eq!(format!("{host}"), "example.com");
+        let host: Host = "Example.com:8080".parse().unwrap();
+        assert_eq!(format!("{host}"), "example.com:8080");
     }
 
     #[test]
@@ -407,10 +1850,4 @@ mod tests {
         let method = RequestMethod::Get;
         assert_eq!(format!("{method}"), "GET");
     }
-
-    #[test]
-    fn test_display_request_path() {
-        let path = RequestPath("/test/path".to_string());
-        assert_eq!(format!("{path}"), "/test/path");
-    }
 }
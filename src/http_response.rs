@@ -1,187 +1,1060 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use crate::{gzip::Gzip, http_request::Encoding};
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{
+    gzip::Gzip,
+    header_map::HeaderMap,
+    http_request::{Charset, Encoding},
+    upgrade::{OnUpgrade, UpgradeFuture, Upgraded},
+};
+
+/// Room for the status line and the typed headers of any response, the
+/// custom media types and headers aside.
+const HEAD_CAPACITY: usize = 192;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct HTTPResponse {
     status: ResponseStatus,
-    header: Option<ResponseHeader>,
+    headers: HeaderMap,
     body: Option<ResponseBody>,
+    close_connection: bool,
+    informational: Vec<InformationalResponse>,
+    trailers: HeaderMap,
+    upgrade: Option<OnUpgrade>,
 }
 impl HTTPResponse {
+    #[must_use]
     pub const fn new_builder(status: ResponseStatus) -> HTTPResponseBuilder {
         HTTPResponseBuilder {
             status,
-            header: None,
+            headers: HeaderMap::new(),
+            body: None,
+            informational: Vec::new(),
+        }
+    }
+    /// Marks the response as the last one sent on its connection.
+    #[must_use]
+    pub fn with_connection_close(self) -> Self {
+        Self {
+            close_connection: true,
+            ..self
+        }
+    }
+    /// Adds a header after the ones already set, keeping those with the same name.
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, &header_value(value));
+        self
+    }
+    /// Sets a header, replacing the values it had.
+    #[must_use]
+    pub fn with_replaced_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, &header_value(value));
+        self
+    }
+    /// Drops the body but keeps the headers describing it, `Content-Length`
+    /// included, as the answer to a `HEAD` request. Trailers go with the body.
+    #[must_use]
+    pub fn without_body(self) -> Self {
+        Self {
             body: None,
+            trailers: HeaderMap::new(),
+            ..self
+        }
+    }
+    /// Adds a trailer field sent after the body, which is then sent chunked
+    /// and announced by the `Trailer` header. Only clients sending
+    /// `TE: trailers` get them, see `without_trailers`.
+    #[must_use]
+    pub fn with_trailer(mut self, name: &str, value: &str) -> Self {
+        self.trailers.append(name, &header_value(value));
+        self
+    }
+    /// Drops the trailers, sending the body with its `Content-Length` again.
+    #[must_use]
+    pub fn without_trailers(self) -> Self {
+        Self {
+            trailers: HeaderMap::new(),
+            ..self
+        }
+    }
+    /// Hands the connection to `handler` once the response is sent, which must
+    /// then be a `101 Switching Protocols`, for the handler to speak the protocol
+    /// the client asked for. See `upgrade::switching_protocols`.
+    #[must_use]
+    pub fn with_upgrade(
+        self,
+        handler: impl for<'a> Fn(Upgraded<'a>) -> UpgradeFuture<'a> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            upgrade: Some(OnUpgrade::new(handler)),
+            ..self
         }
     }
+    /// The handler taking the connection over once the response is sent,
+    /// `None` unless the response is a `101 Switching Protocols`.
+    #[must_use]
+    pub fn upgrade(&self) -> Option<&OnUpgrade> {
+        self.upgrade
+            .as_ref()
+            .filter(|_| self.status == ResponseStatus::Http101)
+    }
+    /// Sends `informational` before the response, after the ones already added.
+    /// Clients speaking HTTP/1.0 don't expect them, so they are skipped for them.
+    #[must_use]
+    pub fn with_informational(mut self, informational: InformationalResponse) -> Self {
+        self.informational.push(informational);
+        self
+    }
+    /// Turns the response back into a builder, to replace its body while
+    /// keeping its status and headers.
+    #[must_use]
+    pub fn into_builder(self) -> HTTPResponseBuilder {
+        HTTPResponseBuilder {
+            status: self.status,
+            headers: self.headers,
+            body: self.body,
+            informational: self.informational,
+        }
+    }
+    #[must_use]
+    pub const fn closes_connection(&self) -> bool {
+        self.close_connection
+    }
+    #[must_use]
+    pub const fn status(&self) -> ResponseStatus {
+        self.status
+    }
+    /// The headers, in the order they are sent.
+    #[must_use]
+    pub const fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+    /// The value of the first `name` header, if any.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+    /// The media type of the body, `None` without a valid `Content-Type`.
+    #[must_use]
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.headers.get("Content-Type")?.parse().ok()
+    }
+    /// The coding the body is compressed with, `None` when it is sent as is.
+    #[must_use]
+    pub fn content_encoding(&self) -> Option<Encoding> {
+        self.headers.get("Content-Encoding")?.parse().ok()
+    }
+    /// The interim responses to send before this one, in order.
+    #[must_use]
+    pub fn informational(&self) -> &[InformationalResponse] {
+        &self.informational
+    }
+    /// The trailer fields to send after the body, in order.
+    #[must_use]
+    pub const fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+    /// Length of the body as sent, after compression.
+    pub fn body_length(&self) -> usize {
+        self.body.as_ref().map_or(0, ResponseBody::length)
+    }
+    /// Length of the body before compression.
+    pub fn decoded_body_length(&self) -> usize {
+        self.body.as_ref().map_or(0, |body| body.decoded_length)
+    }
+    /// The whole response as sent, see `head_bytes`, `body_bytes` and
+    /// `trailer_bytes` to send it without copying the body.
+    #[must_use]
     pub fn as_http_bytes(&self) -> Vec<u8> {
-        let mut buf = vec![];
-        buf.extend_from_slice(format!("{}", self.status).as_bytes());
-
-        match self.header.clone() {
-            Some(header) => {
-                if let Some(encoding) = header.content_encoding {
-                    buf.extend_from_slice(format!("Content-Encoding: {encoding}\r\n").as_bytes());
-                };
-                buf.extend_from_slice(
-                    format!("Content-Type: {}\r\n", header.content_type).as_bytes(),
-                );
-                buf.extend_from_slice(
-                    format!("Content-Length: {}\r\n", header.content_length).as_bytes(),
-                );
-                if let Some(location) = header.location {
-                    buf.extend_from_slice(format!("Location: {location}\r\n").as_bytes());
+        let mut buf = self.head_bytes();
+        buf.extend_from_slice(self.body_bytes());
+        buf.extend_from_slice(&self.trailer_bytes());
+        buf
+    }
+    /// The status line and headers, up to the empty line ending them. With
+    /// trailers, also the size line of the chunk holding the body.
+    #[must_use]
+    pub fn head_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.head_capacity());
+        self.write_head(&mut buf);
+        buf
+    }
+    /// Writes what `head_bytes` returns at the end of `buf`, to serialize
+    /// responses into a buffer reused from one to the next.
+    pub fn write_head(&self, buf: &mut Vec<u8>) {
+        buf.reserve(self.head_capacity());
+        self.status.write_line(buf);
+
+        let chunked = self.is_chunked();
+        if !self.headers.contains("Content-Length") && self.status.allows_body() && !chunked {
+            // Without it a client reads the body until the connection closes
+            buf.extend_from_slice(b"Content-Length: 0\r\n");
+        }
+        for (name, value) in self.headers.iter() {
+            // A chunked body has no length, each chunk tells its own
+            if !(chunked && name.eq_ignore_ascii_case("Content-Length")) {
+                write_field(buf, name, value);
+            }
+        }
+        if self.close_connection {
+            buf.extend_from_slice(b"Connection: close\r\n");
+        }
+        if chunked {
+            buf.extend_from_slice(b"Transfer-Encoding: chunked\r\nTrailer: ");
+            for (i, (name, _)) in self.trailers.iter().enumerate() {
+                if i > 0 {
+                    buf.extend_from_slice(b", ");
                 }
+                buf.extend_from_slice(name.as_bytes());
             }
-            None => buf.extend_from_slice(b"\r\n"),
+            buf.extend_from_slice(b"\r\n");
         }
         buf.extend_from_slice(b"\r\n");
-
-        match &self.body {
-            Some(body) => buf.extend_from_slice(&body.0),
-            None => (),
+        let length = self.body_bytes().len();
+        if chunked && length > 0 {
+            write_integer(buf, length, 16);
+            buf.extend_from_slice(b"\r\n");
         }
-
+    }
+    /// What follows the body when it is sent chunked: the end of its chunk,
+    /// the last empty chunk and the trailer fields. Empty otherwise.
+    #[must_use]
+    pub fn trailer_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.write_trailer(&mut buf);
         buf
     }
+    /// Writes what `trailer_bytes` returns at the end of `buf`.
+    pub fn write_trailer(&self, buf: &mut Vec<u8>) {
+        if !self.is_chunked() {
+            return;
+        }
+        if !self.body_bytes().is_empty() {
+            buf.extend_from_slice(b"\r\n");
+        }
+        buf.extend_from_slice(b"0\r\n");
+        for (name, value) in self.trailers.iter() {
+            write_field(buf, name, value);
+        }
+        buf.extend_from_slice(b"\r\n");
+    }
+    /// Enough room for the head to be written without growing, so it takes a
+    /// single allocation.
+    fn head_capacity(&self) -> usize {
+        let fields = |fields: &HeaderMap| {
+            fields
+                .iter()
+                .map(|(name, value)| name.len() + value.len() + 4)
+                .sum::<usize>()
+        };
+        HEAD_CAPACITY + fields(&self.headers) + fields(&self.trailers)
+    }
+    /// Trailers can only follow a body sent chunked (RFC 9112 section 7.1.2).
+    const fn is_chunked(&self) -> bool {
+        !self.trailers.is_empty() && self.status.allows_body()
+    }
+    /// The body as sent, after compression.
+    #[must_use]
+    pub fn body_bytes(&self) -> &[u8] {
+        self.body.as_ref().map_or(&[], |body| &body.bytes)
+    }
+    /// The body as sent, sharing its buffer with the response.
+    #[must_use]
+    pub fn body(&self) -> Bytes {
+        self.body
+            .as_ref()
+            .map_or_else(Bytes::new, |body| body.bytes.clone())
+    }
+    /// The body before compression, as the client reads it once it undoes
+    /// the `Content-Encoding`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the body isn't validly compressed.
+    pub fn decoded_body(&self) -> std::io::Result<Vec<u8>> {
+        match self.content_encoding() {
+            Some(Encoding::Gzip) => Gzip::decode(self.body_bytes()),
+            Some(Encoding::Identity) | None => Ok(self.body_bytes().to_vec()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct HTTPResponseBuilder {
     status: ResponseStatus,
-    header: Option<ResponseHeader>,
+    headers: HeaderMap,
     body: Option<ResponseBody>,
+    informational: Vec<InformationalResponse>,
 }
 impl HTTPResponseBuilder {
+    #[must_use]
     pub fn with_body(
         &self,
         content: &str,
         content_type: ContentType,
         encoding: &[Encoding],
     ) -> Self {
-        let body = encoding.first().map_or_else(
-            || ResponseBody(content.as_bytes().to_owned()),
-            |_encoding| ResponseBody(Gzip::parse(content).as_bytes().to_owned()),
-        );
-        let header = ResponseHeader::new(content_type, &body, encoding.first().copied());
+        self.with_bytes(
+            Bytes::copy_from_slice(content.as_bytes()),
+            content_type,
+            encoding,
+        )
+    }
+    /// Sets a body held elsewhere, like a file just read or a request body,
+    /// without copying it unless it gets compressed.
+    ///
+    /// The body is compressed when `encoding`, the codings acceptable to the
+    /// client most preferred first, starts with gzip. Replaces the
+    /// `Content-Type`, `Content-Length` and `Content-Encoding` of the body set
+    /// before, if any.
+    #[must_use]
+    #[allow(clippy::needless_pass_by_value)] // An owned `ContentType`, like `with_body` takes
+    pub fn with_bytes(
+        &self,
+        content: Bytes,
+        content_type: ContentType,
+        encoding: &[Encoding],
+    ) -> Self {
+        let mut headers = self.headers.clone();
+        let decoded_length = content.len();
+        let bytes = if encoding.first() == Some(&Encoding::Gzip) {
+            headers.insert("Content-Encoding", Encoding::Gzip.as_str());
+            Gzip::parse(&content).into_bytes().into()
+        } else {
+            headers.remove("Content-Encoding");
+            content
+        };
+        let body = ResponseBody {
+            bytes,
+            decoded_length,
+        };
+        headers.insert("Content-Type", content_type.as_str());
+        headers.insert("Content-Length", &body.length().to_string());
         Self {
-            status: self.status,
-            header: Some(header),
+            headers,
             body: Some(body),
+            ..self.clone()
+        }
+    }
+    /// Sets the `Location` header.
+    #[must_use]
+    pub fn with_location(&self, location: &str) -> Self {
+        self.with_replaced_header("Location", location)
+    }
+    /// Adds a `charset` parameter to the `Content-Type`, like
+    /// `text/plain; charset=utf-8`. Bodies set from `&str` are UTF-8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no body was set before.
+    #[must_use]
+    pub fn with_charset(&self, charset: Charset) -> Self {
+        let media_type = self
+            .headers
+            .get("Content-Type")
+            .expect("Charset is only set on responses with a body");
+        let media_type = media_type.split(';').next().unwrap_or_default();
+        self.with_replaced_header(
+            "Content-Type",
+            &format!("{media_type}; charset={}", charset.as_str()),
+        )
+    }
+    /// Sets the `Content-Language` of a response picked with
+    /// `AcceptLanguage::negotiate`, and tells caches it varies with `Accept-Language`.
+    #[must_use]
+    pub fn with_language(&self, language: &str) -> Self {
+        self.with_header("Content-Language", language)
+            .with_header("Vary", "Accept-Language")
+    }
+    /// Adds a header after the ones already set, keeping those with the same name.
+    #[must_use]
+    pub fn with_header(&self, name: &str, value: &str) -> Self {
+        let mut headers = self.headers.clone();
+        headers.append(name, &header_value(value));
+        Self {
+            headers,
+            ..self.clone()
         }
     }
-    pub fn with_location(&self, location: String) -> Self {
-        // sry im lazy today
-        let header = self.header.clone().unwrap().add_location(location);
+    /// Sets a header, replacing the values it had.
+    #[must_use]
+    pub fn with_replaced_header(&self, name: &str, value: &str) -> Self {
+        let mut headers = self.headers.clone();
+        headers.insert(name, &header_value(value));
         Self {
-            status: self.status,
-            header: Some(header),
-            body: self.body.clone(),
+            headers,
+            ..self.clone()
         }
     }
+    #[must_use]
     pub fn build(&self) -> HTTPResponse {
         HTTPResponse {
             status: self.status,
-            header: self.header.clone(),
+            headers: self.headers.clone(),
             body: self.body.clone(),
+            close_connection: false,
+            informational: self.informational.clone(),
+            trailers: HeaderMap::new(),
+            upgrade: None,
         }
     }
 }
 
+/// An interim `1xx` response, like `100 Continue` or `103 Early Hints`, sent on
+/// the way to the final response of a request.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InformationalResponse {
+    code: u16,
+    headers: HeaderMap,
+}
+impl InformationalResponse {
+    /// Tells a client waiting with `Expect: 100-continue` to send its body.
+    pub const CONTINUE: Self = Self {
+        code: 100,
+        headers: HeaderMap::new(),
+    };
+
+    /// Creates an interim response with status `code`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatusError::NotInformational` if `code` isn't a `1xx` status,
+    /// or is `101 Switching Protocols` which ends the exchange instead.
+    pub fn new(code: u16) -> Result<Self, ResponseStatusError> {
+        if !(100..=199).contains(&code) || code == 101 {
+            return Err(ResponseStatusError::NotInformational(code));
+        }
+        Ok(Self {
+            code,
+            headers: HeaderMap::new(),
+        })
+    }
+    /// `103 Early Hints` telling the client to start loading `links`, each a
+    /// `Link` header value like `</style.css>; rel=preload; as=style`.
+    #[must_use]
+    pub fn early_hints(links: &[&str]) -> Self {
+        links.iter().fold(
+            Self {
+                code: 103,
+                headers: HeaderMap::new(),
+            },
+            |hints, link| hints.with_header("Link", link),
+        )
+    }
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, &header_value(value));
+        self
+    }
+    #[must_use]
+    pub const fn code(&self) -> u16 {
+        self.code
+    }
+    /// The status line and headers, which is all there is to an interim response.
+    #[must_use]
+    pub fn as_http_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEAD_CAPACITY);
+        self.write_to(&mut buf);
+        buf
+    }
+    /// Writes what `as_http_bytes` returns at the end of `buf`.
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        ResponseStatus::Other(self.code).write_line(buf);
+        for (name, value) in self.headers.iter() {
+            write_field(buf, name, value);
+        }
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 // We accept to hardcode version
 pub enum ResponseStatus {
+    /// Ends the exchange by switching the connection to another protocol, see
+    /// `HTTPResponse::with_upgrade`.
+    Http101,
     Http200,
     Http201,
+    Http202,
+    Http204,
+    Http304,
     Http400,
+    Http401,
+    Http403,
     Http404,
+    Http405,
+    Http408,
+    Http412,
+    Http413,
+    Http415,
+    Http421,
+    Http429,
+    Http431,
     Http500,
+    Http503,
+    Http504,
+    Http505,
+    /// Any other final status, from 200 to 599, like the ones simulated by `/status/{code}`.
+    Other(u16),
 }
-impl Display for ResponseStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ResponseStatus {
+    /// The status line of the statuses the server sends itself, preformatted
+    /// so sending them doesn't format anything.
+    const fn static_line(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Http101 => "HTTP/1.1 101 Switching Protocols\r\n",
+            Self::Http200 => "HTTP/1.1 200 OK\r\n",
+            Self::Http201 => "HTTP/1.1 201 Created\r\n",
+            Self::Http202 => "HTTP/1.1 202 Accepted\r\n",
+            Self::Http204 => "HTTP/1.1 204 No Content\r\n",
+            Self::Http304 => "HTTP/1.1 304 Not Modified\r\n",
+            Self::Http400 => "HTTP/1.1 400 Bad Request\r\n",
+            Self::Http401 => "HTTP/1.1 401 Unauthorized\r\n",
+            Self::Http403 => "HTTP/1.1 403 Forbidden\r\n",
+            Self::Http404 => "HTTP/1.1 404 Not Found\r\n",
+            Self::Http405 => "HTTP/1.1 405 Method Not Allowed\r\n",
+            Self::Http408 => "HTTP/1.1 408 Request Timeout\r\n",
+            Self::Http412 => "HTTP/1.1 412 Precondition Failed\r\n",
+            Self::Http413 => "HTTP/1.1 413 Content Too Large\r\n",
+            Self::Http415 => "HTTP/1.1 415 Unsupported Media Type\r\n",
+            Self::Http421 => "HTTP/1.1 421 Misdirected Request\r\n",
+            Self::Http429 => "HTTP/1.1 429 Too Many Requests\r\n",
+            Self::Http431 => "HTTP/1.1 431 Request Header Fields Too Large\r\n",
+            Self::Http500 => "HTTP/1.1 500 Internal Server Error\r\n",
+            Self::Http503 => "HTTP/1.1 503 Service Unavailable\r\n",
+            Self::Http504 => "HTTP/1.1 504 Gateway Timeout\r\n",
+            Self::Http505 => "HTTP/1.1 505 HTTP Version Not Supported\r\n",
+            Self::Other(_) => return None,
+        })
+    }
+    /// Writes the status line, like `HTTP/1.1 200 OK\r\n`, at the end of `buf`.
+    fn write_line(self, buf: &mut Vec<u8>) {
+        if let Some(line) = self.static_line() {
+            buf.extend_from_slice(line.as_bytes());
+            return;
+        }
+        buf.extend_from_slice(b"HTTP/1.1 ");
+        write_integer(buf, usize::from(self.code()), 10);
+        buf.push(b' ');
+        buf.extend_from_slice(reason_phrase(self.code()).as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+    #[must_use]
+    pub const fn code(self) -> u16 {
         match self {
-            Self::Http200 => write!(f, "HTTP/1.1 200 OK\r\n"),
-            Self::Http201 => write!(f, "HTTP/1.1 201 Created\r\n"),
-            Self::Http400 => write!(f, "HTTP/1.1 400 Bad Request\r\n"),
-            Self::Http404 => write!(f, "HTTP/1.1 404 Not Found\r\n"),
-            Self::Http500 => write!(f, "HTTP/1.1 500 Internal Server Error\r\n"),
+            Self::Http101 => 101,
+            Self::Http200 => 200,
+            Self::Http201 => 201,
+            Self::Http202 => 202,
+            Self::Http204 => 204,
+            Self::Http304 => 304,
+            Self::Http400 => 400,
+            Self::Http401 => 401,
+            Self::Http403 => 403,
+            Self::Http404 => 404,
+            Self::Http405 => 405,
+            Self::Http408 => 408,
+            Self::Http412 => 412,
+            Self::Http413 => 413,
+            Self::Http415 => 415,
+            Self::Http421 => 421,
+            Self::Http429 => 429,
+            Self::Http431 => 431,
+            Self::Http500 => 500,
+            Self::Http503 => 503,
+            Self::Http504 => 504,
+            Self::Http505 => 505,
+            Self::Other(code) => code,
         }
     }
 }
-
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct ResponseHeader {
-    content_type: ContentType,
-    content_length: ContentLength,
-    content_encoding: Option<Encoding>,
-    location: Option<String>,
-}
-impl ResponseHeader {
-    fn new(content_type: ContentType, body: &ResponseBody, encoding: Option<Encoding>) -> Self {
-        Self {
-            content_type,
-            content_length: ContentLength::from_body(body),
-            location: None,
-            content_encoding: encoding,
-        }
+impl ResponseStatus {
+    /// Whether responses with this status may have a body, and so need their length.
+    #[must_use]
+    pub const fn allows_body(self) -> bool {
+        !matches!(self.code(), 100..=199 | 204 | 304)
     }
-    const fn add_location(&self, location: String) -> Self {
-        Self {
-            content_type: self.content_type,
-            content_length: self.content_length,
-            location: Some(location),
-            content_encoding: self.content_encoding,
+}
+impl Display for ResponseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.static_line() {
+            Some(line) => f.write_str(line),
+            None => write!(
+                f,
+                "HTTP/1.1 {} {}\r\n",
+                self.code(),
+                reason_phrase(self.code())
+            ),
         }
     }
 }
+impl FromStr for ResponseStatus {
+    type Err = ResponseStatusError;
+    /// Parses a final status code like `404`, informational `1xx` ones can't end an exchange.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s
+            .parse::<u16>()
+            .ok()
+            .filter(|code| (200..=599).contains(code))
+            .ok_or_else(|| ResponseStatusError::InvalidStatus(s.to_string()))?;
+        let known = [
+            Self::Http200,
+            Self::Http201,
+            Self::Http202,
+            Self::Http204,
+            Self::Http304,
+            Self::Http400,
+            Self::Http401,
+            Self::Http403,
+            Self::Http404,
+            Self::Http405,
+            Self::Http408,
+            Self::Http412,
+            Self::Http413,
+            Self::Http415,
+            Self::Http421,
+            Self::Http429,
+            Self::Http431,
+            Self::Http500,
+            Self::Http503,
+            Self::Http504,
+            Self::Http505,
+        ];
+        Ok(known
+            .into_iter()
+            .find(|status| status.code() == code)
+            .unwrap_or(Self::Other(code)))
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum ResponseStatusError {
+    #[error("'{0}' is not a valid status, expected a code between 200 and 599")]
+    InvalidStatus(String),
+    #[error(
+        "{0} is not an informational status, expected a code between 100 and 199 other than 101"
+    )]
+    NotInformational(u16),
+}
 
-impl Display for ResponseHeader {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(encoding) = self.content_encoding {
-            write!(f, "Content-Encoding: {encoding}\r\n")?;
-        }
-        write!(f, "Content-Type: {}\r\n", self.content_type)?;
-        write!(f, "Content-Length: {}\r\n", self.content_length)?;
-        if let Some(location) = self.location.clone() {
-            write!(f, "Location: {location}\r\n")?;
+/// Reason phrase of the statuses RFC 9110 defines, the reason is optional for others.
+const fn reason_phrase(code: u16) -> &'static str {
+    match code {
+        100 => "Continue",
+        102 => "Processing",
+        103 => "Early Hints",
+        203 => "Non-Authoritative Information",
+        205 => "Reset Content",
+        206 => "Partial Content",
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        402 => "Payment Required",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        422 => "Unprocessable Content",
+        426 => "Upgrade Required",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        _ => "",
+    }
+}
+
+/// Replaces the characters that would end a header line early, for a value
+/// taken from a request not to inject headers or a body into the response.
+fn header_value(value: &str) -> String {
+    value.replace(['\r', '\n', '\0'], " ")
+}
+
+/// Writes a `name: value` header line at the end of `buf`.
+fn write_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(b": ");
+    buf.extend_from_slice(value.as_bytes());
+    buf.extend_from_slice(b"\r\n");
+}
+
+/// Writes `n` in `radix`, 10 or 16, at the end of `buf` without going through
+/// a formatted `String`.
+fn write_integer(buf: &mut Vec<u8>, mut n: usize, radix: usize) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    // usize::MAX is 20 digits long in decimal
+    let mut digits = [0; 20];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = DIGITS[n % radix];
+        n /= radix;
+        if n == 0 {
+            break;
         }
-        write!(f, "\r\n")
     }
+    buf.extend_from_slice(&digits[start..]);
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ContentType {
     TextPlain,
+    TextHtml,
     OctetStream,
+    ApplicationJson,
+    /// Any other media type, like `image/png`, lowercased.
+    Other(String),
 }
-impl Display for ContentType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::TextPlain => write!(f, "text/plain"),
-            Self::OctetStream => write!(f, "application/octet-stream"),
+impl FromStr for ContentType {
+    type Err = ContentTypeError;
+    /// Parses the media type of a `Content-Type` value, ignoring its parameters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let media_type = s
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        let is_token = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+        };
+        match media_type.split_once('/') {
+            Some(("text", "plain")) => Ok(Self::TextPlain),
+            Some(("text", "html")) => Ok(Self::TextHtml),
+            Some(("application", "octet-stream")) => Ok(Self::OctetStream),
+            Some(("application", "json")) => Ok(Self::ApplicationJson),
+            Some((kind, subtype)) if is_token(kind) && is_token(subtype) => {
+                Ok(Self::Other(media_type))
+            }
+            _ => Err(ContentTypeError::InvalidMediaType(s.to_string())),
         }
     }
 }
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct ContentLength(usize);
-impl ContentLength {
-    fn from_body(body: &ResponseBody) -> Self {
-        Self(body.length())
+impl ContentType {
+    /// The media type as sent, like `text/plain`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::TextPlain => "text/plain",
+            Self::TextHtml => "text/html",
+            Self::OctetStream => "application/octet-stream",
+            Self::ApplicationJson => "application/json",
+            Self::Other(media_type) => media_type,
+        }
     }
 }
-impl Display for ContentLength {
+impl Display for ContentType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        f.write_str(self.as_str())
     }
 }
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum ContentTypeError {
+    #[error("'{0}' is not a valid media type, expected one like 'text/plain'")]
+    InvalidMediaType(String),
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct ResponseBody(Vec<u8>);
+struct ResponseBody {
+    /// The body as sent, after compression.
+    bytes: Bytes,
+    /// Length of the body before compression.
+    decoded_length: usize,
+}
 impl ResponseBody {
-    fn length(&self) -> usize {
-        self.0.len()
+    const fn length(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    struct Parsed {
+        code: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// Reads `bytes` back with httparse, framing the body by its `Content-Length`.
+    fn parse(bytes: &[u8]) -> Parsed {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut response = httparse::Response::new(&mut headers);
+        let httparse::Status::Complete(head_length) = response.parse(bytes).unwrap() else {
+            panic!("Incomplete response head");
+        };
+        let headers: Vec<(String, String)> = response
+            .headers
+            .iter()
+            .map(|header| {
+                (
+                    header.name.to_string(),
+                    String::from_utf8(header.value.to_vec()).unwrap(),
+                )
+            })
+            .collect();
+        let content_length = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+            .map_or(0, |(_, value)| value.parse::<usize>().unwrap());
+        assert_eq!(bytes.len() - head_length, content_length);
+        Parsed {
+            code: response.code.unwrap(),
+            headers,
+            body: bytes[head_length..].to_vec(),
+        }
+    }
+
+    fn status() -> impl Strategy<Value = ResponseStatus> {
+        prop_oneof![
+            Just(ResponseStatus::Http200),
+            Just(ResponseStatus::Http204),
+            Just(ResponseStatus::Http404),
+            Just(ResponseStatus::Http500),
+            (200u16..=599).prop_map(ResponseStatus::Other),
+        ]
+    }
+
+    fn content_type() -> impl Strategy<Value = ContentType> {
+        prop_oneof![
+            Just(ContentType::TextPlain),
+            Just(ContentType::TextHtml),
+            Just(ContentType::OctetStream),
+            Just(ContentType::ApplicationJson),
+            "[a-z]{1,8}/[a-z0-9.+-]{1,12}".prop_map(ContentType::Other),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_response_round_trip(
+            status in status(),
+            body in proptest::option::of((any::<String>(), content_type(), any::<bool>())),
+            headers in proptest::collection::vec(("X-[A-Za-z0-9-]{1,16}", "[!-~]([ -~]{0,30}[!-~])?"), 0..8),
+            close in any::<bool>(),
+        ) {
+            let mut builder = HTTPResponse::new_builder(status);
+            let body = body.filter(|_| status.allows_body());
+            if let Some((content, content_type, gzip)) = &body {
+                let encoding = if *gzip { vec![Encoding::Gzip] } else { vec![] };
+                builder = builder.with_body(content, content_type.clone(), &encoding);
+            }
+            for (name, value) in &headers {
+                builder = builder.with_header(name, value);
+            }
+            let mut response = builder.build();
+            if close {
+                response = response.with_connection_close();
+            }
+
+            let parsed = parse(&response.as_http_bytes());
+            prop_assert_eq!(parsed.code, status.code());
+            for header in &headers {
+                prop_assert!(parsed.headers.contains(header));
+            }
+            prop_assert_eq!(
+                parsed.headers.contains(&("Connection".to_string(), "close".to_string())),
+                close
+            );
+            match body {
+                Some((content, content_type, gzip)) => {
+                    prop_assert!(parsed
+                        .headers
+                        .contains(&("Content-Type".to_string(), content_type.to_string())));
+                    let body = if gzip {
+                        let mut decoded = String::new();
+                        GzDecoder::new(&parsed.body[..]).read_to_string(&mut decoded).unwrap();
+                        decoded
+                    } else {
+                        String::from_utf8(parsed.body).unwrap()
+                    };
+                    prop_assert_eq!(body, content);
+                }
+                None => prop_assert!(parsed.body.is_empty()),
+            }
+        }
+
+        #[test]
+        fn test_header_values_cannot_inject(value in any::<String>()) {
+            let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+                .with_body("body", ContentType::TextPlain, &[])
+                .with_header("X-Value", &value)
+                .build();
+            let bytes = response.as_http_bytes();
+            let mut headers = [httparse::EMPTY_HEADER; 8];
+            let mut parsed = httparse::Response::new(&mut headers);
+            if let Ok(httparse::Status::Complete(head_length)) = parsed.parse(&bytes) {
+                prop_assert_eq!(parsed.headers.len(), 3);
+                prop_assert_eq!(&bytes[head_length..], b"body");
+            } else {
+                // httparse refuses control characters left in the value, but
+                // the head must still end right before the body
+                prop_assert!(bytes.ends_with(b"\r\n\r\nbody"));
+                prop_assert_eq!(bytes.windows(4).filter(|w| w == b"\r\n\r\n").count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_head_and_body_bytes() {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body("body", ContentType::TextPlain, &[])
+            .build();
+        assert_eq!(
+            response.head_bytes(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 4\r\n\r\n"
+        );
+        assert_eq!(response.body_bytes(), b"body");
+        assert_eq!(
+            [response.head_bytes().as_slice(), response.body_bytes()].concat(),
+            response.as_http_bytes()
+        );
+    }
+
+    #[test]
+    fn test_response_accessors() {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http201)
+            .with_body("created", ContentType::ApplicationJson, &[Encoding::Gzip])
+            .with_header("ETag", "\"1\"")
+            .build();
+        assert_eq!(response.status(), ResponseStatus::Http201);
+        assert_eq!(response.header("etag"), Some("\"1\""));
+        assert_eq!(response.header("Location"), None);
+        assert_eq!(response.content_type(), Some(ContentType::ApplicationJson));
+        assert_eq!(response.content_encoding(), Some(Encoding::Gzip));
+        assert_eq!(response.body(), response.body_bytes());
+        assert_eq!(response.decoded_body().unwrap(), b"created");
+
+        let response = HTTPResponse::new_builder(ResponseStatus::Http204).build();
+        assert_eq!(response.content_type(), None);
+        assert_eq!(response.content_encoding(), None);
+        assert!(response.body().is_empty());
+        assert!(response.decoded_body().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_trailers_framing() {
+        let response = HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body("body", ContentType::TextPlain, &[])
+            .build()
+            .with_trailer("Server-Timing", "db;dur=53")
+            .with_trailer("Digest", "sha-256=abc");
+        assert_eq!(
+            String::from_utf8(response.as_http_bytes()).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\nTrailer: Server-Timing, Digest\r\n\r\n\
+             4\r\nbody\r\n0\r\nServer-Timing: db;dur=53\r\nDigest: sha-256=abc\r\n\r\n"
+        );
+        assert_eq!(
+            response.clone().without_trailers().as_http_bytes(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 4\r\n\r\nbody"
+        );
+        assert!(response.without_body().trailer_bytes().is_empty());
+        assert_eq!(
+            HTTPResponse::new_builder(ResponseStatus::Http201)
+                .build()
+                .with_trailer("Digest", "sha-256=abc")
+                .as_http_bytes(),
+            b"HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\nTrailer: Digest\r\n\r\n0\r\nDigest: sha-256=abc\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_head_written_in_one_allocation() {
+        let response = HTTPResponse::new_builder(ResponseStatus::Other(418))
+            .with_body(
+                &"x".repeat(1234),
+                ContentType::Other("application/problem+json".to_string()),
+                &[Encoding::Gzip],
+            )
+            .with_charset(Charset::Utf8)
+            .with_location("/a/long/location/to/somewhere")
+            .build()
+            .with_header("X-Request-Id", "0190a7c4-5bd4-7e21-9f0e-3c1b2a6d8e47")
+            .with_connection_close();
+        let head = response.head_bytes();
+        assert!(head.starts_with(b"HTTP/1.1 418 \r\nContent-Encoding: gzip\r\nContent-Type: application/problem+json; charset=utf-8\r\n"));
+        assert_eq!(head.capacity(), response.head_capacity());
+    }
+
+    #[test]
+    fn test_write_integer() {
+        for (n, radix, expected) in [
+            (0, 10, "0"),
+            (1234, 10, "1234"),
+            (usize::MAX, 10, "18446744073709551615"),
+            (255, 16, "ff"),
+            (4096, 16, "1000"),
+        ] {
+            let mut buf = b"x".to_vec();
+            write_integer(&mut buf, n, radix);
+            assert_eq!(String::from_utf8(buf).unwrap(), format!("x{expected}"));
+        }
+    }
+
+    #[test]
+    fn test_bodiless_response_framing() {
+        assert_eq!(
+            HTTPResponse::new_builder(ResponseStatus::Http201)
+                .build()
+                .as_http_bytes(),
+            b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n"
+        );
+        assert_eq!(
+            HTTPResponse::new_builder(ResponseStatus::Http204)
+                .build()
+                .as_http_bytes(),
+            b"HTTP/1.1 204 No Content\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_informational_response() {
+        assert_eq!(
+            InformationalResponse::CONTINUE.as_http_bytes(),
+            b"HTTP/1.1 100 Continue\r\n\r\n"
+        );
+        assert_eq!(
+            InformationalResponse::early_hints(&["</style.css>; rel=preload; as=style"])
+                .as_http_bytes(),
+            b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload; as=style\r\n\r\n"
+        );
+        assert_eq!(InformationalResponse::new(102).unwrap().code(), 102);
+        for code in [99, 101, 200] {
+            assert_eq!(
+                InformationalResponse::new(code),
+                Err(ResponseStatusError::NotInformational(code))
+            );
+        }
+
+        let response = HTTPResponse::new_builder(ResponseStatus::Http204)
+            .build()
+            .with_informational(InformationalResponse::early_hints(&[
+                "</a.js>; rel=preload",
+            ]))
+            .into_builder()
+            .with_header("X-Kept", "yes")
+            .build();
+        assert_eq!(response.informational().len(), 1);
+        assert!(response.as_http_bytes().starts_with(b"HTTP/1.1 204"));
     }
 }
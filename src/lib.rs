@@ -1,4 +1,49 @@
+pub mod access_log;
+pub mod access_policy;
+pub mod admin;
+pub mod auth;
 pub mod client_handler;
+pub mod config;
+pub mod connection_limit;
+pub mod csrf;
+#[cfg(unix)]
+pub mod daemon;
+pub mod directory;
+pub mod extensions;
+pub mod forwarding;
 mod gzip;
-mod http_request;
-mod http_response;
+pub mod header_map;
+pub mod hooks;
+pub mod http_date;
+pub mod http_request;
+pub mod http_response;
+pub mod log_file;
+pub mod logging;
+pub mod preconditions;
+pub mod proxy_cache;
+// Deserializing needs serde, which comes with the templates
+#[cfg(feature = "templates")]
+pub mod query;
+pub mod rate_limit;
+pub mod request_id;
+pub mod response_cache;
+#[cfg(unix)]
+pub mod restart;
+pub mod routes;
+pub mod server;
+pub mod server_context;
+pub mod shutdown;
+pub mod stats;
+#[cfg(feature = "templates")]
+pub mod templates;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod throttle;
+#[cfg(feature = "http3")]
+pub mod tls;
+pub mod trace_context;
+pub mod typed_headers;
+pub mod upgrade;
+pub mod upstream;
+pub mod uri;
+pub mod user_agent;
@@ -0,0 +1,253 @@
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+/// When a log file is set aside for a fresh one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogRotation {
+    /// The file grows forever.
+    Never,
+    /// At the start of every hour, UTC.
+    Hourly,
+    /// At midnight UTC.
+    #[default]
+    Daily,
+    /// Before a write would take the file past this many bytes.
+    Size(u64),
+}
+impl LogRotation {
+    /// Index of the rotation period `time` falls in, rotations by size have a single period.
+    fn period(self, time: SystemTime) -> u64 {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        match self {
+            Self::Hourly => seconds / 3600,
+            Self::Daily => seconds / 86_400,
+            Self::Never | Self::Size(_) => 0,
+        }
+    }
+}
+impl FromStr for LogRotation {
+    type Err = LogRotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || LogRotationError::InvalidRotation(s.to_string());
+        let rotation = s.trim().to_uppercase();
+        match rotation.as_str() {
+            "NEVER" => return Ok(Self::Never),
+            "HOURLY" => return Ok(Self::Hourly),
+            "DAILY" => return Ok(Self::Daily),
+            _ => {}
+        }
+        let number = rotation.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let multiplier: u64 = match rotation[number.len()..].trim_end_matches('B') {
+            "" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => return Err(invalid()),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .filter(|bytes| *bytes > 0)
+            .map(Self::Size)
+            .ok_or_else(invalid)
+    }
+}
+impl Display for LogRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Never => write!(f, "never"),
+            Self::Hourly => write!(f, "hourly"),
+            Self::Daily => write!(f, "daily"),
+            Self::Size(bytes) => write!(f, "{bytes}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum LogRotationError {
+    #[error(
+        "'{0}' is not a log rotation, expected 'never', 'hourly', 'daily' or a size like '10MB'"
+    )]
+    InvalidRotation(String),
+}
+
+/// A log file that rotates itself, the way logrotate would.
+///
+/// Rotated files are renamed `<path>.1`, `<path>.2`, ... from the newest to the
+/// oldest, and only `retention` of them are kept.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: String,
+    file: File,
+    size: u64,
+    period: u64,
+    rotation: LogRotation,
+    retention: usize,
+}
+impl RotatingFile {
+    /// Opens `path` for appending, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn open(path: &str, rotation: LogRotation, retention: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+        Ok(Self {
+            path: path.to_string(),
+            file,
+            size: metadata.len(),
+            period: rotation.period(modified),
+            rotation,
+            retention,
+        })
+    }
+
+    fn write_at(&mut self, buf: &[u8], now: SystemTime) -> io::Result<usize> {
+        let incoming = u64::try_from(buf.len()).unwrap_or(u64::MAX);
+        let due = match self.rotation {
+            LogRotation::Never => false,
+            LogRotation::Size(max) => self.size > 0 && self.size.saturating_add(incoming) > max,
+            LogRotation::Hourly | LogRotation::Daily => self.rotation.period(now) != self.period,
+        };
+        if due {
+            self.rotate()?;
+        }
+        self.period = self.rotation.period(now);
+        let written = self.file.write(buf)?;
+        self.size += u64::try_from(written).unwrap_or(u64::MAX);
+        Ok(written)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.retention == 0 {
+            remove_if_exists(&self.path)?;
+        } else {
+            // Oldest first so no file is overwritten
+            remove_if_exists(&self.rotated_path(self.retention))?;
+            for index in (1..self.retention).rev() {
+                rename_if_exists(&self.rotated_path(index), &self.rotated_path(index + 1))?;
+            }
+            rename_if_exists(&self.path, &self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> String {
+        format!("{}.{index}", self.path)
+    }
+}
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, SystemTime::now())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn remove_if_exists(path: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &str, to: &str) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn log_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("http-server-log-file-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("server.log").to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_log_rotation_from_str() {
+        assert_eq!("daily".parse(), Ok(LogRotation::Daily));
+        assert_eq!("Hourly".parse(), Ok(LogRotation::Hourly));
+        assert_eq!("4096".parse(), Ok(LogRotation::Size(4096)));
+        assert_eq!("10MB".parse(), Ok(LogRotation::Size(10 << 20)));
+        assert_eq!("512k".parse(), Ok(LogRotation::Size(512 << 10)));
+        assert!(matches!(
+            "weekly".parse::<LogRotation>(),
+            Err(LogRotationError::InvalidRotation(_))
+        ));
+        assert!("0".parse::<LogRotation>().is_err());
+    }
+
+    #[test]
+    fn test_rotating_file_rotates_by_size_and_keeps_retention() {
+        let path = log_path("size");
+        let mut file = RotatingFile::open(&path, LogRotation::Size(10), 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(format!("{path}.1")).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(format!("{path}.2")).unwrap(), "second\n");
+        assert!(fs::metadata(format!("{path}.3")).is_err());
+    }
+
+    #[test]
+    fn test_rotating_file_rotates_daily() {
+        let path = log_path("daily");
+        let mut file = RotatingFile::open(&path, LogRotation::Daily, 1).unwrap();
+        let today = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        let tomorrow = UNIX_EPOCH + Duration::from_secs(971_272_536);
+        file.write_at(b"today\n", today).unwrap();
+        file.write_at(b"still today\n", today).unwrap();
+        file.write_at(b"tomorrow\n", tomorrow).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomorrow\n");
+        assert_eq!(
+            fs::read_to_string(format!("{path}.1")).unwrap(),
+            "today\nstill today\n"
+        );
+    }
+
+    #[test]
+    fn test_rotating_file_appends_on_reopen() {
+        let path = log_path("reopen");
+        RotatingFile::open(&path, LogRotation::Never, 1)
+            .unwrap()
+            .write_all(b"before\n")
+            .unwrap();
+        RotatingFile::open(&path, LogRotation::Never, 1)
+            .unwrap()
+            .write_all(b"after\n")
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "before\nafter\n");
+    }
+}
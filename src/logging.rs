@@ -0,0 +1,214 @@
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use thiserror::Error;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    filter::ParseError,
+    fmt::{format::FmtSpan, writer::BoxMakeWriter},
+    util::{SubscriberInitExt, TryInitError},
+    EnvFilter,
+};
+
+use crate::log_file::RotatingFile;
+
+/// Level used when neither `log_level` nor `RUST_LOG` is set.
+const DEFAULT_FILTER: &str = "info";
+
+/// How diagnostic logs are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = LogFormatError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            invalid_format => Err(LogFormatError::InvalidFormat(invalid_format.to_string())),
+        }
+    }
+}
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pretty => write!(f, "pretty"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum LogFormatError {
+    #[error("'{0}' is not a log format, expected 'pretty' or 'json'")]
+    InvalidFormat(String),
+}
+
+/// Changes the levels of the installed subscriber while the server runs.
+#[derive(Clone)]
+pub struct LogFilter {
+    directives: Arc<Mutex<String>>,
+    reload: Arc<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>,
+}
+impl LogFilter {
+    /// The directives logs are filtered with, like `info`.
+    #[must_use]
+    pub fn directives(&self) -> String {
+        self.directives
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Filters logs with `directives` from now on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `directives` isn't a valid filter, see [`validate_filter`],
+    /// or the subscriber is gone.
+    pub fn set(&self, directives: &str) -> Result<(), LogFilterError> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| LogFilterError::InvalidFilter(e.to_string()))?;
+        (self.reload)(filter).map_err(LogFilterError::Reload)?;
+        *self
+            .directives
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = directives.to_string();
+        Ok(())
+    }
+}
+impl Debug for LogFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogFilter")
+            .field("directives", &self.directives())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum LogFilterError {
+    #[error("Invalid log filter: {0}")]
+    InvalidFilter(String),
+    #[error("Can't change the log filter: {0}")]
+    Reload(String),
+}
+
+/// Checks that `directives` is a valid filter, like `warn` or
+/// `info,http_server_starter_rust::client_handler=error`.
+///
+/// # Errors
+///
+/// Returns an error describing the first invalid directive.
+pub fn validate_filter(directives: &str) -> Result<(), ParseError> {
+    EnvFilter::try_new(directives).map(|_| ())
+}
+
+/// Installs the global subscriber printing logs to `file`, or to the standard
+/// error leaving the standard output to the access log.
+///
+/// Levels and targets come from `filter` when given, else from `RUST_LOG`, and
+/// default to `info`. Connection and request spans are logged when they close,
+/// with their fields and how long they took.
+///
+/// # Returns
+///
+/// Returns the filter of the subscriber, and the guard of the thread writing to `file`, logs still queued are lost unless it is kept until exit.
+///
+/// # Errors
+///
+/// Returns an error if a global subscriber is already installed.
+pub fn init(
+    format: LogFormat,
+    filter: Option<&str>,
+    file: Option<RotatingFile>,
+) -> Result<(LogFilter, Option<WorkerGuard>), TryInitError> {
+    let filter = filter
+        .map_or_else(
+            || EnvFilter::try_from_default_env().ok(),
+            |directives| EnvFilter::try_new(directives).ok(),
+        )
+        .unwrap_or_else(|| EnvFilter::new(DEFAULT_FILTER));
+    let directives = Arc::new(Mutex::new(filter.to_string()));
+    let (writer, guard) = file.map_or_else(
+        || (BoxMakeWriter::new(std::io::stderr), None),
+        |file| {
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            (BoxMakeWriter::new(writer), Some(guard))
+        },
+    );
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(guard.is_none())
+        .with_writer(writer);
+    // The handles of both formats have different types, they are called the same way
+    let reload: Arc<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync> = match format {
+        LogFormat::Pretty => {
+            let builder = builder.with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.finish().try_init()?;
+            Arc::new(move |filter| handle.reload(filter).map_err(|e| e.to_string()))
+        }
+        LogFormat::Json => {
+            let builder = builder.json().with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.finish().try_init()?;
+            Arc::new(move |filter| handle.reload(filter).map_err(|e| e.to_string()))
+        }
+    };
+    Ok((LogFilter { directives, reload }, guard))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format_from_str() {
+        assert_eq!("pretty".parse(), Ok(LogFormat::Pretty));
+        assert_eq!("JSON".parse(), Ok(LogFormat::Json));
+        assert!(matches!(
+            "xml".parse::<LogFormat>(),
+            Err(LogFormatError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_filter() {
+        assert!(validate_filter("warn").is_ok());
+        assert!(validate_filter("info,http_server_starter_rust::client_handler=error").is_ok());
+        assert!(validate_filter("info,client_handler=loud").is_err());
+    }
+
+    #[test]
+    fn test_log_filter_set() {
+        let reloaded = Arc::new(Mutex::new(Vec::new()));
+        let log_filter = LogFilter {
+            directives: Arc::new(Mutex::new("info".to_string())),
+            reload: Arc::new({
+                let reloaded = Arc::clone(&reloaded);
+                move |filter: EnvFilter| {
+                    reloaded.lock().unwrap().push(filter.to_string());
+                    Ok(())
+                }
+            }),
+        };
+        assert!(matches!(
+            log_filter.set("info,client_handler=loud"),
+            Err(LogFilterError::InvalidFilter(_))
+        ));
+        assert_eq!(log_filter.directives(), "info");
+        log_filter.set("debug").unwrap();
+        assert_eq!(log_filter.directives(), "debug");
+        assert_eq!(*reloaded.lock().unwrap(), ["debug"]);
+    }
+}
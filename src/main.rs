@@ -1,37 +1,76 @@
 use std::env;
 
 use anyhow::{Context, Result};
-use http_server_starter_rust::client_handler::ClientHandler;
-use tokio::net::TcpListener;
+use http_server_starter_rust::{
+    config::{Config, RuntimeFlavor},
+    log_file::RotatingFile,
+    logging::{self, LogFilter},
+    server::Server,
+};
+use tokio::runtime::{Builder, Runtime};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    println!("Logs from your program will appear here!");
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let config = Config::load(&args).context("Can't load configuration")?;
+    #[cfg(unix)]
+    let _pid_file = {
+        use http_server_starter_rust::daemon::{self, PidFile};
 
-    let directory = env::args()
-        .rposition(|arg| arg == "--directory")
-        .map(|dir_arg_position| {
-            env::args()
-                .nth(dir_arg_position + 1)
-                .expect("Directory argument given but no directory given")
-        });
-    if let Some(dir) = directory.clone() {
-        let _ = tokio::fs::read_dir(dir)
-            .await
-            .expect("Can't read directory provided");
+        if config.daemon && !daemon::is_daemonized() {
+            let pid = daemon::spawn(&args, &config)?;
+            println!("Server running in the background with PID {pid}");
+            return Ok(());
+        }
+        if config.daemon {
+            daemon::detach()?;
+        }
+        // Removed on the way out, once the server has drained
+        config
+            .pid_file
+            .as_deref()
+            .map(PidFile::create)
+            .transpose()?
+    };
+    #[cfg(not(unix))]
+    if config.daemon || config.pid_file.is_some() {
+        anyhow::bail!("daemon and pid_file are only supported on unix");
     }
-    let listener = TcpListener::bind("127.0.0.1:4221")
-        .await
-        .context("Can't start listener")?;
+    let log_file = config
+        .log_file
+        .as_deref()
+        .map(|path| RotatingFile::open(path, config.log_rotation, config.log_retention))
+        .transpose()
+        .context("Can't open log file")?;
+    // Dropping the guard flushes the log file, it must outlive the server
+    let (log_filter, _log_guard) =
+        logging::init(config.log_format, config.log_level.as_deref(), log_file)
+            .context("Can't set up logging")?;
+    build_runtime(&config)
+        .context("Can't start the async runtime")?
+        .block_on(serve(args, config, log_filter))
+}
 
-    while let Ok((mut stream, _socket_address)) = listener.accept().await {
-        let directory = directory.clone();
-        tokio::spawn(async move {
-            if let Err(e) = ClientHandler::parse_request(&mut stream, directory.clone()).await {
-                panic!("Error handling client request: {e}");
-            }
-        });
+fn build_runtime(config: &Config) -> std::io::Result<Runtime> {
+    let mut builder = match config.runtime {
+        RuntimeFlavor::MultiThread => Builder::new_multi_thread(),
+        RuntimeFlavor::CurrentThread => Builder::new_current_thread(),
+    };
+    if let Some(worker_threads) = config.worker_threads {
+        builder.worker_threads(worker_threads);
+    }
+    if let Some(max_blocking_threads) = config.max_blocking_threads {
+        builder.max_blocking_threads(max_blocking_threads);
     }
+    builder.enable_all().build()
+}
 
+async fn serve(args: Vec<String>, config: Config, log_filter: LogFilter) -> Result<()> {
+    Server::builder()
+        .config(config)
+        .log_filter(log_filter)
+        .restart_on_sigusr2(args.clone())
+        .reload_on_sighup(args)
+        .run()
+        .await?;
     Ok(())
 }
@@ -0,0 +1,176 @@
+use std::{fs::Metadata, time::UNIX_EPOCH};
+
+use crate::{http_date::HttpDate, http_request::RequestHeader, typed_headers::EntityTag};
+
+/// Strong entity tag of a file, from its size and modification time.
+///
+/// Any write through the server changes the modification time, so the tag
+/// changes with the content without the file having to be read.
+#[must_use]
+pub fn etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!(
+        "\"{:x}-{:x}.{:x}\"",
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )
+}
+
+/// The tag of [`etag`] as preconditions compare it, `None`, matching no tag,
+/// if it can't be parsed back.
+fn entity_tag(metadata: &Metadata) -> Option<EntityTag> {
+    etag(metadata).parse().ok()
+}
+
+/// `Last-Modified` date of a file, if the platform records one.
+#[must_use]
+pub fn last_modified(metadata: &Metadata) -> Option<HttpDate> {
+    metadata.modified().ok().map(HttpDate::from)
+}
+
+/// Evaluates `If-Match` and `If-Unmodified-Since` (RFC 9110 section 13.2.2)
+/// against the file a request targets, `None` when it doesn't exist yet.
+///
+/// # Returns
+///
+/// Returns `false` if the client's copy is outdated and the request must be refused.
+pub(crate) fn hold(request_header: &RequestHeader, current: Option<&Metadata>) -> bool {
+    if let Some(if_match) = request_header.if_match() {
+        // Weak tags never match with the strong comparison writes require
+        return current
+            .and_then(entity_tag)
+            .is_some_and(|current| if_match.matches_strong(&current));
+    }
+    let Some(if_unmodified_since) = request_header
+        .get("If-Unmodified-Since")
+        .and_then(|date| date.parse::<HttpDate>().ok())
+    else {
+        return true;
+    };
+    current
+        .and_then(last_modified)
+        .is_none_or(|last_modified| last_modified <= if_unmodified_since)
+}
+
+/// Evaluates `If-None-Match`, or else `If-Modified-Since` (RFC 9110 section
+/// 13.2.2), against the file a `GET` or `HEAD` request reads.
+///
+/// # Returns
+///
+/// Returns `true` if the client's copy is current and can be answered with
+/// `304 Not Modified`.
+pub(crate) fn not_modified(request_header: &RequestHeader, current: &Metadata) -> bool {
+    if let Some(if_none_match) = request_header.if_none_match() {
+        // Reads use the weak comparison, the client may hold a weak tag of ours
+        return entity_tag(current).is_some_and(|current| if_none_match.matches_weak(&current));
+    }
+    let Some(if_modified_since) = request_header
+        .get("If-Modified-Since")
+        .and_then(|date| date.parse::<HttpDate>().ok())
+    else {
+        return false;
+    };
+    last_modified(current).is_some_and(|last_modified| last_modified <= if_modified_since)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    fn header(lines: &str) -> RequestHeader {
+        format!("PUT /files/a HTTP/1.1\r\nHost: localhost\r\n{lines}\r\n")
+            .parse()
+            .unwrap()
+    }
+
+    fn file(name: &str) -> Metadata {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, "content").unwrap();
+        std::fs::metadata(path).unwrap()
+    }
+
+    #[test]
+    fn test_hold_without_preconditions() {
+        assert!(hold(&header(""), None));
+        assert!(hold(
+            &header(""),
+            Some(&file("http-server-preconditions-none"))
+        ));
+    }
+
+    #[test]
+    fn test_hold_if_match() {
+        let metadata = file("http-server-preconditions-if-match");
+        let current = etag(&metadata);
+        assert!(hold(
+            &header(&format!("If-Match: \"other\", {current}\r\n")),
+            Some(&metadata)
+        ));
+        assert!(!hold(&header("If-Match: \"other\"\r\n"), Some(&metadata)));
+        assert!(!hold(
+            &header(&format!("If-Match: W/{current}\r\n")),
+            Some(&metadata)
+        ));
+        assert!(hold(&header("If-Match: *\r\n"), Some(&metadata)));
+        assert!(!hold(&header("If-Match: *\r\n"), None));
+    }
+
+    #[test]
+    fn test_hold_if_unmodified_since() {
+        let metadata = file("http-server-preconditions-if-unmodified-since");
+        let modified = last_modified(&metadata).unwrap();
+        let before = HttpDate::from(SystemTime::from(modified) - Duration::from_mins(1));
+        assert!(hold(
+            &header(&format!("If-Unmodified-Since: {modified}\r\n")),
+            Some(&metadata)
+        ));
+        assert!(!hold(
+            &header(&format!("If-Unmodified-Since: {before}\r\n")),
+            Some(&metadata)
+        ));
+        assert!(hold(
+            &header(&format!("If-Unmodified-Since: {before}\r\n")),
+            None
+        ));
+        assert!(hold(
+            &header("If-Unmodified-Since: yesterday\r\n"),
+            Some(&metadata)
+        ));
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let metadata = file("http-server-preconditions-not-modified");
+        let current = etag(&metadata);
+        let modified = last_modified(&metadata).unwrap();
+        let before = HttpDate::from(SystemTime::from(modified) - Duration::from_mins(1));
+        assert!(!not_modified(&header(""), &metadata));
+        assert!(not_modified(
+            &header(&format!("If-None-Match: \"other\", W/{current}\r\n")),
+            &metadata
+        ));
+        assert!(not_modified(&header("If-None-Match: *\r\n"), &metadata));
+        assert!(!not_modified(
+            &header(&format!(
+                "If-None-Match: \"other\"\r\nIf-Modified-Since: {modified}\r\n"
+            )),
+            &metadata
+        ));
+        assert!(not_modified(
+            &header(&format!("If-Modified-Since: {modified}\r\n")),
+            &metadata
+        ));
+        assert!(!not_modified(
+            &header(&format!("If-Modified-Since: {before}\r\n")),
+            &metadata
+        ));
+    }
+}
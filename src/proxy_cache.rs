@@ -0,0 +1,472 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{header_map::HeaderMap, http_date::HttpDate, typed_headers::CacheControl};
+
+/// Statuses a cache may store without explicit freshness, their freshness
+/// then coming from `Expires` (RFC 9110 section 15.1).
+const CACHEABLE_BY_DEFAULT: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Parameters of the 64-bit FNV-1a hash naming the files.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Extension of the files responses are written to before being renamed.
+const PARTIAL_EXTENSION: &str = "partial";
+
+/// Numbers the files being written, for concurrent stores of the same
+/// response not to write to the same one.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Responses of upstream servers kept on disk, one file each under a
+/// directory, for the server to answer as a small caching proxy.
+///
+/// Proxied `GET` requests go through it when `proxy_cache_directory` is
+/// set. [`storable`] tells which responses to keep,
+/// [`CachedResponse::is_fresh`] whether one can be sent as is, and
+/// [`CachedResponse::revalidation_headers`] how to ask upstream whether a
+/// stale one is still valid, a `304 Not Modified` being merged back with
+/// [`CachedResponse::revalidated`]. A stale one may still be sent while it
+/// is revalidated or when upstream fails, as
+/// [`CachedResponse::usable_while_revalidating`] and
+/// [`CachedResponse::usable_on_error`] tell.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProxyCache {
+    directory: PathBuf,
+}
+
+/// A response as stored, with the time it was received or last revalidated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    pub stored: SystemTime,
+}
+
+/// Whether a response with `status` and `headers` may be stored by a shared
+/// cache (RFC 9111 section 3): neither `no-store` nor `private`, and either
+/// cacheable by default or with an explicit freshness.
+#[must_use]
+pub fn storable(status: u16, headers: &HeaderMap) -> bool {
+    let cache_control = cache_control(headers);
+    !cache_control.no_store
+        && !cache_control.private
+        && (CACHEABLE_BY_DEFAULT.contains(&status)
+            || cache_control.shared_max_age().is_some()
+            || headers.contains("Expires"))
+}
+
+fn cache_control(headers: &HeaderMap) -> CacheControl {
+    headers
+        .get("Cache-Control")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default()
+}
+
+fn date(headers: &HeaderMap, name: &str) -> Option<SystemTime> {
+    headers
+        .get(name)?
+        .parse::<HttpDate>()
+        .ok()
+        .map(SystemTime::from)
+}
+
+impl CachedResponse {
+    /// How long the response stays fresh (RFC 9111 section 4.2.1): its
+    /// `s-maxage` or `max-age`, or else the time from its `Date` to its
+    /// `Expires`. `None` without any, the response then having to be
+    /// revalidated before it is sent.
+    #[must_use]
+    pub fn freshness_lifetime(&self) -> Option<Duration> {
+        if let Some(seconds) = cache_control(&self.headers).shared_max_age() {
+            return Some(Duration::from_secs(seconds));
+        }
+        // An invalid `Expires`, like `0`, means already expired
+        let expires = self.headers.get("Expires")?;
+        let Ok(expires) = expires.parse::<HttpDate>().map(SystemTime::from) else {
+            return Some(Duration::ZERO);
+        };
+        let date = date(&self.headers, "Date").unwrap_or(self.stored);
+        Some(expires.duration_since(date).unwrap_or_default())
+    }
+
+    /// Age of the response at `now`: the `Age` upstream sent plus the time
+    /// it has been stored.
+    #[must_use]
+    pub fn age(&self, now: SystemTime) -> Duration {
+        let upstream = self
+            .headers
+            .get("Age")
+            .and_then(|age| age.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        upstream + now.duration_since(self.stored).unwrap_or_default()
+    }
+
+    /// Whether the response can be sent at `now` without asking upstream,
+    /// `no-cache` requiring it be revalidated every time.
+    #[must_use]
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        !cache_control(&self.headers).no_cache
+            && self
+                .freshness_lifetime()
+                .is_some_and(|lifetime| self.age(now) < lifetime)
+    }
+
+    /// Whether the response, stale at `now`, can still be sent while it is
+    /// revalidated in the background, per its `stale-while-revalidate`
+    /// (RFC 5861 section 3).
+    #[must_use]
+    pub fn usable_while_revalidating(&self, now: SystemTime) -> bool {
+        let cache_control = cache_control(&self.headers);
+        !cache_control.no_cache && self.within(now, cache_control.stale_while_revalidate)
+    }
+
+    /// Whether the response, stale at `now`, can still be sent instead of an
+    /// error from upstream, a `500`, `502`, `503` or `504` or no response at
+    /// all, per its `stale-if-error` (RFC 5861 section 4).
+    #[must_use]
+    pub fn usable_on_error(&self, now: SystemTime) -> bool {
+        self.within(now, cache_control(&self.headers).stale_if_error)
+    }
+
+    /// Whether the age at `now` is within `stale` seconds past the freshness lifetime.
+    fn within(&self, now: SystemTime, stale: Option<u64>) -> bool {
+        let (Some(lifetime), Some(stale)) = (self.freshness_lifetime(), stale) else {
+            return false;
+        };
+        self.age(now) < lifetime + Duration::from_secs(stale)
+    }
+
+    /// The headers making a request for the response conditional, for
+    /// upstream to answer `304 Not Modified` if it is still valid:
+    /// `If-None-Match` with its `ETag` and `If-Modified-Since` with its `Last-Modified`.
+    #[must_use]
+    pub fn revalidation_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = self.headers.get("ETag") {
+            headers.insert("If-None-Match", etag);
+        }
+        if let Some(last_modified) = self.headers.get("Last-Modified") {
+            headers.insert("If-Modified-Since", last_modified);
+        }
+        headers
+    }
+
+    /// The response once upstream confirmed it with a `304 Not Modified`
+    /// received at `now`, updated with the headers of the `304` (RFC 9111
+    /// section 4.3.4).
+    #[must_use]
+    pub fn revalidated(mut self, not_modified: &HeaderMap, now: SystemTime) -> Self {
+        for (name, _) in not_modified.iter() {
+            // The length is the stored body's, not the empty one of the 304
+            if name.eq_ignore_ascii_case("Content-Length") {
+                continue;
+            }
+            self.headers.remove(name);
+            for value in not_modified.get_all(name) {
+                self.headers.append(name, value);
+            }
+        }
+        self.stored = now;
+        self
+    }
+
+    /// The response as written to disk: the key it is stored under, the
+    /// status and storage time in seconds, the headers, then the body.
+    fn to_bytes(&self, key: &str) -> Vec<u8> {
+        let stored = self
+            .stored
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut bytes = format!("{key}\n{} {stored}\n", self.status).into_bytes();
+        for (name, value) in self.headers.iter() {
+            bytes.extend_from_slice(format!("{name}: {value}\n").as_bytes());
+        }
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    /// Reads back a response written by `to_bytes`, `None` if it is stored
+    /// under another key or corrupt.
+    fn from_bytes(bytes: &[u8], key: &str) -> Option<Self> {
+        let mut rest = bytes;
+        let mut line = || {
+            let end = rest.iter().position(|&byte| byte == b'\n')?;
+            let line = std::str::from_utf8(&rest[..end]).ok()?;
+            rest = &rest[end + 1..];
+            Some(line)
+        };
+        if line()? != key {
+            return None;
+        }
+        let (status, stored) = line()?.split_once(' ')?;
+        let (status, stored) = (status.parse().ok()?, stored.parse().ok()?);
+        let mut headers = HeaderMap::new();
+        loop {
+            let header = line()?;
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(": ")?;
+            headers.append(name, value);
+        }
+        Some(Self {
+            status,
+            headers,
+            body: rest.to_vec(),
+            stored: UNIX_EPOCH + Duration::from_secs(stored),
+        })
+    }
+}
+
+impl ProxyCache {
+    /// A cache keeping its files in `directory`, created when the first
+    /// response is stored.
+    #[must_use]
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    #[must_use]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The file of the response stored under `key`, named after its FNV-1a
+    /// hash, which unlike the standard hasher is the same from one release
+    /// to the next, so responses stored before an upgrade are still found.
+    /// Keys are written in the files, so a collision reads as a miss.
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = key.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        });
+        self.directory.join(format!("{hash:016x}"))
+    }
+
+    /// The response stored under `key`, like the URL it was fetched from,
+    /// fresh or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but can't be read.
+    pub async fn get(&self, key: &str) -> io::Result<Option<CachedResponse>> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(bytes) => Ok(CachedResponse::from_bytes(&bytes, key)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores `response` under `key`, replacing the one stored before.
+    /// The file is written aside under a name of its own, by this process and
+    /// store, then renamed, readers never seeing half of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub async fn put(&self, key: &str, response: &CachedResponse) -> io::Result<()> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        let path = self.path(key);
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        let partial = path.with_extension(format!(
+            "{}-{write}.{PARTIAL_EXTENSION}",
+            std::process::id()
+        ));
+        if let Err(e) = tokio::fs::write(&partial, response.to_bytes(key)).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+        tokio::fs::rename(&partial, &path).await
+    }
+
+    /// Forgets the response stored under `key`.
+    ///
+    /// # Returns
+    ///
+    /// Returns whether a response was stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but can't be removed.
+    pub async fn purge(&self, key: &str) -> io::Result<bool> {
+        match tokio::fs::remove_file(self.path(key)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Forgets every response stored, leaving the files still being written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of responses forgotten.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be listed or a file removed.
+    pub async fn purge_all(&self) -> io::Result<usize> {
+        let mut entries = match tokio::fs::read_dir(&self.directory).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut purged = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let partial = path
+                .extension()
+                .is_some_and(|extension| extension == PARTIAL_EXTENSION);
+            if !partial && entry.file_type().await?.is_file() {
+                tokio::fs::remove_file(path).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: headers.iter().copied().collect(),
+            body: b"cached".to_vec(),
+            stored: UNIX_EPOCH + Duration::from_secs(1_000_000),
+        }
+    }
+
+    #[test]
+    fn test_freshness() {
+        let stored = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let later = |seconds| stored + Duration::from_secs(seconds);
+        let max_age = response(&[("Cache-Control", "max-age=60"), ("Age", "10")]);
+        assert!(max_age.is_fresh(later(49)));
+        assert!(!max_age.is_fresh(later(50)));
+
+        let expires = response(&[
+            ("Date", &HttpDate::from(stored).to_string()),
+            ("Expires", &HttpDate::from(later(30)).to_string()),
+        ]);
+        assert_eq!(expires.freshness_lifetime(), Some(Duration::from_secs(30)));
+        assert!(expires.is_fresh(later(29)));
+        assert!(!response(&[("Expires", "0")]).is_fresh(stored));
+        assert!(!response(&[("Cache-Control", "no-cache, max-age=60")]).is_fresh(stored));
+        assert!(!response(&[]).is_fresh(stored));
+    }
+
+    #[test]
+    fn test_stale_responses() {
+        let stored = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let later = |seconds| stored + Duration::from_secs(seconds);
+        let stale = response(&[(
+            "Cache-Control",
+            "max-age=60, stale-while-revalidate=30, stale-if-error=300",
+        )]);
+        assert!(stale.usable_while_revalidating(later(89)));
+        assert!(!stale.usable_while_revalidating(later(90)));
+        assert!(stale.usable_on_error(later(359)));
+        assert!(!stale.usable_on_error(later(360)));
+        let max_age = response(&[("Cache-Control", "max-age=60")]);
+        assert!(!max_age.usable_while_revalidating(later(60)));
+        assert!(!max_age.usable_on_error(later(60)));
+    }
+
+    #[test]
+    fn test_storable() {
+        let headers = |headers: &[(&str, &str)]| headers.iter().copied().collect::<HeaderMap>();
+        assert!(storable(200, &headers(&[])));
+        assert!(!storable(200, &headers(&[("Cache-Control", "private")])));
+        assert!(!storable(404, &headers(&[("Cache-Control", "no-store")])));
+        assert!(!storable(302, &headers(&[])));
+        assert!(storable(302, &headers(&[("Cache-Control", "max-age=5")])));
+    }
+
+    #[test]
+    fn test_revalidation() {
+        let stale = response(&[
+            ("ETag", "\"v1\""),
+            ("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("Cache-Control", "max-age=0"),
+            ("Content-Length", "6"),
+        ]);
+        assert_eq!(
+            stale.revalidation_headers(),
+            [
+                ("If-None-Match", "\"v1\""),
+                ("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")
+            ]
+            .into_iter()
+            .collect()
+        );
+        let now = stale.stored + Duration::from_secs(100);
+        let revalidated = stale.revalidated(
+            &[("Cache-Control", "max-age=60"), ("Content-Length", "0")]
+                .into_iter()
+                .collect(),
+            now,
+        );
+        assert_eq!(revalidated.headers.get("Content-Length"), Some("6"));
+        assert!(revalidated.is_fresh(now + Duration::from_secs(59)));
+    }
+
+    #[tokio::test]
+    async fn test_store_and_purge() {
+        let directory = std::env::temp_dir().join("http-server-proxy-cache");
+        let _ = std::fs::remove_dir_all(&directory);
+        let cache = ProxyCache::new(&directory);
+        let stored = response(&[("Content-Type", "text/plain"), ("ETag", "\"v1\"")]);
+        assert_eq!(cache.get("http://upstream/a").await.unwrap(), None);
+        cache.put("http://upstream/a", &stored).await.unwrap();
+        cache.put("http://upstream/b", &stored).await.unwrap();
+        assert_eq!(cache.get("http://upstream/a").await.unwrap(), Some(stored));
+
+        assert!(cache.purge("http://upstream/a").await.unwrap());
+        assert!(!cache.purge("http://upstream/a").await.unwrap());
+        // Still being written, not a response yet
+        let partial = directory.join("0000000000000000.1-0.partial");
+        std::fs::write(&partial, "HTTP/1.1").unwrap();
+        assert_eq!(cache.purge_all().await.unwrap(), 1);
+        assert_eq!(cache.get("http://upstream/b").await.unwrap(), None);
+        assert!(partial.exists());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_stores() {
+        let directory = std::env::temp_dir().join("http-server-proxy-cache-concurrent");
+        let _ = std::fs::remove_dir_all(&directory);
+        let cache = ProxyCache::new(&directory);
+        let stored = |etag| response(&[("ETag", etag)]);
+        let (first, second) = (stored("\"v1\""), stored("\"v2\""));
+        let (a, b) = tokio::join!(
+            cache.put("http://upstream/a", &first),
+            cache.put("http://upstream/a", &second)
+        );
+        a.unwrap();
+        b.unwrap();
+        let cached = cache.get("http://upstream/a").await.unwrap();
+        assert!(cached == Some(first) || cached == Some(second));
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_file_names_are_stable() {
+        let cache = ProxyCache::new("/cache");
+        assert_eq!(cache.path(""), Path::new("/cache/cbf29ce484222325"));
+        assert_eq!(cache.path("a"), Path::new("/cache/af63dc4c8601ec8c"));
+    }
+}
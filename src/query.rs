@@ -0,0 +1,280 @@
+use serde::{
+    de::{
+        self, value::StringDeserializer, DeserializeOwned, IntoDeserializer, Unexpected, Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
+};
+use thiserror::Error;
+
+use crate::{
+    http_request::{percent_decode, HTTPRequest},
+    http_response::{ContentType, HTTPResponse, ResponseStatus},
+};
+
+/// The query string of a request deserialized into `T`, like a struct with
+/// `sort: Option<String>` and `limit: u32` fields for `?sort=name&limit=10`,
+/// so handlers don't parse parameters by hand.
+///
+/// Values are percent-decoded, with `+` standing for a space as in forms.
+/// Parameters `T` doesn't have are ignored unless it denies unknown fields,
+/// and a parameter without `=` has an empty value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Query<T>(pub T);
+impl<T: DeserializeOwned> Query<T> {
+    /// Deserializes the query string of `request`, an empty one when it has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `QueryError` if the query isn't validly encoded
+    /// or doesn't fit `T`, see `QueryError::response` to answer it.
+    pub fn from_request(request: &HTTPRequest) -> Result<Self, QueryError> {
+        Self::parse(request.line().uri().query().unwrap_or(""))
+    }
+
+    /// Deserializes `query`, a query string without its leading `?`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `QueryError` if `query` isn't validly encoded or
+    /// doesn't fit `T`.
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (name, value) = param.split_once('=').unwrap_or((param, ""));
+                Ok((decode(name)?, decode(value)?))
+            })
+            .collect::<Result<Vec<_>, QueryError>>()?;
+        T::deserialize(QueryDeserializer { params }).map(Self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum QueryError {
+    #[error("'{0}' in the query string is not validly percent-encoded")]
+    InvalidEncoding(String),
+    #[error("Query parameter '{0}' is invalid: {1}")]
+    InvalidParameter(String, String),
+    #[error("Query string is invalid: {0}")]
+    Invalid(String),
+}
+impl QueryError {
+    /// A `400 Bad Request` telling the client what is wrong with its query.
+    #[must_use]
+    pub fn response(&self) -> HTTPResponse {
+        HTTPResponse::new_builder(ResponseStatus::Http400)
+            .with_body(&self.to_string(), ContentType::TextPlain, &[])
+            .build()
+    }
+}
+impl de::Error for QueryError {
+    fn custom<M: std::fmt::Display>(message: M) -> Self {
+        Self::Invalid(message.to_string())
+    }
+}
+
+fn decode(component: &str) -> Result<String, QueryError> {
+    percent_decode(&component.replace('+', " "))
+        .map_err(|_| QueryError::InvalidEncoding(component.to_string()))
+}
+
+/// Reads the parameters as a map from their names to their values.
+struct QueryDeserializer {
+    params: Vec<(String, String)>,
+}
+impl<'de> Deserializer<'de> for QueryDeserializer {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(Params {
+            params: self.params.into_iter(),
+            value: None,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Params {
+    params: std::vec::IntoIter<(String, String)>,
+    /// The parameter whose name was just read.
+    value: Option<(String, String)>,
+}
+impl<'de> de::MapAccess<'de> for Params {
+    type Error = QueryError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((name, value)) = self.params.next() else {
+            return Ok(None);
+        };
+        let key = seed.deserialize(name.clone().into_deserializer())?;
+        self.value = Some((name, value));
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (name, value) = self
+            .value
+            .take()
+            .ok_or_else(|| QueryError::Invalid("value asked before its name".to_string()))?;
+        seed.deserialize(Value(value)).map_err(|e| match e {
+            QueryError::Invalid(message) => QueryError::InvalidParameter(name, message),
+            e => e,
+        })
+    }
+}
+
+/// A parameter value, parsed into the type asked for.
+struct Value(String);
+impl Value {
+    fn parse<'de, T: std::str::FromStr, V: Visitor<'de>>(
+        self,
+        visitor: &V,
+    ) -> Result<T, QueryError> {
+        self.0
+            .parse()
+            .map_err(|_| de::Error::invalid_value(Unexpected::Str(&self.0), visitor))
+    }
+
+    fn string(self) -> StringDeserializer<QueryError> {
+        self.0.into_deserializer()
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let parsed = self.parse(&visitor)?;
+                visitor.$visit(parsed)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.string().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn test_query_parse() {
+        let Query(params) =
+            Query::<BTreeMap<String, String>>::parse("sort=last+name&limit=10&flag&q=caf%C3%A9&&")
+                .unwrap();
+        assert_eq!(
+            params.into_iter().collect::<Vec<_>>(),
+            [
+                ("flag".to_string(), String::new()),
+                ("limit".to_string(), "10".to_string()),
+                ("q".to_string(), "café".to_string()),
+                ("sort".to_string(), "last name".to_string()),
+            ]
+        );
+
+        let Query(limits) =
+            Query::<BTreeMap<String, Option<u32>>>::parse("limit=10&offset=0").unwrap();
+        assert_eq!(limits.get("limit"), Some(&Some(10)));
+        assert_eq!(
+            Query::<BTreeMap<String, bool>>::parse("sorted=true").unwrap(),
+            Query(BTreeMap::from([("sorted".to_string(), true)]))
+        );
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let error = Query::<BTreeMap<String, u32>>::parse("limit=ten").unwrap_err();
+        assert_eq!(
+            error,
+            QueryError::InvalidParameter(
+                "limit".to_string(),
+                "invalid value: string \"ten\", expected u32".to_string()
+            )
+        );
+        let response = error.response();
+        assert_eq!(response.status(), ResponseStatus::Http400);
+        assert_eq!(
+            response.body_bytes(),
+            b"Query parameter 'limit' is invalid: invalid value: string \"ten\", expected u32"
+        );
+        assert_eq!(
+            Query::<BTreeMap<String, String>>::parse("q=%ZZ"),
+            Err(QueryError::InvalidEncoding("%ZZ".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_query_from_request() {
+        let request = HTTPRequest::parse(
+            "GET /list?limit=5 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            Some(bytes::Bytes::new()),
+            "127.0.0.1:4000".parse().unwrap(),
+            false,
+        )
+        .unwrap();
+        let Query(params) = Query::<BTreeMap<String, u8>>::from_request(&request).unwrap();
+        assert_eq!(params.get("limit"), Some(&5));
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+/// Number of clients tracked before buckets that refilled are forgotten.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// How many requests a client may send: `burst` at once, then `per_second` on average.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+/// What identifies a client for rate limiting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RateLimitKey {
+    /// The address of the peer.
+    #[default]
+    Ip,
+    /// The value of a request header, e.g. an API key or `X-Forwarded-For` behind a proxy.
+    /// Requests without it are keyed by address.
+    Header(String),
+}
+impl FromStr for RateLimitKey {
+    type Err = RateLimitKeyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("ip") {
+            return Ok(Self::Ip);
+        }
+        match s.split_once(':') {
+            Some((prefix, header))
+                if prefix.eq_ignore_ascii_case("header") && !header.is_empty() =>
+            {
+                Ok(Self::Header(header.trim().to_string()))
+            }
+            _ => Err(RateLimitKeyError::InvalidKey(s.to_string())),
+        }
+    }
+}
+impl Display for RateLimitKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ip => write!(f, "ip"),
+            Self::Header(header) => write!(f, "header:{header}"),
+        }
+    }
+}
+
+/// Where a client stands after a request the rate limiter let through, left in
+/// the extensions of the request.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RateLimitStatus {
+    /// The address or header value the client is tracked by.
+    pub client: String,
+    pub limit: RateLimit,
+    /// Requests the client may still send at once.
+    pub remaining: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum RateLimitKeyError {
+    #[error("'{0}' is not a rate limit key, expected 'ip' or 'header:<name>'")]
+    InvalidKey(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets shared by every connection, one per client.
+///
+/// The limit is passed on each check so a configuration reload applies to
+/// clients already tracked.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+impl RateLimiter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of clients currently tracked.
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .len()
+    }
+
+    /// Forgets the buckets of `client`, those of access policies included, or
+    /// of every client when `None`, giving them their full burst back.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of buckets forgotten.
+    pub fn evict(&self, client: Option<&str>) -> usize {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let before = buckets.len();
+        match client {
+            // Policies key their buckets by `prefix client`
+            Some(client) => buckets.retain(|key, _| {
+                key != client && key.rsplit_once(' ').is_none_or(|(_, key)| key != client)
+            }),
+            None => buckets.clear(),
+        }
+        before - buckets.len()
+    }
+
+    /// Takes a token from the client's bucket.
+    ///
+    /// # Returns
+    ///
+    /// Returns the whole tokens left in the bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns how long the client should wait before retrying if its bucket is empty.
+    pub fn check(&self, client: &str, limit: RateLimit) -> Result<u32, Duration> {
+        self.check_at(client, limit, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, limit: RateLimit, now: Instant) -> Result<u32, Duration> {
+        let rate = f64::from(limit.per_second.max(1));
+        let burst = f64::from(limit.burst.max(1));
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| {
+                now.duration_since(bucket.updated)
+                    .as_secs_f64()
+                    .mul_add(rate, bucket.tokens)
+                    < burst
+            });
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = elapsed.mul_add(rate, bucket.tokens).min(burst);
+        bucket.updated = now;
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            // Never above the burst, which is a u32
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Ok(bucket.tokens.floor() as u32)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        };
+        drop(buckets);
+        result
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        per_second: 2,
+        burst: 3,
+    };
+
+    #[test]
+    fn test_rate_limit_key_from_str() {
+        assert_eq!("ip".parse(), Ok(RateLimitKey::Ip));
+        assert_eq!(
+            "header:X-Api-Key".parse(),
+            Ok(RateLimitKey::Header("X-Api-Key".to_string()))
+        );
+        assert!(matches!(
+            "cookie".parse::<RateLimitKey>(),
+            Err(RateLimitKeyError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_allows_burst_then_limits() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for remaining in [2, 1, 0] {
+            assert_eq!(limiter.check_at("127.0.0.1", LIMIT, now), Ok(remaining));
+        }
+        let retry_after = limiter.check_at("127.0.0.1", LIMIT, now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_check_refills_over_time() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at("127.0.0.1", LIMIT, now).unwrap();
+        }
+        assert!(limiter.check_at("127.0.0.1", LIMIT, now).is_err());
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check_at("127.0.0.1", LIMIT, later).is_ok());
+        assert!(limiter.check_at("127.0.0.1", LIMIT, later).is_err());
+    }
+
+    #[test]
+    fn test_check_keeps_clients_apart() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at("127.0.0.1", LIMIT, now).unwrap();
+        }
+        assert!(limiter.check_at("127.0.0.1", LIMIT, now).is_err());
+        assert!(limiter.check_at("127.0.0.2", LIMIT, now).is_ok());
+    }
+
+    #[test]
+    fn test_evict() {
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for client in [
+            "127.0.0.1",
+            "/files/ 127.0.0.1",
+            "127.0.0.2",
+            "/files/ 127.0.0.12",
+        ] {
+            limiter.check_at(client, LIMIT, now).unwrap();
+        }
+        assert_eq!(limiter.evict(Some("127.0.0.1")), 2);
+        assert_eq!(limiter.tracked_clients(), 2);
+        assert_eq!(limiter.evict(Some("10.0.0.1")), 0);
+        assert_eq!(limiter.evict(None), 2);
+        assert_eq!(limiter.tracked_clients(), 0);
+    }
+}
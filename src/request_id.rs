@@ -0,0 +1,100 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Header carrying the request ID, both ways.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest request ID adopted from a client.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The ID of a request, found in its extensions.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestId(pub String);
+impl RequestId {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Returns a new request ID, unique across restarts of the server.
+///
+/// IDs are made of a prefix drawn when the process starts and a counter, e.g.
+/// `18f2a9c37b1d4e60-0000002a`.
+#[must_use]
+pub fn generate() -> String {
+    static PREFIX: OnceLock<u64> = OnceLock::new();
+    let prefix = PREFIX.get_or_init(|| {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos());
+        // Only the low bits change between two starts, mix the PID in for servers started together
+        u64::try_from(nanos & u128::from(u64::MAX)).unwrap_or_default()
+            ^ (u64::from(std::process::id()) << 40)
+    });
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{prefix:016x}-{count:08x}")
+}
+
+/// Picks the ID of `request`, as read from the client: the `X-Request-Id` it
+/// sent if it is a sane one, a new one otherwise.
+///
+/// IDs from clients are kept to printable ASCII without spaces so they can't
+/// tamper with headers or log lines.
+pub(crate) fn from_request(request: &[u8]) -> String {
+    String::from_utf8_lossy(request)
+        .split("\r\n")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case(REQUEST_ID_HEADER)
+                .then(|| value.trim())
+        })
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id.bytes().all(|byte| byte.is_ascii_graphic())
+        })
+        .map_or_else(generate, ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_is_unique() {
+        let first = generate();
+        let second = generate();
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 25);
+        assert_eq!(first[..16], second[..16]);
+    }
+
+    #[test]
+    fn test_from_request_adopts_client_id() {
+        let request = b"GET / HTTP/1.1\r\nx-request-id: abc-123\r\n\r\n";
+        assert_eq!(from_request(request), "abc-123");
+    }
+
+    #[test]
+    fn test_from_request_replaces_unsafe_id() {
+        let request = b"GET / HTTP/1.1\r\nX-Request-Id: two words\r\n\r\n";
+        assert_ne!(from_request(request), "two words");
+        let long = format!(
+            "GET / HTTP/1.1\r\nX-Request-Id: {}\r\n\r\n",
+            "a".repeat(129)
+        );
+        assert_eq!(from_request(long.as_bytes()).len(), 25);
+        assert_eq!(from_request(b"GET / HTTP/1.1\r\n\r\n").len(), 25);
+    }
+}
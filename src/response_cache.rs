@@ -0,0 +1,382 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{http_request::Encoding, http_response::HTTPResponse};
+
+/// Responses kept in memory so that hot files and computed responses aren't
+/// read or built again for every request, the least recently used going
+/// first once the bodies held reach the capacity.
+///
+/// Once stale, a response may still be sent for a while, see [`Lifetime`].
+#[derive(Debug)]
+pub struct ResponseCache {
+    capacity: u64,
+    max_entry_size: u64,
+    entries: Mutex<Entries>,
+}
+
+/// What a response is cached under: the request target, query included, and
+/// the coding its body was sent with, each coding being a separate entry.
+///
+/// Hosts served from different directories are told apart by their name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CacheKey {
+    host: String,
+    target: String,
+    encoding: Encoding,
+}
+impl CacheKey {
+    #[must_use]
+    pub fn new(target: impl Into<String>, encoding: Encoding) -> Self {
+        Self {
+            host: String::new(),
+            target: target.into(),
+            encoding,
+        }
+    }
+
+    /// The same key for a response to requests addressed to `host`.
+    #[must_use]
+    pub fn for_host(self, host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            ..self
+        }
+    }
+
+    /// The path of the target, without its query.
+    fn path(&self) -> &str {
+        self.target
+            .split_once('?')
+            .map_or(self.target.as_str(), |(path, _)| path)
+    }
+}
+
+/// How long a cached response is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lifetime {
+    /// Time the response is fresh for.
+    pub fresh: Duration,
+    /// Time the response is still sent once stale while a new one is built
+    /// (RFC 5861 section 3).
+    pub stale_while_revalidate: Duration,
+    /// Time the response is still sent once stale when building a new one
+    /// fails (RFC 5861 section 4).
+    pub stale_if_error: Duration,
+}
+impl Lifetime {
+    /// A lifetime without a stale period.
+    #[must_use]
+    pub const fn fresh_for(fresh: Duration) -> Self {
+        Self {
+            fresh,
+            stale_while_revalidate: Duration::ZERO,
+            stale_if_error: Duration::ZERO,
+        }
+    }
+}
+
+/// A response found in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub response: HTTPResponse,
+    /// Time since the response was cached.
+    pub age: Duration,
+    pub staleness: Staleness,
+}
+
+/// What a cached response may be used for at its age.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Staleness {
+    /// It may be sent.
+    Fresh,
+    /// It may be sent while a new one is built, `refresh` being set for the
+    /// first lookup finding it stale, which should build it.
+    Revalidating { refresh: bool },
+    /// It may only be sent if building a new one fails.
+    IfError,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_key: BTreeMap<CacheKey, Entry>,
+    /// Keys by the tick they were last used at, least recently used first.
+    by_use: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    size: u64,
+}
+impl Entries {
+    fn remove(&mut self, key: &CacheKey) -> Option<Entry> {
+        let entry = self.by_key.remove(key)?;
+        self.by_use.remove(&entry.used);
+        self.size -= entry.size;
+        Some(entry)
+    }
+
+    const fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: HTTPResponse,
+    size: u64,
+    stored: Instant,
+    lifetime: Lifetime,
+    /// Whether a lookup found it stale and is building a new one.
+    refreshing: bool,
+    used: u64,
+}
+
+impl ResponseCache {
+    /// A cache holding bodies of up to `capacity` bytes together, none larger
+    /// than `max_entry_size`.
+    #[must_use]
+    pub fn new(capacity: u64, max_entry_size: u64) -> Self {
+        Self {
+            capacity,
+            max_entry_size,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// The response cached under `key` if its lifetime isn't over, along
+    /// with its age and what it may be used for.
+    pub fn get(&self, key: &CacheKey) -> Option<Hit> {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let entry = entries.by_key.get(key)?;
+        let (age, lifetime) = (entry.stored.elapsed(), entry.lifetime);
+        let staleness = if age < lifetime.fresh {
+            Staleness::Fresh
+        } else if age < lifetime.fresh + lifetime.stale_while_revalidate {
+            Staleness::Revalidating {
+                refresh: !entry.refreshing,
+            }
+        } else if age < lifetime.fresh + lifetime.stale_if_error {
+            Staleness::IfError
+        } else {
+            entries.remove(key);
+            return None;
+        };
+        let tick = entries.next_tick();
+        let entry = entries.by_key.get_mut(key)?;
+        let previous = std::mem::replace(&mut entry.used, tick);
+        entry.refreshing |= matches!(staleness, Staleness::Revalidating { .. });
+        let response = entry.response.clone();
+        entries.by_use.remove(&previous);
+        entries.by_use.insert(tick, key.clone());
+        drop(entries);
+        Some(Hit {
+            response,
+            age,
+            staleness,
+        })
+    }
+
+    /// Caches `response` under `key` for `lifetime`, making room by evicting
+    /// the least recently used entries.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if the body is too large to be cached.
+    pub fn insert(&self, key: CacheKey, response: HTTPResponse, lifetime: Lifetime) -> bool {
+        let size = response.body_length() as u64;
+        if size > self.max_entry_size || size > self.capacity {
+            return false;
+        }
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        entries.remove(&key);
+        while entries.size + size > self.capacity {
+            let Some((_, oldest)) = entries.by_use.pop_first() else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+        let used = entries.next_tick();
+        entries.size += size;
+        entries.by_use.insert(used, key.clone());
+        entries.by_key.insert(
+            key,
+            Entry {
+                response,
+                size,
+                stored: Instant::now(),
+                lifetime,
+                refreshing: false,
+                used,
+            },
+        );
+        true
+    }
+
+    /// Lets the next lookup finding the response cached under `key` stale
+    /// build a new one, the one started having failed to replace it.
+    pub fn refresh_failed(&self, key: &CacheKey) {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(entry) = entries.by_key.get_mut(key) {
+            entry.refreshing = false;
+        }
+    }
+
+    /// Forgets the responses cached for `path`, whatever their host, query
+    /// and coding, or every response when `None`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of responses forgotten.
+    pub fn evict(&self, path: Option<&str>) -> usize {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let evicted: Vec<CacheKey> = entries
+            .by_key
+            .keys()
+            .filter(|key| path.is_none_or(|path| key.path() == path))
+            .cloned()
+            .collect();
+        for key in &evicted {
+            entries.remove(key);
+        }
+        drop(entries);
+        evicted.len()
+    }
+
+    /// Number of responses cached, fresh or not.
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .by_key
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes of the bodies cached.
+    pub fn size(&self) -> u64 {
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .size
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::http_response::{ContentType, ResponseStatus};
+
+    fn response(body: &str) -> HTTPResponse {
+        HTTPResponse::new_builder(ResponseStatus::Http200)
+            .with_body(body, ContentType::TextPlain, &[])
+            .build()
+    }
+
+    fn key(target: &str) -> CacheKey {
+        CacheKey::new(target, Encoding::Identity)
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted() {
+        let cache = ResponseCache::new(10, 10);
+        let ttl = Lifetime::fresh_for(Duration::from_mins(1));
+        assert!(cache.insert(key("/a"), response("aaaa"), ttl));
+        assert!(cache.insert(key("/b"), response("bbbb"), ttl));
+        assert!(cache.get(&key("/a")).is_some());
+        assert!(cache.insert(key("/c"), response("cccc"), ttl));
+        assert!(cache.get(&key("/b")).is_none());
+        assert_eq!(
+            cache.get(&key("/a")).unwrap().response.body_bytes(),
+            b"aaaa"
+        );
+        assert_eq!((cache.len(), cache.size()), (2, 8));
+        assert!(!cache.insert(key("/d"), response("too large a body"), ttl));
+        assert!(cache.get(&CacheKey::new("/a", Encoding::Gzip)).is_none());
+    }
+
+    #[test]
+    fn test_expired_entries_dropped() {
+        let cache = ResponseCache::new(10, 10);
+        cache.insert(key("/a"), response("a"), Lifetime::default());
+        assert!(cache.get(&key("/a")).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_stale_responses() {
+        let cache = ResponseCache::new(10, 10);
+        let revalidating = Lifetime {
+            stale_while_revalidate: Duration::from_mins(1),
+            ..Lifetime::default()
+        };
+        cache.insert(key("/a"), response("a"), revalidating);
+        let staleness = |target| cache.get(&key(target)).map(|hit| hit.staleness);
+        assert_eq!(
+            staleness("/a"),
+            Some(Staleness::Revalidating { refresh: true })
+        );
+        // Refreshed once
+        assert_eq!(
+            staleness("/a"),
+            Some(Staleness::Revalidating { refresh: false })
+        );
+        // Refreshed again once the first refresh failed
+        cache.refresh_failed(&key("/a"));
+        assert_eq!(
+            staleness("/a"),
+            Some(Staleness::Revalidating { refresh: true })
+        );
+        assert_eq!(
+            staleness("/a"),
+            Some(Staleness::Revalidating { refresh: false })
+        );
+        let if_error = Lifetime {
+            stale_if_error: Duration::from_mins(1),
+            ..Lifetime::default()
+        };
+        cache.insert(key("/b"), response("b"), if_error);
+        assert_eq!(staleness("/b"), Some(Staleness::IfError));
+        cache.insert(
+            key("/a"),
+            response("a"),
+            Lifetime::fresh_for(Duration::from_mins(1)),
+        );
+        assert_eq!(staleness("/a"), Some(Staleness::Fresh));
+    }
+
+    #[test]
+    fn test_evict_path() {
+        let cache = ResponseCache::new(100, 100);
+        let ttl = Lifetime::fresh_for(Duration::from_mins(1));
+        cache.insert(key("/files/a"), response("a"), ttl);
+        cache.insert(key("/files/a?download=1"), response("a"), ttl);
+        cache.insert(
+            CacheKey::new("/files/a", Encoding::Gzip),
+            response("a"),
+            ttl,
+        );
+        cache.insert(key("/files/b"), response("b"), ttl);
+        assert_eq!(cache.evict(Some("/files/a")), 3);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.evict(None), 1);
+        assert_eq!(cache.size(), 0);
+    }
+}
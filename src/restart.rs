@@ -0,0 +1,113 @@
+use std::io;
+
+use rustix::process::{kill_process, Pid, Signal};
+use thiserror::Error;
+use tokio::{
+    process::Command,
+    signal::unix::{signal, SignalKind},
+};
+use tracing::{error, info, warn};
+
+/// Environment variable giving a restarted server the PID of the server it replaces.
+pub const REPLACES_PID_ENV: &str = "HTTP_SERVER_REPLACES_PID";
+
+/// Starts a new server with the command line `args` each time the process
+/// receives `SIGUSR2`, for a new binary to take over without dropping connections.
+///
+/// The new server listens on the same port next to this one, both sockets
+/// being bound with `SO_REUSEPORT`, then tells this one to drain with
+/// `SIGTERM`, see [`replace_previous`]. A new server that fails to start
+/// leaves this one serving.
+///
+/// # Errors
+///
+/// Returns an error if the `SIGUSR2` handler cannot be installed.
+pub async fn restart_on_sigusr2(args: Vec<String>) -> io::Result<()> {
+    let mut restart = signal(SignalKind::user_defined2())?;
+    while restart.recv().await.is_some() {
+        let spawned = std::env::current_exe().and_then(|executable| {
+            Command::new(executable)
+                .args(args.iter().skip(1))
+                .env(REPLACES_PID_ENV, std::process::id().to_string())
+                // Whatever started this server has stopped waiting for it
+                .env_remove(crate::daemon::READY_PID_ENV)
+                .spawn()
+        });
+        let mut replacement = match spawned {
+            Ok(replacement) => replacement,
+            Err(e) => {
+                error!(error = %e, "Can't start a new server, keeping this one");
+                continue;
+            }
+        };
+        info!(
+            pid = replacement.id(),
+            "Started a new server to replace this one"
+        );
+        // Only returns early if the new server fails, it stops this one otherwise
+        tokio::spawn(async move {
+            match replacement.wait().await {
+                Ok(status) => warn!(%status, "New server exited, keeping this one"),
+                Err(e) => warn!(error = %e, "Lost track of the new server"),
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Tells the server this process was started to replace, if any, to stop
+/// accepting connections and drain the ones it has, now that this one listens.
+///
+/// # Returns
+///
+/// Returns the PID of the server replaced, `None` if this process doesn't replace one.
+///
+/// # Errors
+///
+/// Returns an error if the PID in [`REPLACES_PID_ENV`] is invalid or the process can't be signaled.
+pub fn replace_previous() -> Result<Option<u32>, RestartError> {
+    let Ok(pid) = std::env::var(REPLACES_PID_ENV) else {
+        return Ok(None);
+    };
+    // Parsed unsigned, negative PIDs signal whole process groups
+    let Some((previous, raw)) = pid.parse::<u32>().ok().and_then(|previous| {
+        let raw = Pid::from_raw(i32::try_from(previous).ok()?)?;
+        Some((previous, raw))
+    }) else {
+        return Err(RestartError::InvalidPid(pid));
+    };
+    kill_process(raw, Signal::TERM).map_err(|e| RestartError::Signal(previous, e.into()))?;
+    info!(pid = previous, "Told the previous server to drain");
+    Ok(Some(previous))
+}
+
+#[derive(Debug, Error)]
+pub enum RestartError {
+    #[error("'{0}' is not the PID of the server to replace")]
+    InvalidPid(String),
+    #[error("Can't tell server {0} to drain: {1}")]
+    Signal(u32, io::Error),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_pid() {
+        for pid in ["0", "-1", "server"] {
+            assert!(matches!(
+                replace_pid(pid),
+                Err(RestartError::InvalidPid(invalid)) if invalid == pid
+            ));
+        }
+    }
+
+    fn replace_pid(pid: &str) -> Result<Option<u32>, RestartError> {
+        std::env::set_var(REPLACES_PID_ENV, pid);
+        let replaced = replace_previous();
+        std::env::remove_var(REPLACES_PID_ENV);
+        replaced
+    }
+}
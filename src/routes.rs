@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use crate::{
+    http_request::{HTTPRequest, RequestMethod},
+    http_response::{HTTPResponse, InformationalResponse, ResponseStatus},
+};
+
+type RouteHandler = dyn Fn(&HTTPRequest) -> HTTPResponse + Send + Sync;
+
+/// Handlers added by users of the library, answering requests by their method
+/// and exact path, the query left out.
+///
+/// They are tried after authentication and rate limiting and before the
+/// built-in routes, which they can replace. Requests no route answers go to the
+/// fallback handler, if one was set.
+#[derive(Clone, Default)]
+pub struct Routes {
+    handlers: Vec<(RequestMethod, String, Arc<RouteHandler>)>,
+    hints: Vec<(RequestMethod, String, InformationalResponse)>,
+    fallback: Option<Arc<RouteHandler>>,
+}
+impl Routes {
+    /// Answers `method path` requests with `handler`, in place of any handler
+    /// added for them before.
+    pub fn add(
+        &mut self,
+        method: RequestMethod,
+        path: &str,
+        handler: impl Fn(&HTTPRequest) -> HTTPResponse + Send + Sync + 'static,
+    ) {
+        self.handlers
+            .retain(|(existing, existing_path, _)| *existing != method || existing_path != path);
+        self.handlers
+            .push((method, path.to_string(), Arc::new(handler)));
+    }
+
+    /// Sends `103 Early Hints` with `links` to `method path` requests as soon as
+    /// they are read, before they are checked and handled, for the client to
+    /// start loading what the final response will need. Each link is a `Link`
+    /// header value like `</style.css>; rel=preload; as=style`, in place of any
+    /// added for the route before.
+    pub fn add_early_hints(&mut self, method: RequestMethod, path: &str, links: &[&str]) {
+        self.hints
+            .retain(|(existing, existing_path, _)| *existing != method || existing_path != path);
+        self.hints.push((
+            method,
+            path.to_string(),
+            InformationalResponse::early_hints(links),
+        ));
+    }
+
+    /// The `103 Early Hints` added for `method path`, `HEAD` requests getting
+    /// those of `GET` when they have none of their own.
+    #[must_use]
+    pub fn early_hints(
+        &self,
+        method: &RequestMethod,
+        path: &str,
+    ) -> Option<&InformationalResponse> {
+        let find = |method: &RequestMethod| {
+            self.hints
+                .iter()
+                .find(|(hints_method, hints_path, _)| hints_method == method && hints_path == path)
+        };
+        find(method)
+            .or_else(|| (*method == RequestMethod::Head).then(|| find(&RequestMethod::Get))?)
+            .map(|(_, _, hints)| hints)
+    }
+
+    /// Answers the requests no route matches with `handler`, instead of an
+    /// empty `404 Not Found`, e.g. to send a branded page or log probes.
+    pub fn set_fallback(
+        &mut self,
+        handler: impl Fn(&HTTPRequest) -> HTTPResponse + Send + Sync + 'static,
+    ) {
+        self.fallback = Some(Arc::new(handler));
+    }
+
+    /// The response to `request` when no route matches it, from the fallback
+    /// handler or else an empty `404 Not Found`.
+    #[must_use]
+    pub fn not_found(&self, request: &HTTPRequest) -> HTTPResponse {
+        self.fallback.as_ref().map_or_else(
+            || HTTPResponse::new_builder(ResponseStatus::Http404).build(),
+            |fallback| fallback(request),
+        )
+    }
+
+    /// The response of the handler added for `request`, `None` if there is none.
+    /// `HEAD` requests without a handler of their own get the response of the
+    /// `GET` handler, its body being dropped when sent.
+    #[must_use]
+    pub fn respond(&self, request: &HTTPRequest) -> Option<HTTPResponse> {
+        let (method, path) = (request.line().method(), request.line().uri().path());
+        let find = |method: &RequestMethod| {
+            self.handlers
+                .iter()
+                .find(|(route_method, route_path, _)| route_method == method && route_path == path)
+        };
+        find(method)
+            .or_else(|| (*method == RequestMethod::Head).then(|| find(&RequestMethod::Get))?)
+            .map(|(_, _, handler)| handler(request))
+    }
+}
+impl std::fmt::Debug for Routes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.handlers
+                    .iter()
+                    .map(|(method, path, _)| format!("{method} {path}"))
+                    .chain(self.fallback.as_ref().map(|_| "fallback".to_string())),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,703 @@
+use std::{
+    future::Future,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpSocket},
+    sync::watch,
+    task::JoinSet,
+    time::timeout,
+};
+use tracing::{error, info, info_span, Instrument};
+
+#[cfg(feature = "http3")]
+use crate::tls::{self, Certificates, TlsError};
+use crate::{
+    access_log::AccessLog,
+    client_handler::{ClientHandler, ErrorHandler},
+    config::Config,
+    connection_limit::{Admission, ConnectionLimit},
+    directory::{self, DirectoryError},
+    hooks::{Hooks, ResponseEvent},
+    http_request::{HTTPRequest, RequestMethod},
+    http_response::HTTPResponse,
+    log_file::RotatingFile,
+    logging::LogFilter,
+    routes::Routes,
+    server_context::ServerContext,
+    shutdown,
+};
+
+/// Address the server listens on unless told otherwise.
+pub const DEFAULT_ADDRESS: SocketAddr =
+    SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), 4221);
+
+/// A server listening for connections, started with [`Server::builder`].
+///
+/// ```no_run
+/// # async fn example() -> Result<(), http_server_starter_rust::server::ServerError> {
+/// use http_server_starter_rust::{
+///     http_request::RequestMethod,
+///     http_response::{ContentType, HTTPResponse, ResponseStatus},
+///     server::Server,
+/// };
+///
+/// Server::builder()
+///     .bind("127.0.0.1:8080".parse().unwrap())
+///     .serve_dir("/srv/files")
+///     .route(RequestMethod::Get, "/hello", |_| {
+///         HTTPResponse::new_builder(ResponseStatus::Http200)
+///             .with_body("hello", ContentType::TextPlain, &[])
+///             .build()
+///     })
+///     .run()
+///     .await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    context: Arc<ServerContext>,
+    config: watch::Receiver<Arc<Config>>,
+    connection_limit: ConnectionLimit,
+    #[cfg(feature = "http3")]
+    quic: Option<quinn::Endpoint>,
+    #[cfg(feature = "http3")]
+    certificates: Option<Arc<Certificates>>,
+}
+impl Server {
+    #[must_use]
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// The address the server listens on, with the port picked by the system
+    /// when it was bound to port 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listening socket can't be queried.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The UDP address HTTP/3 is served on, if it is.
+    #[cfg(feature = "http3")]
+    #[must_use]
+    pub fn http3_addr(&self) -> Option<SocketAddr> {
+        self.quic.as_ref()?.local_addr().ok()
+    }
+
+    /// Serves connections until the process is asked to stop with `SIGINT`,
+    /// `SIGTERM` or `POST /admin/shutdown`, then gives open connections the
+    /// configured grace period to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signal handlers can't be installed or connections
+    /// were still open at the end of the grace period.
+    pub async fn run(self) -> Result<(), ServerError> {
+        self.serve(shutdown::requested()).await
+    }
+
+    /// Serves connections until `shutdown` completes or `POST /admin/shutdown`
+    /// asks to stop, then gives open connections the configured grace period to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if connections were still open at the end of the grace period.
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) -> Result<(), ServerError> {
+        self.serve(async {
+            shutdown.await;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn serve(
+        self,
+        shutdown: impl Future<Output = std::io::Result<()>>,
+    ) -> Result<(), ServerError> {
+        let Self {
+            listener,
+            mut context,
+            mut config,
+            connection_limit,
+            #[cfg(feature = "http3")]
+            quic,
+            #[cfg(feature = "http3")]
+            certificates,
+        } = self;
+        info!(address = %listener.local_addr()?, "Listening");
+        let (draining_sender, draining_receiver) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        // Drained along with the TCP connections, once it stops accepting
+        #[cfg(feature = "http3")]
+        if let Some(endpoint) = quic {
+            connections.spawn(ClientHandler::serve_http3(
+                endpoint,
+                Arc::clone(&context),
+                config.clone(),
+                draining_receiver.clone(),
+            ));
+        }
+        #[cfg(feature = "http3")]
+        let certificate_reloads = certificates
+            .map(|certificates| tokio::spawn(tls::keep_reloaded(certificates, config.clone())));
+        let shutdown = or_requested(shutdown, Arc::clone(&context));
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                result = &mut shutdown => {
+                    result.map_err(ServerError::Signals)?;
+                    break;
+                }
+                accepted = connection_limit.accept(&listener) => {
+                    let Ok((mut stream, socket_address, admission)) = accepted else {
+                        break;
+                    };
+                    let Admission::Admitted(permit) = admission else {
+                        connections.spawn(async move {
+                            let _ = ClientHandler::reject_overloaded(&mut stream).await;
+                        });
+                        continue;
+                    };
+                    // Connections keep the configuration they were accepted with
+                    if config.has_changed().unwrap_or(false) {
+                        let reloaded = config.borrow_and_update().clone();
+                        context = Arc::new(context.with_config(reloaded));
+                    }
+                    let context = Arc::clone(&context);
+                    let draining = draining_receiver.clone();
+                    let span = info_span!("connection", peer = %socket_address);
+                    connections.spawn(async move {
+                        let _permit = permit;
+                        if let Err(e) = ClientHandler::handle_connection(
+                            &mut stream,
+                            socket_address,
+                            context,
+                            draining,
+                        )
+                        .await
+                        {
+                            error!(error = %e, "Error handling connection");
+                        }
+                    }.instrument(span));
+                }
+                Some(joined) = connections.join_next(), if !connections.is_empty() => {
+                    // Panics in handlers are answered with a 500, these happened elsewhere on the connection
+                    if let Err(e) = joined {
+                        error!(error = %e, "Connection task failed");
+                    }
+                }
+            }
+        }
+        drop(listener);
+        draining_sender.send_replace(true);
+        #[cfg(feature = "http3")]
+        if let Some(reloads) = certificate_reloads {
+            reloads.abort();
+        }
+
+        let grace_period = config.borrow().shutdown_grace_period;
+        info!(
+            connections = connections.len(),
+            ?grace_period,
+            "Shutting down, draining connections"
+        );
+        let drained = timeout(grace_period, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            let remaining = connections.len();
+            connections.shutdown().await;
+            return Err(ServerError::ForcedShutdown(remaining, grace_period));
+        }
+        info!("Server stopped");
+        Ok(())
+    }
+}
+
+/// Completes with `shutdown`, or once `POST /admin/shutdown` asks to stop,
+/// through `context` or any context reloaded from it as they share the admin API.
+async fn or_requested(
+    shutdown: impl Future<Output = std::io::Result<()>>,
+    context: Arc<ServerContext>,
+) -> std::io::Result<()> {
+    tokio::select! {
+        result = shutdown => result,
+        () = context.admin().shutdown_requested() => Ok(()),
+    }
+}
+
+/// Settings of a [`Server`] before it starts listening.
+#[derive(Debug)]
+pub struct ServerBuilder {
+    address: SocketAddr,
+    config: Config,
+    directory: Option<String>,
+    routes: Routes,
+    hooks: Hooks,
+    error_handler: ErrorHandler,
+    access_log: Option<AccessLog>,
+    log_filter: Option<LogFilter>,
+    reload_args: Option<Vec<String>>,
+    restart_args: Option<Vec<String>>,
+}
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_ADDRESS,
+            config: Config::default(),
+            directory: None,
+            routes: Routes::default(),
+            hooks: Hooks::default(),
+            error_handler: ErrorHandler::default(),
+            access_log: None,
+            log_filter: None,
+            reload_args: None,
+            restart_args: None,
+        }
+    }
+}
+impl ServerBuilder {
+    /// Listens on `address`, `127.0.0.1:4221` by default.
+    #[must_use]
+    pub const fn bind(mut self, address: SocketAddr) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Serves the files of `directory` under `/files/`, whatever the
+    /// configuration says.
+    #[must_use]
+    pub fn serve_dir(mut self, directory: impl AsRef<Path>) -> Self {
+        self.directory = Some(directory.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Answers `method path` requests with `handler`, see [`Routes`].
+    #[must_use]
+    pub fn route(
+        mut self,
+        method: RequestMethod,
+        path: &str,
+        handler: impl Fn(&HTTPRequest) -> HTTPResponse + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.add(method, path, handler);
+        self
+    }
+
+    /// Answers the requests no route matches with `handler`, see [`Routes::set_fallback`].
+    #[must_use]
+    pub fn fallback(
+        mut self,
+        handler: impl Fn(&HTTPRequest) -> HTTPResponse + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.set_fallback(handler);
+        self
+    }
+
+    /// Calls `hook` with every request parsed, see [`Hooks`].
+    #[must_use]
+    pub fn on_request(mut self, hook: impl Fn(&HTTPRequest) + Send + Sync + 'static) -> Self {
+        self.hooks.on_request(hook);
+        self
+    }
+
+    /// Calls `hook` with every response sent and the time it took, see [`Hooks`].
+    #[must_use]
+    pub fn on_response(mut self, hook: impl Fn(&ResponseEvent) + Send + Sync + 'static) -> Self {
+        self.hooks.on_response(hook);
+        self
+    }
+
+    /// Runs with `config` instead of the default settings.
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Answers failed requests with `error_handler`.
+    #[must_use]
+    pub fn error_handler(mut self, error_handler: ErrorHandler) -> Self {
+        self.error_handler = error_handler;
+        self
+    }
+
+    /// Records responses in `access_log` instead of the file or standard output
+    /// the configuration picks.
+    #[must_use]
+    pub fn access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// Lets the admin API change the levels of the logs with `log_filter`, as
+    /// returned by [`crate::logging::init`].
+    #[must_use]
+    pub fn log_filter(mut self, log_filter: LogFilter) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    /// Reloads the configuration from the command line `args` on `SIGHUP`, see
+    /// [`crate::config::reload_on_sighup`]. A reloaded configuration replaces the
+    /// one given to the builder, directory included.
+    #[must_use]
+    pub fn reload_on_sighup(mut self, args: Vec<String>) -> Self {
+        self.reload_args = Some(args);
+        self
+    }
+
+    /// Starts a new server from the command line `args` on `SIGUSR2` when
+    /// `graceful_restart` is set, see [`crate::restart::restart_on_sigusr2`].
+    #[must_use]
+    pub fn restart_on_sigusr2(mut self, args: Vec<String>) -> Self {
+        self.restart_args = Some(args);
+        self
+    }
+
+    /// Checks the settings and starts listening, without serving connections yet.
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the served directory can't be used, see
+    /// [`directory::prepare`], the access log
+    /// file can't be opened or the address can't be bound.
+    pub async fn listen(self) -> Result<Server, ServerError> {
+        let mut config = self.config;
+        if self.directory.is_some() {
+            config.directory = self.directory;
+        }
+        for (directory, writable) in config.directories() {
+            directory::prepare(directory, config.create_directory, writable).await?;
+        }
+        let access_log = match (self.access_log, &config.access_log_file) {
+            (Some(access_log), _) => access_log,
+            (None, Some(path)) => AccessLog::spawn_blocking_to(
+                RotatingFile::open(path, config.log_rotation, config.log_retention)
+                    .map_err(|e| ServerError::AccessLogFile(path.clone(), e))?,
+            ),
+            (None, None) => AccessLog::spawn(),
+        };
+        let connection_limit = ConnectionLimit::new(config.max_connections, config.on_saturation);
+        let listener = bind(self.address, config.graceful_restart)
+            .map_err(|e| ServerError::Bind(self.address, e))?;
+        #[cfg(feature = "http3")]
+        let (quic, certificates) = match config.http3_port {
+            Some(port) => {
+                let address = SocketAddr::new(self.address.ip(), port);
+                let certificates = Arc::new(Certificates::load(&config)?);
+                let endpoint = bind_quic(address, &config, Arc::clone(&certificates))?;
+                // Advertised with the port the system picked when asked for port 0
+                let bound = endpoint
+                    .local_addr()
+                    .map_err(|e| ServerError::Bind(address, e))?;
+                config.http3_port = Some(bound.port());
+                (Some(endpoint), Some(certificates))
+            }
+            None => (None, None),
+        };
+
+        let (config_sender, config_receiver) = watch::channel(Arc::new(config));
+        #[cfg(unix)]
+        if let Some(args) = self.reload_args {
+            tokio::spawn(async move {
+                if let Err(e) = crate::config::reload_on_sighup(args, config_sender).await {
+                    tracing::warn!(error = %e, "Configuration reload on SIGHUP is disabled");
+                }
+            });
+        }
+        #[cfg(not(unix))]
+        drop((self.reload_args, config_sender));
+        #[cfg(unix)]
+        if let Err(e) = crate::daemon::notify_ready() {
+            tracing::warn!(error = %e, "Can't tell the terminal the server started");
+        }
+        #[cfg(unix)]
+        if config_receiver.borrow().graceful_restart {
+            // Only once listening, so that no connection is refused meanwhile
+            if let Err(e) = crate::restart::replace_previous() {
+                tracing::warn!(error = %e, "Previous server left running");
+            }
+            if let Some(args) = self.restart_args {
+                tokio::spawn(async move {
+                    if let Err(e) = crate::restart::restart_on_sigusr2(args).await {
+                        tracing::warn!(error = %e, "Restart on SIGUSR2 is disabled");
+                    }
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        drop(self.restart_args);
+        let mut context = ServerContext::new(config_receiver.borrow().clone(), access_log)
+            .with_error_handler(self.error_handler)
+            .with_routes(self.routes)
+            .with_hooks(self.hooks);
+        if let Some(log_filter) = self.log_filter {
+            context = context.with_log_filter(log_filter);
+        }
+        Ok(Server {
+            listener,
+            context: Arc::new(context),
+            config: config_receiver,
+            connection_limit,
+            #[cfg(feature = "http3")]
+            quic,
+            #[cfg(feature = "http3")]
+            certificates,
+        })
+    }
+
+    /// Starts listening and serves connections until the process is asked to stop.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server can't start, see [`ServerBuilder::listen`],
+    /// or can't stop cleanly, see [`Server::run`].
+    pub async fn run(self) -> Result<(), ServerError> {
+        self.listen().await?.run().await
+    }
+}
+
+/// Listens on `address`, next to other sockets bound to it with `reuse_port`
+/// set, for a restarted server to listen before the previous one stops.
+fn bind(address: SocketAddr, reuse_port: bool) -> std::io::Result<TcpListener> {
+    let socket = if address.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    // Like `TcpListener::bind`, so that a restart doesn't wait for TIME_WAIT
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(reuse_port)?;
+    #[cfg(not(unix))]
+    let _ = reuse_port;
+    socket.bind(address)?;
+    socket.listen(1024)
+}
+
+/// Listens for HTTP/3 on `address`, with `certificates` and the TLS settings of `config`.
+#[cfg(feature = "http3")]
+fn bind_quic(
+    address: SocketAddr,
+    config: &Config,
+    certificates: Arc<Certificates>,
+) -> Result<quinn::Endpoint, ServerError> {
+    let tls = crate::tls::server_config(config, certificates)?;
+    if !tls.alpn_protocols.iter().any(|protocol| protocol == b"h3") {
+        return Err(TlsError::MissingAlpnProtocol("h3").into());
+    }
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+        .map_err(|_| TlsError::NoQuicCipherSuite)?;
+    let server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    quinn::Endpoint::server(server_config, address).map_err(|e| ServerError::Bind(address, e))
+}
+
+#[derive(Debug, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum ServerError {
+    #[error("{0}")]
+    Directory(#[from] DirectoryError),
+    #[error("Can't open access log file '{0}': {1}")]
+    AccessLogFile(String, std::io::Error),
+    #[error("Can't listen on {0}: {1}")]
+    Bind(SocketAddr, std::io::Error),
+    #[error("Can't listen for shutdown signals: {0}")]
+    Signals(std::io::Error),
+    #[error("Listener failed: {0}")]
+    Listener(#[from] std::io::Error),
+    #[error("Forced shutdown, {0} connection(s) still open after {1:?}")]
+    ForcedShutdown(usize, Duration),
+    #[cfg(feature = "http3")]
+    #[error("Can't serve HTTP/3: {0}")]
+    Tls(#[from] TlsError),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        http_response::{ContentType, ResponseStatus},
+        test_util::TestResponse,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::oneshot,
+    };
+
+    async fn get(address: SocketAddr, path: &str) -> TestResponse {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        TestResponse::parse(&response)
+    }
+
+    #[tokio::test]
+    async fn test_builder_serves_routes_and_directory() {
+        let directory = std::env::temp_dir().join("http-server-builder");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("note"), "from disk").unwrap();
+        let responses = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&responses);
+        let server = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .serve_dir(&directory)
+            .access_log(AccessLog::spawn_to(tokio::io::sink()))
+            .route(RequestMethod::Get, "/hello", |request| {
+                HTTPResponse::new_builder(ResponseStatus::Http200)
+                    .with_body(
+                        &format!("hello {}", request.peer().ip()),
+                        ContentType::TextPlain,
+                        &[],
+                    )
+                    .build()
+            })
+            .fallback(|request| {
+                HTTPResponse::new_builder(ResponseStatus::Http404)
+                    .with_body(
+                        &format!("nothing at {}", request.line().uri()),
+                        ContentType::TextPlain,
+                        &[],
+                    )
+                    .build()
+            })
+            .on_response(move |event| {
+                seen.lock().unwrap().push(format!(
+                    "{} {}",
+                    event.path,
+                    event.response.status().code()
+                ));
+            })
+            .listen()
+            .await
+            .unwrap();
+        let address = server.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(server.run_until(async {
+            let _ = stopped.await;
+        }));
+
+        assert_eq!(get(address, "/hello?x=1").await.text(), "hello 127.0.0.1");
+        assert_eq!(get(address, "/files/note").await.text(), "from disk");
+        assert_eq!(get(address, "/echo/built-in").await.text(), "built-in");
+        let missing = get(address, "/missing").await;
+        assert_eq!(missing.status, 404);
+        assert_eq!(missing.text(), "nothing at /missing");
+        // A known route answering 404 isn't a missing route
+        assert_eq!(get(address, "/files/absent").await.text(), "");
+
+        stop.send(()).unwrap();
+        running.await.unwrap().unwrap();
+        let mut responses = responses.lock().unwrap().clone();
+        responses.sort();
+        assert_eq!(
+            responses,
+            [
+                "/echo/built-in 200",
+                "/files/absent 404",
+                "/files/note 200",
+                "/hello 200",
+                "/missing 404"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_endpoint_drains_the_server() {
+        let server = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .config(Config {
+                shutdown_tokens: Some("st0p".parse().unwrap()),
+                ..Config::default()
+            })
+            .access_log(AccessLog::spawn_to(tokio::io::sink()))
+            .listen()
+            .await
+            .unwrap();
+        let address = server.local_addr().unwrap();
+        let running = tokio::spawn(server.run_until(std::future::pending()));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"POST /admin/shutdown HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer st0p\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        assert_eq!(TestResponse::parse(&response).status, 202);
+        tokio::time::timeout(Duration::from_secs(5), running)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_graceful_restart_shares_the_port() {
+        let config = Config {
+            graceful_restart: true,
+            ..Config::default()
+        };
+        let previous = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .config(config.clone())
+            .access_log(AccessLog::spawn_to(tokio::io::sink()))
+            .listen()
+            .await
+            .unwrap();
+        let address = previous.local_addr().unwrap();
+        let next = Server::builder()
+            .bind(address)
+            .config(config)
+            .access_log(AccessLog::spawn_to(tokio::io::sink()))
+            .listen()
+            .await
+            .unwrap();
+        assert_eq!(next.local_addr().unwrap(), address);
+
+        let taken = Server::builder()
+            .bind(address)
+            .access_log(AccessLog::spawn_to(tokio::io::sink()))
+            .listen()
+            .await;
+        assert!(matches!(taken, Err(ServerError::Bind(..))));
+    }
+
+    #[tokio::test]
+    async fn test_builder_rejects_unreadable_directory() {
+        let result = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .serve_dir("/nonexistent/http-server-builder")
+            .listen()
+            .await;
+        assert!(matches!(
+            result,
+            Err(ServerError::Directory(DirectoryError::Missing(directory)))
+                if directory == "/nonexistent/http-server-builder"
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    access_log::AccessLog, admin::Admin, client_handler::ErrorHandler, config::Config,
+    hooks::Hooks, http_date::DateHeader, http_request::Host, logging::LogFilter,
+    rate_limit::RateLimiter, response_cache::ResponseCache, routes::Routes, stats::Stats,
+    throttle::Throttle,
+};
+
+/// What every connection of a server shares: its configuration, the directory
+/// it serves files from and the state kept across connections.
+///
+/// Built once per configuration and shared behind an `Arc`, so accepting a
+/// connection clones a pointer rather than the settings.
+#[derive(Debug, Clone)]
+pub struct ServerContext {
+    config: Arc<Config>,
+    root: Option<PathBuf>,
+    rate_limiter: Arc<RateLimiter>,
+    access_log: AccessLog,
+    stats: Arc<Stats>,
+    admin: Arc<Admin>,
+    log_filter: Option<LogFilter>,
+    error_handler: ErrorHandler,
+    routes: Routes,
+    hooks: Hooks,
+    throttle: Option<Arc<Throttle>>,
+    response_cache: Option<Arc<ResponseCache>>,
+    date: DateHeader,
+}
+impl ServerContext {
+    /// A context serving `config`, with fresh rate limits and stats, that records
+    /// responses in `access_log` and answers failed requests in plain text.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[must_use]
+    pub fn new(config: Arc<Config>, access_log: AccessLog) -> Self {
+        Self {
+            root: config.directory.as_deref().map(PathBuf::from),
+            throttle: config
+                .max_total_rate
+                .map(|rate| Arc::new(Throttle::new(rate))),
+            response_cache: response_cache(&config),
+            config,
+            rate_limiter: Arc::new(RateLimiter::new()),
+            access_log,
+            stats: Arc::new(Stats::new()),
+            admin: Arc::new(Admin::new()),
+            log_filter: None,
+            error_handler: ErrorHandler::default(),
+            routes: Routes::default(),
+            hooks: Hooks::default(),
+            date: DateHeader::spawn(),
+        }
+    }
+
+    /// Answers failed requests with `error_handler` instead.
+    #[must_use]
+    pub fn with_error_handler(self, error_handler: ErrorHandler) -> Self {
+        Self {
+            error_handler,
+            ..self
+        }
+    }
+
+    /// Answers the requests `routes` have a handler for with it.
+    #[must_use]
+    pub fn with_routes(self, routes: Routes) -> Self {
+        Self { routes, ..self }
+    }
+
+    /// Tells `hooks` about the requests and responses served.
+    #[must_use]
+    pub fn with_hooks(self, hooks: Hooks) -> Self {
+        Self { hooks, ..self }
+    }
+
+    /// Lets the admin API change the levels of the logs with `log_filter`.
+    #[must_use]
+    pub fn with_log_filter(self, log_filter: LogFilter) -> Self {
+        Self {
+            log_filter: Some(log_filter),
+            ..self
+        }
+    }
+
+    /// The same context serving a reloaded `config`, keeping the rate limits,
+    /// stats, admin changes and logs of the previous one, and its bandwidth unless the rate changed.
+    /// The response cache starts empty, the responses of the previous
+    /// configuration may not be those of this one.
+    ///
+    /// The HTTP/3 port stays the one bound at startup, which a reload doesn't rebind.
+    #[must_use]
+    pub fn with_config(&self, config: Arc<Config>) -> Self {
+        #[cfg(feature = "http3")]
+        let config = if config.http3_port == self.config.http3_port {
+            config
+        } else {
+            Arc::new(Config {
+                http3_port: self.config.http3_port,
+                ..(*config).clone()
+            })
+        };
+        let throttle = match (&self.throttle, config.max_total_rate) {
+            (Some(throttle), Some(rate)) if throttle.rate() == rate => Some(Arc::clone(throttle)),
+            (_, rate) => rate.map(|rate| Arc::new(Throttle::new(rate))),
+        };
+        Self {
+            root: config.directory.as_deref().map(PathBuf::from),
+            response_cache: response_cache(&config),
+            config,
+            throttle,
+            ..self.clone()
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The directory files are served from, if any.
+    #[must_use]
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// The directory files are served from for requests to `host`, its own
+    /// when `host_directories` names it, see [`Config::directory_for`].
+    #[must_use]
+    pub fn root_for(&self, host: Option<&Host>) -> Option<&Path> {
+        if self.config.host_directories.is_empty() {
+            return self.root();
+        }
+        self.config
+            .directory_for(host.map(Host::name))
+            .map(Path::new)
+    }
+
+    #[must_use]
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    #[must_use]
+    pub const fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    #[must_use]
+    pub fn admin(&self) -> &Admin {
+        &self.admin
+    }
+
+    /// The filter of the logs, when the server installed the subscriber.
+    #[must_use]
+    pub const fn log_filter(&self) -> Option<&LogFilter> {
+        self.log_filter.as_ref()
+    }
+
+    /// Whether files are only published, as the admin API or else the configuration sets it.
+    #[must_use]
+    pub fn read_only(&self) -> bool {
+        self.admin.read_only().unwrap_or(self.config.read_only)
+    }
+
+    #[must_use]
+    pub const fn error_handler(&self) -> &ErrorHandler {
+        &self.error_handler
+    }
+
+    /// Caps the bytes written per second by all connections together, if configured.
+    #[must_use]
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_deref()
+    }
+
+    /// Responses kept for `GET` and `HEAD` requests, if configured.
+    #[must_use]
+    pub fn response_cache(&self) -> Option<&ResponseCache> {
+        self.response_cache.as_deref()
+    }
+
+    #[must_use]
+    pub const fn routes(&self) -> &Routes {
+        &self.routes
+    }
+
+    #[must_use]
+    pub const fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// The `Date` header of the responses, shared by reloaded contexts.
+    #[must_use]
+    pub const fn date(&self) -> &DateHeader {
+        &self.date
+    }
+}
+
+fn response_cache(config: &Config) -> Option<Arc<ResponseCache>> {
+    config.response_cache_size.map(|capacity| {
+        Arc::new(ResponseCache::new(
+            capacity,
+            config.response_cache_max_entry_size,
+        ))
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_with_config_keeps_shared_state() {
+        let context = ServerContext::new(
+            Arc::new(Config::default()),
+            AccessLog::spawn_to(tokio::io::sink()),
+        );
+        assert_eq!(context.root(), None);
+
+        let reloaded = context.with_config(Arc::new(Config {
+            directory: Some("/srv/files".to_string()),
+            ..Config::default()
+        }));
+        assert_eq!(reloaded.root(), Some(Path::new("/srv/files")));
+        assert!(std::ptr::eq(reloaded.stats(), context.stats()));
+        assert!(std::ptr::eq(
+            reloaded.rate_limiter(),
+            context.rate_limiter()
+        ));
+        assert!(std::ptr::eq(reloaded.admin(), context.admin()));
+    }
+
+    #[cfg(feature = "http3")]
+    #[tokio::test]
+    async fn test_with_config_keeps_http3_port() {
+        // Listening on the port picked for `http3_port = 0`
+        let context = ServerContext::new(
+            Arc::new(Config {
+                http3_port: Some(4433),
+                ..Config::default()
+            }),
+            AccessLog::spawn_to(tokio::io::sink()),
+        );
+        let reloaded = context.with_config(Arc::new(Config {
+            http3_port: Some(0),
+            ..Config::default()
+        }));
+        assert_eq!(reloaded.config().http3_port, Some(4433));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_override() {
+        let context = ServerContext::new(
+            Arc::new(Config::default()),
+            AccessLog::spawn_to(tokio::io::sink()),
+        );
+        assert!(!context.read_only());
+        context.admin().set_read_only(Some(true));
+        assert!(context.read_only());
+
+        let reloaded = context.with_config(Arc::new(Config {
+            read_only: false,
+            ..Config::default()
+        }));
+        assert!(reloaded.read_only());
+    }
+}
@@ -0,0 +1,22 @@
+/// Completes once the process is asked to stop, either with Ctrl-C (`SIGINT`)
+/// or, on unix, with `SIGTERM`.
+///
+/// # Errors
+///
+/// Returns an error if the signal handlers cannot be installed.
+pub async fn requested() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use crate::http_request::Encoding;
+
+/// Counters shared by every connection, reported by `GET /admin/stats`.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    open_connections: AtomicUsize,
+    in_flight_requests: AtomicUsize,
+    requests_served: AtomicU64,
+    response_cache_hits: AtomicU64,
+    response_cache_misses: AtomicU64,
+    gzip: BodyCounters,
+    identity: BodyCounters,
+    compression_skipped: AtomicU64,
+    tls_handshake_failures: Mutex<BTreeMap<&'static str, u64>>,
+    routes: Mutex<BTreeMap<String, u64>>,
+}
+
+/// Counts of the bodies sent with a coding.
+#[derive(Debug, Default)]
+struct BodyCounters {
+    responses: AtomicU64,
+    /// Bytes before compression.
+    bytes_in: AtomicU64,
+    /// Bytes sent.
+    bytes_out: AtomicU64,
+}
+impl BodyCounters {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"responses\":{},\"bytes_in\":{},\"bytes_out\":{}}}",
+            self.responses.load(Ordering::Relaxed),
+            self.bytes_in.load(Ordering::Relaxed),
+            self.bytes_out.load(Ordering::Relaxed),
+        )
+    }
+}
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            open_connections: AtomicUsize::new(0),
+            in_flight_requests: AtomicUsize::new(0),
+            requests_served: AtomicU64::new(0),
+            response_cache_hits: AtomicU64::new(0),
+            response_cache_misses: AtomicU64::new(0),
+            gzip: BodyCounters::default(),
+            identity: BodyCounters::default(),
+            compression_skipped: AtomicU64::new(0),
+            tls_handshake_failures: Mutex::new(BTreeMap::new()),
+            routes: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+impl Stats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a connection as open until the returned guard is dropped.
+    pub fn open_connection(&self) -> Gauge<'_> {
+        Gauge::increment(&self.open_connections)
+    }
+
+    /// Counts a request as in flight until the returned guard is dropped.
+    pub fn start_request(&self) -> Gauge<'_> {
+        Gauge::increment(&self.in_flight_requests)
+    }
+
+    /// Counts a response sent.
+    pub fn request_served(&self) {
+        self.requests_served.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a lookup in the response cache, answered from it when `hit`.
+    pub fn count_response_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.response_cache_hits
+        } else {
+            &self.response_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a body sent with `encoding`, `bytes_in` long before compression
+    /// and `bytes_out` as sent. `skipped` tells a body sent as is although the
+    /// client preferred it compressed, its handler not compressing it.
+    pub fn count_body(&self, encoding: Encoding, bytes_in: u64, bytes_out: u64, skipped: bool) {
+        let counters = match encoding {
+            Encoding::Gzip => &self.gzip,
+            Encoding::Identity => &self.identity,
+        };
+        counters.responses.fetch_add(1, Ordering::Relaxed);
+        counters.bytes_in.fetch_add(bytes_in, Ordering::Relaxed);
+        counters.bytes_out.fetch_add(bytes_out, Ordering::Relaxed);
+        if skipped {
+            self.compression_skipped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a TLS handshake failed for `reason`.
+    pub fn count_tls_handshake_failure(&self, reason: &'static str) {
+        *self
+            .tls_handshake_failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .entry(reason)
+            .or_insert(0) += 1;
+    }
+
+    /// Counts a request to `path`, grouped by route so clients can't make the map grow.
+    pub fn count_route(&self, method: &str, path: &str) {
+        let route = format!("{method} {}", route(path));
+        *self
+            .routes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .entry(route)
+            .or_insert(0) += 1;
+    }
+
+    /// Reports the counters as a JSON object, along with the sizes of the
+    /// caches given as `(name, entries)`.
+    #[must_use]
+    pub fn to_json(&self, caches: &[(&str, usize)]) -> String {
+        let mut json = format!(
+            "{{\"uptime_seconds\":{},\"open_connections\":{},\"in_flight_requests\":{},\"requests_served\":{},\
+             \"response_cache_hits\":{},\"response_cache_misses\":{},\
+             \"compression\":{{\"gzip\":{},\"identity\":{},\"skipped\":{}}},\
+             \"tls_handshake_failures\":{{{}}},\"routes\":{{",
+            self.started.elapsed().as_secs(),
+            self.open_connections.load(Ordering::Relaxed),
+            self.in_flight_requests.load(Ordering::Relaxed),
+            self.requests_served.load(Ordering::Relaxed),
+            self.response_cache_hits.load(Ordering::Relaxed),
+            self.response_cache_misses.load(Ordering::Relaxed),
+            self.gzip.to_json(),
+            self.identity.to_json(),
+            self.compression_skipped.load(Ordering::Relaxed),
+            counts(
+                &self
+                    .tls_handshake_failures
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+            ),
+        );
+        let routes = counts(
+            &self
+                .routes
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        );
+        json.push_str(&routes);
+        json.push_str("},\"caches\":{");
+        let caches = caches
+            .iter()
+            .map(|(name, entries)| format!("\"{name}\":{entries}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = write!(json, "{caches}}}}}");
+        json
+    }
+}
+
+/// Keeps a counter incremented while it lives.
+#[derive(Debug)]
+pub struct Gauge<'a>(&'a AtomicUsize);
+impl<'a> Gauge<'a> {
+    fn increment(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+impl Drop for Gauge<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The `"key":count` members of a JSON object.
+fn counts<K: std::fmt::Display>(counts: &BTreeMap<K, u64>) -> String {
+    counts
+        .iter()
+        .map(|(key, count)| format!("\"{key}\":{count}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The route serving `path`, with its variable part replaced by `*`.
+fn route(path: &str) -> &'static str {
+    match path {
+        "/" => "/",
+        "/user-agent" => "/user-agent",
+        "/headers" => "/headers",
+        "/echo" => "/echo",
+        "/healthz" => "/healthz",
+        "/readyz" => "/readyz",
+        "/admin/stats" => "/admin/stats",
+        _ if path.starts_with("/echo/") => "/echo/*",
+        _ if path.starts_with("/status/") => "/status/*",
+        _ if path.starts_with("/delay/") => "/delay/*",
+        _ if path.starts_with("/files/") => "/files/*",
+        _ => "unmatched",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gauges_follow_guards() {
+        let stats = Stats::new();
+        let connection = stats.open_connection();
+        let request = stats.start_request();
+        assert!(stats
+            .to_json(&[])
+            .contains("\"open_connections\":1,\"in_flight_requests\":1,"));
+        drop(request);
+        drop(connection);
+        assert!(stats
+            .to_json(&[])
+            .contains("\"open_connections\":0,\"in_flight_requests\":0,"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let stats = Stats::new();
+        stats.count_route("GET", "/echo/abc");
+        stats.count_route("GET", "/echo/def");
+        stats.count_route("POST", "/files/a");
+        stats.count_route("GET", "/nothing/here");
+        stats.request_served();
+        stats.count_response_cache_lookup(true);
+        stats.count_response_cache_lookup(false);
+        stats.count_response_cache_lookup(true);
+        stats.count_body(Encoding::Gzip, 1000, 200, false);
+        stats.count_body(Encoding::Identity, 10, 10, true);
+        stats.count_tls_handshake_failure("bad_certificate");
+        assert_eq!(
+            stats.to_json(&[("rate_limiter_clients", 2)]),
+            "{\"uptime_seconds\":0,\"open_connections\":0,\"in_flight_requests\":0,\"requests_served\":1,\
+             \"response_cache_hits\":2,\"response_cache_misses\":1,\
+             \"compression\":{\"gzip\":{\"responses\":1,\"bytes_in\":1000,\"bytes_out\":200},\
+             \"identity\":{\"responses\":1,\"bytes_in\":10,\"bytes_out\":10},\"skipped\":1},\
+             \"tls_handshake_failures\":{\"bad_certificate\":1},\
+             \"routes\":{\"GET /echo/*\":2,\"GET unmatched\":1,\"POST /files/*\":1},\
+             \"caches\":{\"rate_limiter_clients\":2}}"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::{cmp::Ordering, path::Path, sync::Arc};
+
+use minijinja::{path_loader, Environment};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::http_response::{ContentType, HTTPResponse, ResponseStatus};
+
+/// Templates read from a directory, rendered with minijinja.
+///
+/// Templates are read when first rendered and kept afterwards, reload the
+/// configuration to pick up edits. Values are HTML-escaped in templates whose
+/// name ends in `.html`, `.htm` or `.xml`.
+#[derive(Clone)]
+pub struct Templates {
+    directory: String,
+    environment: Arc<Environment<'static>>,
+}
+impl Templates {
+    /// Serves the templates of `directory`, named by their path inside it like `errors/404.html`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `directory` isn't a readable directory.
+    pub fn load(directory: &str) -> Result<Self, TemplatesError> {
+        std::fs::read_dir(directory).map_err(|e| {
+            TemplatesError::UnreadableDirectory(directory.to_string(), e.to_string())
+        })?;
+        let mut environment = Environment::new();
+        environment.set_loader(path_loader(directory));
+        Ok(Self {
+            directory: directory.to_string(),
+            environment: Arc::new(environment),
+        })
+    }
+
+    /// Whether the directory has a template called `name`.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.environment.get_template(name).is_ok()
+    }
+
+    /// Renders the template `name` with the values of `context`, like a struct
+    /// deriving `Serialize` or `minijinja::context!`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template doesn't exist or can't be rendered.
+    pub fn render_to_string<S: Serialize>(
+        &self,
+        name: &str,
+        context: S,
+    ) -> Result<String, TemplatesError> {
+        self.environment
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(|e| TemplatesError::RenderingFailed(name.to_string(), e.to_string()))
+    }
+
+    /// Renders the template `name` into an HTML response with `status`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template doesn't exist or can't be rendered.
+    pub fn render<S: Serialize>(
+        &self,
+        status: ResponseStatus,
+        name: &str,
+        context: S,
+    ) -> Result<HTTPResponse, TemplatesError> {
+        let html = self.render_to_string(name, context)?;
+        Ok(HTTPResponse::new_builder(status)
+            .with_body(&html, ContentType::TextHtml, &[])
+            .build())
+    }
+
+    #[must_use]
+    pub fn directory(&self) -> &Path {
+        Path::new(&self.directory)
+    }
+}
+impl std::fmt::Debug for Templates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Templates")
+            .field("directory", &self.directory)
+            .finish_non_exhaustive()
+    }
+}
+// Templates are compared by directory, for the configuration holding them to be compared
+impl PartialEq for Templates {
+    fn eq(&self, other: &Self) -> bool {
+        self.directory == other.directory
+    }
+}
+impl Eq for Templates {}
+impl PartialOrd for Templates {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Templates {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.directory.cmp(&other.directory)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum TemplatesError {
+    #[error("Can't read templates directory '{0}': {1}")]
+    UnreadableDirectory(String, String),
+    #[error("Can't render template '{0}': {1}")]
+    RenderingFailed(String, String),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use minijinja::context;
+
+    use super::*;
+
+    fn templates(name: &str) -> Templates {
+        let directory = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(directory.join("errors")).unwrap();
+        std::fs::write(
+            directory.join("errors/404.html"),
+            "<p>{{ path }} not found</p>",
+        )
+        .unwrap();
+        Templates::load(&directory.to_string_lossy()).unwrap()
+    }
+
+    #[test]
+    fn test_render_escapes_html() {
+        let templates = templates("http-server-templates-render");
+        assert!(templates.contains("errors/404.html"));
+        let response = templates
+            .render(
+                ResponseStatus::Http404,
+                "errors/404.html",
+                context! { path => "/<script>" },
+            )
+            .unwrap();
+        assert_eq!(
+            response.as_http_bytes(),
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/html\r\nContent-Length: 37\r\n\r\n\
+              <p>&#x2f;&lt;script&gt; not found</p>"
+        );
+    }
+
+    #[test]
+    fn test_render_missing_template() {
+        let templates = templates("http-server-templates-missing");
+        assert!(!templates.contains("listing.html"));
+        assert!(matches!(
+            templates.render_to_string("listing.html", context! {}),
+            Err(TemplatesError::RenderingFailed(..))
+        ));
+    }
+
+    #[test]
+    fn test_load_missing_directory() {
+        assert!(matches!(
+            Templates::load("/nonexistent/http-server-templates"),
+            Err(TemplatesError::UnreadableDirectory(..))
+        ));
+    }
+}
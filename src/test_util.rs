@@ -0,0 +1,246 @@
+use std::{fmt::Write as _, net::SocketAddr, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    task::JoinHandle,
+};
+
+use crate::{
+    access_log::AccessLog,
+    client_handler::{ClientHandler, ErrorHandler},
+    config::Config,
+    server_context::ServerContext,
+};
+
+/// A server listening on an ephemeral localhost port, and a client sending it requests.
+///
+/// Each request is sent on its own connection, which the server closes once it
+/// answered. The server stops when the client is dropped.
+#[derive(Debug)]
+pub struct TestClient {
+    address: SocketAddr,
+    server: JoinHandle<()>,
+    _draining: watch::Sender<bool>,
+}
+impl TestClient {
+    /// Starts a server with the default configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no local port can be bound.
+    pub async fn start() -> Self {
+        Self::with_config(Config::default()).await
+    }
+
+    /// Starts a server with `config`, logging requests nowhere.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no local port can be bound.
+    pub async fn with_config(config: Config) -> Self {
+        Self::with_error_handler(config, ErrorHandler::default()).await
+    }
+
+    /// Starts a server with `config`, answering failed requests with `error_handler`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no local port can be bound.
+    pub async fn with_error_handler(config: Config, error_handler: ErrorHandler) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Can't bind a local port");
+        let address = listener.local_addr().expect("Listener has an address");
+        let context = Arc::new(
+            ServerContext::new(Arc::new(config), AccessLog::spawn_to(tokio::io::sink()))
+                .with_error_handler(error_handler),
+        );
+        let (draining_sender, draining) = watch::channel(false);
+        let server = tokio::spawn(async move {
+            while let Ok((mut stream, peer)) = listener.accept().await {
+                let (context, draining) = (Arc::clone(&context), draining.clone());
+                tokio::spawn(async move {
+                    let _ = ClientHandler::handle_connection(&mut stream, peer, context, draining)
+                        .await;
+                });
+            }
+        });
+        Self {
+            address,
+            server,
+            _draining: draining_sender,
+        }
+    }
+
+    #[must_use]
+    pub const fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends `GET path`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached or its response can't be parsed.
+    pub async fn get(&self, path: &str) -> TestResponse {
+        self.request("GET", path, &[], b"").await
+    }
+
+    /// Sends `POST path` with `body`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached or its response can't be parsed.
+    pub async fn post(&self, path: &str, body: &str) -> TestResponse {
+        self.request("POST", path, &[], body.as_bytes()).await
+    }
+
+    /// Sends `PUT path` with `body`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached or its response can't be parsed.
+    pub async fn put(&self, path: &str, body: &str) -> TestResponse {
+        self.request("PUT", path, &[], body.as_bytes()).await
+    }
+
+    /// Sends `DELETE path`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached or its response can't be parsed.
+    pub async fn delete(&self, path: &str) -> TestResponse {
+        self.request("DELETE", path, &[], b"").await
+    }
+
+    /// Sends a request with `headers` after `Host`, and `Content-Length` when
+    /// `body` isn't empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached or its response can't be parsed.
+    pub async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> TestResponse {
+        let mut request = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n");
+        for (name, value) in headers {
+            let _ = write!(request, "{name}: {value}\r\n");
+        }
+        if !body.is_empty() {
+            let _ = write!(request, "Content-Length: {}\r\n", body.len());
+        }
+        request.push_str("Connection: close\r\n\r\n");
+        let mut request = request.into_bytes();
+        request.extend_from_slice(body);
+        TestResponse::parse(&self.raw(&request).await)
+    }
+
+    /// Sends `bytes` as they are and returns everything the server answered
+    /// before closing the connection, for requests the helpers can't write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached.
+    pub async fn raw(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut stream = TcpStream::connect(self.address)
+            .await
+            .expect("Server accepts connections");
+        stream
+            .write_all(bytes)
+            .await
+            .expect("Server reads requests");
+        stream.shutdown().await.expect("Server reads requests");
+        let mut response = Vec::new();
+        // The server may reset a connection it rejects, what it sent before still counts
+        let _ = stream.read_to_end(&mut response).await;
+        response
+    }
+}
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// A response read by `TestClient`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TestResponse {
+    pub status: u16,
+    /// Headers in the order they were sent, except `X-Request-Id`, which differs on every run.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+impl TestResponse {
+    /// Parses a single response, its body being everything after the head.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` doesn't start with a status line and headers.
+    #[must_use]
+    pub fn parse(bytes: &[u8]) -> Self {
+        let head_length = bytes
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap_or_else(|| {
+                panic!("Response has no head: {:?}", String::from_utf8_lossy(bytes))
+            });
+        let head = std::str::from_utf8(&bytes[..head_length]).expect("Response head is UTF-8");
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|status_line| status_line.split(' ').nth(1))
+            .and_then(|code| code.parse().ok())
+            .expect("Response starts with a status line");
+        let headers = lines
+            .map(|line| {
+                let (name, value) = line.split_once(':').expect("Header has a name");
+                (name.to_string(), value.trim().to_string())
+            })
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("X-Request-Id"))
+            .collect();
+        Self {
+            status,
+            headers,
+            body: bytes[head_length + 4..].to_vec(),
+        }
+    }
+
+    /// Value of the first header called `name`, compared case-insensitively.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body as text, invalid UTF-8 replaced.
+    #[must_use]
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Drops the `X-Request-Id` headers of raw responses, generated IDs differ on every run.
+#[must_use]
+pub fn without_request_ids(responses: &str) -> String {
+    responses
+        .split_inclusive("\r\n")
+        .filter(|line| !line.starts_with("X-Request-Id: "))
+        .collect()
+}
+
+/// Drops the `Date` headers of raw responses, which change every second.
+#[must_use]
+pub fn without_dates(responses: &str) -> String {
+    responses
+        .split_inclusive("\r\n")
+        .filter(|line| !line.starts_with("Date: "))
+        .collect()
+}
@@ -0,0 +1,201 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+/// Share of a second of bandwidth a single write may use, so throttled
+/// responses go out in small steady chunks rather than in bursts.
+const CHUNKS_PER_SECOND: u64 = 20;
+
+/// A number of bytes per second, written like `1MiB/s`, `512KB/s` or `2000`.
+///
+/// Units are `B`, the decimal `KB`, `MB` and `GB`, and the binary `KiB`, `MiB`
+/// and `GiB`, the `/s` suffix being optional.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteRate(u64);
+impl ByteRate {
+    #[must_use]
+    pub const fn per_second(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    #[must_use]
+    pub const fn bytes_per_second(self) -> u64 {
+        self.0
+    }
+}
+impl FromStr for ByteRate {
+    type Err = ByteRateError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ByteRateError::InvalidRate(s.to_string());
+        let rate = s.trim();
+        let rate = rate.strip_suffix("/s").unwrap_or(rate);
+        let unit_start = rate
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rate.len());
+        let (amount, unit) = rate.split_at(unit_start);
+        let amount: u64 = amount.parse().map_err(|_| invalid())?;
+        let multiplier = match unit.trim().to_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "kib" => 1 << 10,
+            "mb" => 1_000_000,
+            "mib" => 1 << 20,
+            "gb" => 1_000_000_000,
+            "gib" => 1 << 30,
+            _ => return Err(invalid()),
+        };
+        amount
+            .checked_mul(multiplier)
+            .filter(|bytes| *bytes > 0)
+            .map(Self)
+            .ok_or_else(invalid)
+    }
+}
+impl Display for ByteRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}B/s", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum ByteRateError {
+    #[error("'{0}' is not a rate, expected bytes per second like '1MiB/s' or '500KB/s'")]
+    InvalidRate(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    bytes: f64,
+    updated: Instant,
+}
+
+/// Caps the bytes written per second, for one connection or shared by all of them.
+#[derive(Debug)]
+pub struct Throttle {
+    rate: ByteRate,
+    bucket: Mutex<Bucket>,
+}
+impl Throttle {
+    #[must_use]
+    pub fn new(rate: ByteRate) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                bytes: Self::chunk(rate),
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    #[must_use]
+    pub const fn rate(&self) -> ByteRate {
+        self.rate
+    }
+
+    /// Waits until some of `wanted` bytes may be written.
+    ///
+    /// # Returns
+    ///
+    /// Returns how many bytes may be written, at most `wanted`.
+    pub async fn take(&self, wanted: usize) -> usize {
+        loop {
+            match self.take_at(wanted, Instant::now()) {
+                Ok(allowed) => return allowed,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Returns `bytes` taken but not written.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn give_back(&self, bytes: usize) {
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        bucket.bytes = (bucket.bytes + bytes as f64).min(Self::chunk(self.rate));
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    fn take_at(&self, wanted: usize, now: Instant) -> Result<usize, Duration> {
+        let rate = self.rate.bytes_per_second() as f64;
+        let chunk = Self::chunk(self.rate);
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.bytes = elapsed.mul_add(rate, bucket.bytes).min(chunk);
+        bucket.updated = now;
+        let needed = (wanted as f64).min(chunk);
+        let result = if bucket.bytes >= needed {
+            bucket.bytes -= needed;
+            Ok(needed as usize)
+        } else {
+            Err(Duration::from_secs_f64((needed - bucket.bytes) / rate))
+        };
+        drop(bucket);
+        result
+    }
+
+    /// Most bytes a single write may take.
+    #[allow(clippy::cast_precision_loss)]
+    fn chunk(rate: ByteRate) -> f64 {
+        (rate.bytes_per_second() / CHUNKS_PER_SECOND).max(1) as f64
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_rate_from_str() {
+        assert_eq!("1MiB/s".parse(), Ok(ByteRate::per_second(1 << 20)));
+        assert_eq!("500KB/s".parse(), Ok(ByteRate::per_second(500_000)));
+        assert_eq!("2 kib".parse(), Ok(ByteRate::per_second(2048)));
+        assert_eq!("2000".parse(), Ok(ByteRate::per_second(2000)));
+        for invalid in ["", "0", "fast", "1TB/s", "-1MB/s", "99999999999GiB"] {
+            assert_eq!(
+                invalid.parse::<ByteRate>(),
+                Err(ByteRateError::InvalidRate(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_take_in_chunks_then_waits() {
+        let throttle = Throttle::new(ByteRate::per_second(2000));
+        let now = Instant::now();
+        assert_eq!(throttle.take_at(500, now), Ok(100));
+        assert_eq!(throttle.take_at(500, now), Err(Duration::from_millis(50)));
+        assert_eq!(
+            throttle.take_at(30, now + Duration::from_millis(25)),
+            Ok(30)
+        );
+        throttle.give_back(30);
+        assert_eq!(
+            throttle.take_at(50, now + Duration::from_millis(25)),
+            Ok(50)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_take_follows_rate() {
+        let throttle = Throttle::new(ByteRate::per_second(2000));
+        let started = Instant::now();
+        let mut sent = 0;
+        while sent < 500 {
+            sent += throttle.take(500 - sent).await;
+        }
+        // The first chunk is there at once, the other 400 bytes take 200ms
+        assert!(started.elapsed() >= Duration::from_millis(190));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+use crate::header_map::HeaderMap;
+
+/// Header carrying the trace and parent span of a request, per W3C Trace Context.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+/// Header carrying vendor-specific trace data along `traceparent`.
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Longest `tracestate` kept, the limit the W3C recommends propagating.
+const MAX_TRACESTATE_LENGTH: usize = 512;
+
+/// The trace a request is part of, from its `traceparent` header, like
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TraceParent {
+    version: u8,
+    trace_id: String,
+    parent_id: String,
+    flags: u8,
+}
+impl TraceParent {
+    /// The 32 lowercase hexadecimal digits identifying the whole trace.
+    #[must_use]
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// The 16 lowercase hexadecimal digits identifying the span of the caller.
+    #[must_use]
+    pub fn parent_id(&self) -> &str {
+        &self.parent_id
+    }
+
+    /// Whether the caller records this trace.
+    #[must_use]
+    pub const fn sampled(&self) -> bool {
+        self.flags & 1 == 1
+    }
+}
+impl FromStr for TraceParent {
+    type Err = TraceParentError;
+    /// Parses a `traceparent` value, ignoring the fields versions after `00` may add.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TraceParentError::InvalidTraceParent(s.to_string());
+        let s = s.trim();
+        let mut fields = s.split('-');
+        let version = fields.next().and_then(hex_byte).ok_or_else(invalid)?;
+        let trace_id = fields.next().filter(|id| is_hex_id(id, 32));
+        let parent_id = fields.next().filter(|id| is_hex_id(id, 16));
+        let flags = fields.next().and_then(hex_byte);
+        let (Some(trace_id), Some(parent_id), Some(flags)) = (trace_id, parent_id, flags) else {
+            return Err(invalid());
+        };
+        if version == 0xff || (version == 0 && fields.next().is_some()) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            version,
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags,
+        })
+    }
+}
+impl Display for TraceParent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02x}-{}-{}-{:02x}",
+            self.version, self.trace_id, self.parent_id, self.flags
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum TraceParentError {
+    #[error(
+        "'{0}' is not a traceparent, expected 'version-trace_id-parent_id-flags' in hexadecimal"
+    )]
+    InvalidTraceParent(String),
+}
+
+/// The W3C Trace Context a request was sent with, to attach to its logs and
+/// pass on to the requests made on its behalf.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TraceContext {
+    pub parent: TraceParent,
+    /// Vendor-specific data, only meaningful along `parent`.
+    pub state: Option<String>,
+}
+impl TraceContext {
+    /// The headers to send on requests made on behalf of this one so they join its trace.
+    #[must_use]
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![(TRACEPARENT_HEADER, self.parent.to_string())];
+        if let Some(state) = &self.state {
+            headers.push((TRACESTATE_HEADER, state.clone()));
+        }
+        headers
+    }
+}
+
+/// Reads the trace context of `request`, as read from the client. A missing or
+/// invalid `traceparent` means the request starts a trace of its own, and its
+/// `tracestate` is dropped along with it.
+pub(crate) fn from_request(request: &[u8]) -> Option<TraceContext> {
+    let request = String::from_utf8_lossy(request);
+    from_fields(
+        request
+            .split("\r\n")
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':')),
+    )
+}
+
+/// Reads the trace context of a request with `headers`, like [`from_request`].
+#[must_use]
+pub fn from_headers(headers: &HeaderMap) -> Option<TraceContext> {
+    from_fields(headers.iter())
+}
+
+fn from_fields<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> Option<TraceContext> {
+    let (mut parent, mut state) = (None, None);
+    for (name, value) in headers {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case(TRACEPARENT_HEADER) && parent.is_none() {
+            parent = Some(value.parse().ok()?);
+        } else if name.eq_ignore_ascii_case(TRACESTATE_HEADER) {
+            let value = value.trim();
+            // Several tracestate headers make up a single list
+            state = Some(state.map_or_else(
+                || value.to_string(),
+                |previous| format!("{previous},{value}"),
+            ));
+        }
+    }
+    Some(TraceContext {
+        parent: parent?,
+        state: state.filter(|state| {
+            !state.is_empty()
+                && state.len() <= MAX_TRACESTATE_LENGTH
+                && state
+                    .bytes()
+                    .all(|byte| byte == b' ' || byte.is_ascii_graphic())
+        }),
+    })
+}
+
+fn hex_byte(field: &str) -> Option<u8> {
+    is_hex(field, 2)
+        .then(|| u8::from_str_radix(field, 16).ok())
+        .flatten()
+}
+
+/// Whether `id` is `length` lowercase hexadecimal digits, not all zeros.
+fn is_hex_id(id: &str, length: usize) -> bool {
+    is_hex(id, length) && id.bytes().any(|byte| byte != b'0')
+}
+
+fn is_hex(field: &str, length: usize) -> bool {
+    field.len() == length
+        && field
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_traceparent_from_str() {
+        let parent: TraceParent = TRACEPARENT.parse().unwrap();
+        assert_eq!(parent.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parent.parent_id(), "00f067aa0ba902b7");
+        assert!(parent.sampled());
+        assert_eq!(parent.to_string(), TRACEPARENT);
+
+        let future: TraceParent = "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra"
+            .parse()
+            .unwrap();
+        assert!(!future.sampled());
+        for invalid in [
+            "",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert_eq!(
+                invalid.parse::<TraceParent>(),
+                Err(TraceParentError::InvalidTraceParent(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_request() {
+        let request = format!(
+            "GET / HTTP/1.1\r\nTraceparent: {TRACEPARENT}\r\ntracestate: a=1\r\ntracestate: b=2\r\n\r\n"
+        );
+        let context = from_request(request.as_bytes()).unwrap();
+        assert_eq!(context.parent.to_string(), TRACEPARENT);
+        assert_eq!(context.state.as_deref(), Some("a=1,b=2"));
+        assert_eq!(
+            context.headers(),
+            vec![
+                ("traceparent", TRACEPARENT.to_string()),
+                ("tracestate", "a=1,b=2".to_string())
+            ]
+        );
+
+        assert_eq!(
+            from_request(b"GET / HTTP/1.1\r\ntracestate: a=1\r\n\r\n"),
+            None
+        );
+        assert_eq!(
+            from_request(b"GET / HTTP/1.1\r\ntraceparent: 00-bad\r\n\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let headers: HeaderMap = [("Traceparent", TRACEPARENT), ("tracestate", "a=1")]
+            .into_iter()
+            .collect();
+        let context = from_headers(&headers).unwrap();
+        assert_eq!(context.parent.to_string(), TRACEPARENT);
+        assert_eq!(context.state.as_deref(), Some("a=1"));
+
+        let mut headers = HeaderMap::new();
+        headers.append("tracestate", "a=1");
+        assert_eq!(from_headers(&headers), None);
+    }
+}
@@ -0,0 +1,692 @@
+use std::{cmp::Reverse, fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{http_request::parse_quality, http_response::ContentType};
+
+/// Media types a client accepts, from an `Accept` header like
+/// `text/html, application/json;q=0.9, */*;q=0.1`.
+///
+/// Without ranges, as when the header is missing, any media type is acceptable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Accept {
+    ranges: Vec<MediaRange>,
+}
+impl Accept {
+    /// Quality of `media_type` in thousandths, given by the most specific
+    /// range that matches it, 1000 when any media type is acceptable.
+    #[must_use]
+    pub fn quality(&self, media_type: &ContentType) -> u16 {
+        self.best_range(media_type)
+            .map_or(0, |(_, range)| range.quality)
+    }
+
+    /// Picks the media type of `available` the client prefers. On equal
+    /// qualities, the one matched by the more specific range wins, then the
+    /// one the client listed first, then the first of `available`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the client accepts none of them.
+    #[must_use]
+    pub fn negotiate<'a>(&self, available: &'a [ContentType]) -> Option<&'a ContentType> {
+        available
+            .iter()
+            .filter_map(|media_type| {
+                let (position, range) = self.best_range(media_type)?;
+                let rank = (range.quality, range.specificity(), Reverse(position));
+                (range.quality > 0).then_some((rank, media_type))
+            })
+            // `max_by_key` keeps the last of equal ranks, the first one available
+            .rev()
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, media_type)| media_type)
+    }
+
+    /// The most specific range matching `media_type`, with its position.
+    fn best_range(&self, media_type: &ContentType) -> Option<(usize, &MediaRange)> {
+        if self.ranges.is_empty() {
+            return Some((0, &ANY));
+        }
+        let (kind, subtype) = media_type
+            .as_str()
+            .split_once('/')
+            .unwrap_or((media_type.as_str(), ""));
+        self.ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.matches(kind, subtype))
+            // The first of equally specific ranges, like `max_by_key` wouldn't
+            .min_by_key(|(position, range)| (Reverse(range.specificity()), *position))
+    }
+}
+impl FromStr for Accept {
+    type Err = std::convert::Infallible;
+    /// Parses the comma-separated ranges, skipping the invalid ones.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            ranges: s
+                .split(',')
+                .filter(|range| !range.trim().is_empty())
+                .filter_map(|range| range.parse().ok())
+                .collect(),
+        })
+    }
+}
+
+static ANY: MediaRange = MediaRange {
+    kind: String::new(),
+    subtype: String::new(),
+    quality: 1000,
+};
+
+/// A media range of `Accept`, like `text/*`, with its quality in thousandths.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MediaRange {
+    kind: String,
+    subtype: String,
+    quality: u16,
+}
+impl MediaRange {
+    fn matches(&self, kind: &str, subtype: &str) -> bool {
+        (self.kind == "*" || self.kind == kind) && (self.subtype == "*" || self.subtype == subtype)
+    }
+
+    /// 2 for `type/subtype`, 1 for `type/*`, 0 for `*/*`.
+    fn specificity(&self) -> u8 {
+        u8::from(self.kind != "*") + u8::from(self.subtype != "*")
+    }
+}
+impl FromStr for MediaRange {
+    type Err = MediaRangeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let range = parts.next().unwrap_or_default().trim().to_lowercase();
+        let is_token = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+*".contains(c))
+        };
+        let (kind, subtype) = match range.split_once('/') {
+            Some((kind, subtype))
+                if is_token(kind) && is_token(subtype) && (kind != "*" || subtype == "*") =>
+            {
+                (kind.to_string(), subtype.to_string())
+            }
+            _ => return Err(MediaRangeError::InvalidRange(range)),
+        };
+        let mut quality = 1000;
+        for parameter in parts {
+            let Some((name, value)) = parameter.split_once('=') else {
+                continue;
+            };
+            if name.trim().eq_ignore_ascii_case("q") {
+                quality = parse_quality(value.trim())
+                    .ok_or_else(|| MediaRangeError::InvalidQuality(value.trim().to_string()))?;
+            }
+        }
+        Ok(Self {
+            kind,
+            subtype,
+            quality,
+        })
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum MediaRangeError {
+    #[error("'{0}' is not a valid media range, expected one like 'text/*'")]
+    InvalidRange(String),
+    #[error("'{0}' is not a valid quality, expected a number between 0 and 1")]
+    InvalidQuality(String),
+}
+
+/// The media type of a body, from a `Content-Type` like
+/// `multipart/form-data; boundary="----abc"`.
+///
+/// The type, subtype and parameter names are lowercased, as they are
+/// case-insensitive, parameter values are kept as sent once unquoted.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MediaType {
+    kind: String,
+    subtype: String,
+    parameters: Vec<(String, String)>,
+}
+impl MediaType {
+    /// The top-level type, like `multipart`.
+    #[must_use]
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    #[must_use]
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// The type and subtype without parameters, like `multipart/form-data`.
+    #[must_use]
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.kind, self.subtype)
+    }
+
+    /// The value of the first parameter called `name`, compared
+    /// case-insensitively.
+    #[must_use]
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[must_use]
+    pub fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+
+    #[must_use]
+    pub fn is_multipart(&self) -> bool {
+        self.kind == "multipart"
+    }
+
+    /// The `boundary` separating the parts of a `multipart` body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` for other types, or if the boundary is missing or not
+    /// 1 to 70 characters long as RFC 2046 section 5.1.1 requires.
+    #[must_use]
+    pub fn boundary(&self) -> Option<&str> {
+        self.parameter("boundary")
+            .filter(|boundary| self.is_multipart() && (1..=70).contains(&boundary.len()))
+    }
+}
+impl FromStr for MediaType {
+    type Err = MediaTypeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (essence, mut rest) = s.split_once(';').unwrap_or((s, ""));
+        let essence = essence.trim().to_lowercase();
+        let (kind, subtype) = essence
+            .split_once('/')
+            .filter(|(kind, subtype)| is_token(kind) && is_token(subtype))
+            .ok_or_else(|| MediaTypeError::InvalidMediaType(s.to_string()))?;
+        let mut parameters = vec![];
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ';']);
+            if rest.is_empty() {
+                break;
+            }
+            let invalid = || MediaTypeError::InvalidParameter(rest.to_string());
+            let (name, tail) = rest.split_once('=').ok_or_else(invalid)?;
+            let name = name.trim();
+            if !is_token(name) {
+                return Err(invalid());
+            }
+            let (value, tail) = if let Some(quoted) = tail.strip_prefix('"') {
+                split_quoted(quoted).ok_or_else(invalid)?
+            } else {
+                let end = tail.find(';').unwrap_or(tail.len());
+                let value = tail[..end].trim();
+                if !is_token(value) {
+                    return Err(invalid());
+                }
+                (value.to_string(), &tail[end..])
+            };
+            parameters.push((name.to_lowercase(), value));
+            rest = tail;
+        }
+        Ok(Self {
+            kind: kind.to_string(),
+            subtype: subtype.to_string(),
+            parameters,
+        })
+    }
+}
+impl Display for MediaType {
+    /// Writes the media type back as a `Content-Type` value, quoting the
+    /// parameter values that aren't tokens.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.kind, self.subtype)?;
+        for (name, value) in &self.parameters {
+            if is_token(value) {
+                write!(f, "; {name}={value}")?;
+            } else {
+                let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {name}=\"{value}\"")?;
+            }
+        }
+        Ok(())
+    }
+}
+impl From<&MediaType> for ContentType {
+    fn from(media_type: &MediaType) -> Self {
+        let essence = media_type.essence();
+        essence.parse().unwrap_or(Self::Other(essence))
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum MediaTypeError {
+    #[error("'{0}' is not a valid media type, expected one like 'text/plain'")]
+    InvalidMediaType(String),
+    #[error("'{0}' is not a list of 'name=value' parameters")]
+    InvalidParameter(String),
+}
+
+/// Whether `s` is a token (RFC 9110 section 5.6.2).
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+/// Splits the quoted string opening `s`, its `"` already removed, from what
+/// follows it, undoing its `\` escapes. `None` if it isn't closed.
+fn split_quoted(s: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?.1),
+            '"' => return Some((value, &s[i + 1..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// An entity tag (RFC 9110 section 8.8.3), like `"abc"` or the weak `W/"abc"`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntityTag {
+    weak: bool,
+    /// The tag without its quotes.
+    opaque: String,
+}
+impl EntityTag {
+    #[must_use]
+    pub const fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Whether both tags are strong and the same, as writes require.
+    #[must_use]
+    pub fn strong_eq(&self, other: &Self) -> bool {
+        !self.weak && !other.weak && self.opaque == other.opaque
+    }
+
+    /// Whether both tags are the same, weak or not, as reads allow.
+    #[must_use]
+    pub fn weak_eq(&self, other: &Self) -> bool {
+        self.opaque == other.opaque
+    }
+}
+impl FromStr for EntityTag {
+    type Err = EntityTagError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (weak, tag) = s.strip_prefix("W/").map_or((false, s), |tag| (true, tag));
+        let opaque = tag
+            .strip_prefix('"')
+            .and_then(|tag| tag.strip_suffix('"'))
+            .filter(|opaque| !opaque.contains('"'))
+            .ok_or_else(|| EntityTagError::InvalidTag(s.to_string()))?;
+        Ok(Self {
+            weak,
+            opaque: opaque.to_string(),
+        })
+    }
+}
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.opaque)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum EntityTagError {
+    #[error("'{0}' is not an entity tag, expected one like '\"abc\"' or 'W/\"abc\"'")]
+    InvalidTag(String),
+}
+
+/// The value of an `If-Match` or `If-None-Match` header: `*` or a list of tags.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EntityTags {
+    /// `*`, matching any current representation.
+    Any,
+    List(Vec<EntityTag>),
+}
+impl EntityTags {
+    /// Whether `tag` is in the list with the strong comparison, `If-Match`'s.
+    #[must_use]
+    pub fn matches_strong(&self, tag: &EntityTag) -> bool {
+        match self {
+            Self::Any => true,
+            Self::List(tags) => tags.iter().any(|listed| listed.strong_eq(tag)),
+        }
+    }
+
+    /// Whether `tag` is in the list with the weak comparison, `If-None-Match`'s.
+    #[must_use]
+    pub fn matches_weak(&self, tag: &EntityTag) -> bool {
+        match self {
+            Self::Any => true,
+            Self::List(tags) => tags.iter().any(|listed| listed.weak_eq(tag)),
+        }
+    }
+}
+impl FromStr for EntityTags {
+    type Err = EntityTagError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "*" {
+            return Ok(Self::Any);
+        }
+        s.split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self::List)
+    }
+}
+
+/// A range of bytes of a `Range` header, bounds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ByteRange {
+    /// `first-last`.
+    FromTo(u64, u64),
+    /// `first-`, up to the end.
+    From(u64),
+    /// `-length`, the last `length` bytes.
+    Suffix(u64),
+}
+impl ByteRange {
+    /// The first and last byte of the range in a representation of `length`
+    /// bytes, `None` if it starts past its end or is an empty suffix.
+    #[must_use]
+    pub fn resolve(self, length: u64) -> Option<(u64, u64)> {
+        let last = length.checked_sub(1)?;
+        match self {
+            Self::FromTo(first, end) if first <= last => Some((first, end.min(last))),
+            Self::From(first) if first <= last => Some((first, last)),
+            Self::Suffix(suffix) if suffix > 0 => Some((length.saturating_sub(suffix), last)),
+            _ => None,
+        }
+    }
+}
+
+/// The byte ranges of a `Range` header, like `bytes=0-499, -500`
+/// (RFC 9110 section 14.1.2).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Range {
+    ranges: Vec<ByteRange>,
+}
+impl Range {
+    /// The ranges, in the order they were asked for.
+    #[must_use]
+    pub fn ranges(&self) -> &[ByteRange] {
+        &self.ranges
+    }
+}
+impl FromStr for Range {
+    type Err = RangeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RangeError::InvalidRange(s.to_string());
+        let (unit, ranges) = s.split_once('=').ok_or_else(invalid)?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return Err(RangeError::UnsupportedUnit(unit.trim().to_string()));
+        }
+        let position = |position: &str| {
+            (!position.is_empty() && position.bytes().all(|byte| byte.is_ascii_digit()))
+                .then(|| position.parse::<u64>().ok())
+                .flatten()
+        };
+        let ranges = ranges
+            .split(',')
+            .map(str::trim)
+            .filter(|range| !range.is_empty())
+            .map(|range| {
+                let (first, last) = range.split_once('-')?;
+                match (position(first.trim()), position(last.trim())) {
+                    (Some(first), Some(last)) if first <= last => {
+                        Some(ByteRange::FromTo(first, last))
+                    }
+                    (Some(first), None) if last.trim().is_empty() => Some(ByteRange::From(first)),
+                    (None, Some(suffix)) if first.trim().is_empty() => {
+                        Some(ByteRange::Suffix(suffix))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|ranges| !ranges.is_empty())
+            .ok_or_else(invalid)?;
+        Ok(Self { ranges })
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum RangeError {
+    #[error("'{0}' is not a valid range, expected one like 'bytes=0-499'")]
+    InvalidRange(String),
+    #[error("'{0}' is not a supported range unit, expected 'bytes'")]
+    UnsupportedUnit(String),
+}
+
+/// The directives of a `Cache-Control` header a cache acts on, like
+/// `public, max-age=60, stale-while-revalidate=30` (RFC 9111 section 5.2 and RFC 5861).
+///
+/// Other directives are ignored, as caches must (RFC 9111 section 5.2.3),
+/// and so are the field names `private` and `no-cache` may list, the whole
+/// response being treated as they say.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    /// Seconds the response stays fresh.
+    pub max_age: Option<u64>,
+    /// Seconds the response stays fresh in shared caches, overriding `max_age` there.
+    pub s_maxage: Option<u64>,
+    /// Seconds the response may still be sent once stale, while a new one is fetched.
+    pub stale_while_revalidate: Option<u64>,
+    /// Seconds the response may still be sent once stale, when fetching a new one fails.
+    pub stale_if_error: Option<u64>,
+}
+impl CacheControl {
+    /// Seconds a shared cache may serve the response for, if the header says.
+    #[must_use]
+    pub const fn shared_max_age(&self) -> Option<u64> {
+        match self.s_maxage {
+            Some(seconds) => Some(seconds),
+            None => self.max_age,
+        }
+    }
+}
+impl FromStr for CacheControl {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cache_control = Self::default();
+        for directive in s.split(',').map(str::trim) {
+            let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+            let seconds = || value.trim().trim_matches('"').parse().ok();
+            match name.trim().to_lowercase().as_str() {
+                "no-store" => cache_control.no_store = true,
+                "no-cache" => cache_control.no_cache = true,
+                "private" => cache_control.private = true,
+                "max-age" => cache_control.max_age = seconds(),
+                "s-maxage" => cache_control.s_maxage = seconds(),
+                "stale-while-revalidate" => cache_control.stale_while_revalidate = seconds(),
+                "stale-if-error" => cache_control.stale_if_error = seconds(),
+                _ => {}
+            }
+        }
+        Ok(cache_control)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_negotiate() {
+        let (text, json) = (ContentType::TextPlain, ContentType::ApplicationJson);
+        let available = [text.clone(), json.clone()];
+        let accept = |s: &str| s.parse::<Accept>().unwrap();
+        assert_eq!(
+            accept("application/json").negotiate(&available),
+            Some(&json)
+        );
+        assert_eq!(accept("*/*").negotiate(&available), Some(&text));
+        assert_eq!(accept("").negotiate(&available), Some(&text));
+        assert_eq!(
+            accept("application/json, text/plain").negotiate(&available),
+            Some(&json)
+        );
+        assert_eq!(
+            accept("text/*;q=0.5, application/*;q=0.8").negotiate(&available),
+            Some(&json)
+        );
+        assert_eq!(
+            accept("text/html, application/xhtml+xml, */*;q=0.8").negotiate(&available),
+            Some(&text)
+        );
+        assert_eq!(accept("image/png").negotiate(&available), None);
+        assert_eq!(
+            accept("*/*, text/plain;q=0").negotiate(&available),
+            Some(&json)
+        );
+        assert_eq!(accept("text/plain;q=0.3, */*;q=0.9").quality(&text), 300);
+        assert_eq!(
+            accept("*/plain, nonsense, text/plain;q=2, image/*").quality(&text),
+            0
+        );
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let cache_control = |s: &str| s.parse::<CacheControl>().unwrap();
+        assert_eq!(cache_control(""), CacheControl::default());
+        let public = cache_control("public, Max-Age=60, must-revalidate");
+        assert_eq!(public.max_age, Some(60));
+        assert_eq!(public.shared_max_age(), Some(60));
+        assert_eq!(
+            cache_control("max-age=60, s-maxage=\"10\"").shared_max_age(),
+            Some(10)
+        );
+        let private = cache_control("private=\"Set-Cookie\", no-cache, no-store");
+        assert!(private.private && private.no_cache && private.no_store);
+        assert_eq!(cache_control("max-age=soon").max_age, None);
+        let stale = cache_control("max-age=1, stale-while-revalidate=30, stale-if-error=600");
+        assert_eq!(
+            (stale.stale_while_revalidate, stale.stale_if_error),
+            (Some(30), Some(600))
+        );
+    }
+
+    #[test]
+    fn test_entity_tags() {
+        let tag = |s: &str| s.parse::<EntityTag>().unwrap();
+        assert!(tag("W/\"a\"").is_weak());
+        assert_eq!(tag("W/\"a\"").to_string(), "W/\"a\"");
+        assert!(tag("\"a\"").strong_eq(&tag("\"a\"")));
+        assert!(!tag("W/\"a\"").strong_eq(&tag("\"a\"")));
+        assert!(tag("W/\"a\"").weak_eq(&tag("\"a\"")));
+        assert!(EntityTag::from_str("a").is_err());
+        assert!(EntityTag::from_str("\"a\"b\"").is_err());
+
+        let tags: EntityTags = "\"a\", W/\"b\"".parse().unwrap();
+        assert!(tags.matches_strong(&tag("\"a\"")));
+        assert!(!tags.matches_strong(&tag("\"b\"")));
+        assert!(tags.matches_weak(&tag("\"b\"")));
+        assert!("*"
+            .parse::<EntityTags>()
+            .unwrap()
+            .matches_strong(&tag("\"c\"")));
+        assert!(EntityTags::from_str("\"a\", b").is_err());
+    }
+
+    #[test]
+    fn test_range_from_str() {
+        let range: Range = "bytes=0-499, 500-, -200".parse().unwrap();
+        assert_eq!(
+            range.ranges(),
+            [
+                ByteRange::FromTo(0, 499),
+                ByteRange::From(500),
+                ByteRange::Suffix(200)
+            ]
+        );
+        assert_eq!(ByteRange::FromTo(0, 499).resolve(100), Some((0, 99)));
+        assert_eq!(ByteRange::From(500).resolve(100), None);
+        assert_eq!(ByteRange::Suffix(200).resolve(100), Some((0, 99)));
+        assert_eq!(ByteRange::Suffix(0).resolve(100), None);
+        assert_eq!(ByteRange::From(0).resolve(0), None);
+        assert_eq!(
+            Range::from_str("items=0-1"),
+            Err(RangeError::UnsupportedUnit("items".to_string()))
+        );
+        for invalid in [
+            "bytes=",
+            "bytes=5-1",
+            "bytes=a-b",
+            "bytes=-",
+            "0-1",
+            "bytes=1-2-3",
+        ] {
+            assert_eq!(
+                Range::from_str(invalid),
+                Err(RangeError::InvalidRange(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_media_type_from_str() {
+        let media_type: MediaType =
+            "Multipart/Form-Data; Boundary=\"----a;b\\\"c\"; charset=utf-8;"
+                .parse()
+                .unwrap();
+        assert_eq!(media_type.essence(), "multipart/form-data");
+        assert!(media_type.is_multipart());
+        assert_eq!(media_type.boundary(), Some("----a;b\"c"));
+        assert_eq!(media_type.parameter("CHARSET"), Some("utf-8"));
+        assert_eq!(
+            media_type.to_string(),
+            "multipart/form-data; boundary=\"----a;b\\\"c\"; charset=utf-8"
+        );
+        assert_eq!(
+            media_type.to_string().parse::<MediaType>().unwrap(),
+            media_type
+        );
+
+        let media_type: MediaType = "application/json".parse().unwrap();
+        assert_eq!(ContentType::from(&media_type), ContentType::ApplicationJson);
+        assert_eq!(media_type.boundary(), None);
+        assert_eq!(
+            "text/plain; boundary=abc"
+                .parse::<MediaType>()
+                .unwrap()
+                .boundary(),
+            None
+        );
+        assert_eq!(
+            ContentType::from(&"image/png".parse::<MediaType>().unwrap()),
+            ContentType::Other("image/png".to_string())
+        );
+
+        assert!(matches!(
+            "text".parse::<MediaType>(),
+            Err(MediaTypeError::InvalidMediaType(_))
+        ));
+        assert!(matches!(
+            "text/plain; charset".parse::<MediaType>(),
+            Err(MediaTypeError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            "text/plain; name=\"unclosed".parse::<MediaType>(),
+            Err(MediaTypeError::InvalidParameter(_))
+        ));
+    }
+}
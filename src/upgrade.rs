@@ -0,0 +1,244 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    http_request::HTTPRequest,
+    http_response::{HTTPResponse, ResponseStatus},
+};
+
+/// The stream of a connection, as handed to an upgrade handler.
+pub trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + ?Sized> Io for T {}
+
+/// What an upgrade handler returns, the future speaking the new protocol.
+pub type UpgradeFuture<'a> = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send + 'a>>;
+
+type UpgradeHandler = dyn for<'a> Fn(Upgraded<'a>) -> UpgradeFuture<'a> + Send + Sync;
+
+/// A connection taken over by an upgrade handler once the
+/// `101 Switching Protocols` response was sent, speaking the new protocol.
+///
+/// Reading it first gives the bytes the client sent after its request, which
+/// the server had already read, then what comes from the stream.
+pub struct Upgraded<'a> {
+    io: &'a mut dyn Io,
+    buffered: Bytes,
+}
+impl<'a> Upgraded<'a> {
+    pub(crate) fn new(io: &'a mut dyn Io, buffered: Bytes) -> Self {
+        Self { io, buffered }
+    }
+
+    /// The stream and the bytes already read from it, for protocols that
+    /// rather frame them themselves.
+    #[must_use]
+    pub fn into_parts(self) -> (&'a mut dyn Io, Bytes) {
+        (self.io, self.buffered)
+    }
+}
+impl AsyncRead for Upgraded<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.buffered.is_empty() {
+            return Pin::new(&mut *self.io).poll_read(cx, buf);
+        }
+        let length = self.buffered.len().min(buf.remaining());
+        buf.put_slice(&self.buffered[..length]);
+        self.buffered.advance(length);
+        Poll::Ready(Ok(()))
+    }
+}
+impl AsyncWrite for Upgraded<'_> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut *self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut *self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.io).poll_shutdown(cx)
+    }
+}
+impl std::fmt::Debug for Upgraded<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upgraded")
+            .field("buffered", &self.buffered.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The handler a `101 Switching Protocols` response hands its connection to,
+/// see `HTTPResponse::with_upgrade`.
+///
+/// Clones share the same handler, and compare equal only to each other.
+#[derive(Clone)]
+pub struct OnUpgrade(Arc<UpgradeHandler>);
+impl OnUpgrade {
+    pub fn new(
+        handler: impl for<'a> Fn(Upgraded<'a>) -> UpgradeFuture<'a> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(handler))
+    }
+
+    /// Speaks the new protocol on `upgraded`, until the handler is done with it.
+    pub(crate) fn run<'a>(&self, upgraded: Upgraded<'a>) -> UpgradeFuture<'a> {
+        (self.0)(upgraded)
+    }
+
+    fn address(&self) -> *const () {
+        Arc::as_ptr(&self.0).cast()
+    }
+}
+impl PartialEq for OnUpgrade {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for OnUpgrade {}
+impl PartialOrd for OnUpgrade {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for OnUpgrade {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.address().cmp(&other.address())
+    }
+}
+impl std::fmt::Debug for OnUpgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OnUpgrade").finish_non_exhaustive()
+    }
+}
+
+/// Whether `request` asks to switch to `protocol`, like `websocket` or `h2c`,
+/// with `upgrade` among its `Connection` options.
+///
+/// `protocol` is compared case-insensitively with each protocol of the
+/// comma-separated lists of its `Upgrade` headers. A protocol asked for
+/// without a version, like `websocket`, matches any version offered, like
+/// `websocket/13`, one asked for with a version only matches that version.
+#[must_use]
+pub fn requested(request: &HTTPRequest, protocol: &str) -> bool {
+    let header = request.header();
+    header.connection().iter().any(|option| option == "upgrade")
+        && header
+            .headers()
+            .get_all("Upgrade")
+            .flat_map(|upgrade| upgrade.split(','))
+            .map(str::trim)
+            .any(|offered| {
+                offered.eq_ignore_ascii_case(protocol)
+                    || offered
+                        .split_once('/')
+                        .is_some_and(|(name, _)| name.eq_ignore_ascii_case(protocol))
+            })
+}
+
+/// A `101 Switching Protocols` response to `protocol`, handing the connection
+/// to `handler` once sent.
+pub fn switching_protocols(
+    protocol: &str,
+    handler: impl for<'a> Fn(Upgraded<'a>) -> UpgradeFuture<'a> + Send + Sync + 'static,
+) -> HTTPResponse {
+    HTTPResponse::new_builder(ResponseStatus::Http101)
+        .with_header("Connection", "upgrade")
+        .with_header("Upgrade", protocol)
+        .build()
+        .with_upgrade(handler)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    fn request(head: &str) -> HTTPRequest {
+        HTTPRequest::parse(
+            head,
+            Some(Bytes::new()),
+            "127.0.0.1:4000".parse().unwrap(),
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_requested() {
+        let upgrade = request(
+            "GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive, Upgrade\r\nUpgrade: websocket/13, h2c\r\n\r\n",
+        );
+        assert!(requested(&upgrade, "websocket"));
+        assert!(requested(&upgrade, "H2C"));
+        assert!(!requested(&upgrade, "irc"));
+        assert!(requested(&upgrade, "WebSocket/13"));
+        assert!(!requested(&upgrade, "websocket/14"));
+        let several = request(
+            "GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: UPGRADE\r\nUpgrade: irc\r\nUpgrade: WEBSOCKET\r\n\r\n",
+        );
+        assert!(requested(&several, "websocket"));
+        assert!(!requested(&several, "websocket/13"));
+        let without_option =
+            request("GET /chat HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\r\n");
+        assert!(!requested(&without_option, "websocket"));
+    }
+
+    #[tokio::test]
+    async fn test_upgraded_reads_buffered_bytes_first() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(b" world").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut upgraded = Upgraded::new(&mut server, Bytes::from_static(b"hello"));
+        let mut read = String::new();
+        upgraded.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "hello world");
+        upgraded.write_all(b"bye").await.unwrap();
+        drop(upgraded);
+        drop(server);
+        let mut sent = String::new();
+        client.read_to_string(&mut sent).await.unwrap();
+        assert_eq!(sent, "bye");
+    }
+
+    #[test]
+    fn test_switching_protocols() {
+        let response = switching_protocols("websocket", |_| Box::pin(async { Ok(()) }));
+        assert_eq!(
+            response.as_http_bytes(),
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: upgrade\r\nUpgrade: websocket\r\n\r\n"
+        );
+        assert!(response.upgrade().is_some());
+        assert_eq!(response.clone(), response);
+    }
+}
@@ -0,0 +1,366 @@
+use std::{fmt::Write, io};
+
+use bytes::{Buf, Bytes, BytesMut};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    forwarding,
+    header_map::HeaderMap,
+    http_request::{self, HTTPRequest, RequestMethod},
+    http_response::{ContentType, HTTPResponse, ResponseStatus},
+};
+
+/// Largest upstream response read, head and body included, for a proxied
+/// response not to take all the memory of the server.
+pub const MAX_UPSTREAM_RESPONSE_SIZE: usize = 8 << 20;
+
+/// A response of an upstream server, its body already unchunked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+impl UpstreamResponse {
+    /// The response to send back to the client, hop-by-hop headers and
+    /// `strip` removed and the server added to `Via`.
+    ///
+    /// The body is passed on as upstream coded it, never compressed again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status isn't a final one.
+    pub fn into_response(self, strip: &[String]) -> Result<HTTPResponse, UpstreamError> {
+        let status: ResponseStatus = self
+            .status
+            .to_string()
+            .parse()
+            .map_err(|_| UpstreamError::InvalidResponse(format!("status {}", self.status)))?;
+        let headers = forwarding::downstream_headers(&self.headers, strip);
+        let mut builder = HTTPResponse::new_builder(status);
+        if status.allows_body() {
+            let content_type = headers
+                .get("Content-Type")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(ContentType::OctetStream);
+            builder = builder.with_bytes(self.body, content_type, &[]);
+        }
+        for (name, value) in headers.iter() {
+            builder = match name {
+                _ if name.eq_ignore_ascii_case("Content-Length") => builder,
+                // Replaces the media type alone `with_bytes` set, parameters included
+                _ if name.eq_ignore_ascii_case("Content-Type") => {
+                    builder.with_replaced_header(name, value)
+                }
+                _ => builder.with_header(name, value),
+            };
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Sends `request` to the server at `upstream`, a `host:port`, with
+/// `headers` in place of its own and its body as received, never
+/// transcoded, and reads its response.
+///
+/// The connection is closed once the response is read, upstream connections
+/// aren't kept for later requests.
+///
+/// # Errors
+///
+/// Returns an error if upstream can't be reached, or its response is
+/// invalid, cut short or larger than [`MAX_UPSTREAM_RESPONSE_SIZE`].
+pub async fn send(
+    request: &HTTPRequest,
+    upstream: &str,
+    headers: &HeaderMap,
+) -> Result<UpstreamResponse, UpstreamError> {
+    let mut stream = TcpStream::connect(upstream)
+        .await
+        .map_err(UpstreamError::Connect)?;
+    exchange(&mut stream, request, headers).await
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    request: &HTTPRequest,
+    headers: &HeaderMap,
+) -> Result<UpstreamResponse, UpstreamError> {
+    let request_line = request.line();
+    let body = request
+        .body()
+        .map(http_request::RequestBody::bytes)
+        .unwrap_or_default();
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\n",
+        request_line.method(),
+        request_line.uri()
+    );
+    for (name, value) in headers.iter() {
+        if !name.eq_ignore_ascii_case("Content-Length") {
+            let _ = write!(head, "{name}: {value}\r\n");
+        }
+    }
+    if !body.is_empty()
+        || matches!(
+            request_line.method(),
+            RequestMethod::Post | RequestMethod::Put
+        )
+    {
+        let _ = write!(head, "Content-Length: {}\r\n", body.len());
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.flush().await?;
+    read_response(stream, *request_line.method() == RequestMethod::Head).await
+}
+
+/// Reads a response off `stream`, skipping the interim ones before it. The
+/// response to a `HEAD` request, `head_only`, has no body whatever its headers say.
+async fn read_response<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    head_only: bool,
+) -> Result<UpstreamResponse, UpstreamError> {
+    let mut buf = BytesMut::new();
+    loop {
+        let head_len = loop {
+            if let Some(position) = find(&buf, b"\r\n\r\n") {
+                break position + 4;
+            }
+            fill(stream, &mut buf).await?;
+        };
+        let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+        buf.advance(head_len);
+        let (status, headers) = parse_head(&head)?;
+        if (100..200).contains(&status) {
+            continue;
+        }
+        let body = if head_only || matches!(status, 204 | 304) {
+            Bytes::new()
+        } else if headers
+            .get_all("Transfer-Encoding")
+            .any(|coding| coding.to_ascii_lowercase().contains("chunked"))
+        {
+            read_chunked(stream, &mut buf).await?
+        } else {
+            let lengths: Vec<&str> = headers.get_all("Content-Length").collect();
+            match http_request::content_length(&lengths) {
+                Ok(Some(length)) => {
+                    let length = usize::try_from(length)
+                        .ok()
+                        .filter(|length| *length <= MAX_UPSTREAM_RESPONSE_SIZE)
+                        .ok_or(UpstreamError::TooLarge)?;
+                    while buf.len() < length {
+                        fill(stream, &mut buf).await?;
+                    }
+                    buf.split_to(length).freeze()
+                }
+                // Without a length, the body ends with the connection
+                Ok(None) => loop {
+                    if stream.read_buf(&mut buf).await? == 0 {
+                        break buf.split().freeze();
+                    }
+                    if buf.len() > MAX_UPSTREAM_RESPONSE_SIZE {
+                        return Err(UpstreamError::TooLarge);
+                    }
+                },
+                Err(e) => return Err(UpstreamError::InvalidResponse(e.to_string())),
+            }
+        };
+        return Ok(UpstreamResponse {
+            status,
+            headers,
+            body,
+        });
+    }
+}
+
+/// Parses the status line and headers of a response head.
+fn parse_head(head: &str) -> Result<(u16, HeaderMap), UpstreamError> {
+    let invalid = || UpstreamError::InvalidResponse(head.lines().next().unwrap_or_default().into());
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.get(2..5))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(invalid)?;
+    let mut headers = HeaderMap::new();
+    for line in lines.take_while(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or_else(invalid)?;
+        headers.append(name.trim(), value.trim());
+    }
+    Ok((status, headers))
+}
+
+/// Reads a chunked body, dropping its trailers.
+async fn read_chunked<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+) -> Result<Bytes, UpstreamError> {
+    let mut body = BytesMut::new();
+    loop {
+        let line = read_line(stream, buf).await?;
+        let size = line
+            .split(';')
+            .next()
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| UpstreamError::InvalidResponse(format!("chunk size '{line}'")))?;
+        if size == 0 {
+            while !read_line(stream, buf).await?.is_empty() {}
+            return Ok(body.freeze());
+        }
+        if size > MAX_UPSTREAM_RESPONSE_SIZE - body.len() {
+            return Err(UpstreamError::TooLarge);
+        }
+        while buf.len() < size + 2 {
+            fill(stream, buf).await?;
+        }
+        body.extend_from_slice(&buf[..size]);
+        buf.advance(size + 2);
+    }
+}
+
+/// Reads a line ending with CRLF off the front of `buf`, without its end.
+async fn read_line<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+) -> Result<String, UpstreamError> {
+    loop {
+        if let Some(end) = find(buf, b"\r\n") {
+            let line = String::from_utf8_lossy(&buf[..end]).into_owned();
+            buf.advance(end + 2);
+            return Ok(line);
+        }
+        fill(stream, buf).await?;
+    }
+}
+
+/// Reads more of the response into `buf`.
+async fn fill<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+) -> Result<(), UpstreamError> {
+    if buf.len() > MAX_UPSTREAM_RESPONSE_SIZE {
+        return Err(UpstreamError::TooLarge);
+    }
+    if stream.read_buf(buf).await? == 0 {
+        return Err(UpstreamError::Incomplete);
+    }
+    Ok(())
+}
+
+fn find(buf: &[u8], needle: &[u8]) -> Option<usize> {
+    buf.windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[derive(Debug, Error)]
+pub enum UpstreamError {
+    #[error("Can't connect to upstream: {0}")]
+    Connect(io::Error),
+    #[error("Upstream connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("Upstream closed the connection before the end of its response")]
+    Incomplete,
+    #[error("Upstream sent an invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("Upstream response is larger than {MAX_UPSTREAM_RESPONSE_SIZE} bytes")]
+    TooLarge,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    async fn read(response: &[u8], head_only: bool) -> Result<UpstreamResponse, UpstreamError> {
+        read_response(&mut &response[..], head_only).await
+    }
+
+    #[tokio::test]
+    async fn test_read_response() {
+        let response = read(
+            b"HTTP/1.1 100 Continue\r\n\r\n\
+              HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello, ignored",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (response.status, response.body.as_ref()),
+            (200, &b"hello"[..])
+        );
+
+        let chunked = read(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3\r\nabc\r\n2;ext=1\r\nde\r\n0\r\nX-Trailer: 1\r\n\r\n",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(chunked.body.as_ref(), b"abcde");
+
+        let until_close = read(b"HTTP/1.0 404 Not Found\r\n\r\ngone", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            (until_close.status, until_close.body.as_ref()),
+            (404, &b"gone"[..])
+        );
+        let head = read(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", true)
+            .await
+            .unwrap();
+        assert!(head.body.is_empty());
+
+        assert!(matches!(
+            read(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort", false).await,
+            Err(UpstreamError::Incomplete)
+        ));
+        assert!(matches!(
+            read(b"SSH-2.0-OpenSSH\r\n\r\n", false).await,
+            Err(UpstreamError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            read(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 99999999999\r\n\r\n",
+                false
+            )
+            .await,
+            Err(UpstreamError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_into_response() {
+        let upstream = UpstreamResponse {
+            status: 200,
+            headers: [
+                ("Content-Type", "text/plain; charset=utf-8"),
+                ("Content-Length", "2"),
+                ("Content-Encoding", "gzip"),
+                ("Keep-Alive", "timeout=5"),
+                ("X-Internal", "1"),
+            ]
+            .into_iter()
+            .collect(),
+            body: Bytes::from_static(b"gz"),
+        };
+        let response = upstream.into_response(&["X-Internal".to_string()]).unwrap();
+        assert_eq!(
+            response.headers().iter().collect::<Vec<_>>(),
+            [
+                ("Content-Type", "text/plain; charset=utf-8"),
+                ("Content-Length", "2"),
+                ("Content-Encoding", "gzip"),
+                ("Via", forwarding::VIA),
+            ]
+        );
+        assert_eq!(response.body_bytes(), b"gz");
+    }
+}
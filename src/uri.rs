@@ -0,0 +1,274 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::http_request::{percent_decode, HTTPPathError};
+
+/// The target of a request in origin form, like `/files/a%20b.txt?download=1`.
+///
+/// Its path is normalized when parsed: `.` and `..` segments, `%2E` dots
+/// included, are resolved so it can't climb above the root. It is kept
+/// percent-encoded, `original` giving the target as the client sent it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Uri {
+    original: String,
+    path: String,
+    query: Option<String>,
+}
+impl Uri {
+    /// The target as sent, before normalization.
+    #[must_use]
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// The normalized path, without its query string.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The query string without its leading `?`, `None` when there is no `?`.
+    #[must_use]
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// The segments of the path, still percent-encoded: `/a/b/` has `a`, `b`
+    /// and an empty last one.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.path[1..].split('/')
+    }
+
+    /// The value of the first `name` parameter of the query string, as sent.
+    #[must_use]
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query()?.split('&').find_map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (key == name).then_some(value)
+        })
+    }
+
+    /// The path relative to `base`, a directory like `/files/`, so
+    /// `/files/a/b.txt` is `a/b.txt`. A `base` without a trailing slash only
+    /// matches whole segments: `/files` is a base of `/files/a` but not of
+    /// `/filesystem`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the path isn't under `base`.
+    #[must_use]
+    pub fn relativize(&self, base: &str) -> Option<&str> {
+        let relative = self.path.strip_prefix(base)?;
+        if base.ends_with('/') || relative.is_empty() {
+            return Some(relative);
+        }
+        relative.strip_prefix('/')
+    }
+
+    /// Resolves `reference` against this URI as RFC 3986 section 5.2 does for
+    /// paths: an absolute path replaces it, a relative one replaces its last
+    /// segment, like a link in a page or the `Location` of a response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of type `HTTPPathError` if `reference` has a scheme or
+    /// an authority, which don't resolve to a path of this server.
+    pub fn join(&self, reference: &str) -> Result<Self, HTTPPathError> {
+        let has_scheme = reference
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains(['/', '?']));
+        if has_scheme || reference.starts_with("//") {
+            return Err(HTTPPathError::InvalidHTTPPath(format!(
+                "'{reference}' names another server"
+            )));
+        }
+        match reference.chars().next() {
+            None => Ok(self.clone()),
+            Some('/') => reference.parse(),
+            Some('?') => format!("{}{reference}", self.path).parse(),
+            Some(_) => {
+                let directory = &self.path[..=self.path.rfind('/').unwrap_or(0)];
+                format!("{directory}{reference}").parse()
+            }
+        }
+    }
+
+    /// The file this URI names inside `directory`, the part of its path under
+    /// `base` being decoded and joined to it segment by segment.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the path isn't under `base`, isn't validly encoded, or
+    /// has a segment decoding to something that isn't a plain file name, like
+    /// `%2F` or `%5C` smuggling a separator in.
+    #[must_use]
+    pub fn file_in(&self, base: &str, directory: &Path) -> Option<PathBuf> {
+        let mut file = directory.to_path_buf();
+        for segment in self.relativize(base)?.split('/') {
+            let name = percent_decode(segment).ok()?;
+            if matches!(name.as_str(), "." | "..") || name.contains(['/', '\\', '\0']) {
+                return None;
+            }
+            if !name.is_empty() {
+                file.push(name);
+            }
+        }
+        Some(file)
+    }
+}
+impl FromStr for Uri {
+    type Err = HTTPPathError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('/') {
+            return Err(HTTPPathError::InvalidHTTPPath(format!(
+                "Path '{s}' does not start with '/'"
+            )));
+        }
+        let (path, query) = s
+            .split_once('?')
+            .map_or((s, None), |(path, query)| (path, Some(query.to_string())));
+        Ok(Self {
+            original: s.to_string(),
+            path: remove_dot_segments(path),
+            query,
+        })
+    }
+}
+impl Display for Uri {
+    /// Writes the normalized form of the URI, its query included.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.query {
+            Some(query) => write!(f, "{}?{query}", self.path),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// Resolves the `.` and `..` segments of an absolute path, as RFC 3986
+/// section 5.2.4 describes, so `/a/./b/../c` becomes `/a/c`. Dots encoded as
+/// `%2E` count as dots.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut ends_in_directory = false;
+    for segment in path.trim_start_matches('/').split('/') {
+        let dots = segment.to_lowercase().replace("%2e", ".");
+        ends_in_directory = matches!(dots.as_str(), "." | "..");
+        match dots.as_str() {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    if ends_in_directory && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+    normalized
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn uri(s: &str) -> Uri {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_uri_from_str() {
+        let target = uri("/files/./a%20b.txt?download=1&x=../y");
+        assert_eq!(target.original(), "/files/./a%20b.txt?download=1&x=../y");
+        assert_eq!(target.path(), "/files/a%20b.txt");
+        assert_eq!(target.query(), Some("download=1&x=../y"));
+        assert_eq!(target.to_string(), "/files/a%20b.txt?download=1&x=../y");
+        assert_eq!(
+            target.segments().collect::<Vec<_>>(),
+            ["files", "a%20b.txt"]
+        );
+        assert_eq!(uri("/a/b/").segments().collect::<Vec<_>>(), ["a", "b", ""]);
+        assert_eq!(uri("/").query(), None);
+        assert_eq!(uri("/?").query(), Some(""));
+        assert!(matches!(
+            Uri::from_str("test/path"),
+            Err(HTTPPathError::InvalidHTTPPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_uri_removes_dot_segments() {
+        let path = |s: &str| uri(s).to_string();
+        assert_eq!(path("/a/./b/../c"), "/a/c");
+        assert_eq!(path("/a/b/.."), "/a/");
+        assert_eq!(path("/files/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(path("/files/%2E%2e/secret"), "/secret");
+        assert_eq!(path("/echo/a/../b?x=../y"), "/echo/b?x=../y");
+        assert_eq!(path("/files/"), "/files/");
+        assert_eq!(path("/"), "/");
+        assert_eq!(path("/.."), "/");
+        assert_eq!(path("/a//b"), "/a//b");
+    }
+
+    #[test]
+    fn test_uri_query_param() {
+        let target = uri("/files/a?flag&api_key=abc&api_key=def");
+        assert_eq!(target.query_param("api_key"), Some("abc"));
+        assert_eq!(target.query_param("flag"), Some(""));
+        assert_eq!(target.query_param("other"), None);
+        assert_eq!(uri("/").query_param("a"), None);
+    }
+
+    #[test]
+    fn test_uri_relativize() {
+        let target = uri("/files/a/b.txt?x=1");
+        assert_eq!(target.relativize("/files/"), Some("a/b.txt"));
+        assert_eq!(target.relativize("/files"), Some("a/b.txt"));
+        assert_eq!(target.relativize("/"), Some("files/a/b.txt"));
+        assert_eq!(uri("/files").relativize("/files"), Some(""));
+        assert_eq!(uri("/filesystem").relativize("/files"), None);
+        assert_eq!(target.relativize("/echo/"), None);
+    }
+
+    #[test]
+    fn test_uri_join() {
+        let base = uri("/docs/guide/intro.html?x=1");
+        assert_eq!(
+            base.join("setup.html").unwrap().to_string(),
+            "/docs/guide/setup.html"
+        );
+        assert_eq!(base.join("../api/").unwrap().to_string(), "/docs/api/");
+        assert_eq!(base.join("/other?y=2").unwrap().to_string(), "/other?y=2");
+        assert_eq!(
+            base.join("?y=2").unwrap().to_string(),
+            "/docs/guide/intro.html?y=2"
+        );
+        assert_eq!(base.join("").unwrap(), base);
+        assert_eq!(base.join("../../../..").unwrap().to_string(), "/");
+        assert!(base.join("//example.com/").is_err());
+        assert!(base.join("http://example.com/").is_err());
+    }
+
+    #[test]
+    fn test_uri_file_in() {
+        let directory = Path::new("/srv");
+        let file = |s: &str| uri(s).file_in("/files/", directory);
+        assert_eq!(
+            file("/files/a%20b.txt"),
+            Some(PathBuf::from("/srv/a b.txt"))
+        );
+        assert_eq!(
+            file("/files/dir//c.txt?x"),
+            Some(PathBuf::from("/srv/dir/c.txt"))
+        );
+        assert_eq!(file("/files/../etc/passwd"), None);
+        assert_eq!(file("/files/a%2F..%2F..%2Fetc"), None);
+        assert_eq!(file("/files/..%5Csecret"), None);
+        assert_eq!(file("/files/%ZZ"), None);
+        assert_eq!(file("/echo/a"), None);
+    }
+}
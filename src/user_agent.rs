@@ -0,0 +1,220 @@
+use std::fmt::Display;
+
+/// The `User-Agent` of a request, like `curl/8.5.0` or
+/// `Mozilla/5.0 (X11; Linux x86_64) Gecko/20100101 Firefox/126.0`.
+///
+/// It is kept as sent, `products` reading it on demand.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UserAgent(pub(crate) String);
+impl UserAgent {
+    /// The products the value lists, each with the comments following it, as
+    /// RFC 9110 section 10.1.5 describes them.
+    ///
+    /// Values that don't follow the grammar are read as far as they can be,
+    /// an unclosed comment running to the end.
+    #[must_use]
+    pub fn products(&self) -> Vec<Product> {
+        let mut products: Vec<Product> = vec![];
+        let mut rest = self.0.trim();
+        while !rest.is_empty() {
+            if let Some(comment) = rest.strip_prefix('(') {
+                let (comment, tail) = split_comment(comment);
+                match products.last_mut() {
+                    Some(product) => product.comments.push(comment.to_string()),
+                    None => products.push(Product {
+                        name: String::new(),
+                        version: None,
+                        comments: vec![comment.to_string()],
+                    }),
+                }
+                rest = tail.trim_start();
+                continue;
+            }
+            let end = rest.find([' ', '\t', '(']).unwrap_or(rest.len());
+            let (product, tail) = rest.split_at(end);
+            let (name, version) = product
+                .split_once('/')
+                .map_or((product, None), |(name, version)| (name, Some(version)));
+            products.push(Product {
+                name: name.to_string(),
+                version: version
+                    .filter(|version| !version.is_empty())
+                    .map(ToString::to_string),
+                comments: vec![],
+            });
+            rest = tail.trim_start();
+        }
+        products
+    }
+
+    /// Whether the request comes from the curl command line tool.
+    #[must_use]
+    pub fn is_curl(&self) -> bool {
+        self.products()
+            .first()
+            .is_some_and(|product| product.name.eq_ignore_ascii_case("curl"))
+    }
+
+    /// The family of the browser sending the request, `None` for other clients.
+    ///
+    /// Browsers claim to be each other for compatibility, so Chrome lists
+    /// `Safari` and Edge lists `Chrome`: the most specific product wins.
+    #[must_use]
+    pub fn browser_family(&self) -> Option<BrowserFamily> {
+        let products = self.products();
+        let has = |names: &[&str]| {
+            products
+                .iter()
+                .any(|product| names.contains(&product.name.as_str()))
+        };
+        if has(&["Edg", "Edge", "EdgA", "EdgiOS"]) {
+            Some(BrowserFamily::Edge)
+        } else if has(&["OPR", "Opera"]) {
+            Some(BrowserFamily::Opera)
+        } else if has(&["Firefox", "FxiOS"]) {
+            Some(BrowserFamily::Firefox)
+        } else if has(&["Chrome", "Chromium", "CriOS"]) {
+            Some(BrowserFamily::Chrome)
+        } else if has(&["Safari"]) && has(&["Version"]) {
+            Some(BrowserFamily::Safari)
+        } else {
+            None
+        }
+    }
+}
+impl Display for UserAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A product of a `User-Agent`, like `Firefox/126.0`, with the comments
+/// following it, like `X11; Linux x86_64` in `Mozilla/5.0 (X11; Linux x86_64)`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Product {
+    name: String,
+    version: Option<String>,
+    comments: Vec<String>,
+}
+impl Product {
+    /// The name of the product, empty for comments opening the value.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// The comments following the product, without their parentheses.
+    #[must_use]
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+}
+
+/// The browser families `UserAgent::browser_family` recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BrowserFamily {
+    Chrome,
+    Edge,
+    Firefox,
+    Opera,
+    Safari,
+}
+impl Display for BrowserFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Chrome => "Chrome",
+            Self::Edge => "Edge",
+            Self::Firefox => "Firefox",
+            Self::Opera => "Opera",
+            Self::Safari => "Safari",
+        })
+    }
+}
+
+/// Splits the comment opening `s`, its `(` already removed, from what follows
+/// it. Comments nest, and `\` quotes the character after it.
+fn split_comment(s: &str) -> (&str, &str) {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' if depth == 0 => return (&s[..i], &s[i + 1..]),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    (s, "")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const FIREFOX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:126.0) Gecko/20100101 Firefox/126.0";
+    const CHROME: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+    const EDGE: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36 Edg/125.0.2535.51";
+    const SAFARI: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15";
+
+    fn user_agent(s: &str) -> UserAgent {
+        UserAgent(s.to_string())
+    }
+
+    #[test]
+    fn test_user_agent_products() {
+        let products = user_agent(FIREFOX).products();
+        assert_eq!(
+            products
+                .iter()
+                .map(|product| (product.name(), product.version()))
+                .collect::<Vec<_>>(),
+            [
+                ("Mozilla", Some("5.0")),
+                ("Gecko", Some("20100101")),
+                ("Firefox", Some("126.0"))
+            ]
+        );
+        assert_eq!(products[0].comments(), ["X11; Linux x86_64; rv:126.0"]);
+
+        let products = user_agent("(nested (comment \\) here)) tool/ other(a)(b)").products();
+        assert_eq!(products[0].name(), "");
+        assert_eq!(products[0].comments(), ["nested (comment \\) here)"]);
+        assert_eq!(products[1].version(), None);
+        assert_eq!(products[2].name(), "other");
+        assert_eq!(products[2].comments(), ["a", "b"]);
+        assert_eq!(
+            user_agent("x (unclosed").products()[0].comments(),
+            ["unclosed"]
+        );
+        assert!(user_agent("  ").products().is_empty());
+    }
+
+    #[test]
+    fn test_user_agent_families() {
+        assert!(user_agent("curl/8.5.0").is_curl());
+        assert!(!user_agent("Wget/1.21").is_curl());
+        assert_eq!(user_agent("curl/8.5.0").browser_family(), None);
+        assert_eq!(
+            user_agent(FIREFOX).browser_family(),
+            Some(BrowserFamily::Firefox)
+        );
+        assert_eq!(
+            user_agent(CHROME).browser_family(),
+            Some(BrowserFamily::Chrome)
+        );
+        assert_eq!(user_agent(EDGE).browser_family(), Some(BrowserFamily::Edge));
+        assert_eq!(
+            user_agent(SAFARI).browser_family(),
+            Some(BrowserFamily::Safari)
+        );
+        assert_eq!(BrowserFamily::Edge.to_string(), "Edge");
+    }
+}